@@ -0,0 +1,750 @@
+//! Declarative definition of the command line. Parsing, `--help` for every
+//! command, shell completions and man pages are all generated from it.
+
+use super::output::Format;
+use crate::Result;
+use crate::messaging::export::ExportFormat;
+use crate::messaging::queue::MessageQueue;
+use crate::messaging::read::LogReader;
+use crate::messaging::rotate::{RotationPolicy, parse_duration, parse_size};
+use crate::messaging::search::LogSearch;
+use crate::messaging::send::MessageSender;
+use crate::session_sync::MIN_SYNC_INTERVAL;
+use crate::tmux::Target;
+use clap::{Args, Parser, Subcommand};
+use clap_complete::Shell;
+use std::path::PathBuf;
+use std::time::Duration;
+
+const TARGETS: &str = "\
+Targets:
+  all                     every pane
+  $1, @3, %7              a session, window or pane by id
+  work:build.1            a tmux target: session, window and pane
+  @swarm_role=reviewer    panes with that tag, see `pane tag`; @swarm_role for any value
+  agent-*                 sessions whose names match, else windows
+  work                    a session by name, else windows by name
+Commands that act on panes use the active pane of windows and sessions.";
+
+/// Swarm Control CLI: drive tmux sessions of coding agents, their
+/// messages and their logs
+#[derive(Debug, Parser)]
+#[command(
+    name = "swarm_test",
+    version,
+    arg_required_else_help = true,
+    after_help = TARGETS
+)]
+pub struct Cli {
+    #[command(flatten)]
+    pub global: GlobalArgs,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+/// Flags accepted before or after any command, except `--format`
+#[derive(Debug, Args)]
+pub struct GlobalArgs {
+    /// tmux server socket, like `tmux -S`
+    #[arg(long, global = true, value_name = "PATH")]
+    pub socket: Option<PathBuf>,
+
+    /// Directory holding prompt files and the message queue [default: current directory]
+    #[arg(long, global = true, value_name = "DIR")]
+    pub base_path: Option<PathBuf>,
+
+    /// Directory holding session and pane logs [default: /tmp/tmux_logs]
+    #[arg(long, global = true, value_name = "DIR")]
+    pub log_dir: Option<PathBuf>,
+
+    /// Write JSON instead of text; errors go to stderr as JSON
+    #[arg(long, global = true)]
+    pub json: bool,
+
+    /// Output format; `--format json` is the same as `--json`. Given before
+    /// the command: after it, `export --format` picks the transcript format.
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub format: Option<Format>,
+
+    /// Echo each tmux command to stderr before it runs
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+}
+
+impl GlobalArgs {
+    pub fn format(&self) -> Format {
+        if self.json {
+            Format::Json
+        } else {
+            self.format.unwrap_or_default()
+        }
+    }
+
+    pub fn base_path(&self) -> Result<PathBuf> {
+        match &self.base_path {
+            Some(path) => Ok(path.clone()),
+            None => Ok(std::env::current_dir()?),
+        }
+    }
+
+    pub fn reader(&self) -> LogReader {
+        match &self.log_dir {
+            Some(dir) => LogReader::with_dir(dir.clone()),
+            None => LogReader::new(),
+        }
+    }
+
+    pub fn search(&self) -> LogSearch {
+        match &self.log_dir {
+            Some(dir) => LogSearch::with_dir(dir.clone()),
+            None => LogSearch::new(),
+        }
+    }
+
+    pub fn sender(&self) -> Result<MessageSender> {
+        Ok(MessageSender::new(self.base_path()?))
+    }
+
+    pub fn queue(&self) -> Result<MessageQueue> {
+        MessageQueue::new(self.base_path()?)
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Session management
+    Session {
+        #[command(subcommand)]
+        command: SessionCommand,
+    },
+    /// Window management
+    Window {
+        #[command(subcommand)]
+        command: WindowCommand,
+    },
+    /// Pane management
+    Pane {
+        #[command(subcommand)]
+        command: PaneCommand,
+    },
+    /// Message passing
+    Message {
+        #[command(subcommand)]
+        command: MessageCommand,
+    },
+    /// Messages waiting for delivery
+    Queue {
+        #[command(subcommand)]
+        command: QueueCommand,
+    },
+    /// Links between OpenCode sessions and tmux sessions
+    Mapping {
+        /// Mapping store [default: ~/.swarm_test/sessions.json]
+        #[arg(long, global = true, value_name = "FILE")]
+        store: Option<PathBuf>,
+
+        #[command(subcommand)]
+        command: MappingCommand,
+    },
+    /// Output monitoring
+    Output {
+        #[command(subcommand)]
+        command: OutputCommand,
+    },
+    /// Export a session transcript
+    Export(ExportArgs),
+    /// Search every session and pane log
+    Search(SearchArgs),
+    /// Show sessions and available logs
+    Status,
+    /// Interactive view of every pane and its agent
+    Dashboard(DashboardArgs),
+    /// Reconcile OpenCode sessions with tmux sessions
+    Sync(SyncArgs),
+    /// Serve the OpenCode endpoints locally for offline development
+    MockOpencode(MockOpencodeArgs),
+    /// Print a shell completion script
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+    /// Print the man page, or write one per command to a directory
+    Man {
+        /// Directory for `swarm_test.1` and a page for every command
+        #[arg(long, value_name = "DIR")]
+        out_dir: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SessionCommand {
+    /// Create a new session
+    Start { name: String },
+    /// Stop sessions
+    Stop {
+        /// Sessions to stop
+        #[arg(value_name = "TARGET")]
+        target: Target,
+    },
+    /// List all sessions
+    List,
+    /// Attach to a session
+    Attach {
+        /// The session to attach to
+        #[arg(value_name = "TARGET")]
+        target: Target,
+    },
+    /// Detach clients from sessions
+    Detach {
+        /// Sessions to detach
+        #[arg(value_name = "TARGET")]
+        target: Target,
+    },
+    /// Rename a session
+    Rename {
+        /// The session to rename
+        #[arg(value_name = "TARGET")]
+        target: Target,
+        new_name: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum WindowCommand {
+    /// Create a window in a session
+    New {
+        /// The session to add the window to
+        #[arg(value_name = "TARGET")]
+        session: Target,
+        name: String,
+    },
+    /// List windows
+    List {
+        /// Sessions or windows to list [default: every session]
+        #[arg(value_name = "TARGET")]
+        target: Option<Target>,
+    },
+    /// Rename a window
+    Rename {
+        /// The window to rename
+        #[arg(value_name = "TARGET")]
+        target: Target,
+        new_name: String,
+    },
+    /// Make windows the current one of their session
+    Select {
+        /// Windows to select
+        #[arg(value_name = "TARGET")]
+        target: Target,
+    },
+    /// Close windows and their panes
+    Kill {
+        /// Windows to close, or the active window of sessions
+        #[arg(value_name = "TARGET")]
+        target: Target,
+    },
+    /// Move sessions to their next window
+    Next {
+        /// Sessions to move
+        #[arg(value_name = "TARGET")]
+        session: Target,
+    },
+    /// Move sessions to their previous window
+    Previous {
+        /// Sessions to move
+        #[arg(value_name = "TARGET")]
+        session: Target,
+    },
+    /// Move sessions back to the window they were on before
+    Last {
+        /// Sessions to move
+        #[arg(value_name = "TARGET")]
+        session: Target,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum PaneCommand {
+    /// Split windows, side by side unless --vertical
+    Split {
+        /// Windows to split, or the active window of sessions
+        #[arg(value_name = "TARGET")]
+        target: Target,
+        /// Stack the new pane below instead
+        #[arg(long)]
+        vertical: bool,
+    },
+    /// List panes
+    List {
+        /// Sessions, windows or panes to list [default: every session]
+        #[arg(value_name = "TARGET")]
+        target: Option<Target>,
+    },
+    /// Make panes the active one of their window
+    Select {
+        /// Panes to select
+        #[arg(value_name = "TARGET")]
+        target: Target,
+    },
+    /// Resize panes
+    Resize {
+        /// Panes to resize, or the active pane of windows and sessions
+        #[arg(value_name = "TARGET")]
+        target: Target,
+        /// Width in columns
+        #[arg(long, required_unless_present = "height", value_name = "COLS")]
+        width: Option<usize>,
+        /// Height in lines
+        #[arg(long, required_unless_present = "width", value_name = "LINES")]
+        height: Option<usize>,
+    },
+    /// Print what panes show
+    Capture {
+        /// Panes to capture, or the active pane of windows and sessions
+        #[arg(value_name = "TARGET")]
+        target: Target,
+        /// Also include this many lines of scrollback
+        #[arg(long, value_name = "N")]
+        history: Option<usize>,
+    },
+    /// Tag panes for `@KEY=VALUE` targets
+    Tag {
+        /// Panes to tag, or the active pane of windows and sessions
+        #[arg(value_name = "TARGET")]
+        target: Target,
+        /// Tag to set; an empty value removes it
+        #[arg(value_name = "KEY=VALUE")]
+        tag: String,
+    },
+    /// Close panes
+    Kill {
+        /// Panes to close, or the active pane of windows and sessions
+        #[arg(value_name = "TARGET")]
+        target: Target,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum MessageCommand {
+    /// Send a message to a pane, or to every pane a target picks
+    Send {
+        /// Panes, or the active pane of windows and sessions
+        #[arg(value_name = "TARGET")]
+        target: Target,
+        /// Words are joined with spaces
+        #[arg(required = true, num_args = 1..)]
+        message: Vec<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum QueueCommand {
+    /// Queue a message for every pane a target picks
+    Push {
+        /// Panes, or the active pane of windows and sessions
+        #[arg(value_name = "TARGET")]
+        target: Target,
+        /// Words are joined with spaces
+        #[arg(required = true, num_args = 1..)]
+        message: Vec<String>,
+    },
+    /// List queued messages, oldest first
+    List,
+    /// Deliver queued messages to their panes
+    Drain,
+    /// Requeue messages that ran out of retries
+    Retry,
+    /// Count queued and failed messages
+    Stats,
+    /// Drop every queued message
+    Clear,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum MappingCommand {
+    /// List links
+    List {
+        /// Only links of this OpenCode session or tmux session
+        #[arg(value_name = "SESSION")]
+        session: Option<String>,
+    },
+    /// Link an OpenCode session to a tmux session
+    Add {
+        opencode_id: String,
+        /// tmux session
+        #[arg(value_name = "TARGET")]
+        session: Target,
+        /// Narrow the link to a window
+        #[arg(long, value_name = "TARGET")]
+        window: Option<Target>,
+        /// Narrow the link to a pane
+        #[arg(long, value_name = "TARGET")]
+        pane: Option<Target>,
+        /// Agent running in the session
+        #[arg(long, value_name = "NAME")]
+        agent: Option<String>,
+        /// Directory the agent works in
+        #[arg(long, value_name = "DIR")]
+        project_dir: Option<PathBuf>,
+    },
+    /// Remove the links of an OpenCode session
+    Remove {
+        opencode_id: String,
+        /// Only the links into this tmux session
+        #[arg(value_name = "SESSION")]
+        session: Option<String>,
+    },
+    /// Remove every link
+    Clear,
+}
+
+/// Parsing of a log into agent responses
+#[derive(Debug, Args)]
+pub struct ParseArgs {
+    /// Only responses matching the query, e.g. 'tool:bash is:failed since:1h'
+    #[arg(short, long)]
+    pub query: Option<String>,
+
+    /// Log dialect to parse [default: detected, or opencode for watch]
+    #[arg(long, value_name = "NAME")]
+    pub dialect: Option<String>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum OutputCommand {
+    /// Read full session output
+    Read {
+        /// Session log: an id, or a target picking one session
+        session_id: String,
+        #[command(flatten)]
+        parse: ParseArgs,
+    },
+    /// Tail the last lines or responses of a session
+    Tail {
+        /// Session log: an id, or a target picking one session
+        session_id: String,
+        #[arg(default_value_t = 20)]
+        n: usize,
+        #[command(flatten)]
+        parse: ParseArgs,
+    },
+    /// Follow output in real time; several targets share one labelled stream
+    Watch(WatchArgs),
+    /// Responses matching a query, across sessions
+    Query {
+        /// Query, e.g. 'tool:bash is:failed since:1h'
+        query: String,
+        /// Session logs or targets to search [default: every session with a log]
+        session_ids: Vec<String>,
+        #[arg(long, value_name = "NAME")]
+        dialect: Option<String>,
+        /// Stop after this many matches
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    /// Rotate and compress logs, then apply retention
+    Rotate(RotateArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct WatchArgs {
+    /// Targets, or session and pane log ids and `*` globs over them
+    #[arg(required = true, value_name = "TARGET")]
+    pub targets: Vec<String>,
+
+    /// Print parsed events (tool calls, errors, completions) instead of raw lines
+    #[arg(long)]
+    pub parse: bool,
+
+    #[command(flatten)]
+    pub parse_args: ParseArgs,
+
+    /// Only output written from now on
+    #[arg(long)]
+    pub from_end: bool,
+
+    /// Start with the last `n` lines of each log
+    #[arg(long, value_name = "N")]
+    pub tail: Option<u64>,
+
+    /// Stop after this long, e.g. 10m
+    #[arg(long, value_parser = duration, value_name = "DURATION")]
+    pub timeout: Option<Duration>,
+}
+
+#[derive(Debug, Args)]
+pub struct RotateArgs {
+    /// Session logs or targets to rotate [default: every session with a log]
+    pub session_ids: Vec<String>,
+
+    #[command(flatten)]
+    pub rotation: RotationArgs,
+
+    /// Rotate even when no limit is reached
+    #[arg(long)]
+    pub force: bool,
+}
+
+/// When to rotate logs and which rotated segments to keep
+#[derive(Debug, Args)]
+pub struct RotationArgs {
+    /// Rotate the active log once it reaches this size, e.g. 100M
+    #[arg(long, value_parser = size, value_name = "SIZE")]
+    pub max_size: Option<u64>,
+
+    /// Rotate the active log once it is this old, e.g. 1d
+    #[arg(long, value_parser = duration, value_name = "AGE")]
+    pub max_age: Option<Duration>,
+
+    /// Rotated segments to keep per log
+    #[arg(long, value_name = "N")]
+    pub keep: Option<usize>,
+
+    /// Remove segments older than this
+    #[arg(long, value_parser = duration, value_name = "AGE")]
+    pub retain: Option<Duration>,
+
+    /// Remove the oldest segments while all of them together exceed this size
+    #[arg(long, value_parser = size, value_name = "SIZE")]
+    pub max_total: Option<u64>,
+
+    /// Keep rotated segments uncompressed
+    #[arg(long)]
+    pub no_compress: bool,
+}
+
+impl RotationArgs {
+    /// Whether any rotation or retention limit is given
+    pub fn has_limits(&self) -> bool {
+        self.max_size.is_some()
+            || self.max_age.is_some()
+            || self.keep.is_some()
+            || self.retain.is_some()
+            || self.max_total.is_some()
+    }
+
+    pub fn policy(&self) -> RotationPolicy {
+        let mut policy = RotationPolicy::new().with_compress(!self.no_compress);
+        if let Some(size) = self.max_size {
+            policy = policy.with_max_size(size);
+        }
+        if let Some(size) = self.max_total {
+            policy = policy.with_max_total_size(size);
+        }
+        if let Some(age) = self.max_age {
+            policy = policy.with_max_age(age);
+        }
+        if let Some(age) = self.retain {
+            policy = policy.with_retention(age);
+        }
+        if let Some(segments) = self.keep {
+            policy = policy.with_max_segments(segments);
+        }
+        policy
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    /// Session log: an id, or a target picking one session
+    pub session_id: String,
+
+    /// md, html or jsonl [default: from the output extension, else md; jsonl with --json]
+    #[arg(short, long, value_parser = export_format, value_name = "FORMAT")]
+    pub format: Option<ExportFormat>,
+
+    /// Write to a file instead of stdout
+    #[arg(short, long, value_name = "FILE")]
+    pub output: Option<String>,
+
+    /// Export one pane of the session
+    #[arg(long, value_name = "PANE_ID")]
+    pub pane: Option<String>,
+
+    #[arg(long, value_name = "NAME")]
+    pub dialect: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct SearchArgs {
+    /// Words, "phrases", /regexes/, session:/pane: filters, AND, OR, NOT and parentheses
+    pub query: String,
+
+    /// Lines of context after each hit
+    #[arg(short = 'A', long, value_name = "N")]
+    pub after: Option<usize>,
+
+    /// Lines of context before each hit
+    #[arg(short = 'B', long, value_name = "N")]
+    pub before: Option<usize>,
+
+    /// Lines of context before and after each hit
+    #[arg(short = 'C', long, value_name = "N")]
+    pub context: Option<usize>,
+
+    /// Stop after this many hits
+    #[arg(short = 'n', long, value_name = "N")]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Args)]
+pub struct DashboardArgs {
+    /// How often the view is refreshed, e.g. 5s
+    #[arg(long, value_parser = refresh, default_value = "1s", value_name = "DURATION")]
+    pub refresh: Duration,
+}
+
+#[derive(Debug, Args)]
+pub struct SyncArgs {
+    /// Keep reconciling until interrupted
+    #[arg(long)]
+    pub daemon: bool,
+
+    /// Time between passes with --daemon, at least 1s, e.g. 30s or 5m; a
+    /// bare number is seconds
+    #[arg(long, value_parser = interval, default_value = "30s", value_name = "DURATION")]
+    pub interval: Duration,
+
+    /// OpenCode server [default: $OPENCODE_SERVER_URL, else http://127.0.0.1:4096]
+    #[arg(long)]
+    pub url: Option<String>,
+
+    /// Report what would change without creating or pruning anything
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Mapping store [default: ~/.swarm_test/sessions.json]
+    #[arg(long, value_name = "FILE")]
+    pub store: Option<PathBuf>,
+
+    #[command(
+        flatten,
+        next_help_heading = "Log rotation (with --daemon, after every pass)"
+    )]
+    pub rotation: RotationArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct MockOpencodeArgs {
+    /// Address to listen on
+    #[arg(default_value = "127.0.0.1:4096")]
+    pub addr: String,
+
+    /// Session ids to pre-register
+    pub sessions: Vec<String>,
+}
+
+fn duration(text: &str) -> std::result::Result<Duration, String> {
+    parse_duration(text).ok_or_else(|| format!("invalid duration '{}', e.g. 30s, 10m, 1h", text))
+}
+
+fn refresh(text: &str) -> std::result::Result<Duration, String> {
+    duration(text).and_then(|d| {
+        (!d.is_zero())
+            .then_some(d)
+            .ok_or_else(|| "refresh must be longer than zero".to_string())
+    })
+}
+
+fn interval(text: &str) -> std::result::Result<Duration, String> {
+    let interval = match text.parse::<u64>() {
+        Ok(secs) => Duration::from_secs(secs),
+        Err(_) => duration(text)?,
+    };
+    (interval >= MIN_SYNC_INTERVAL)
+        .then_some(interval)
+        .ok_or_else(|| "interval must be at least 1s".to_string())
+}
+
+fn size(text: &str) -> std::result::Result<u64, String> {
+    parse_size(text).ok_or_else(|| format!("invalid size '{}', e.g. 512K, 100M, 1G", text))
+}
+
+fn export_format(text: &str) -> Result<ExportFormat> {
+    text.parse()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli_definition_is_consistent() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_output_and_export_formats_do_not_clash() {
+        let cli = Cli::try_parse_from([
+            "swarm_test",
+            "--format",
+            "json",
+            "export",
+            "s1",
+            "-f",
+            "html",
+        ])
+        .unwrap();
+        assert_eq!(cli.global.format(), Format::Json);
+        match cli.command {
+            Command::Export(args) => assert_eq!(args.format, Some(ExportFormat::Html)),
+            other => panic!("Expected export, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_sync_interval_is_at_least_a_second() {
+        let interval = |text: &str| match Cli::try_parse_from([
+            "swarm_test",
+            "sync",
+            "--daemon",
+            "--interval",
+            text,
+        ]) {
+            Ok(Cli {
+                command: Command::Sync(args),
+                ..
+            }) => Ok(args.interval),
+            Ok(other) => panic!("Expected sync, got {:?}", other.command),
+            Err(e) => Err(e.kind()),
+        };
+
+        assert_eq!(interval("10"), Ok(Duration::from_secs(10)));
+        assert_eq!(interval("5m"), Ok(Duration::from_secs(300)));
+        assert_eq!(interval("1s"), Ok(Duration::from_secs(1)));
+        for text in ["0", "0s", "500ms", "soon"] {
+            assert_eq!(interval(text), Err(clap::error::ErrorKind::ValueValidation));
+        }
+    }
+
+    #[test]
+    fn test_sync_takes_rotation_limits() {
+        let cli = Cli::try_parse_from([
+            "swarm_test",
+            "sync",
+            "--daemon",
+            "--max-size",
+            "100M",
+            "--keep",
+            "5",
+        ])
+        .unwrap();
+        match cli.command {
+            Command::Sync(args) => {
+                assert!(args.rotation.has_limits());
+                let policy = args.rotation.policy();
+                assert_eq!(policy.max_size, Some(100 * 1024 * 1024));
+                assert_eq!(policy.max_segments, Some(5));
+            }
+            other => panic!("Expected sync, got {:?}", other),
+        }
+
+        let cli = Cli::try_parse_from(["swarm_test", "sync", "--daemon"]).unwrap();
+        match cli.command {
+            Command::Sync(args) => assert!(!args.rotation.has_limits()),
+            other => panic!("Expected sync, got {:?}", other),
+        }
+    }
+}
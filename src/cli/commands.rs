@@ -1,197 +1,267 @@
+use super::args::{
+    ExportArgs, GlobalArgs, MappingCommand, MessageCommand, MockOpencodeArgs, OutputCommand,
+    ParseArgs, RotateArgs, SearchArgs, SessionCommand, SyncArgs, WatchArgs,
+};
 use super::main::colors;
+use super::output;
+use crate::Result;
+use crate::error::TmuxError;
+use crate::messaging::export::{self, ExportFormat};
+use crate::messaging::query::Query;
+use crate::messaging::rotate::{LogRotator, RotationPolicy, RotationReport};
+use crate::messaging::search::{LogSource, SearchHit, SearchOptions, SearchQuery};
+use crate::messaging::stream::{StreamingParser, capture_time};
+use crate::messaging::transcript::Transcript;
+use crate::messaging::watch::{WatchHandle, WatchOptions};
+use crate::opencode::{AgentResponse, MessageType, MockOpenCodeServer, OpenCodeClient};
+use crate::session_mapping::{SessionMapping, SessionMappingStore};
+use crate::session_sync::{SessionReconciler, SyncOptions, SyncReport};
+use crate::tmux::{Picked, Selection, Target, pane, resolve_target, session as tmux_session};
+use crate::types::{PaneId, Session, SessionId};
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
 use std::io::Write;
 use std::path::PathBuf;
-use swarm_test::Result;
-use swarm_test::messaging::read::LogReader;
-use swarm_test::messaging::send::MessageSender;
-use swarm_test::tmux::session as tmux_session;
-use swarm_test::types::{PaneId, SessionId};
-
-pub fn print_usage() {
-    println!("{}Swarm Control CLI{}", colors::bold(), colors::reset());
-    println!();
-    println!("{}Usage:{}", colors::cyan(), colors::reset());
-    println!(
-        "  {}swarm_test{} <command> [args]",
-        colors::yellow(),
-        colors::reset()
-    );
-    println!();
-    println!("{}Commands:{}", colors::cyan(), colors::reset());
-    println!(
-        "  {}session{} <subcommand>     Session management",
-        colors::green(),
-        colors::reset()
-    );
-    println!(
-        "    {}start{} <name>            Create a new session",
-        colors::yellow(),
-        colors::reset()
-    );
-    println!(
-        "    {}stop{} <id|name>          Stop a session",
-        colors::yellow(),
-        colors::reset()
-    );
-    println!(
-        "    {}list{}                    List all sessions",
-        colors::yellow(),
-        colors::reset()
-    );
-    println!(
-        "    {}attach{} <id|name>        Attach to a session",
-        colors::yellow(),
-        colors::reset()
-    );
-    println!(
-        "    {}detach{} <id|name>        Detach from a session",
-        colors::yellow(),
-        colors::reset()
-    );
-    println!();
-    println!(
-        "  {}message{} <subcommand>     Message management",
-        colors::green(),
-        colors::reset()
-    );
-    println!(
-        "    {}send{} <pane_id> <msg>    Send message to pane",
-        colors::yellow(),
-        colors::reset()
-    );
-    println!();
-    println!(
-        "  {}output{} <subcommand>      Output management",
-        colors::green(),
-        colors::reset()
-    );
-    println!(
-        "    {}read{} <session_id>       Read session output",
-        colors::yellow(),
-        colors::reset()
-    );
-    println!(
-        "    {}tail{} <session_id> [n]   Tail last N lines",
-        colors::yellow(),
-        colors::reset()
-    );
-    println!(
-        "    {}watch{} <session_id>      Watch session output live",
-        colors::yellow(),
-        colors::reset()
-    );
-    println!();
-    println!(
-        "  {}status{}                   Show system status",
-        colors::green(),
-        colors::reset()
-    );
-    println!();
-    println!(
-        "  {}help{}                     Show this help",
-        colors::green(),
-        colors::reset()
-    );
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::mpsc;
+use std::time::Duration;
+
+pub fn handle_session(global: &GlobalArgs, command: SessionCommand) -> Result<()> {
+    match command {
+        SessionCommand::Start { name } => session_start(global, &name),
+        SessionCommand::Stop { target } => session_stop(&target),
+        SessionCommand::List => session_list(),
+        SessionCommand::Attach { target } => session_attach(&target),
+        SessionCommand::Detach { target } => session_detach(&target),
+        SessionCommand::Rename { target, new_name } => session_rename(&target, &new_name),
+    }
 }
 
-pub fn handle_session(args: &[String]) -> Result<()> {
-    if args.is_empty() {
-        eprintln!(
-            "{}Error: session subcommand required{}",
-            colors::red(),
-            colors::reset()
-        );
-        eprintln!("  session start <name>");
-        eprintln!("  session stop <id|name>");
-        eprintln!("  session list");
-        eprintln!("  session attach <id|name>");
-        eprintln!("  session detach <id|name>");
-        std::process::exit(1);
-    }
-
-    let subcommand = &args[0];
-    let subcommand_args = &args[1..];
-
-    match subcommand.as_str() {
-        "start" => session_start(subcommand_args),
-        "stop" => session_stop(subcommand_args),
-        "list" => session_list(subcommand_args),
-        "attach" => session_attach(subcommand_args),
-        "detach" => session_detach(subcommand_args),
-        _ => {
-            eprintln!(
-                "{}Unknown session subcommand: '{}'",
-                colors::red(),
-                subcommand
-            );
-            std::process::exit(1);
+pub fn handle_message(global: &GlobalArgs, command: MessageCommand) -> Result<()> {
+    match command {
+        MessageCommand::Send { target, message } => {
+            message_send(global, &target, &message.join(" "))
         }
     }
 }
 
-pub fn handle_message(args: &[String]) -> Result<()> {
-    if args.is_empty() {
-        eprintln!(
-            "{}Error: message subcommand required{}",
-            colors::red(),
-            colors::reset()
-        );
-        eprintln!("  message send <pane_id> <message>");
-        std::process::exit(1);
+pub fn handle_output(global: &GlobalArgs, command: OutputCommand) -> Result<()> {
+    match command {
+        OutputCommand::Read { session_id, parse } => {
+            output_read(global, log_session(&session_id)?, parse)
+        }
+        OutputCommand::Tail {
+            session_id,
+            n,
+            parse,
+        } => output_tail(global, log_session(&session_id)?, n, parse),
+        OutputCommand::Watch(args) => output_watch(global, args),
+        OutputCommand::Query {
+            query,
+            session_ids,
+            dialect,
+            limit,
+        } => output_query(global, &query, session_ids, dialect, limit),
+        OutputCommand::Rotate(args) => output_rotate(global, args),
     }
+}
 
-    let subcommand = &args[0];
-    let subcommand_args = &args[1..];
+pub fn handle_export(global: &GlobalArgs, args: ExportArgs) -> Result<()> {
+    let ExportArgs {
+        session_id,
+        format,
+        output,
+        pane,
+        dialect,
+    } = args;
 
-    match subcommand.as_str() {
-        "send" => message_send(subcommand_args),
-        _ => {
+    let default_format = if output::is_json() {
+        ExportFormat::Jsonl
+    } else {
+        ExportFormat::Markdown
+    };
+    let format = format
+        .or_else(|| output.as_deref().and_then(ExportFormat::from_path))
+        .unwrap_or(default_format);
+    let mut builder = Transcript::builder(log_session(&session_id)?)
+        .base_path(global.base_path()?)
+        .log_reader(global.reader());
+    if let Some(pane) = pane {
+        builder = builder.pane(PaneId(pane));
+    }
+    if let Some(dialect) = dialect {
+        builder = builder.dialect(dialect);
+    }
+    let transcript = builder.build()?;
+
+    match output {
+        Some(path) => {
+            let file = std::fs::File::create(&path)?;
+            export::export(&transcript, format, std::io::BufWriter::new(file))?;
+            if output::is_json() {
+                return output::emit(&serde_json::json!({
+                    "path": path,
+                    "entries": transcript.len(),
+                }));
+            }
             eprintln!(
-                "{}Unknown message subcommand: '{}'",
-                colors::red(),
-                subcommand
+                "{}Exported {} entries to {}{}",
+                colors::green(),
+                transcript.len(),
+                path,
+                colors::reset()
             );
-            std::process::exit(1);
         }
+        None => export::export(&transcript, format, std::io::stdout().lock())?,
     }
+
+    Ok(())
 }
 
-pub fn handle_output(args: &[String]) -> Result<()> {
-    if args.is_empty() {
-        eprintln!(
-            "{}Error: output subcommand required{}",
-            colors::red(),
-            colors::reset()
-        );
-        eprintln!("  output read <session_id>");
-        eprintln!("  output tail <session_id> [n]");
-        eprintln!("  output watch <session_id>");
-        std::process::exit(1);
+pub fn handle_search(global: &GlobalArgs, args: SearchArgs) -> Result<()> {
+    let query = SearchQuery::parse(&args.query)?;
+
+    let mut options = SearchOptions::new();
+    if let Some(lines) = args.context {
+        options = options.with_context(lines);
+    }
+    if let Some(lines) = args.after {
+        options = options.with_after(lines);
+    }
+    if let Some(lines) = args.before {
+        options = options.with_before(lines);
+    }
+    if let Some(limit) = args.limit {
+        options = options.with_limit(limit);
+    }
+
+    let search = global.search();
+    let hits = search.search(&query, &options)?;
+    if output::is_json() {
+        let hits: Vec<_> = hits.iter().map(search_hit_json).collect();
+        return output::emit(&hits);
+    }
+    if hits.is_empty() {
+        println!("{}No matches{}", colors::yellow(), colors::reset());
+        return Ok(());
     }
 
-    let subcommand = &args[0];
-    let subcommand_args = &args[1..];
+    // Like grep: lines shared by nearby hits are printed once, a hit's own
+    // line always as a hit, and separate groups are divided by `--`
+    let mut lines: BTreeMap<(usize, u64), (&SearchHit, Option<&str>)> = BTreeMap::new();
+    let mut log = 0;
+    for (i, hit) in hits.iter().enumerate() {
+        if i > 0 && hit.path != hits[i - 1].path {
+            log += 1;
+        }
+        let first = hit.line - hit.before.len() as u64;
+        let context = hit
+            .before
+            .iter()
+            .zip(first..)
+            .chain(hit.after.iter().zip(hit.line + 1..));
+        for (text, line) in context {
+            lines
+                .entry((log, line))
+                .or_insert((hit, Some(text.as_str())));
+        }
+        lines.insert((log, hit.line), (hit, None));
+    }
 
-    match subcommand.as_str() {
-        "read" => output_read(subcommand_args),
-        "tail" => output_tail(subcommand_args),
-        "watch" => output_watch(subcommand_args),
-        _ => {
-            eprintln!(
-                "{}Unknown output subcommand: '{}'",
-                colors::red(),
-                subcommand
-            );
-            std::process::exit(1);
+    let mut previous = None;
+    for ((log, line), (hit, context)) in lines {
+        if previous.is_some_and(|(prev_log, prev_line)| prev_log != log || prev_line + 1 != line) {
+            println!("{}--{}", colors::blue(), colors::reset());
         }
+        match context {
+            Some(text) => print_search_context(hit, line, text),
+            None => print_search_hit(hit),
+        }
+        previous = Some((log, line));
     }
+
+    eprintln!(
+        "{}{} match(es) in {} log(s){}",
+        colors::cyan(),
+        hits.len(),
+        log + 1,
+        colors::reset()
+    );
+    Ok(())
 }
 
-pub fn handle_status(_args: &[String]) -> Result<()> {
-    println!("{}System Status{}", colors::bold(), colors::reset());
-    println!();
+fn print_search_hit(hit: &SearchHit) {
+    let mut text = String::new();
+    let mut at = 0;
+    for span in &hit.spans {
+        text.push_str(&hit.text[at..span.start]);
+        text.push_str(colors::bold());
+        text.push_str(colors::red());
+        text.push_str(&hit.text[span.clone()]);
+        text.push_str(colors::reset());
+        at = span.end;
+    }
+    text.push_str(&hit.text[at..]);
 
+    let timestamp = hit
+        .timestamp
+        .map(|ts| format!("{} ", ts.format("%Y-%m-%d %H:%M:%S")))
+        .unwrap_or_default();
+    println!(
+        "{}{}{}:{}{}{}:@{} {}{}",
+        colors::cyan(),
+        hit.source,
+        colors::reset(),
+        colors::green(),
+        hit.line + 1,
+        colors::reset(),
+        hit.offset,
+        timestamp,
+        text
+    );
+}
+
+fn search_hit_json(hit: &SearchHit) -> serde_json::Value {
+    serde_json::json!({
+        "log": hit.source.id(),
+        "path": hit.path,
+        "line": hit.line,
+        "offset": hit.offset,
+        "text": hit.text,
+        "timestamp": hit.timestamp.map(|ts| ts.to_rfc3339()),
+        "before": hit.before,
+        "after": hit.after,
+        "spans": hit.spans,
+    })
+}
+
+fn print_search_context(hit: &SearchHit, line: u64, text: &str) {
+    println!(
+        "{}{}{}-{}{}{}- {}",
+        colors::cyan(),
+        hit.source,
+        colors::reset(),
+        colors::green(),
+        line + 1,
+        colors::reset(),
+        text
+    );
+}
+
+pub fn handle_status(global: &GlobalArgs) -> Result<()> {
     let sessions = tmux_session::list_sessions()?;
+    let log_reader = global.reader();
+    let logged_sessions = log_reader.list_session_logs()?;
+    if output::is_json() {
+        return output::emit(&serde_json::json!({
+            "sessions": sessions,
+            "logs": logged_sessions,
+        }));
+    }
+
+    println!("{}System Status{}", colors::bold(), colors::reset());
+    println!();
     println!(
         "{}Active Sessions: {}{}",
         colors::cyan(),
@@ -227,8 +297,6 @@ pub fn handle_status(_args: &[String]) -> Result<()> {
     }
     println!();
 
-    let log_reader = LogReader::new();
-    let logged_sessions = log_reader.list_session_logs()?;
     println!(
         "{}Available Logs: {}{}",
         colors::cyan(),
@@ -247,115 +315,459 @@ pub fn handle_status(_args: &[String]) -> Result<()> {
     Ok(())
 }
 
-fn session_start(args: &[String]) -> Result<()> {
-    if args.is_empty() {
-        eprintln!(
-            "{}Error: session name required{}",
-            colors::red(),
-            colors::reset()
-        );
-        std::process::exit(1);
+pub fn handle_sync(global: &GlobalArgs, args: SyncArgs) -> Result<()> {
+    let SyncArgs {
+        daemon,
+        interval,
+        url,
+        dry_run,
+        store,
+        rotation,
+    } = args;
+    if rotation.has_limits() && !daemon {
+        return Err(TmuxError::Usage(
+            "log rotation limits need --daemon; use `output rotate` for a single pass".to_string(),
+        ));
     }
+    let server_url = url
+        .or_else(|| std::env::var("OPENCODE_SERVER_URL").ok())
+        .unwrap_or_else(|| "http://127.0.0.1:4096".to_string());
 
-    let name = &args[0];
-    println!(
-        "{}Creating session: {}{}{}",
-        colors::cyan(),
-        colors::bold(),
-        name,
-        colors::reset()
-    );
+    let options = SyncOptions {
+        dry_run,
+        log_dir: global.log_dir.clone(),
+        ..SyncOptions::default()
+    };
+    let client = OpenCodeClient::new(&server_url);
+    let store = open_store(store)?;
+    let mut reconciler = SessionReconciler::new(client, store, options);
 
-    match tmux_session::new_session(name) {
-        Ok(session) => {
+    let runtime = tokio::runtime::Runtime::new()?;
+
+    if daemon {
+        if !output::is_json() {
             println!(
-                "{}Session created successfully!{}",
-                colors::green(),
+                "{}Syncing with {} every {:?}{}",
+                colors::cyan(),
+                server_url,
+                interval,
                 colors::reset()
             );
             println!(
-                "  {}ID: {}{}",
-                colors::blue(),
-                session.id.0,
+                "{}Press Ctrl+C to stop{}",
+                colors::yellow(),
                 colors::reset()
             );
+        }
+
+        // Logs are maintained after every pass, whether or not it succeeded
+        let rotator = rotation
+            .has_limits()
+            .then(|| log_rotator(global, rotation.policy()));
+        let maintain_logs = || {
+            let Some(rotator) = &rotator else {
+                return;
+            };
+            let result = rotator.maintain_all().and_then(|report| {
+                if report.rotated.is_empty() && report.removed.is_empty() {
+                    Ok(())
+                } else if output::is_json() {
+                    output::emit_line(&serde_json::json!({
+                        "rotated": report.rotated,
+                        "removed": report.removed,
+                    }))
+                } else {
+                    print_rotation_report(&report);
+                    Ok(())
+                }
+            });
+            if let Err(e) = result {
+                if output::is_json() {
+                    output::print_error(&e);
+                } else {
+                    eprintln!("{}Rotation failed: {}{}", colors::red(), e, colors::reset());
+                }
+            }
+        };
+
+        // In JSON mode each report with changes is one line on stdout and
+        // each failed pass one error line on stderr
+        let stop = Arc::new(AtomicBool::new(false));
+        runtime.block_on(reconciler.run(
+            interval,
+            stop,
+            |report| {
+                if report.has_changes() || !report.errors.is_empty() {
+                    if !output::is_json() {
+                        print_sync_report(report);
+                    } else if let Err(e) = output::emit_line(report) {
+                        output::print_error(&e);
+                    }
+                }
+                maintain_logs();
+            },
+            |e| {
+                if output::is_json() {
+                    output::print_error(e);
+                } else {
+                    eprintln!("{}Sync failed: {}{}", colors::red(), e, colors::reset());
+                }
+                maintain_logs();
+            },
+        ));
+        return Ok(());
+    }
+
+    let report = runtime.block_on(reconciler.reconcile())?;
+    if output::is_json() {
+        return output::emit(&report);
+    }
+    print_sync_report(&report);
+    Ok(())
+}
+
+/// Mapping store at `path`, or the default one
+fn open_store(path: Option<PathBuf>) -> Result<SessionMappingStore> {
+    match path {
+        Some(path) => SessionMappingStore::with_path(path),
+        None => SessionMappingStore::new(),
+    }
+}
+
+pub fn handle_mapping(store: Option<PathBuf>, command: MappingCommand) -> Result<()> {
+    let mut store = open_store(store)?;
+
+    match command {
+        MappingCommand::List { session } => mapping_list(&store, session.as_deref()),
+        MappingCommand::Add {
+            opencode_id,
+            session,
+            window,
+            pane,
+            agent,
+            project_dir,
+        } => {
+            let session = session.resolve()?.session()?.name.clone();
+            let mut link = SessionMapping::new(opencode_id, session);
+            if let Some(window) = window {
+                link = link.with_window(window.resolve()?.window()?.id.0.clone());
+            }
+            if let Some(pane) = pane {
+                link = link.with_pane(pane.resolve()?.pane()?.id.0.clone());
+            }
+            if let Some(agent) = agent {
+                link = link.with_agent(agent);
+            }
+            if let Some(project_dir) = project_dir {
+                link = link.with_project_dir(project_dir);
+            }
+            store.add_link(link.clone())?;
+
+            if output::is_json() {
+                return output::emit(&link);
+            }
             println!(
-                "  {}Name: {}{}",
-                colors::blue(),
-                session.name,
+                "{}Linked {} -> {}{}",
+                colors::green(),
+                link.opencode_session_id,
+                link.tmux_session_name,
                 colors::reset()
             );
+            Ok(())
+        }
+        MappingCommand::Remove {
+            opencode_id,
+            session,
+        } => {
+            let before = store.list().len();
+            match session {
+                Some(session) => store.remove_link(&opencode_id, &session_name(&session)?)?,
+                None => store.remove(&opencode_id)?,
+            }
+            let removed = before - store.list().len();
+
+            if output::is_json() {
+                return output::emit(&serde_json::json!({ "removed": removed }));
+            }
             println!(
-                "  {}Windows: {}{}",
-                colors::blue(),
-                session.windows.len(),
+                "{}Removed {} link(s){}",
+                colors::green(),
+                removed,
                 colors::reset()
             );
             Ok(())
         }
-        Err(e) => {
-            eprintln!(
-                "{}Failed to create session: {}",
-                colors::red(),
-                e,
-                colors::reset()
-            );
-            std::process::exit(1);
+        MappingCommand::Clear => {
+            store.clear()?;
+            if output::is_json() {
+                return output::emit(&serde_json::json!({ "cleared": true }));
+            }
+            println!("{}Cleared every link{}", colors::green(), colors::reset());
+            Ok(())
         }
     }
 }
 
-fn session_stop(args: &[String]) -> Result<()> {
-    if args.is_empty() {
-        eprintln!(
-            "{}Error: session id or name required{}",
-            colors::red(),
-            colors::reset()
+fn mapping_list(store: &SessionMappingStore, session: Option<&str>) -> Result<()> {
+    let mut links = store.list();
+    if let Some(identifier) = session {
+        // An OpenCode id, or a tmux session
+        let tmux_name = session_name(identifier)?;
+        links.retain(|m| m.opencode_session_id == identifier || m.tmux_session_name == tmux_name);
+    }
+    if output::is_json() {
+        return output::emit(&links);
+    }
+
+    println!("{}Session links{}", colors::bold(), colors::reset());
+    println!();
+
+    if links.is_empty() {
+        println!("{}No links{}", colors::yellow(), colors::reset());
+        return Ok(());
+    }
+    for link in &links {
+        let target = [link.tmux_window_id.as_deref(), link.tmux_pane_id.as_deref()]
+            .into_iter()
+            .flatten()
+            .fold(link.tmux_session_name.clone(), |target, id| {
+                format!("{} {}", target, id)
+            });
+        println!(
+            "  {}{}{} -> {}",
+            colors::blue(),
+            link.opencode_session_id,
+            colors::reset(),
+            target
         );
-        std::process::exit(1);
+        if let Some(agent) = &link.agent_name {
+            println!("    Agent: {}", agent);
+        }
+        if let Some(dir) = &link.project_dir {
+            println!("    Project: {}", dir.display());
+        }
+    }
+    Ok(())
+}
+
+fn print_sync_report(report: &SyncReport) {
+    for link in &report.created {
+        println!(
+            "  {}created{} {} -> {}",
+            colors::green(),
+            colors::reset(),
+            link.opencode_session_id,
+            link.tmux_session_name
+        );
+    }
+    for link in &report.adopted {
+        println!(
+            "  {}adopted{} {} -> {}",
+            colors::green(),
+            colors::reset(),
+            link.opencode_session_id,
+            link.tmux_session_name
+        );
+    }
+    for link in &report.pruned {
+        println!(
+            "  {}pruned{}  {} -> {}",
+            colors::yellow(),
+            colors::reset(),
+            link.opencode_session_id,
+            link.tmux_session_name
+        );
+    }
+    for id in &report.orphaned_opencode {
+        println!(
+            "  {}orphan{}  OpenCode session {}",
+            colors::yellow(),
+            colors::reset(),
+            id
+        );
+    }
+    for name in &report.orphaned_tmux {
+        println!(
+            "  {}orphan{}  tmux session {}",
+            colors::yellow(),
+            colors::reset(),
+            name
+        );
+    }
+    for error in &report.errors {
+        println!("  {}error{}   {}", colors::red(), colors::reset(), error);
+    }
+    if !report.has_changes()
+        && report.orphaned_opencode.is_empty()
+        && report.orphaned_tmux.is_empty()
+        && report.errors.is_empty()
+    {
+        println!("{}Sessions in sync{}", colors::green(), colors::reset());
+    }
+}
+
+pub fn handle_mock_opencode(args: MockOpencodeArgs) -> Result<()> {
+    let server = MockOpenCodeServer::bind(&args.addr)?;
+    for session in &args.sessions {
+        server.add_session(session, session);
+    }
+
+    if output::is_json() {
+        output::emit_line(&serde_json::json!({ "url": server.url() }))?;
+        server.wait();
+        return Ok(());
     }
 
-    let identifier = &args[0];
     println!(
-        "{}Stopping session: {}{}{}",
+        "{}Mock OpenCode server listening on: {}{}{}",
         colors::cyan(),
         colors::bold(),
-        identifier,
+        server.url(),
+        colors::reset()
+    );
+    println!(
+        "{}Press Ctrl+C to stop{}",
+        colors::yellow(),
         colors::reset()
     );
 
-    let sessions = tmux_session::list_sessions()?;
-    let session = sessions
-        .iter()
-        .find(|s| s.id.0 == *identifier || s.name == *identifier);
+    server.wait();
+    Ok(())
+}
 
-    match session {
-        Some(s) => {
-            tmux_session::kill_session(&s.id)?;
-            println!(
-                "{}Session stopped successfully!{}",
-                colors::green(),
-                colors::reset()
-            );
-            Ok(())
+/// Create a session and log its output, so `output` and `export` can read it
+fn start_logged_session(global: &GlobalArgs, name: &str) -> Result<Session> {
+    let session = tmux_session::new_session(name)?;
+    let log_path = global.reader().session_log_path(&session.id);
+    tmux_session::pipe_session_log(&session, &log_path)?;
+    Ok(session)
+}
+
+fn session_start(global: &GlobalArgs, name: &str) -> Result<()> {
+    if output::is_json() {
+        return output::emit(&start_logged_session(global, name)?);
+    }
+
+    println!(
+        "{}Creating session: {}{}{}",
+        colors::cyan(),
+        colors::bold(),
+        name,
+        colors::reset()
+    );
+
+    let session = start_logged_session(global, name)?;
+    println!(
+        "{}Session created successfully!{}",
+        colors::green(),
+        colors::reset()
+    );
+    println!(
+        "  {}ID: {}{}",
+        colors::blue(),
+        session.id.0,
+        colors::reset()
+    );
+    println!(
+        "  {}Name: {}{}",
+        colors::blue(),
+        session.name,
+        colors::reset()
+    );
+    println!(
+        "  {}Windows: {}{}",
+        colors::blue(),
+        session.windows.len(),
+        colors::reset()
+    );
+    Ok(())
+}
+
+/// Name of the one session `text` picks. A session that is gone can
+/// still be named, so text that picks nothing is taken as the name.
+fn session_name(text: &str) -> Result<String> {
+    match resolve_target(text) {
+        Ok(selection) => Ok(selection.session()?.name.clone()),
+        Err(TmuxError::NotFound(_) | TmuxError::NotConnected | TmuxError::Parse(_)) => {
+            Ok(text.to_string())
         }
-        None => {
-            eprintln!(
-                "{}Session not found: {}",
-                colors::red(),
-                identifier,
-                colors::reset()
-            );
-            std::process::exit(1);
+        Err(e) => Err(e),
+    }
+}
+
+/// Session whose log a command reads: the one session `text` picks, or
+/// `text` itself as the id of a log that outlived its session
+fn log_session(text: &str) -> Result<SessionId> {
+    match resolve_target(text) {
+        Ok(selection) => Ok(selection.session()?.id.clone()),
+        Err(TmuxError::NotFound(_) | TmuxError::NotConnected | TmuxError::Parse(_)) => {
+            Ok(SessionId(text.to_string()))
         }
+        Err(e) => Err(e),
     }
 }
 
-fn session_list(_args: &[String]) -> Result<()> {
-    println!("{}Sessions{}", colors::bold(), colors::reset());
-    println!();
+/// Sessions whose logs a command reads: every session each text picks, or
+/// the text itself as a log id
+fn log_sessions(texts: &[String]) -> Result<Vec<SessionId>> {
+    let mut ids: Vec<SessionId> = Vec::new();
+    for text in texts {
+        let found = match resolve_target(text) {
+            Ok(selection) => selection.sessions().iter().map(|s| s.id.clone()).collect(),
+            Err(TmuxError::NotFound(_) | TmuxError::NotConnected | TmuxError::Parse(_)) => {
+                vec![SessionId(text.clone())]
+            }
+            Err(e) => return Err(e),
+        };
+        for id in found {
+            if !ids.contains(&id) {
+                ids.push(id);
+            }
+        }
+    }
+    Ok(ids)
+}
 
+fn session_stop(target: &Target) -> Result<()> {
+    let sessions = target
+        .resolve()?
+        .sessions()
+        .into_iter()
+        .cloned()
+        .collect::<Vec<_>>();
+    if output::is_json() {
+        for session in &sessions {
+            tmux_session::kill_session(&session.id)?;
+        }
+        return output::emit(&sessions);
+    }
+
+    for session in &sessions {
+        println!(
+            "{}Stopping session: {}{}{}",
+            colors::cyan(),
+            colors::bold(),
+            session.name,
+            colors::reset()
+        );
+        tmux_session::kill_session(&session.id)?;
+    }
+    println!(
+        "{}Session stopped successfully!{}",
+        colors::green(),
+        colors::reset()
+    );
+    Ok(())
+}
+
+fn session_list() -> Result<()> {
     let sessions = tmux_session::list_sessions()?;
+    if output::is_json() {
+        return output::emit(&sessions);
+    }
+
+    println!("{}Sessions{}", colors::bold(), colors::reset());
+    println!();
 
     if sessions.is_empty() {
         println!("{}No active sessions{}", colors::yellow(), colors::reset());
@@ -367,7 +779,12 @@ fn session_list(_args: &[String]) -> Result<()> {
                 format!("{}detached{}", colors::yellow(), colors::reset())
             };
             println!("  {}{}{}", colors::blue(), session.id.0, colors::reset());
-            println!("    Name: {}", colors::bold(), session.name);
+            println!(
+                "    Name: {}{}{}",
+                colors::bold(),
+                session.name,
+                colors::reset()
+            );
             println!("    Status: {}", status);
             println!("    Windows: {}", session.windows.len());
             println!();
@@ -377,169 +794,149 @@ fn session_list(_args: &[String]) -> Result<()> {
     Ok(())
 }
 
-fn session_attach(args: &[String]) -> Result<()> {
-    if args.is_empty() {
-        eprintln!(
-            "{}Error: session id or name required{}",
-            colors::red(),
-            colors::reset()
-        );
-        std::process::exit(1);
+fn session_rename(target: &Target, new_name: &str) -> Result<()> {
+    let mut session = target.resolve()?.session()?.clone();
+    let old_name = std::mem::replace(&mut session.name, new_name.to_string());
+    tmux_session::rename_session(&session.id, new_name)?;
+    if output::is_json() {
+        return output::emit(&session);
     }
 
-    let identifier = &args[0];
     println!(
-        "{}Attaching to session: {}{}{}",
-        colors::cyan(),
+        "{}Renamed session {} to {}{}{}",
+        colors::green(),
+        old_name,
         colors::bold(),
-        identifier,
+        new_name,
         colors::reset()
     );
+    Ok(())
+}
 
-    let sessions = tmux_session::list_sessions()?;
-    let session = sessions
-        .iter()
-        .find(|s| s.id.0 == *identifier || s.name == *identifier);
+fn session_attach(target: &Target) -> Result<()> {
+    let selection = target.resolve()?;
+    let session = selection.session()?;
+    if output::is_json() {
+        output::emit(session)?;
+    } else {
+        println!(
+            "{}Attaching to session: {}{}{}",
+            colors::cyan(),
+            colors::bold(),
+            session.name,
+            colors::reset()
+        );
+    }
 
-    match session {
-        Some(s) => {
-            tmux_session::attach_session(&s.id)?;
-            Ok(())
-        }
-        None => {
-            eprintln!(
-                "{}Session not found: {}",
-                colors::red(),
-                identifier,
-                colors::reset()
-            );
-            std::process::exit(1);
+    tmux_session::attach_session(&session.id)
+}
+
+fn session_detach(target: &Target) -> Result<()> {
+    let sessions = target
+        .resolve()?
+        .sessions()
+        .into_iter()
+        .cloned()
+        .collect::<Vec<_>>();
+    if output::is_json() {
+        for session in &sessions {
+            tmux_session::detach_session(&session.id)?;
         }
+        return output::emit(&sessions);
     }
-}
 
-fn session_detach(args: &[String]) -> Result<()> {
-    if args.is_empty() {
-        eprintln!(
-            "{}Error: session id or name required{}",
-            colors::red(),
+    for session in &sessions {
+        println!(
+            "{}Detaching from session: {}{}{}",
+            colors::cyan(),
+            colors::bold(),
+            session.name,
             colors::reset()
         );
-        std::process::exit(1);
+        tmux_session::detach_session(&session.id)?;
     }
-
-    let identifier = &args[0];
     println!(
-        "{}Detaching from session: {}{}{}",
-        colors::cyan(),
-        colors::bold(),
-        identifier,
+        "{}Detached from session{}",
+        colors::green(),
         colors::reset()
     );
+    Ok(())
+}
 
-    let sessions = tmux_session::list_sessions()?;
-    let session = sessions
-        .iter()
-        .find(|s| s.id.0 == *identifier || s.name == *identifier);
-
-    match session {
-        Some(s) => {
-            tmux_session::detach_session(&s.id)?;
+fn message_send(global: &GlobalArgs, target: &Target, message: &str) -> Result<()> {
+    let selection = target.resolve()?;
+    let panes = selection.panes();
+    if !output::is_json() {
+        for found in &panes {
             println!(
-                "{}Detached from session{}",
-                colors::green(),
-                colors::reset()
-            );
-            Ok(())
-        }
-        None => {
-            eprintln!(
-                "{}Session not found: {}",
-                colors::red(),
-                identifier,
+                "{}Sending message to pane: {}{}{}",
+                colors::cyan(),
+                colors::bold(),
+                found.id.0,
                 colors::reset()
             );
-            std::process::exit(1);
         }
+        println!("  Message: {}", message);
     }
-}
 
-pub fn message_send(args: &[String]) -> Result<()> {
-    if args.len() < 2 {
-        eprintln!(
-            "{}Error: pane_id and message required{}",
-            colors::red(),
-            colors::reset()
-        );
-        eprintln!("  Usage: message send <pane_id> <message>");
-        std::process::exit(1);
-    }
+    // Hybrid approach: Write to file AND inject via tmux
+    let sender = global.sender()?;
 
-    let pane_id = PaneId(args[0].clone());
-    let message = if args.len() > 1 {
-        args[1..].join(" ")
-    } else {
-        String::new()
-    };
+    let mut sent = Vec::new();
+    for found in &panes {
+        // First write to file (for metadata/tracking). Delivery does not
+        // depend on it, so a failure is only reported.
+        let recorded = match sender.send_prompt(&found.id, message) {
+            Ok(_) => true,
+            Err(e) => {
+                if !output::is_json() {
+                    eprintln!(
+                        "{}Failed to write prompt file for {}: {}{}",
+                        colors::red(),
+                        found.id.0,
+                        e,
+                        colors::reset()
+                    );
+                }
+                false
+            }
+        };
+
+        // Then inject into tmux pane (for actual delivery)
+        pane::send_keys(&found.id, message)?;
+        sent.push(serde_json::json!({
+            "pane_id": found.id,
+            "message": message,
+            "recorded": recorded,
+        }));
+    }
 
+    if output::is_json() {
+        return output::emit(&sent);
+    }
     println!(
-        "{}Sending message to pane: {}{}",
-        colors::cyan(),
-        colors::bold(),
-        pane_id.0,
+        "{}Message sent successfully!{}",
+        colors::green(),
         colors::reset()
     );
-    println!("  Message: {}", message);
+    Ok(())
+}
 
-    // Hybrid approach: Write to file AND inject via tmux
-    let base_path = PathBuf::from("/home/sam/code/swarm_test");
-    let sender = MessageSender::new(base_path);
-
-    // First write to file (for metadata/tracking)
-    if let Err(e) = sender.send_prompt(&pane_id, &message) {
-        eprintln!(
-            "{}Failed to write prompt file: {}",
-            colors::red(),
-            e,
-            colors::reset()
-        );
-    }
+fn output_read(global: &GlobalArgs, session_id: SessionId, parse: ParseArgs) -> Result<()> {
+    let query = parse_query(&parse)?;
+    let log_reader = global.reader();
 
-    // Then inject into tmux pane (for actual delivery)
-    use swarm_test::tmux::pane;
-    match pane::send_keys(&pane_id, &message) {
-        Ok(_) => {
-            println!(
-                "{}Message sent successfully!{}",
-                colors::green(),
-                colors::reset()
-            );
+    if output::is_json() {
+        if let Some(query) = &query {
+            let responses = log_reader.read_responses(&session_id, parse.dialect.as_deref())?;
+            return output::emit(&query.apply(&responses));
         }
-        Err(e) => {
-            eprintln!(
-                "{}Failed to inject message: {}",
-                colors::red(),
-                e,
-                colors::reset()
-            );
-            std::process::exit(1);
-        }
-    }
-}
-
-fn output_read(args: &[String]) -> Result<()> {
-    if args.is_empty() {
-        eprintln!(
-            "{}Error: session_id required{}",
-            colors::red(),
-            colors::reset()
-        );
-        std::process::exit(1);
+        let output = log_reader.read_log(&session_id)?;
+        return output::emit(&output.lines().collect::<Vec<_>>());
     }
 
-    let session_id = SessionId(args[0].clone());
     println!(
-        "{}Reading output for session: {}{}",
+        "{}Reading output for session: {}{}{}",
         colors::cyan(),
         colors::bold(),
         session_id.0,
@@ -547,7 +944,18 @@ fn output_read(args: &[String]) -> Result<()> {
     );
     println!();
 
-    let log_reader = LogReader::new();
+    if let Some(query) = &query {
+        let responses = log_reader.read_responses(&session_id, parse.dialect.as_deref())?;
+        let matches = query.apply(&responses);
+        if matches.is_empty() {
+            println!("{}No matching output{}", colors::yellow(), colors::reset());
+        }
+        for response in matches {
+            print_agent_response(response);
+        }
+        return Ok(());
+    }
+
     let output = log_reader.read_log(&session_id)?;
 
     if output.trim().is_empty() {
@@ -559,32 +967,48 @@ fn output_read(args: &[String]) -> Result<()> {
     Ok(())
 }
 
-fn output_tail(args: &[String]) -> Result<()> {
-    let session_id = match args.first() {
-        Some(id) => SessionId(id.clone()),
-        None => {
-            eprintln!(
-                "{}Error: session_id required{}",
-                colors::red(),
-                colors::reset()
-            );
-            std::process::exit(1);
-        }
-    };
+fn output_tail(
+    global: &GlobalArgs,
+    session_id: SessionId,
+    n: usize,
+    parse: ParseArgs,
+) -> Result<()> {
+    let query = parse_query(&parse)?;
+    let log_reader = global.reader();
 
-    let n: usize = args.get(1).and_then(|s| s.parse().ok()).unwrap_or(20);
+    if output::is_json() {
+        if let Some(query) = &query {
+            let responses = log_reader.read_responses(&session_id, parse.dialect.as_deref())?;
+            let matches = query.apply(&responses);
+            return output::emit(&matches[matches.len().saturating_sub(n)..]);
+        }
+        return output::emit(&log_reader.tail_log(&session_id, n)?);
+    }
 
+    let unit = if query.is_some() { "matches" } else { "lines" };
     println!(
-        "{}Tailing last {} lines for session: {}{}{}",
+        "{}Tailing last {} {} for session: {}{}{}",
         colors::cyan(),
         n,
+        unit,
         colors::bold(),
         session_id.0,
         colors::reset()
     );
     println!();
 
-    let log_reader = LogReader::new();
+    if let Some(query) = &query {
+        let responses = log_reader.read_responses(&session_id, parse.dialect.as_deref())?;
+        let matches = query.apply(&responses);
+        if matches.is_empty() {
+            println!("{}No matching output{}", colors::yellow(), colors::reset());
+        }
+        for response in &matches[matches.len().saturating_sub(n)..] {
+            print_agent_response(response);
+        }
+        return Ok(());
+    }
+
     let lines = log_reader.tail_log(&session_id, n)?;
 
     if lines.is_empty() {
@@ -598,46 +1022,475 @@ fn output_tail(args: &[String]) -> Result<()> {
     Ok(())
 }
 
-fn output_watch(args: &[String]) -> Result<()> {
-    if args.is_empty() {
-        eprintln!(
-            "{}Error: session_id required{}",
-            colors::red(),
+fn output_watch(global: &GlobalArgs, args: WatchArgs) -> Result<()> {
+    let query = parse_query(&args.parse_args)?;
+    let targets = resolve_watch_targets(global, &args.targets)?;
+    // A single log is printed as-is; several get a label and time per line
+    let merged = targets.len() > 1;
+
+    if output::is_json() {
+        // Lines are written as JSON Lines, without banners
+    } else if merged {
+        let labels: Vec<&str> = targets.iter().map(|(label, _)| label.as_str()).collect();
+        println!(
+            "{}Watching {}{}{}{} logs: {}{}",
+            colors::cyan(),
+            colors::bold(),
+            targets.len(),
+            colors::reset(),
+            colors::cyan(),
+            labels.join(", "),
+            colors::reset()
+        );
+    } else {
+        println!(
+            "{}Watching output for {}{}{}",
+            colors::cyan(),
+            colors::bold(),
+            targets[0].1,
+            colors::reset()
+        );
+    }
+    if !output::is_json() {
+        println!(
+            "{}Press Ctrl+C to stop{}",
+            colors::yellow(),
             colors::reset()
         );
-        std::process::exit(1);
+        println!();
+    }
+
+    let parse = args.parse || query.is_some() || args.parse_args.dialect.is_some();
+    let query = query.unwrap_or_default();
+    let mut parsers = Vec::new();
+    if parse {
+        let dialect = args.parse_args.dialect.as_deref().unwrap_or("opencode");
+        for _ in &targets {
+            parsers.push(StreamingParser::for_dialect(dialect)?);
+        }
+    }
+
+    let mut watch_options = WatchOptions::new();
+    if args.from_end {
+        watch_options = watch_options.from_end();
+    }
+    if let Some(lines) = args.tail {
+        watch_options = watch_options.from_tail(lines);
+    }
+    if let Some(timeout) = args.timeout {
+        watch_options = watch_options.with_timeout(timeout);
+    }
+
+    let log_reader = global.reader();
+    let (sender, receiver) = mpsc::channel();
+    let mut handles = Vec::new();
+    for (index, (_, source)) in targets.iter().enumerate() {
+        let sender = sender.clone();
+        let handle = log_reader.watch(source, watch_options.clone(), move |line| {
+            let _ = sender.send((index, line));
+        })?;
+        handles.push(Some(handle));
+    }
+    drop(sender);
+
+    let width = targets
+        .iter()
+        .map(|(label, _)| label.chars().count())
+        .max()
+        .unwrap_or_default();
+    let palette = [
+        colors::cyan,
+        colors::yellow,
+        colors::green,
+        colors::magenta,
+        colors::blue,
+        colors::red,
+    ];
+    let prefixes: Vec<String> = targets
+        .iter()
+        .enumerate()
+        .map(|(index, (label, _))| {
+            format!(
+                "{}{:<width$} |{} ",
+                palette[index % palette.len()](),
+                label,
+                colors::reset()
+            )
+        })
+        .collect();
+
+    // Merged lines are prefixed with their log's label and capture time,
+    // or the time they arrived when the log has no capture timestamps
+    let line_prefix = |index: usize, time: Option<DateTime<Utc>>| {
+        if !merged {
+            return String::new();
+        }
+        format!(
+            "{}{}{}{} ",
+            prefixes[index],
+            colors::dim(),
+            time.unwrap_or_else(Utc::now).format("%H:%M:%S"),
+            colors::reset()
+        )
+    };
+
+    loop {
+        let (index, line) = match receiver.recv_timeout(Duration::from_millis(500)) {
+            Ok(received) => received,
+            // Every watch has ended and dropped its sender
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                check_watches(&mut handles)?;
+                continue;
+            }
+        };
+
+        if parse {
+            let responses = parsers[index].feed(&format!("{}\n", line.text));
+            print_watch_responses(&targets[index], &query, responses, |response| {
+                line_prefix(index, response.received())
+            })?;
+        } else if output::is_json() {
+            let (label, source) = &targets[index];
+            output::emit_line(&serde_json::json!({
+                "log": source.id(),
+                "label": label,
+                "line": line.line,
+                "offset": line.offset,
+                "text": line.text,
+            }))?;
+        } else {
+            println!(
+                "{}{}",
+                line_prefix(index, capture_time(&line.text)),
+                line.text
+            );
+            std::io::stdout().flush()?;
+        }
+    }
+
+    let result = check_watches(&mut handles);
+
+    // A log's last response is only complete once its input has ended
+    for (index, parser) in parsers.iter_mut().enumerate() {
+        print_watch_responses(&targets[index], &query, parser.finish(), |response| {
+            line_prefix(index, response.received())
+        })?;
+    }
+
+    result
+}
+
+/// Print the responses parsed from a watched log that match the query,
+/// each line led by `prefix` of its response, or emit them as JSON Lines
+fn print_watch_responses(
+    target: &(String, LogSource),
+    query: &Query,
+    responses: Vec<AgentResponse>,
+    prefix: impl Fn(&AgentResponse) -> String,
+) -> Result<()> {
+    let (label, source) = target;
+    for response in responses.iter().filter(|response| query.matches(response)) {
+        if output::is_json() {
+            output::emit_line(&serde_json::json!({
+                "log": source.id(),
+                "label": label,
+                "response": response,
+            }))?;
+        } else {
+            let prefix = prefix(response);
+            for text in agent_response_lines(response) {
+                println!("{}{}", prefix, text);
+            }
+        }
+    }
+    if !output::is_json() {
+        std::io::stdout().flush()?;
     }
+    Ok(())
+}
 
-    let session_id = SessionId(args[0].clone());
+/// First error of the watches that have finished
+fn check_watches(handles: &mut [Option<WatchHandle>]) -> Result<()> {
+    for slot in handles.iter_mut() {
+        if !slot.as_ref().is_some_and(WatchHandle::is_finished) {
+            continue;
+        }
+        if let Some(result) = slot.take().map(WatchHandle::join) {
+            result?;
+        }
+    }
+    Ok(())
+}
+
+/// Logs named by `output watch` arguments, with their labels. An argument
+/// is a target, or a session or pane log id; `*` globs also match the ids
+/// of existing logs.
+fn resolve_watch_targets(
+    global: &GlobalArgs,
+    patterns: &[String],
+) -> Result<Vec<(String, LogSource)>> {
+    let logs: Vec<LogSource> = global
+        .search()
+        .logs()?
+        .into_iter()
+        .map(|(source, _)| source)
+        .collect();
+    // Names are only known while the tmux server runs
+    let sessions = tmux_session::list_sessions().unwrap_or_default();
+    let label = |source: &LogSource| {
+        sessions
+            .iter()
+            .find(|session| source.session() == Some(&session.id))
+            .map_or_else(|| source.id().to_string(), |session| session.name.clone())
+    };
+
+    let mut targets: Vec<(String, LogSource)> = Vec::new();
+    for pattern in patterns {
+        let target = Target::parse(pattern)?;
+        let mut found = Vec::new();
+        if let Target::Glob(glob) = &target {
+            found.extend(
+                logs.iter()
+                    .filter(|source| source.matches_glob(glob))
+                    .cloned(),
+            );
+        }
+
+        match &target {
+            // The log may not exist yet; the watch waits for it
+            Target::Pane(id) => found.push(LogSource::Pane(id.clone())),
+            Target::Name(id) if logs.contains(&LogSource::Session(SessionId(id.clone()))) => {
+                found.push(LogSource::Session(SessionId(id.clone())))
+            }
+            _ => match target.resolve_in(sessions.clone()) {
+                Ok(selection) => found.extend(watch_sources(&selection, &logs)),
+                Err(TmuxError::NotFound(_)) => match &target {
+                    Target::Session(id) => found.push(LogSource::Session(id.clone())),
+                    Target::Name(id) => found.push(LogSource::Session(SessionId(id.clone()))),
+                    Target::Glob(_) if !found.is_empty() => {}
+                    Target::Glob(_) => {
+                        return Err(TmuxError::NotFound(format!(
+                            "No logs or sessions match '{}'",
+                            pattern
+                        )));
+                    }
+                    _ => return Err(TmuxError::NotFound(format!("No logs match '{}'", pattern))),
+                },
+                Err(e) => return Err(e),
+            },
+        }
+
+        for source in found {
+            if !targets.iter().any(|(_, known)| *known == source) {
+                targets.push((label(&source), source));
+            }
+        }
+    }
+
+    Ok(targets)
+}
+
+/// Logs of what a target picked: a picked session's log, and for a window
+/// or pane the pane log if there is one, else its session's
+fn watch_sources(selection: &Selection, logs: &[LogSource]) -> Vec<LogSource> {
+    let picked_sessions: Vec<&SessionId> = selection
+        .picked()
+        .iter()
+        .filter_map(|picked| match picked {
+            Picked::Session(id) => Some(id),
+            _ => None,
+        })
+        .collect();
+
+    let mut sources: Vec<LogSource> = picked_sessions
+        .iter()
+        .map(|id| LogSource::Session((*id).clone()))
+        .collect();
+    for found in selection.all_panes() {
+        if picked_sessions.contains(&&found.session_id) {
+            continue;
+        }
+        let source = LogSource::Pane(found.id.clone());
+        if logs.contains(&source) {
+            sources.push(source);
+        } else {
+            sources.push(LogSource::Session(found.session_id.clone()));
+        }
+    }
+    sources
+}
+
+fn output_query(
+    global: &GlobalArgs,
+    text: &str,
+    session_ids: Vec<String>,
+    dialect: Option<String>,
+    limit: Option<usize>,
+) -> Result<()> {
+    let query = Query::parse(text)?;
+
+    let log_reader = global.reader();
+    let mut session_ids = log_sessions(&session_ids)?;
+    if session_ids.is_empty() {
+        session_ids = log_reader.list_session_logs()?;
+        session_ids.sort_by(|a, b| a.0.cmp(&b.0));
+    }
+
+    let mut total = 0;
+    let mut matches = Vec::new();
+    let limit_reached = |total: usize| limit.is_some_and(|limit| total >= limit);
+    'sessions: for session_id in &session_ids {
+        if limit_reached(total) {
+            break;
+        }
+        let responses = log_reader.read_responses(session_id, dialect.as_deref())?;
+        for response in query.apply(&responses) {
+            if limit_reached(total) {
+                break 'sessions;
+            }
+            if output::is_json() {
+                matches.push(serde_json::json!({
+                    "session_id": session_id,
+                    "response": response,
+                }));
+            } else {
+                println!("{}{}{}", colors::blue(), session_id.0, colors::reset());
+                print_agent_response(response);
+            }
+            total += 1;
+        }
+    }
+    if output::is_json() {
+        return output::emit(&matches);
+    }
+
+    println!();
     println!(
-        "{}Watching output for session: {}{}{}",
+        "{}{} match(es) in {} session(s){}",
         colors::cyan(),
-        colors::bold(),
-        session_id.0,
+        total,
+        session_ids.len(),
         colors::reset()
     );
+
+    Ok(())
+}
+
+/// Rotator for the logs under `--log-dir`
+fn log_rotator(global: &GlobalArgs, policy: RotationPolicy) -> LogRotator {
+    match &global.log_dir {
+        Some(dir) => LogRotator::with_dir(dir.clone(), policy),
+        None => LogRotator::new(policy),
+    }
+}
+
+fn output_rotate(global: &GlobalArgs, args: RotateArgs) -> Result<()> {
+    let mut session_ids = log_sessions(&args.session_ids)?;
+    let rotator = log_rotator(global, args.rotation.policy());
+    if session_ids.is_empty() {
+        session_ids = global.reader().list_session_logs()?;
+        session_ids.sort_by(|a, b| a.0.cmp(&b.0));
+    }
+
+    let (mut rotated, mut removed) = (0, 0);
+    let mut reports = Vec::new();
+    for session_id in &session_ids {
+        let report = if args.force {
+            let segment = rotator.rotate(session_id)?;
+            let removed = rotator.apply_retention(session_id)?;
+            RotationReport {
+                rotated: segment.into_iter().collect(),
+                removed,
+            }
+        } else {
+            rotator.maintain(session_id)?
+        };
+
+        if output::is_json() {
+            reports.push(serde_json::json!({
+                "session_id": session_id,
+                "rotated": report.rotated,
+                "removed": report.removed,
+            }));
+            continue;
+        }
+        print_rotation_report(&report);
+        rotated += report.rotated.len();
+        removed += report.removed.len();
+    }
+    if output::is_json() {
+        return output::emit(&reports);
+    }
+
     println!(
-        "{}Press Ctrl+C to stop{}",
-        colors::yellow(),
+        "{}{} log(s) rotated, {} segment(s) removed across {} session(s){}",
+        colors::cyan(),
+        rotated,
+        removed,
+        session_ids.len(),
         colors::reset()
     );
-    println!();
 
-    let log_reader = LogReader::new();
+    Ok(())
+}
 
-    match log_reader.watch_log(&session_id, |line| {
-        print!("{}", line);
-        std::io::stdout().flush().unwrap();
-    }) {
-        Ok(_) => Ok(()),
-        Err(e) => {
-            eprintln!(
-                "{}Error watching log: {}",
-                colors::red(),
-                e,
-                colors::reset()
-            );
-            std::process::exit(1);
+fn print_rotation_report(report: &RotationReport) {
+    for path in &report.rotated {
+        println!(
+            "{}rotated{} {}",
+            colors::green(),
+            colors::reset(),
+            path.display()
+        );
+    }
+    for path in &report.removed {
+        println!(
+            "{}removed{} {}",
+            colors::yellow(),
+            colors::reset(),
+            path.display()
+        );
+    }
+}
+
+/// Query of `--query`, when given
+fn parse_query(args: &ParseArgs) -> Result<Option<Query>> {
+    args.query.as_deref().map(Query::parse).transpose()
+}
+
+fn print_agent_response(response: &AgentResponse) {
+    for line in agent_response_lines(response) {
+        println!("{}", line);
+    }
+    std::io::stdout().flush().unwrap();
+}
+
+fn agent_response_lines(response: &AgentResponse) -> Vec<String> {
+    let (color, label) = match response.message_type {
+        MessageType::Message => (colors::reset(), "message"),
+        MessageType::ToolCall => (colors::cyan(), "tool_call"),
+        MessageType::Error => (colors::red(), "error"),
+        MessageType::Completion => (colors::green(), "complete"),
+    };
+
+    let mut lines = vec![format!("{}[{}]{}", color, label, colors::reset())];
+    for call in &response.tool_calls {
+        let args = call.argument_text("raw_args").unwrap_or_default();
+        lines.push(format!(
+            "  {}{}{} {}",
+            colors::bold(),
+            call.tool_name,
+            colors::reset(),
+            args
+        ));
+    }
+    if let Some(error) = &response.error {
+        lines.push(format!("  {}{}{}", colors::red(), error, colors::reset()));
+    } else if !response.content.is_empty() {
+        for line in response.content.lines() {
+            lines.push(format!("  {}", line));
         }
     }
+    lines
 }
@@ -0,0 +1,543 @@
+//! Terminal dashboard for the swarm: every pane with its agent's state,
+//! last message and queue depth, a live preview of the selected pane and
+//! keys to prompt, interrupt, restart or attach to it.
+
+use super::args::{DashboardArgs, GlobalArgs};
+use super::main::colors;
+use super::output;
+use crate::Result;
+use crate::error::TmuxError;
+use crate::messaging::queue::MessageQueue;
+use crate::messaging::read::LogReader;
+use crate::messaging::send::MessageSender;
+use crate::swarm_status::{AgentState, PaneStatus, SwarmStatus};
+use crate::tmux::{self, pane, session as tmux_session, window};
+use std::io::{self, IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+// Widths of the fixed table columns
+const SESSION_WIDTH: usize = 16;
+const WINDOW_WIDTH: usize = 12;
+const PANE_WIDTH: usize = 5;
+const STATE_WIDTH: usize = 8;
+const QUEUE_WIDTH: usize = 5;
+
+// Terminal control, independent of NO_COLOR
+const REVERSE: &str = "\x1b[7m";
+const PLAIN: &str = "\x1b[0m";
+const CLEAR_LINE: &str = "\x1b[K";
+
+pub fn handle_dashboard(global: &GlobalArgs, args: DashboardArgs) -> Result<()> {
+    // Scripts get one snapshot instead of the interactive view
+    if output::is_json() {
+        let status = SwarmStatus::collect(&global.reader(), Some(&global.queue()?))?;
+        return output::emit(&status);
+    }
+
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        return Err(TmuxError::Command(
+            "The dashboard needs a terminal".to_string(),
+        ));
+    }
+
+    let mut dashboard = Dashboard {
+        reader: global.reader(),
+        queue: Some(global.queue()?),
+        sender: global.sender()?,
+        status: None,
+        selected: 0,
+        preview: Vec::new(),
+        mode: Mode::Browse,
+        notice: None,
+    };
+    let terminal = Terminal::enter()?;
+    dashboard.run(&terminal, args.refresh)
+}
+
+/// What the keyboard is doing
+enum Mode {
+    Browse,
+    /// Typing a prompt for the selected pane
+    Prompt(String),
+    /// Waiting for `y` to restart the selected pane
+    ConfirmRestart,
+}
+
+/// Last action's outcome, shown in the footer until the next key
+struct Notice {
+    text: String,
+    error: bool,
+}
+
+impl Notice {
+    fn done(text: String) -> Option<Self> {
+        Some(Self { text, error: false })
+    }
+
+    fn failed(text: String) -> Option<Self> {
+        Some(Self { text, error: true })
+    }
+}
+
+struct Dashboard {
+    reader: LogReader,
+    queue: Option<MessageQueue>,
+    sender: MessageSender,
+    status: Option<SwarmStatus>,
+    selected: usize,
+    preview: Vec<String>,
+    mode: Mode,
+    notice: Option<Notice>,
+}
+
+impl Dashboard {
+    fn run(&mut self, terminal: &Terminal, refresh: Duration) -> Result<()> {
+        let mut next_refresh = Instant::now();
+        loop {
+            if Instant::now() >= next_refresh {
+                self.refresh();
+                next_refresh = Instant::now() + refresh;
+            }
+            self.draw()?;
+
+            let wait = next_refresh.saturating_duration_since(Instant::now());
+            let Some(key) = read_key(wait)? else {
+                continue;
+            };
+            match self.handle_key(key, terminal)? {
+                Flow::Quit => return Ok(()),
+                Flow::Refresh => next_refresh = Instant::now(),
+                Flow::Redraw => {}
+            }
+        }
+    }
+
+    fn refresh(&mut self) {
+        match SwarmStatus::collect(&self.reader, self.queue.as_ref()) {
+            Ok(status) => {
+                self.selected = self.selected.min(status.panes.len().saturating_sub(1));
+                self.status = Some(status);
+            }
+            Err(e) => self.notice = Notice::failed(format!("Failed to read tmux: {}", e)),
+        }
+        self.update_preview();
+    }
+
+    fn update_preview(&mut self) {
+        self.preview = match self.selected_pane() {
+            Some(status) => match pane::capture_pane_output(&status.pane.id) {
+                Ok(output) => output.lines().map(str::to_string).collect(),
+                Err(e) => vec![format!("Failed to capture pane: {}", e)],
+            },
+            None => Vec::new(),
+        };
+        while self.preview.last().is_some_and(|l| l.trim().is_empty()) {
+            self.preview.pop();
+        }
+    }
+
+    fn selected_pane(&self) -> Option<&PaneStatus> {
+        self.status.as_ref()?.panes.get(self.selected)
+    }
+
+    fn handle_key(&mut self, key: Key, terminal: &Terminal) -> Result<Flow> {
+        let Some(selected) = self.selected_pane().cloned() else {
+            return Ok(match key {
+                Key::Char('q') | Key::CtrlC => Flow::Quit,
+                _ => Flow::Redraw,
+            });
+        };
+        let pane_id = &selected.pane.id;
+
+        match std::mem::replace(&mut self.mode, Mode::Browse) {
+            Mode::Browse => {
+                self.notice = None;
+                match key {
+                    Key::Char('q') | Key::CtrlC => return Ok(Flow::Quit),
+                    Key::Up | Key::Char('k') => self.select(self.selected.saturating_sub(1)),
+                    Key::Down | Key::Char('j') => self.select(self.selected + 1),
+                    Key::Enter | Key::Char('s') => self.mode = Mode::Prompt(String::new()),
+                    Key::Char('i') => {
+                        self.notice = match pane::send_keys(pane_id, "C-c") {
+                            Ok(()) => Notice::done(format!("Interrupted {}", pane_id.0)),
+                            Err(e) => Notice::failed(format!("Failed to interrupt: {}", e)),
+                        };
+                        return Ok(Flow::Refresh);
+                    }
+                    Key::Char('r') => self.mode = Mode::ConfirmRestart,
+                    Key::Char('a') => {
+                        if let Err(e) = attach(terminal, &selected) {
+                            self.notice = Notice::failed(format!("Failed to attach: {}", e));
+                        }
+                        return Ok(Flow::Refresh);
+                    }
+                    _ => {}
+                }
+            }
+            Mode::Prompt(mut text) => match key {
+                Key::Enter if !text.trim().is_empty() => {
+                    // Recorded like `message send`, then typed into the pane
+                    let _ = self.sender.send_prompt(pane_id, &text);
+                    self.notice = match pane::send_literal_enter(pane_id, &text) {
+                        Ok(()) => Notice::done(format!("Sent prompt to {}", pane_id.0)),
+                        Err(e) => Notice::failed(format!("Failed to send prompt: {}", e)),
+                    };
+                    return Ok(Flow::Refresh);
+                }
+                Key::Escape | Key::CtrlC | Key::Enter => {}
+                Key::Backspace => {
+                    text.pop();
+                    self.mode = Mode::Prompt(text);
+                }
+                Key::Char(c) => {
+                    text.push(c);
+                    self.mode = Mode::Prompt(text);
+                }
+                _ => self.mode = Mode::Prompt(text),
+            },
+            Mode::ConfirmRestart => {
+                if key == Key::Char('y') {
+                    self.notice = match pane::respawn_pane(pane_id) {
+                        Ok(()) => Notice::done(format!("Restarted {}", pane_id.0)),
+                        Err(e) => Notice::failed(format!("Failed to restart: {}", e)),
+                    };
+                    return Ok(Flow::Refresh);
+                }
+            }
+        }
+
+        Ok(Flow::Redraw)
+    }
+
+    fn select(&mut self, index: usize) {
+        let count = self.status.as_ref().map_or(0, |s| s.panes.len());
+        let index = index.min(count.saturating_sub(1));
+        if index != self.selected {
+            self.selected = index;
+            self.update_preview();
+        }
+    }
+
+    fn draw(&self) -> io::Result<()> {
+        let (width, height) = terminal_size();
+        let mut lines = vec![self.header(), String::new()];
+        let panes = self.status.as_ref().map_or(&[][..], |s| &s.panes[..]);
+
+        lines.push(format!(
+            "{}  {:<SESSION_WIDTH$} {:<WINDOW_WIDTH$} {:<PANE_WIDTH$} {:<STATE_WIDTH$} {:>QUEUE_WIDTH$}  {}{}",
+            colors::bold(),
+            "SESSION",
+            "WINDOW",
+            "PANE",
+            "STATE",
+            "QUEUE",
+            "LAST MESSAGE",
+            colors::reset()
+        ));
+        if panes.is_empty() {
+            lines.push(format!(
+                "  {}No tmux panes{}",
+                colors::yellow(),
+                colors::reset()
+            ));
+        }
+
+        // The table takes up to half the screen, scrolled to the selection
+        let rows = (height / 2).saturating_sub(lines.len()).max(1);
+        let first = (self.selected + 1).saturating_sub(rows);
+        for (index, status) in panes.iter().enumerate().skip(first).take(rows) {
+            lines.push(table_row(status, index == self.selected, width));
+        }
+
+        if let Some(status) = self.selected_pane() {
+            let title = format!(
+                "── {} {}:{} ",
+                status.pane.id.0, status.session_name, status.window_name
+            );
+            lines.push(format!(
+                "{}{}{}",
+                colors::dim(),
+                fit(&(title + &"─".repeat(width)), width),
+                colors::reset()
+            ));
+            let room = height.saturating_sub(lines.len() + 1);
+            let skip = self.preview.len().saturating_sub(room);
+            lines.extend(self.preview[skip..].iter().map(|line| fit(line, width)));
+        }
+
+        lines.truncate(height.saturating_sub(1));
+        lines.resize(height.saturating_sub(1), String::new());
+        lines.push(self.footer(width));
+
+        let mut frame = String::from("\x1b[H");
+        frame.push_str(&lines.join(&format!("{}\r\n", CLEAR_LINE)));
+        frame.push_str(CLEAR_LINE);
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(frame.as_bytes())?;
+        stdout.flush()
+    }
+
+    fn header(&self) -> String {
+        let mut header = format!("{}Swarm Dashboard{}", colors::bold(), colors::reset());
+        let Some(status) = &self.status else {
+            return header;
+        };
+
+        for (state, count) in status.counts() {
+            header.push_str(&format!(
+                "  {}{} {}{}",
+                state_color(state),
+                count,
+                state,
+                colors::reset()
+            ));
+        }
+        if let Some(queue) = &status.queue {
+            header.push_str(&format!(
+                "  {}queue:{} {} queued, {} failed",
+                colors::cyan(),
+                colors::reset(),
+                queue.queued,
+                queue.failed
+            ));
+        }
+        header
+    }
+
+    fn footer(&self, width: usize) -> String {
+        let pane = self.selected_pane().map_or("", |s| s.pane.id.0.as_str());
+        match (&self.mode, &self.notice) {
+            (Mode::Prompt(text), _) => fit(&format!("Prompt for {}: {}█", pane, text), width),
+            (Mode::ConfirmRestart, _) => format!(
+                "{}{}{}",
+                colors::yellow(),
+                fit(&format!("Restart {}? (y/n)", pane), width),
+                colors::reset()
+            ),
+            (Mode::Browse, Some(notice)) => format!(
+                "{}{}{}",
+                if notice.error {
+                    colors::red()
+                } else {
+                    colors::green()
+                },
+                fit(&notice.text, width),
+                colors::reset()
+            ),
+            (Mode::Browse, None) => format!(
+                "{}{}{}",
+                colors::dim(),
+                fit(
+                    "↑/↓ select  s prompt  i interrupt  r restart  a attach  q quit",
+                    width
+                ),
+                colors::reset()
+            ),
+        }
+    }
+}
+
+/// What the loop does after a key
+enum Flow {
+    Quit,
+    /// Collect the status again, as after acting on a pane
+    Refresh,
+    Redraw,
+}
+
+fn table_row(status: &PaneStatus, selected: bool, width: usize) -> String {
+    let on = if selected { REVERSE } else { "" };
+    let marker = if selected { "> " } else { "  " };
+    let columns = format!(
+        "{}{} {} {} ",
+        marker,
+        fit(&status.session_name, SESSION_WIDTH),
+        fit(&status.window_name, WINDOW_WIDTH),
+        fit(&status.pane.id.0, PANE_WIDTH)
+    );
+    let rest = format!(
+        " {:>QUEUE_WIDTH$}  {}",
+        status.queue_depth,
+        status.last_message.as_deref().unwrap_or_default()
+    );
+    let used = columns.chars().count() + STATE_WIDTH;
+
+    format!(
+        "{}{}{}{}{}{}{}{}",
+        on,
+        columns,
+        state_color(status.state),
+        fit(&status.state.to_string(), STATE_WIDTH),
+        colors::reset(),
+        on,
+        fit(&rest, width.saturating_sub(used)),
+        PLAIN
+    )
+}
+
+fn state_color(state: AgentState) -> &'static str {
+    match state {
+        AgentState::Idle => colors::blue(),
+        AgentState::Working => colors::green(),
+        AgentState::Errored => colors::red(),
+        AgentState::Dead => colors::dim(),
+    }
+}
+
+/// Exactly `width` characters: cut, or padded with spaces
+fn fit(text: &str, width: usize) -> String {
+    let mut fitted: String = text
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .take(width)
+        .collect();
+    let len = fitted.chars().count();
+    fitted.extend(std::iter::repeat_n(' ', width - len));
+    fitted
+}
+
+/// Leave the dashboard for the pane. Inside tmux this client switches to
+/// it; outside, `tmux attach` takes over the terminal until detached.
+fn attach(terminal: &Terminal, status: &PaneStatus) -> Result<()> {
+    window::select_window(&status.window_id)?;
+    pane::select_pane(&status.pane.id)?;
+
+    if std::env::var_os("TMUX").is_some() {
+        return tmux_session::switch_client(&status.session_id);
+    }
+
+    let mut command = std::process::Command::new("tmux");
+    if let Some(socket) = tmux::default_socket() {
+        command.arg("-S").arg(socket);
+    }
+    command.args(["attach-session", "-t", &status.session_id.0]);
+    let exit = terminal.suspend(|| command.status())??;
+    if !exit.success() {
+        return Err(TmuxError::Command(format!(
+            "tmux attach exited with {}",
+            exit
+        )));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Up,
+    Down,
+    Enter,
+    Escape,
+    Backspace,
+    CtrlC,
+    Char(char),
+}
+
+/// Next key press, waiting at most `timeout`
+fn read_key(timeout: Duration) -> io::Result<Option<Key>> {
+    let mut fds = libc::pollfd {
+        fd: libc::STDIN_FILENO,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let timeout = timeout.as_millis().min(i32::MAX as u128) as i32;
+    // SAFETY: one valid pollfd. An interrupted poll, as on SIGWINCH,
+    // counts as a timeout so the screen is redrawn.
+    if unsafe { libc::poll(&mut fds, 1, timeout) } <= 0 {
+        return Ok(None);
+    }
+
+    // Read the fd directly: io::stdin() buffers bytes poll would not see
+    let mut buf = [0u8; 16];
+    // SAFETY: reads at most buf.len() bytes into buf
+    let read = unsafe { libc::read(libc::STDIN_FILENO, buf.as_mut_ptr().cast(), buf.len()) };
+    if read < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(parse_key(&buf[..read as usize]))
+}
+
+fn parse_key(bytes: &[u8]) -> Option<Key> {
+    match bytes {
+        [0x1b, b'[' | b'O', b'A', ..] => Some(Key::Up),
+        [0x1b, b'[' | b'O', b'B', ..] => Some(Key::Down),
+        [0x1b] => Some(Key::Escape),
+        [0x1b, ..] => None,
+        [b'\r' | b'\n', ..] => Some(Key::Enter),
+        [0x7f | 0x08, ..] => Some(Key::Backspace),
+        [0x03, ..] => Some(Key::CtrlC),
+        _ => std::str::from_utf8(bytes)
+            .ok()?
+            .chars()
+            .next()
+            .filter(|c| !c.is_control())
+            .map(Key::Char),
+    }
+}
+
+/// Columns and rows of the terminal
+fn terminal_size() -> (usize, usize) {
+    // SAFETY: TIOCGWINSZ fills in a winsize
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    let found = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+    if found && size.ws_col > 0 && size.ws_row > 0 {
+        (size.ws_col as usize, size.ws_row as usize)
+    } else {
+        (80, 24)
+    }
+}
+
+/// Raw keyboard input on the alternate screen, restored on drop
+struct Terminal {
+    original: libc::termios,
+}
+
+impl Terminal {
+    fn enter() -> io::Result<Self> {
+        // SAFETY: tcgetattr fills in the termios
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let terminal = Self { original };
+        terminal.start()?;
+        Ok(terminal)
+    }
+
+    fn start(&self) -> io::Result<()> {
+        let mut raw = self.original;
+        // SAFETY: cfmakeraw only edits the struct
+        unsafe { libc::cfmakeraw(&mut raw) };
+        set_attributes(&raw)?;
+        print!("\x1b[?1049h\x1b[?25l");
+        io::stdout().flush()
+    }
+
+    fn stop(&self) -> io::Result<()> {
+        print!("\x1b[?25h\x1b[?1049l");
+        io::stdout().flush()?;
+        set_attributes(&self.original)
+    }
+
+    /// Hand the terminal back to the shell state while `f` runs, as for
+    /// a child process that needs it
+    fn suspend<T>(&self, f: impl FnOnce() -> T) -> io::Result<T> {
+        self.stop()?;
+        let result = f();
+        self.start()?;
+        Ok(result)
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+fn set_attributes(termios: &libc::termios) -> io::Result<()> {
+    // SAFETY: a termios read by tcgetattr, maybe made raw
+    if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, termios) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -0,0 +1,331 @@
+//! Window and pane commands. Every target goes through the shared
+//! resolver, so a command may act on one window or pane or on a group:
+//! `pane kill @swarm_role=scratch` closes every pane with that tag.
+
+use super::args::{PaneCommand, WindowCommand};
+use super::main::colors;
+use super::output;
+use crate::Result;
+use crate::error::TmuxError;
+use crate::tmux::{Target, pane, session as tmux_session, set_tag, window};
+use crate::types::{Pane, Session, SessionId, Window};
+
+pub fn handle_window(command: WindowCommand) -> Result<()> {
+    match command {
+        WindowCommand::New { session, name } => window_new(&session, &name),
+        WindowCommand::List { target } => window_list(target.as_ref()),
+        WindowCommand::Rename { target, new_name } => window_rename(&target, &new_name),
+        WindowCommand::Select { target } => {
+            let windows = owned(target.resolve()?.windows());
+            for found in &windows {
+                window::select_window(&found.id)?;
+            }
+            report_windows("Selected window", &windows)
+        }
+        WindowCommand::Kill { target } => {
+            let windows = owned(target.resolve()?.windows());
+            for found in &windows {
+                window::kill_window(&found.id)?;
+            }
+            report_windows("Closed window", &windows)
+        }
+        WindowCommand::Next { session } => window_move(&session, window::next_window),
+        WindowCommand::Previous { session } => window_move(&session, window::previous_window),
+        WindowCommand::Last { session } => window_move(&session, window::last_window),
+    }
+}
+
+pub fn handle_pane(command: PaneCommand) -> Result<()> {
+    match command {
+        PaneCommand::Split { target, vertical } => pane_split(&target, vertical),
+        PaneCommand::List { target } => pane_list(target.as_ref()),
+        PaneCommand::Select { target } => {
+            let panes = owned(target.resolve()?.panes());
+            for found in &panes {
+                pane::select_pane(&found.id)?;
+            }
+            report_panes("Selected pane", &panes)
+        }
+        PaneCommand::Resize {
+            target,
+            width,
+            height,
+        } => {
+            let panes = owned(target.resolve()?.panes());
+            for found in &panes {
+                pane::resize_pane(&found.id, width, height)?;
+            }
+            report_panes("Resized pane", &panes)
+        }
+        PaneCommand::Capture { target, history } => pane_capture(&target, history),
+        PaneCommand::Tag { target, tag } => pane_tag(&target, &tag),
+        PaneCommand::Kill { target } => {
+            let panes = owned(target.resolve()?.panes());
+            for found in &panes {
+                pane::kill_pane(&found.id)?;
+            }
+            report_panes("Closed pane", &panes)
+        }
+    }
+}
+
+fn owned<T: Clone>(items: Vec<&T>) -> Vec<T> {
+    items.into_iter().cloned().collect()
+}
+
+fn window_new(session: &Target, name: &str) -> Result<()> {
+    let selection = session.resolve()?;
+    let session = selection.session()?;
+    let created = window::new_window(&session.id, name)?;
+    if output::is_json() {
+        return output::emit(&created);
+    }
+
+    println!(
+        "{}Window created in {}{}{}",
+        colors::green(),
+        colors::bold(),
+        session.name,
+        colors::reset()
+    );
+    println!(
+        "  {}ID: {}{}",
+        colors::blue(),
+        created.id.0,
+        colors::reset()
+    );
+    println!(
+        "  {}Name: {}{}",
+        colors::blue(),
+        created.name,
+        colors::reset()
+    );
+    Ok(())
+}
+
+fn window_list(target: Option<&Target>) -> Result<()> {
+    let (sessions, windows): (Vec<Session>, Vec<Window>) = match target {
+        Some(target) => {
+            let selection = target.resolve()?;
+            (owned(selection.sessions()), owned(selection.all_windows()))
+        }
+        None => {
+            let sessions = tmux_session::list_sessions()?;
+            let windows = sessions.iter().flat_map(|s| s.windows.clone()).collect();
+            (sessions, windows)
+        }
+    };
+    if output::is_json() {
+        return output::emit(&windows);
+    }
+
+    println!("{}Windows{}", colors::bold(), colors::reset());
+    println!();
+
+    if windows.is_empty() {
+        println!("{}No windows{}", colors::yellow(), colors::reset());
+        return Ok(());
+    }
+    for session in &sessions {
+        println!("  {}{}{}", colors::bold(), session.name, colors::reset());
+        for found in windows.iter().filter(|w| w.session_id == session.id) {
+            let marker = if found.active { "*" } else { " " };
+            println!(
+                "  {} {}{:<6}{} {}  {}{} panes{}",
+                marker,
+                colors::blue(),
+                found.id.0,
+                colors::reset(),
+                found.name,
+                colors::dim(),
+                found.panes.len(),
+                colors::reset()
+            );
+        }
+        println!();
+    }
+    Ok(())
+}
+
+fn window_rename(target: &Target, new_name: &str) -> Result<()> {
+    let selection = target.resolve()?;
+    let mut found = selection.window()?.clone();
+    window::rename_window(&found.id, new_name)?;
+    found.name = new_name.to_string();
+    if output::is_json() {
+        return output::emit(&found);
+    }
+    report_windows("Renamed window", &[found])
+}
+
+fn window_move(session: &Target, switch: fn(&SessionId) -> Result<()>) -> Result<()> {
+    let mut current = Vec::new();
+    for session in session.resolve()?.sessions() {
+        switch(&session.id)?;
+        let moved = tmux_session::get_session(&session.id)?;
+        current.extend(moved.windows.into_iter().filter(|w| w.active));
+    }
+    report_windows("Current window", &current)
+}
+
+fn report_windows(action: &str, windows: &[Window]) -> Result<()> {
+    if output::is_json() {
+        return output::emit(windows);
+    }
+    for found in windows {
+        println!(
+            "{}{}: {}{} {}({}){}",
+            colors::green(),
+            action,
+            colors::bold(),
+            found.name,
+            colors::dim(),
+            found.id.0,
+            colors::reset()
+        );
+    }
+    Ok(())
+}
+
+fn pane_split(target: &Target, vertical: bool) -> Result<()> {
+    let mut created = Vec::new();
+    for found in target.resolve()?.windows() {
+        created.push(if vertical {
+            pane::new_pane(&found.id)?
+        } else {
+            pane::split_pane_horizontal(&found.id)?
+        });
+    }
+    report_panes("Created pane", &created)
+}
+
+fn pane_list(target: Option<&Target>) -> Result<()> {
+    let (windows, panes): (Vec<Window>, Vec<Pane>) = match target {
+        Some(target) => {
+            let selection = target.resolve()?;
+            (owned(selection.all_windows()), owned(selection.all_panes()))
+        }
+        None => {
+            let windows: Vec<Window> = tmux_session::list_sessions()?
+                .into_iter()
+                .flat_map(|s| s.windows)
+                .collect();
+            let panes = windows.iter().flat_map(|w| w.panes.clone()).collect();
+            (windows, panes)
+        }
+    };
+    if output::is_json() {
+        return output::emit(&panes);
+    }
+
+    println!("{}Panes{}", colors::bold(), colors::reset());
+    println!();
+
+    if panes.is_empty() {
+        println!("{}No panes{}", colors::yellow(), colors::reset());
+        return Ok(());
+    }
+    for found in &windows {
+        println!(
+            "  {}{}{} {}{}{}",
+            colors::bold(),
+            found.name,
+            colors::reset(),
+            colors::dim(),
+            found.id.0,
+            colors::reset()
+        );
+        for p in panes.iter().filter(|p| p.window_id == found.id) {
+            let marker = if p.active { "*" } else { " " };
+            println!(
+                "  {} {}{:<6}{} {}",
+                marker,
+                colors::blue(),
+                p.id.0,
+                colors::reset(),
+                p.current_path.as_deref().unwrap_or("")
+            );
+        }
+        println!();
+    }
+    Ok(())
+}
+
+fn pane_capture(target: &Target, history: Option<usize>) -> Result<()> {
+    let panes = owned(target.resolve()?.panes());
+    let mut captures = Vec::new();
+    for found in &panes {
+        let text = match history {
+            Some(lines) => pane::capture_pane_start(&found.id, lines)?,
+            None => pane::capture_pane_output(&found.id)?,
+        };
+        captures.push((found, text));
+    }
+
+    if output::is_json() {
+        let captures: Vec<serde_json::Value> = captures
+            .iter()
+            .map(|(found, text)| {
+                serde_json::json!({
+                    "pane_id": found.id,
+                    "lines": text.lines().collect::<Vec<_>>(),
+                })
+            })
+            .collect();
+        return output::emit(&captures);
+    }
+    // A header per pane only when there is more than one
+    let headers = captures.len() > 1;
+    for (found, text) in &captures {
+        if headers {
+            println!(
+                "{}==> {} <=={}",
+                colors::bold(),
+                found.id.0,
+                colors::reset()
+            );
+        }
+        print!("{}", text);
+    }
+    Ok(())
+}
+
+fn pane_tag(target: &Target, tag: &str) -> Result<()> {
+    let (key, value) = tag.split_once('=').ok_or_else(|| {
+        TmuxError::Usage(format!(
+            "Tag '{}' needs a value, e.g. swarm_role=reviewer",
+            tag
+        ))
+    })?;
+    if key.trim_start_matches('@').is_empty() {
+        return Err(TmuxError::Usage(format!("Tag '{}' has no name", tag)));
+    }
+
+    let panes = owned(target.resolve()?.panes());
+    for found in &panes {
+        set_tag(&found.id, key, value)?;
+    }
+    let action = if value.is_empty() {
+        "Untagged pane"
+    } else {
+        "Tagged pane"
+    };
+    report_panes(action, &panes)
+}
+
+fn report_panes(action: &str, panes: &[Pane]) -> Result<()> {
+    if output::is_json() {
+        return output::emit(panes);
+    }
+    for found in panes {
+        println!(
+            "{}{}: {}{}{}",
+            colors::green(),
+            action,
+            colors::bold(),
+            found.id.0,
+            colors::reset()
+        );
+    }
+    Ok(())
+}
@@ -1,62 +1,178 @@
+use super::args::{Cli, Command};
 use super::commands;
-use std::env;
-use std::process;
-use swarm_test::Result;
+use super::dashboard;
+use super::layout;
+use super::output::{self, Format};
+use super::queue;
+use crate::Result;
+use crate::error::TmuxError;
+use crate::tmux;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser};
+use std::io::Write;
+use std::path::PathBuf;
 
 pub fn run() -> Result<()> {
-    let args: Vec<String> = env::args().collect();
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(e) if is_help(&e) => {
+            e.print()?;
+            return Ok(());
+        }
+        Err(e) => {
+            // Parsing failed before the format was known
+            if asks_for_json(std::env::args()) {
+                output::set_format(Format::Json);
+            }
+            return Err(TmuxError::Usage(usage_message(&e)));
+        }
+    };
 
-    if args.len() < 2 {
-        commands::print_usage();
-        process::exit(0);
+    let global = &cli.global;
+    output::set_format(global.format());
+    tmux::set_default_socket(global.socket.clone());
+    tmux::set_trace(global.verbose > 0);
+
+    match cli.command {
+        Command::Session { command } => commands::handle_session(global, command),
+        Command::Window { command } => layout::handle_window(command),
+        Command::Pane { command } => layout::handle_pane(command),
+        Command::Message { command } => commands::handle_message(global, command),
+        Command::Queue { command } => queue::handle_queue(global, command),
+        Command::Mapping { store, command } => commands::handle_mapping(store, command),
+        Command::Output { command } => commands::handle_output(global, command),
+        Command::Export(args) => commands::handle_export(global, args),
+        Command::Search(args) => commands::handle_search(global, args),
+        Command::Status => commands::handle_status(global),
+        Command::Dashboard(args) => dashboard::handle_dashboard(global, args),
+        Command::Sync(args) => commands::handle_sync(global, args),
+        Command::MockOpencode(args) => commands::handle_mock_opencode(args),
+        Command::Completions { shell } => {
+            // Rendered first: clap_complete panics when stdout is closed early
+            let mut command = Cli::command();
+            let name = command.get_name().to_string();
+            let mut script = Vec::new();
+            clap_complete::generate(shell, &mut command, name, &mut script);
+            std::io::stdout().write_all(&script)?;
+            Ok(())
+        }
+        Command::Man { out_dir } => write_man(out_dir),
     }
+}
 
-    let command = &args[1];
-    let command_args = &args[2..];
+/// `--help`, `--version`, and no arguments at all, which show the help
+fn is_help(error: &clap::Error) -> bool {
+    matches!(
+        error.kind(),
+        ErrorKind::DisplayHelp
+            | ErrorKind::DisplayVersion
+            | ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand
+    )
+}
 
-    match command.as_str() {
-        "session" => commands::handle_session(command_args),
-        "message" => commands::handle_message(command_args),
-        "output" => commands::handle_output(command_args),
-        "status" => commands::handle_status(command_args),
-        "help" => {
-            commands::print_usage();
-            Ok(())
+/// Whether raw arguments ask for JSON: `--json`, `--format=json` or
+/// `--format json`
+fn asks_for_json(args: impl IntoIterator<Item = String>) -> bool {
+    let mut previous = String::new();
+    for arg in args {
+        if arg == "--json" || arg == "--format=json" || (previous == "--format" && arg == "json") {
+            return true;
         }
-        _ => {
-            eprintln!("{}Unknown command: '{}'", colors::red(), command);
-            eprintln!("{}Use 'help' for usage information", colors::reset());
-            process::exit(1);
+        previous = arg;
+    }
+    false
+}
+
+/// Clap's message without its `error: ` prefix; the caller adds its own
+fn usage_message(error: &clap::Error) -> String {
+    let text = error.to_string();
+    text.strip_prefix("error: ")
+        .unwrap_or(&text)
+        .trim_end()
+        .to_string()
+}
+
+fn write_man(out_dir: Option<PathBuf>) -> Result<()> {
+    match out_dir {
+        Some(dir) => {
+            std::fs::create_dir_all(&dir)?;
+            clap_mangen::generate_to(Cli::command(), &dir)?;
         }
+        None => clap_mangen::Man::new(Cli::command()).render(&mut std::io::stdout())?,
     }
+    Ok(())
 }
 
 pub mod colors {
+    use std::io::IsTerminal;
+    use std::sync::OnceLock;
+
+    /// Whether escape codes are written: only to a terminal, never in JSON
+    /// output and never when `NO_COLOR` is set to a non-empty value
+    pub fn enabled() -> bool {
+        static ENABLED: OnceLock<bool> = OnceLock::new();
+        *ENABLED.get_or_init(|| {
+            !super::output::is_json()
+                && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                && std::io::stdout().is_terminal()
+        })
+    }
+
+    fn code(code: &'static str) -> &'static str {
+        if enabled() { code } else { "" }
+    }
+
     pub fn red() -> &'static str {
-        "\x1b[31m"
+        code("\x1b[31m")
     }
 
     pub fn green() -> &'static str {
-        "\x1b[32m"
+        code("\x1b[32m")
     }
 
     pub fn yellow() -> &'static str {
-        "\x1b[33m"
+        code("\x1b[33m")
     }
 
     pub fn blue() -> &'static str {
-        "\x1b[34m"
+        code("\x1b[34m")
+    }
+
+    pub fn magenta() -> &'static str {
+        code("\x1b[35m")
     }
 
     pub fn cyan() -> &'static str {
-        "\x1b[36m"
+        code("\x1b[36m")
     }
 
     pub fn bold() -> &'static str {
-        "\x1b[1m"
+        code("\x1b[1m")
+    }
+
+    pub fn dim() -> &'static str {
+        code("\x1b[2m")
     }
 
     pub fn reset() -> &'static str {
-        "\x1b[0m"
+        code("\x1b[0m")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(text: &str) -> Vec<String> {
+        text.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_asks_for_json() {
+        assert!(asks_for_json(args("swarm_test --json bogus")));
+        assert!(asks_for_json(args("swarm_test --format=json bogus")));
+        assert!(asks_for_json(args("swarm_test --format json bogus")));
+        assert!(!asks_for_json(args("swarm_test --format text bogus")));
+        assert!(!asks_for_json(args("swarm_test message send json")));
     }
 }
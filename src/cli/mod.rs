@@ -1,4 +1,9 @@
+pub mod args;
 pub mod commands;
+pub mod dashboard;
+pub mod layout;
 pub mod main;
+pub mod output;
+pub mod queue;
 
 pub use main::run;
@@ -0,0 +1,67 @@
+//! Output format shared by every command: colored text for people, or
+//! JSON for scripts. Chosen once, from the global `--json` and
+//! `--format` flags, before any command runs.
+
+use super::main::colors;
+use crate::Result;
+use crate::error::TmuxError;
+use serde::Serialize;
+use std::io::Write;
+use std::sync::OnceLock;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    #[default]
+    Text,
+    /// One JSON document per command on stdout, or one JSON line per
+    /// event for commands that stream. Errors go to stderr as JSON.
+    Json,
+}
+
+static FORMAT: OnceLock<Format> = OnceLock::new();
+
+/// Set the format for the rest of the process. Only the first call counts.
+pub fn set_format(format: Format) {
+    let _ = FORMAT.set(format);
+}
+
+pub fn format() -> Format {
+    FORMAT.get().copied().unwrap_or_default()
+}
+
+pub fn is_json() -> bool {
+    format() == Format::Json
+}
+
+/// Write `value` to stdout as one JSON document
+pub fn emit<T: Serialize + ?Sized>(value: &T) -> Result<()> {
+    // Serialized first, so a closed stdout is an I/O error, not a parse error
+    let text = serde_json::to_string_pretty(value)?;
+    writeln!(std::io::stdout().lock(), "{}", text)?;
+    Ok(())
+}
+
+/// Write `value` to stdout as a single line of JSON, for streams
+pub fn emit_line<T: Serialize + ?Sized>(value: &T) -> Result<()> {
+    let text = serde_json::to_string(value)?;
+    let mut stdout = std::io::stdout().lock();
+    writeln!(stdout, "{}", text)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Report a failed command on stderr
+pub fn print_error(error: &TmuxError) {
+    if is_json() {
+        let report = serde_json::json!({
+            "error": {
+                "kind": error.kind(),
+                "code": error.exit_code(),
+                "message": error.to_string(),
+            }
+        });
+        eprintln!("{}", report);
+    } else {
+        eprintln!("{}Error: {}{}", colors::red(), error, colors::reset());
+    }
+}
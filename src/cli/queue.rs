@@ -0,0 +1,183 @@
+//! Queue commands. Messages wait on disk under the base path until
+//! `queue drain` types them into their panes.
+
+use super::args::{GlobalArgs, QueueCommand};
+use super::main::colors;
+use super::output;
+use crate::Result;
+use crate::messaging::queue::{DrainReport, MessageQueue, QueuedMessage};
+use crate::tmux::{Target, pane};
+use crate::types::Message;
+
+pub fn handle_queue(global: &GlobalArgs, command: QueueCommand) -> Result<()> {
+    let queue = global.queue()?;
+    match command {
+        QueueCommand::Push { target, message } => queue_push(&queue, &target, &message.join(" ")),
+        QueueCommand::List => queue_list(&queue),
+        QueueCommand::Drain => queue_drain(&queue),
+        QueueCommand::Retry => {
+            let requeued = queue.requeue_failed()?;
+            if output::is_json() {
+                return output::emit(&serde_json::json!({ "requeued": requeued }));
+            }
+            println!(
+                "{}Requeued {} failed message(s){}",
+                colors::green(),
+                requeued,
+                colors::reset()
+            );
+            Ok(())
+        }
+        QueueCommand::Stats => queue_stats(&queue),
+        QueueCommand::Clear => {
+            let cleared = queue.messages()?.len();
+            queue.clear()?;
+            if output::is_json() {
+                return output::emit(&serde_json::json!({ "cleared": cleared }));
+            }
+            println!(
+                "{}Cleared {} message(s){}",
+                colors::green(),
+                cleared,
+                colors::reset()
+            );
+            Ok(())
+        }
+    }
+}
+
+fn queue_push(queue: &MessageQueue, target: &Target, content: &str) -> Result<()> {
+    let mut queued = Vec::new();
+    for found in target.resolve()?.panes() {
+        let message = Message {
+            id: uuid::Uuid::new_v4().to_string(),
+            pane_id: found.id.clone(),
+            content: content.to_string(),
+            timestamp: chrono::Utc::now().timestamp() as u64,
+        };
+        queue.enqueue_message(message.clone())?;
+        queued.push(message);
+    }
+
+    if output::is_json() {
+        return output::emit(&queued);
+    }
+    for message in &queued {
+        println!(
+            "{}Queued message {} for pane {}{}{}",
+            colors::green(),
+            message.id,
+            colors::bold(),
+            message.pane_id.0,
+            colors::reset()
+        );
+    }
+    Ok(())
+}
+
+fn queue_list(queue: &MessageQueue) -> Result<()> {
+    let messages = queue.messages()?;
+    if output::is_json() {
+        return output::emit(&messages);
+    }
+
+    println!("{}Queued messages{}", colors::bold(), colors::reset());
+    println!();
+
+    if messages.is_empty() {
+        println!("{}Queue is empty{}", colors::yellow(), colors::reset());
+        return Ok(());
+    }
+    for queued in &messages {
+        print_queued(queued);
+    }
+    Ok(())
+}
+
+fn print_queued(queued: &QueuedMessage) {
+    let state = if queued.should_retry() {
+        format!(
+            "{}retries {}{}",
+            colors::dim(),
+            queued.retries,
+            colors::reset()
+        )
+    } else {
+        format!("{}failed{}", colors::red(), colors::reset())
+    };
+    println!(
+        "  {}{}{} {} {}",
+        colors::blue(),
+        queued.message.pane_id.0,
+        colors::reset(),
+        queued.id(),
+        state
+    );
+    println!("    {}", queued.message.content);
+}
+
+fn queue_drain(queue: &MessageQueue) -> Result<()> {
+    let report = queue.drain(|queued| {
+        pane::send_literal_enter(&queued.message.pane_id, &queued.message.content)
+    })?;
+    if output::is_json() {
+        return output::emit(&report);
+    }
+    print_drain_report(&report);
+    Ok(())
+}
+
+fn print_drain_report(report: &DrainReport) {
+    println!(
+        "{}Delivered {} message(s){}",
+        colors::green(),
+        report.delivered.len(),
+        colors::reset()
+    );
+    for failure in &report.failed {
+        println!(
+            "  {}failed{} {}: {}",
+            colors::red(),
+            colors::reset(),
+            failure.id,
+            failure.error
+        );
+    }
+    if report.skipped > 0 {
+        println!(
+            "{}{} message(s) out of retries; `queue retry` requeues them{}",
+            colors::yellow(),
+            report.skipped,
+            colors::reset()
+        );
+    }
+}
+
+fn queue_stats(queue: &MessageQueue) -> Result<()> {
+    let stats = queue.stats()?;
+    let depth = queue.depth_by_pane()?;
+    if output::is_json() {
+        return output::emit(&serde_json::json!({
+            "queued": stats.queued,
+            "failed": stats.failed,
+            "by_pane": depth,
+        }));
+    }
+
+    println!("{}Queue{}", colors::bold(), colors::reset());
+    println!("  Queued: {}", stats.queued);
+    println!("  Failed: {}", stats.failed);
+
+    let mut panes: Vec<_> = depth.into_iter().collect();
+    panes.sort();
+    for (pane_id, count) in panes {
+        println!(
+            "    {}{}{} {}",
+            colors::blue(),
+            pane_id,
+            colors::reset(),
+            count
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,51 @@
+//! Configuration Module
+//!
+//! Settings shared by the senders: which transport to prefer and where to
+//! look for an OpenCode server.
+
+use serde::{Deserialize, Serialize};
+
+/// OpenCode server URL used when none is configured
+pub const DEFAULT_OPENCODE_SERVER_URL: &str = "http://127.0.0.1:4096";
+
+/// How a message should be delivered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageMode {
+    /// Try OpenCode, then tmux, then the file-based queue
+    #[default]
+    Auto,
+    /// Only use the OpenCode HTTP API
+    Opencode,
+    /// Skip OpenCode and go straight to tmux, then files
+    Tmux,
+}
+
+/// Sender configuration
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Config {
+    /// Probe for a running OpenCode server when no URL is given
+    pub auto_detect_opencode: bool,
+    /// OpenCode server URL, `None` for `$OPENCODE_SERVER_URL` or the default
+    pub opencode_server_url: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            auto_detect_opencode: true,
+            opencode_server_url: None,
+        }
+    }
+}
+
+impl Config {
+    /// OpenCode server URL to try: the configured one, then
+    /// `$OPENCODE_SERVER_URL`, then the default local server
+    pub fn get_opencode_server_url(&self) -> String {
+        self.opencode_server_url
+            .clone()
+            .or_else(|| std::env::var("OPENCODE_SERVER_URL").ok())
+            .unwrap_or_else(|| DEFAULT_OPENCODE_SERVER_URL.to_string())
+    }
+}
@@ -10,6 +10,49 @@ pub enum TmuxError {
     InvalidState(String),
     Timeout,
     NotConnected,
+    Http(u16, String),
+    Decode(String),
+    Transport(String),
+    /// Missing or unknown command-line arguments
+    Usage(String),
+}
+
+impl TmuxError {
+    /// Process exit code for the error. These are stable: scripts may
+    /// branch on them. 0 is success and 1 is left for failures that are
+    /// not a `TmuxError`.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            TmuxError::Usage(_) => 2,
+            TmuxError::Command(_) => 3,
+            TmuxError::NotFound(_) => 4,
+            TmuxError::Parse(_) => 5,
+            TmuxError::InvalidState(_) => 6,
+            TmuxError::Timeout => 7,
+            TmuxError::NotConnected => 8,
+            TmuxError::Process(..) => 9,
+            TmuxError::Http(..) => 10,
+            TmuxError::Decode(_) => 11,
+            TmuxError::Transport(_) => 12,
+        }
+    }
+
+    /// Stable snake_case name of the variant, for machine-readable output
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TmuxError::Usage(_) => "usage",
+            TmuxError::Command(_) => "command",
+            TmuxError::NotFound(_) => "not_found",
+            TmuxError::Parse(_) => "parse",
+            TmuxError::InvalidState(_) => "invalid_state",
+            TmuxError::Timeout => "timeout",
+            TmuxError::NotConnected => "not_connected",
+            TmuxError::Process(..) => "process",
+            TmuxError::Http(..) => "http",
+            TmuxError::Decode(_) => "decode",
+            TmuxError::Transport(_) => "transport",
+        }
+    }
 }
 
 impl fmt::Display for TmuxError {
@@ -22,6 +65,10 @@ impl fmt::Display for TmuxError {
             TmuxError::InvalidState(msg) => write!(f, "Invalid state: {msg}"),
             TmuxError::Timeout => write!(f, "Operation timed out"),
             TmuxError::NotConnected => write!(f, "Not connected to tmux"),
+            TmuxError::Http(status, body) => write!(f, "HTTP error ({status}): {body}"),
+            TmuxError::Decode(msg) => write!(f, "Decode error: {msg}"),
+            TmuxError::Transport(msg) => write!(f, "Transport error: {msg}"),
+            TmuxError::Usage(msg) => write!(f, "Usage error: {msg}"),
         }
     }
 }
@@ -39,3 +86,32 @@ impl From<serde_json::Error> for TmuxError {
         TmuxError::Parse(err.to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_exit_codes_are_distinct() {
+        let errors = [
+            TmuxError::Usage(String::new()),
+            TmuxError::Command(String::new()),
+            TmuxError::NotFound(String::new()),
+            TmuxError::Parse(String::new()),
+            TmuxError::InvalidState(String::new()),
+            TmuxError::Timeout,
+            TmuxError::NotConnected,
+            TmuxError::Process(io::ErrorKind::Other, String::new()),
+            TmuxError::Http(500, String::new()),
+            TmuxError::Decode(String::new()),
+            TmuxError::Transport(String::new()),
+        ];
+
+        let codes: HashSet<i32> = errors.iter().map(TmuxError::exit_code).collect();
+        let kinds: HashSet<&str> = errors.iter().map(TmuxError::kind).collect();
+        assert_eq!(codes.len(), errors.len());
+        assert_eq!(kinds.len(), errors.len());
+        assert!(codes.iter().all(|code| (2..=125).contains(code)));
+    }
+}
@@ -4,15 +4,18 @@ pub mod error;
 pub mod messaging;
 pub mod opencode;
 pub mod session_mapping;
+pub mod session_sync;
+pub mod swarm_status;
 pub mod tmux;
 pub mod types;
 
-pub use cli::*;
 pub use config::*;
 pub use error::*;
 pub use messaging::*;
 pub use opencode::*;
 pub use session_mapping::*;
+pub use session_sync::*;
+pub use swarm_status::*;
 pub use tmux::*;
 pub use types::*;
 
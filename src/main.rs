@@ -1,15 +1,8 @@
-mod cli;
-
-use cli::run;
+use swarm_test::cli::{self, run};
 
 fn main() {
     if let Err(e) = run() {
-        eprintln!(
-            "{}Error: {}{}",
-            cli::main::colors::red(),
-            e,
-            cli::main::colors::reset()
-        );
-        std::process::exit(1);
+        cli::output::print_error(&e);
+        std::process::exit(e.exit_code());
     }
 }
@@ -0,0 +1,420 @@
+//! Deduplication Module
+//!
+//! Exact deduplication by content hash, and folding of near-duplicates:
+//! spinner frames, ticking timers and TUI screens that repeated
+//! `capture_pane_output` calls pick up again with only small changes.
+
+use crate::opencode::AgentResponse;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// Characters spinners cycle through, besides the braille block
+const SPINNER_CHARS: &[char] = &[
+    '◐', '◓', '◑', '◒', '◴', '◷', '◶', '◵', '◰', '◳', '◲', '◱', '✶', '✸', '✹', '✺', '✻', '✽', '·',
+    '•', '⏺', '●', '○', '◯',
+];
+
+/// Hash of everything that makes a response distinct: type, success flag,
+/// content, error and tool calls. The capture time is not included.
+pub fn content_hash(msg: &AgentResponse) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    msg.message_type.hash(&mut hasher);
+    msg.success.hash(&mut hasher);
+    msg.content.hash(&mut hasher);
+    msg.error.hash(&mut hasher);
+
+    for call in &msg.tool_calls {
+        call.tool_name.hash(&mut hasher);
+        let mut arguments: Vec<_> = call.arguments.iter().collect();
+        arguments.sort_by(|a, b| a.0.cmp(b.0));
+        for (key, value) in arguments {
+            key.hash(&mut hasher);
+            hash_value(value, &mut hasher);
+        }
+    }
+
+    hasher.finish()
+}
+
+fn hash_value(value: &Value, hasher: &mut DefaultHasher) {
+    // Object keys are sorted unless serde_json's preserve_order is on
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            for (key, value) in entries {
+                key.hash(hasher);
+                hash_value(value, hasher);
+            }
+        }
+        other => other.to_string().hash(hasher),
+    }
+}
+
+fn same_message(a: &AgentResponse, b: &AgentResponse) -> bool {
+    a.message_type == b.message_type
+        && a.success == b.success
+        && a.content == b.content
+        && a.error == b.error
+        && a.tool_calls == b.tool_calls
+}
+
+/// Messages with distinct content, first occurrence kept, in order
+pub fn deduplicate(messages: &[AgentResponse]) -> Vec<&AgentResponse> {
+    let mut seen: HashMap<u64, Vec<&AgentResponse>> = HashMap::new();
+    let mut unique = Vec::new();
+
+    for msg in messages {
+        let bucket = seen.entry(content_hash(msg)).or_default();
+        if !bucket.iter().any(|other| same_message(other, msg)) {
+            bucket.push(msg);
+            unique.push(msg);
+        }
+    }
+
+    unique
+}
+
+/// Content reduced to what stays put between redraws: ANSI escapes,
+/// carriage-return overwrites, spinner glyphs and box-drawing characters
+/// are dropped, digit runs become `#`, whitespace is collapsed and blank
+/// lines are removed.
+pub fn normalize_content(text: &str) -> String {
+    let text = strip_ansi(text);
+    let mut lines = Vec::new();
+
+    for line in text.lines() {
+        // A carriage return redraws the line; only the last frame shows
+        let line = line
+            .rsplit('\r')
+            .find(|s| !s.trim().is_empty())
+            .unwrap_or("");
+
+        let mut normalized = String::new();
+        let mut in_digits = false;
+        for c in line.chars() {
+            if c.is_ascii_digit() {
+                if !in_digits {
+                    normalized.push('#');
+                }
+                in_digits = true;
+                continue;
+            }
+            in_digits = false;
+
+            if is_spinner(c) || is_box_drawing(c) {
+                normalized.push(' ');
+            } else {
+                normalized.push(c);
+            }
+        }
+
+        let mut words = normalized.split_whitespace().peekable();
+        // ASCII spinners (| / - \) only count as a frame at the start
+        while words
+            .peek()
+            .is_some_and(|w| w.chars().all(|c| "|/-\\".contains(c)))
+        {
+            words.next();
+        }
+
+        let collapsed = words.collect::<Vec<_>>().join(" ");
+        if !collapsed.is_empty() {
+            lines.push(collapsed);
+        }
+    }
+
+    lines.join("\n")
+}
+
+fn is_spinner(c: char) -> bool {
+    ('\u{2800}'..='\u{28FF}').contains(&c) || SPINNER_CHARS.contains(&c)
+}
+
+fn is_box_drawing(c: char) -> bool {
+    ('\u{2500}'..='\u{259F}').contains(&c)
+}
+
+fn strip_ansi(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            out.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            // CSI: parameters, then a final byte in @..~
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // OSC: terminated by BEL or ESC \
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' {
+                        break;
+                    }
+                    if c == '\x1b' && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    out
+}
+
+/// Similarity of two texts in `0.0..=1.0`: the Dice coefficient of their
+/// character bigrams
+pub fn similarity(a: &str, b: &str) -> f64 {
+    if a == b {
+        return 1.0;
+    }
+
+    let a = bigrams(a);
+    let b = bigrams(b);
+    let total: usize = a.values().sum::<usize>() + b.values().sum::<usize>();
+    if total == 0 {
+        return 0.0;
+    }
+
+    let common: usize = a
+        .iter()
+        .map(|(pair, count)| (*count).min(b.get(pair).copied().unwrap_or(0)))
+        .sum();
+    2.0 * common as f64 / total as f64
+}
+
+fn bigrams(text: &str) -> HashMap<(char, char), usize> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut counts = HashMap::new();
+    for pair in chars.windows(2) {
+        *counts.entry((pair[0], pair[1])).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// Group of near-duplicate messages folded into one
+#[derive(Debug, Clone)]
+pub struct Folded<'a> {
+    /// Latest message of the group, usually the most complete frame
+    pub message: &'a AgentResponse,
+    /// Number of messages folded together
+    pub count: usize,
+    /// Capture time of the first message in the group
+    pub first_received_at: u64,
+}
+
+/// Folds near-duplicate messages into one.
+///
+/// A message joins an earlier group if it has the same type, success flag
+/// and tool calls, and its normalized content (see `normalize_content`) is
+/// at least `threshold` similar. Only the last `window` groups are looked
+/// at, so by default a message only folds into the one before it.
+#[derive(Debug, Clone, Copy)]
+pub struct NearDuplicateFolder {
+    threshold: f64,
+    window: usize,
+}
+
+impl NearDuplicateFolder {
+    pub fn new() -> Self {
+        Self {
+            threshold: 0.9,
+            window: 1,
+        }
+    }
+
+    /// Minimum similarity to fold, clamped to `0.0..=1.0`. 1.0 folds only
+    /// messages that are equal after normalization.
+    pub fn with_threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Number of recent groups a message may fold into, e.g. 2 for a
+    /// screen that alternates between two views
+    pub fn with_window(mut self, window: usize) -> Self {
+        self.window = window.max(1);
+        self
+    }
+
+    pub fn fold<'a>(&self, messages: &'a [AgentResponse]) -> Vec<Folded<'a>> {
+        let mut groups: Vec<Folded<'a>> = Vec::new();
+        let mut normalized: Vec<String> = Vec::new();
+
+        for msg in messages {
+            let text = normalize_content(&format!(
+                "{}\n{}",
+                msg.content,
+                msg.error.as_deref().unwrap_or_default()
+            ));
+
+            let start = groups.len().saturating_sub(self.window);
+            let matched = (start..groups.len()).rev().find(|&i| {
+                let group = groups[i].message;
+                group.message_type == msg.message_type
+                    && group.success == msg.success
+                    && group.tool_calls == msg.tool_calls
+                    && similarity(&normalized[i], &text) >= self.threshold
+            });
+
+            match matched {
+                Some(i) => {
+                    groups[i].message = msg;
+                    groups[i].count += 1;
+                    normalized[i] = text;
+                }
+                None => {
+                    groups.push(Folded {
+                        message: msg,
+                        count: 1,
+                        first_received_at: msg.received_at,
+                    });
+                    normalized.push(text);
+                }
+            }
+        }
+
+        groups
+    }
+}
+
+impl Default for NearDuplicateFolder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opencode::{MessageType, ToolCall};
+
+    fn message(content: &str) -> AgentResponse {
+        AgentResponse::fixture(MessageType::Message, content)
+    }
+
+    #[test]
+    fn test_same_length_different_content_kept() {
+        let messages = vec![message("abc"), message("xyz"), message("abc")];
+        let unique = deduplicate(&messages);
+
+        assert_eq!(unique.len(), 2);
+        assert_eq!(unique[1].content, "xyz");
+    }
+
+    #[test]
+    fn test_hash_covers_tool_arguments_not_capture_time() {
+        let mut a = message("run");
+        a.tool_calls
+            .push(ToolCall::from_command_line("bash", "ls", "bash ls"));
+        let mut b = a.clone().with_received_at(42);
+        assert_eq!(content_hash(&a), content_hash(&b));
+
+        b.tool_calls[0] = ToolCall::from_command_line("bash", "pwd", "bash pwd");
+        assert_ne!(content_hash(&a), content_hash(&b));
+    }
+
+    #[test]
+    fn test_normalize_content() {
+        assert_eq!(
+            normalize_content("\x1b[32m⠋\x1b[0m Thinking... 12s"),
+            normalize_content("⠙ Thinking... 13s")
+        );
+        assert_eq!(
+            normalize_content("| Building\n\n/ Building"),
+            "Building\nBuilding"
+        );
+        assert_eq!(normalize_content("old frame\rnew frame"), "new frame");
+        assert_eq!(normalize_content("│ cell │"), "cell");
+    }
+
+    #[test]
+    fn test_fold_spinner_frames() {
+        let mut messages: Vec<AgentResponse> = ["⠋", "⠙", "⠹", "⠸"]
+            .iter()
+            .enumerate()
+            .map(|(i, frame)| {
+                message(&format!("{} Running tests ({}s)", frame, i)).with_received_at(i as u64)
+            })
+            .collect();
+        messages.push(message("All 12 tests passed"));
+
+        let folded = NearDuplicateFolder::new().fold(&messages);
+        assert_eq!(folded.len(), 2);
+        assert_eq!(folded[0].count, 4);
+        assert_eq!(folded[0].first_received_at, 0);
+        assert!(folded[0].message.content.contains("(3s)"));
+        assert_eq!(folded[1].count, 1);
+    }
+
+    #[test]
+    fn test_fold_rerendered_screen() {
+        let screen = |status: &str| {
+            message(&format!(
+                "┌ Session ──────────────────────────┐\n\
+                 │ Task: refactor the log reader     │\n\
+                 │ Editing src/messaging/read.rs     │\n\
+                 │ Applying patch 3/7                │\n\
+                 │ Tests: 41 passed, 0 failed        │\n\
+                 │ {}                                │\n\
+                 └───────────────────────────────────┘",
+                status
+            ))
+        };
+        let messages = vec![
+            screen("tokens: 1200"),
+            screen("tokens: 1350"),
+            screen("tokens: 1400, paused"),
+            screen("tokens: 1500"),
+        ];
+
+        // Status changes are small relative to the screen
+        let folded = NearDuplicateFolder::new().fold(&messages);
+        assert_eq!(folded.len(), 1);
+        assert_eq!(folded[0].count, 4);
+
+        // A strict threshold only folds screens equal after normalization
+        let strict = NearDuplicateFolder::new()
+            .with_threshold(1.0)
+            .fold(&messages);
+        assert_eq!(strict.len(), 3);
+    }
+
+    #[test]
+    fn test_fold_window() {
+        let messages = vec![
+            message("Viewing the diff of src/main.rs"),
+            message("Listing all running agent sessions"),
+            message("Viewing the diff of src/main.rs"),
+        ];
+
+        assert_eq!(NearDuplicateFolder::new().fold(&messages).len(), 3);
+        assert_eq!(
+            NearDuplicateFolder::new()
+                .with_window(2)
+                .fold(&messages)
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_similarity() {
+        assert_eq!(similarity("abc", "abc"), 1.0);
+        assert_eq!(similarity("", "x"), 0.0);
+        assert!(similarity("cargo test", "cargo test --release") < 0.9);
+    }
+}
@@ -0,0 +1,559 @@
+//! Transcript Export Module
+//!
+//! Renders a `Transcript` as Markdown, a self-contained HTML page or JSON
+//! Lines, for sharing agent runs in code review.
+
+use crate::Result;
+use crate::error::TmuxError;
+use crate::messaging::parser::OutputParser;
+use crate::messaging::transcript::{EntryKind, Transcript, TranscriptEntry};
+use crate::opencode::{AgentResponse, MessageType, ToolCall, value_text};
+use chrono::DateTime;
+use std::fmt::Write as _;
+use std::io::Write;
+use std::str::FromStr;
+
+/// Tool arguments shown on the tool line rather than in the argument list
+const RAW_ARGUMENTS: [&str; 2] = ["raw_args", "command"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Markdown,
+    Html,
+    Jsonl,
+}
+
+impl ExportFormat {
+    /// File extension, without the dot
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Html => "html",
+            ExportFormat::Jsonl => "jsonl",
+        }
+    }
+
+    /// Format for a file name's extension
+    pub fn from_path(path: &str) -> Option<Self> {
+        path.rsplit_once('.').and_then(|(_, ext)| ext.parse().ok())
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = TmuxError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "md" | "markdown" => Ok(ExportFormat::Markdown),
+            "html" | "htm" => Ok(ExportFormat::Html),
+            "jsonl" | "ndjson" => Ok(ExportFormat::Jsonl),
+            other => Err(TmuxError::Parse(format!(
+                "Unknown export format '{}' (known: md, html, jsonl)",
+                other
+            ))),
+        }
+    }
+}
+
+/// Write `transcript` to `writer` in `format`
+pub fn export<W: Write>(
+    transcript: &Transcript,
+    format: ExportFormat,
+    mut writer: W,
+) -> Result<()> {
+    match format {
+        ExportFormat::Jsonl => transcript.write_jsonl(writer),
+        ExportFormat::Markdown => Ok(writer.write_all(render_markdown(transcript).as_bytes())?),
+        ExportFormat::Html => Ok(writer.write_all(render_html(transcript).as_bytes())?),
+    }
+}
+
+/// Piece of response content
+#[derive(Debug, PartialEq)]
+enum Segment {
+    Text(String),
+    Code { lang: String, code: String },
+}
+
+/// Split content into prose and the code blocks `extract_code_blocks`
+/// finds. An unclosed fence and everything after it stays prose.
+fn segments(content: &str) -> Vec<Segment> {
+    let mut blocks = OutputParser::extract_code_blocks(content).into_iter();
+    let mut segments = Vec::new();
+    let mut text = String::new();
+    let mut in_block = false;
+
+    for line in content.lines() {
+        if line.starts_with("```") {
+            if in_block {
+                in_block = false;
+                continue;
+            }
+            if let Some((lang, code)) = blocks.next() {
+                if !text.trim().is_empty() {
+                    segments.push(Segment::Text(text.trim_end().to_string()));
+                }
+                text.clear();
+                segments.push(Segment::Code { lang, code });
+                in_block = true;
+                continue;
+            }
+        }
+
+        if !in_block {
+            text.push_str(line);
+            text.push('\n');
+        }
+    }
+
+    if !text.trim().is_empty() {
+        segments.push(Segment::Text(text.trim_end().to_string()));
+    }
+    segments
+}
+
+fn format_time(millis: u64) -> Option<String> {
+    if millis == 0 {
+        return None;
+    }
+    DateTime::from_timestamp_millis(millis as i64)
+        .map(|ts| ts.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+}
+
+fn type_label(message_type: MessageType) -> &'static str {
+    match message_type {
+        MessageType::Message => "message",
+        MessageType::ToolCall => "tool call",
+        MessageType::Error => "error",
+        MessageType::Completion => "completion",
+    }
+}
+
+/// Content to show below the error. A response with no text of its own
+/// carries the error as its content, which is already shown.
+fn shown_content(response: &AgentResponse) -> &str {
+    if response.error.as_deref() == Some(response.content.as_str()) {
+        ""
+    } else {
+        &response.content
+    }
+}
+
+fn shown_arguments(call: &ToolCall) -> Vec<(&String, String)> {
+    let mut arguments: Vec<_> = call
+        .arguments
+        .iter()
+        .filter(|(key, _)| !RAW_ARGUMENTS.contains(&key.as_str()))
+        .map(|(key, value)| (key, value_text(value)))
+        .collect();
+    arguments.sort();
+    arguments
+}
+
+fn title(transcript: &Transcript) -> String {
+    match &transcript.pane_id {
+        Some(pane_id) => format!(
+            "Transcript: {} (pane {})",
+            transcript.session_id.0, pane_id.0
+        ),
+        None => format!("Transcript: {}", transcript.session_id.0),
+    }
+}
+
+fn summary(transcript: &Transcript) -> String {
+    let prompts = transcript.prompts().count();
+    let responses = transcript.responses().count();
+    let errors = transcript.responses().filter(|r| !r.success).count();
+    let tool_calls: usize = transcript.responses().map(|r| r.tool_calls.len()).sum();
+    format!(
+        "{}, {}, {}, {}",
+        plural(prompts, "prompt"),
+        plural(responses, "response"),
+        plural(tool_calls, "tool call"),
+        plural(errors, "error")
+    )
+}
+
+fn plural(n: usize, word: &str) -> String {
+    format!("{} {}{}", n, word, if n == 1 { "" } else { "s" })
+}
+
+/// Markdown, with errors as GitHub caution alerts
+pub fn render_markdown(transcript: &Transcript) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "# {}\n", title(transcript));
+    let _ = writeln!(out, "_{}_\n", summary(transcript));
+
+    for (n, turn) in transcript.turns().iter().enumerate() {
+        let _ = writeln!(out, "## Turn {}\n", n + 1);
+        if let Some(prompt) = turn.prompt {
+            markdown_entry(&mut out, prompt);
+        }
+        for entry in &turn.responses {
+            markdown_entry(&mut out, entry);
+        }
+    }
+
+    out
+}
+
+fn markdown_entry(out: &mut String, entry: &TranscriptEntry) {
+    let time = format_time(entry.timestamp)
+        .map(|t| format!(" · {}", t))
+        .unwrap_or_default();
+
+    match &entry.kind {
+        EntryKind::Prompt { content, agent, .. } => {
+            let agent = agent
+                .as_deref()
+                .map(|a| format!(" to {}", a))
+                .unwrap_or_default();
+            let _ = writeln!(out, "**Prompt**{}{} · `{}`\n", agent, time, entry.id);
+            for line in content.lines() {
+                let _ = writeln!(out, "> {}", line);
+            }
+            out.push('\n');
+        }
+        EntryKind::Response { response } => {
+            let _ = writeln!(
+                out,
+                "**Response** ({}){} · `{}`\n",
+                type_label(response.message_type),
+                time,
+                entry.id
+            );
+            markdown_response(out, response);
+        }
+    }
+}
+
+fn markdown_response(out: &mut String, response: &AgentResponse) {
+    for call in &response.tool_calls {
+        let command = call.argument_text("command").unwrap_or_default();
+        if command.is_empty() {
+            let _ = writeln!(out, "- Tool call **{}**", call.tool_name);
+        } else {
+            let _ = writeln!(
+                out,
+                "- Tool call **{}**: {}",
+                call.tool_name,
+                inline_code(&command)
+            );
+        }
+        for (key, value) in shown_arguments(call) {
+            if value.contains('\n') {
+                let fence = fence_for(&value);
+                let _ = writeln!(out, "  - `{}`:\n\n    {}", key, fence);
+                for line in value.lines() {
+                    let _ = writeln!(out, "    {}", line);
+                }
+                let _ = writeln!(out, "    {}", fence);
+            } else {
+                let _ = writeln!(out, "  - `{}`: {}", key, inline_code(&value));
+            }
+        }
+    }
+    if !response.tool_calls.is_empty() {
+        out.push('\n');
+    }
+
+    if let Some(error) = &response.error {
+        out.push_str("> [!CAUTION]\n");
+        for line in error.lines() {
+            let _ = writeln!(out, "> {}", line);
+        }
+        out.push('\n');
+    }
+
+    for segment in segments(shown_content(response)) {
+        match segment {
+            Segment::Text(text) => {
+                let _ = writeln!(out, "{}\n", text);
+            }
+            Segment::Code { lang, code } => {
+                let fence = fence_for(&code);
+                let _ = writeln!(out, "{}{}\n{}\n{}\n", fence, lang, code, fence);
+            }
+        }
+    }
+}
+
+/// Backtick fence longer than any backtick run in `text`
+fn fence_for(text: &str) -> String {
+    "`".repeat((longest_backtick_run(text) + 1).max(3))
+}
+
+fn longest_backtick_run(text: &str) -> usize {
+    text.split(|c| c != '`').map(str::len).max().unwrap_or(0)
+}
+
+fn inline_code(text: &str) -> String {
+    let ticks = "`".repeat(longest_backtick_run(text) + 1);
+    if text.starts_with('`') || text.ends_with('`') {
+        format!("{} {} {}", ticks, text, ticks)
+    } else {
+        format!("{}{}{}", ticks, text, ticks)
+    }
+}
+
+const HTML_STYLE: &str = "\
+body{font:15px/1.5 -apple-system,BlinkMacSystemFont,'Segoe UI',Helvetica,Arial,sans-serif;\
+max-width:60rem;margin:2rem auto;padding:0 1rem;color:#1f2328;background:#fff}\
+h1{font-size:1.5rem}h2{font-size:1.1rem;border-bottom:1px solid #d0d7de;padding-bottom:.3rem;margin-top:2rem}\
+.meta{color:#59636e}.entry{border:1px solid #d0d7de;border-radius:6px;margin:.75rem 0;padding:.5rem .75rem}\
+.prompt{background:#f6f8fa;border-left:4px solid #0969da}.failed{border-left:4px solid #cf222e}\
+.label{font-size:.85rem;color:#59636e;margin-bottom:.25rem}.label b{color:#1f2328}\
+.label code{font-size:.8rem}.text{white-space:pre-wrap;margin:.25rem 0}\
+.tool{margin:.25rem 0}.tool-name{font-weight:600;color:#8250df}\
+table.args{border-collapse:collapse;margin:.25rem 0 .25rem 1rem;font-size:.9rem}\
+table.args td{border:1px solid #d0d7de;padding:.1rem .4rem;vertical-align:top}\
+table.args td:first-child{font-family:monospace;color:#59636e}\
+.error{background:#ffebe9;border:1px solid #ff8182;color:#82071e;border-radius:6px;padding:.4rem .6rem;\
+white-space:pre-wrap;font-family:ui-monospace,SFMono-Regular,Menlo,monospace;font-size:.85rem}\
+pre{background:#f6f8fa;border-radius:6px;padding:.6rem;overflow-x:auto;font-size:.85rem}\
+pre[data-lang]::before{content:attr(data-lang);display:block;color:#59636e;font-size:.75rem;margin-bottom:.25rem}\
+code{font-family:ui-monospace,SFMono-Regular,Menlo,monospace}";
+
+/// Single HTML page with inline styles and no external resources
+pub fn render_html(transcript: &Transcript) -> String {
+    let title = escape_html(&title(transcript));
+    let mut out = String::new();
+
+    let _ = write!(
+        out,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{}</title>\n<style>{}</style>\n</head>\n<body>\n",
+        title, HTML_STYLE
+    );
+    let _ = writeln!(
+        out,
+        "<header><h1>{}</h1><p class=\"meta\">{}</p></header>",
+        title,
+        escape_html(&summary(transcript))
+    );
+
+    for (n, turn) in transcript.turns().iter().enumerate() {
+        let _ = writeln!(out, "<section class=\"turn\">\n<h2>Turn {}</h2>", n + 1);
+        if let Some(prompt) = turn.prompt {
+            html_entry(&mut out, prompt);
+        }
+        for entry in &turn.responses {
+            html_entry(&mut out, entry);
+        }
+        out.push_str("</section>\n");
+    }
+
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+fn html_entry(out: &mut String, entry: &TranscriptEntry) {
+    let time = format_time(entry.timestamp)
+        .map(|t| format!(" · <time>{}</time>", t))
+        .unwrap_or_default();
+
+    match &entry.kind {
+        EntryKind::Prompt { content, agent, .. } => {
+            let agent = agent
+                .as_deref()
+                .map(|a| format!(" to {}", escape_html(a)))
+                .unwrap_or_default();
+            let _ = writeln!(
+                out,
+                "<div class=\"entry prompt\" id=\"{id}\">\n<div class=\"label\"><b>Prompt</b>{}{} · <code>{id}</code></div>\n<div class=\"text\">{}</div>\n</div>",
+                agent,
+                time,
+                escape_html(content),
+                id = escape_html(&entry.id)
+            );
+        }
+        EntryKind::Response { response } => {
+            let class = if response.success { "" } else { " failed" };
+            let _ = writeln!(
+                out,
+                "<div class=\"entry response{}\" id=\"{id}\">\n<div class=\"label\"><b>Response</b> ({}){} · <code>{id}</code></div>",
+                class,
+                type_label(response.message_type),
+                time,
+                id = escape_html(&entry.id)
+            );
+            html_response(out, response);
+            out.push_str("</div>\n");
+        }
+    }
+}
+
+fn html_response(out: &mut String, response: &AgentResponse) {
+    for call in &response.tool_calls {
+        let command = call.argument_text("command").unwrap_or_default();
+        let _ = write!(
+            out,
+            "<div class=\"tool\">Tool call <span class=\"tool-name\">{}</span>",
+            escape_html(&call.tool_name)
+        );
+        if !command.is_empty() {
+            let _ = write!(out, " <code>{}</code>", escape_html(&command));
+        }
+        let arguments = shown_arguments(call);
+        if !arguments.is_empty() {
+            out.push_str("\n<table class=\"args\">");
+            for (key, value) in arguments {
+                let _ = write!(
+                    out,
+                    "<tr><td>{}</td><td class=\"text\">{}</td></tr>",
+                    escape_html(key),
+                    escape_html(&value)
+                );
+            }
+            out.push_str("</table>");
+        }
+        out.push_str("</div>\n");
+    }
+
+    if let Some(error) = &response.error {
+        let _ = writeln!(out, "<div class=\"error\">{}</div>", escape_html(error));
+    }
+
+    for segment in segments(shown_content(response)) {
+        match segment {
+            Segment::Text(text) => {
+                let _ = writeln!(out, "<div class=\"text\">{}</div>", escape_html(&text));
+            }
+            Segment::Code { lang, code } if lang.is_empty() => {
+                let _ = writeln!(out, "<pre><code>{}</code></pre>", escape_html(&code));
+            }
+            Segment::Code { lang, code } => {
+                let lang = escape_html(&lang);
+                let _ = writeln!(
+                    out,
+                    "<pre data-lang=\"{lang}\"><code class=\"language-{lang}\">{}</code></pre>",
+                    escape_html(&code)
+                );
+            }
+        }
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::SessionId;
+
+    fn transcript() -> Transcript {
+        let mut transcript = Transcript::new(SessionId("swarm".to_string()));
+        transcript.push(TranscriptEntry {
+            id: "p1".to_string(),
+            timestamp: 1_704_164_645_000,
+            pane_id: None,
+            kind: EntryKind::Prompt {
+                content: "Fix the <parser> & run tests".to_string(),
+                agent: Some("claude".to_string()),
+                retries: 0,
+            },
+        });
+
+        let output = "Here is the fix:\n```rust\nfn main() {}\n```\nRunning: edit filePath=src/lib.rs replace=true\n";
+        let response = OutputParser::parse_agent_output(output).unwrap();
+        transcript.push_response(response.with_received_at(1_704_164_650_000));
+        transcript
+            .push_response(OutputParser::parse_agent_output("error: 2 tests failed").unwrap());
+        transcript
+    }
+
+    #[test]
+    fn test_format_names() {
+        assert_eq!(
+            "md".parse::<ExportFormat>().unwrap(),
+            ExportFormat::Markdown
+        );
+        assert_eq!("HTML".parse::<ExportFormat>().unwrap(), ExportFormat::Html);
+        assert!("pdf".parse::<ExportFormat>().is_err());
+        assert_eq!(
+            ExportFormat::from_path("run.jsonl"),
+            Some(ExportFormat::Jsonl)
+        );
+        assert_eq!(ExportFormat::from_path("run"), None);
+    }
+
+    #[test]
+    fn test_segments() {
+        let content = "intro\n```sh\nls\n```\nmiddle\n```\nplain\n```\n```py\nunclosed";
+        assert_eq!(
+            segments(content),
+            vec![
+                Segment::Text("intro".to_string()),
+                Segment::Code {
+                    lang: "sh".to_string(),
+                    code: "ls".to_string()
+                },
+                Segment::Text("middle".to_string()),
+                Segment::Code {
+                    lang: String::new(),
+                    code: "plain".to_string()
+                },
+                Segment::Text("```py\nunclosed".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_markdown() {
+        let md = render_markdown(&transcript());
+
+        assert!(md.starts_with("# Transcript: swarm\n"));
+        assert!(md.contains("_1 prompt, 2 responses, 1 tool call, 1 error_"));
+        assert!(md.contains("**Prompt** to claude · 2024-01-02 03:04:05 UTC · `p1`"));
+        assert!(md.contains("> Fix the <parser> & run tests"));
+        assert!(md.contains("- Tool call **edit**: `edit filePath=src/lib.rs replace=true`"));
+        assert!(md.contains("  - `filePath`: `src/lib.rs`"));
+        assert!(md.contains("```rust\nfn main() {}\n```"));
+        assert!(md.contains("> [!CAUTION]\n> error: 2 tests failed"));
+        assert_eq!(md.matches("2 tests failed").count(), 1);
+    }
+
+    #[test]
+    fn test_markdown_fences_longer_than_content() {
+        assert_eq!(fence_for("a ``` b"), "````");
+        assert_eq!(inline_code("a`b"), "``a`b``");
+        assert_eq!(inline_code("`x"), "`` `x ``");
+    }
+
+    #[test]
+    fn test_html_is_escaped_and_self_contained() {
+        let html = render_html(&transcript());
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("Fix the &lt;parser&gt; &amp; run tests"));
+        assert!(html.contains("<code class=\"language-rust\">fn main() {}</code>"));
+        assert!(html.contains("<div class=\"error\">error: 2 tests failed</div>"));
+        assert_eq!(html.matches("2 tests failed").count(), 1);
+        assert!(html.contains("<td>filePath</td><td class=\"text\">src/lib.rs</td>"));
+        assert!(html.contains("entry response failed"));
+        for external in ["<script", "<link", "src=", "href=", "url("] {
+            assert!(!html.contains(external), "{}", external);
+        }
+    }
+
+    #[test]
+    fn test_jsonl() {
+        let mut out = Vec::new();
+        export(&transcript(), ExportFormat::Jsonl, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap().lines().count(), 3);
+    }
+}
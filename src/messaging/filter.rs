@@ -1,24 +1,26 @@
-use crate::opencode::{AgentResponse, MessageType};
+use crate::messaging::dedup::{self, NearDuplicateFolder};
+use crate::messaging::query::{Predicate, Query};
+use crate::messaging::timestamp::find_timestamp;
+use crate::opencode::{AgentResponse, ErrorCategory, MessageType, ToolArguments};
 use std::collections::HashMap;
 
 pub struct MessageFilter;
 
 impl MessageFilter {
-    pub fn filter_by_type<'a>(
-        messages: &'a [AgentResponse],
+    /// Messages matching `query`; see `Query` for combining conditions
+    pub fn query<'a>(messages: &'a [AgentResponse], query: &Query) -> Vec<&'a AgentResponse> {
+        query.apply(messages)
+    }
+
+    pub fn filter_by_type(
+        messages: &[AgentResponse],
         message_type: MessageType,
-    ) -> Vec<&'a AgentResponse> {
-        messages
-            .iter()
-            .filter(|msg| msg.message_type == message_type)
-            .collect()
+    ) -> Vec<&AgentResponse> {
+        Query::new().message_type(message_type).apply(messages)
     }
 
     pub fn filter_by_success(messages: &[AgentResponse], success: bool) -> Vec<&AgentResponse> {
-        messages
-            .iter()
-            .filter(|msg| msg.success == success)
-            .collect()
+        Query::new().success(success).apply(messages)
     }
 
     pub fn filter_by_tool_name<'a>(
@@ -28,7 +30,9 @@ impl MessageFilter {
         messages
             .iter()
             .filter(|msg| {
-                msg.tool_calls.iter().any(|call| call.tool_name == tool_name)
+                msg.tool_calls
+                    .iter()
+                    .any(|call| call.tool_name == tool_name)
             })
             .collect()
     }
@@ -37,34 +41,46 @@ impl MessageFilter {
         messages: &'a [AgentResponse],
         pattern: &str,
     ) -> Vec<&'a AgentResponse> {
-        messages
-            .iter()
-            .filter(|msg| msg.content.contains(pattern))
-            .collect()
+        Query::new().content(pattern).apply(messages)
     }
 
-    pub fn filter_by_time_range<'a>(
-        messages: &'a [AgentResponse],
+    /// Messages whose timestamp (see `AgentResponse::timestamp`) lies in
+    /// `start..=end`, in seconds since the Unix epoch
+    pub fn filter_by_time_range(
+        messages: &[AgentResponse],
         start: u64,
         end: u64,
-    ) -> Vec<&'a AgentResponse> {
-        messages
-            .iter()
-            .filter(|msg| {
-                if let Some(timestamp) = Self::extract_timestamp(&msg.content) {
-                    timestamp >= start && timestamp <= end
-                } else {
-                    false
-                }
-            })
-            .collect()
+    ) -> Vec<&AgentResponse> {
+        let start = (start as i64).saturating_mul(1000);
+        let end = (end as i64).saturating_mul(1000).saturating_add(999);
+        Query::from(Predicate::Since(start))
+            .with(Predicate::Until(end))
+            .apply(messages)
     }
 
     pub fn filter_by_error(messages: &[AgentResponse]) -> Vec<&AgentResponse> {
-        messages
-            .iter()
-            .filter(|msg| !msg.success && msg.error.is_some())
-            .collect()
+        Query::new().failed().has_error().apply(messages)
+    }
+
+    /// Messages reporting an error of `category`
+    pub fn filter_by_error_category(
+        messages: &[AgentResponse],
+        category: ErrorCategory,
+    ) -> Vec<&AgentResponse> {
+        Query::new().error_category(category).apply(messages)
+    }
+
+    /// Error reports per category, across all messages
+    pub fn count_by_error_category(messages: &[AgentResponse]) -> HashMap<ErrorCategory, usize> {
+        let mut counts = HashMap::new();
+
+        for msg in messages {
+            for error in msg.classified_errors() {
+                *counts.entry(error.category).or_insert(0) += 1;
+            }
+        }
+
+        counts
     }
 
     pub fn filter_by_has_tool_calls(messages: &[AgentResponse]) -> Vec<&AgentResponse> {
@@ -78,20 +94,12 @@ impl MessageFilter {
         messages: &'a [AgentResponse],
         file_path: &str,
     ) -> Vec<&'a AgentResponse> {
-        messages
-            .iter()
-            .filter(|msg| {
-                msg.content.contains(file_path) || 
-                msg.tool_calls.iter().any(|call| {
-                    call.arguments.values().any(|v| v.contains(file_path))
-                })
-            })
-            .collect()
+        Query::new().file(file_path).apply(messages)
     }
 
     pub fn unique_tool_names(messages: &[AgentResponse]) -> Vec<String> {
         let mut names = HashMap::new();
-        
+
         for msg in messages {
             for call in &msg.tool_calls {
                 names.insert(call.tool_name.clone(), true);
@@ -103,9 +111,9 @@ impl MessageFilter {
 
     pub fn count_by_type(messages: &[AgentResponse]) -> HashMap<MessageType, usize> {
         let mut counts = HashMap::new();
-        
+
         for msg in messages {
-            *counts.entry(msg.message_type.clone()).or_insert(0) += 1;
+            *counts.entry(msg.message_type).or_insert(0) += 1;
         }
 
         counts
@@ -113,7 +121,7 @@ impl MessageFilter {
 
     pub fn count_by_tool(messages: &[AgentResponse]) -> HashMap<String, usize> {
         let mut counts = HashMap::new();
-        
+
         for msg in messages {
             for call in &msg.tool_calls {
                 *counts.entry(call.tool_name.clone()).or_insert(0) += 1;
@@ -124,7 +132,9 @@ impl MessageFilter {
     }
 
     pub fn find_first_error(messages: &[AgentResponse]) -> Option<&AgentResponse> {
-        messages.iter().find(|msg| !msg.success && msg.error.is_some())
+        messages
+            .iter()
+            .find(|msg| !msg.success && msg.error.is_some())
     }
 
     pub fn find_last_message(messages: &[AgentResponse]) -> Option<&AgentResponse> {
@@ -134,7 +144,7 @@ impl MessageFilter {
     pub fn extract_tool_arguments<'a>(
         messages: &'a [AgentResponse],
         tool_name: &str,
-    ) -> Vec<&'a HashMap<String, String>> {
+    ) -> Vec<&'a ToolArguments> {
         messages
             .iter()
             .filter_map(|msg| {
@@ -148,10 +158,10 @@ impl MessageFilter {
 
     pub fn group_by_type(messages: &[AgentResponse]) -> HashMap<MessageType, Vec<&AgentResponse>> {
         let mut groups = HashMap::new();
-        
+
         for msg in messages {
             groups
-                .entry(msg.message_type.clone())
+                .entry(msg.message_type)
                 .or_insert_with(Vec::new)
                 .push(msg);
         }
@@ -168,64 +178,38 @@ impl MessageFilter {
     }
 
     pub fn extract_content_only(messages: &[AgentResponse]) -> Vec<String> {
-        messages
-            .iter()
-            .map(|msg| msg.content.clone())
-            .collect()
+        messages.iter().map(|msg| msg.content.clone()).collect()
     }
 
+    /// First timestamp in `content`, in seconds since the Unix epoch
     pub fn extract_timestamp(content: &str) -> Option<u64> {
-        let patterns = [
-            r"\[(\d{10})\]",
-            r"\[(\d{13})\]",
-            r"T(\d{10})",
-        ];
-
-        for pattern in &patterns {
-            if let Some(re) = regex_lite::Regex::new(pattern).ok() {
-                if let Some(captures) = re.find(content) {
-                    let timestamp_str = captures.as_str();
-                    let digits: String = timestamp_str
-                        .chars()
-                        .filter(|c| c.is_ascii_digit())
-                        .collect();
-                    
-                    if let Ok(ts) = digits.parse::<u64>() {
-                        return Some(ts);
-                    }
-                }
-            }
-        }
-
-        None
+        find_timestamp(content).map(|ts| ts.timestamp().max(0) as u64)
     }
 
+    /// Messages with distinct content (see `dedup::content_hash`), first
+    /// occurrence kept
     pub fn deduplicate_messages(messages: &[AgentResponse]) -> Vec<&AgentResponse> {
-        let mut seen = HashMap::new();
-        let mut unique = Vec::new();
-
-        for msg in messages {
-            let key = format!("{}:{}:{}", msg.message_type as u8, msg.success, msg.content.len());
-            
-            if !seen.contains_key(&key) {
-                seen.insert(key, true);
-                unique.push(msg);
-            }
-        }
+        dedup::deduplicate(messages)
+    }
 
-        unique
+    /// Latest message of each run of near-duplicates, such as spinner
+    /// frames or re-rendered screens; see `NearDuplicateFolder`
+    pub fn fold_near_duplicates(messages: &[AgentResponse], threshold: f64) -> Vec<&AgentResponse> {
+        NearDuplicateFolder::new()
+            .with_threshold(threshold)
+            .fold(messages)
+            .into_iter()
+            .map(|folded| folded.message)
+            .collect()
     }
 
-    pub fn sort_by_timestamp<'a>(
-        messages: &'a [AgentResponse],
-        ascending: bool,
-    ) -> Vec<&'a AgentResponse> {
+    pub fn sort_by_timestamp(messages: &[AgentResponse], ascending: bool) -> Vec<&AgentResponse> {
         let mut sorted: Vec<_> = messages.iter().collect();
-        
+
         sorted.sort_by(|a, b| {
-            let a_ts = Self::extract_timestamp(&a.content).unwrap_or(0);
-            let b_ts = Self::extract_timestamp(&b.content).unwrap_or(0);
-            
+            let a_ts = a.timestamp().map(|ts| ts.timestamp_millis()).unwrap_or(0);
+            let b_ts = b.timestamp().map(|ts| ts.timestamp_millis()).unwrap_or(0);
+
             if ascending {
                 a_ts.cmp(&b_ts)
             } else {
@@ -240,10 +224,14 @@ impl MessageFilter {
         messages.iter().take(n).collect()
     }
 
-    pub fn paginate_messages(messages: &[AgentResponse], page: usize, page_size: usize) -> Vec<&AgentResponse> {
+    pub fn paginate_messages(
+        messages: &[AgentResponse],
+        page: usize,
+        page_size: usize,
+    ) -> Vec<&AgentResponse> {
         let start = page.saturating_mul(page_size);
         let end = start.saturating_add(page_size);
-        
+
         messages.iter().skip(start).take(end - start).collect()
     }
 
@@ -252,27 +240,24 @@ impl MessageFilter {
         regex: &str,
     ) -> Result<Vec<&'a AgentResponse>, regex_lite::Error> {
         let re = regex_lite::Regex::new(regex)?;
-        
+
         Ok(messages
             .iter()
             .filter(|msg| re.is_match(&msg.content))
             .collect())
     }
 
-    pub fn filter_multiline_content<'a>(
-        messages: &'a [AgentResponse],
+    pub fn filter_multiline_content(
+        messages: &[AgentResponse],
         min_lines: usize,
-    ) -> Vec<&'a AgentResponse> {
+    ) -> Vec<&AgentResponse> {
         messages
             .iter()
             .filter(|msg| msg.content.lines().count() >= min_lines)
             .collect()
     }
 
-    pub fn filter_by_length<'a>(
-        messages: &'a [AgentResponse],
-        min_length: usize,
-    ) -> Vec<&'a AgentResponse> {
+    pub fn filter_by_length(messages: &[AgentResponse], min_length: usize) -> Vec<&AgentResponse> {
         messages
             .iter()
             .filter(|msg| msg.content.len() >= min_length)
@@ -0,0 +1,244 @@
+//! JSON Extraction Module
+//!
+//! Finds JSON values embedded in agent output, either inside ```json (or
+//! bare ```) fences or inline in prose, and reports where each one sits.
+
+use crate::Result;
+use crate::error::TmuxError;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::ops::Range;
+
+/// JSON value found in agent output
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonBlock {
+    pub value: Value,
+    /// Byte range of the JSON text in the output, without fence lines
+    pub span: Range<usize>,
+    /// Whether the value was the whole body of a code fence
+    pub fenced: bool,
+}
+
+impl JsonBlock {
+    /// Deserialize the value into `T`
+    pub fn parse<T: DeserializeOwned>(&self) -> Result<T> {
+        T::deserialize(&self.value).map_err(|e| {
+            TmuxError::Parse(format!(
+                "JSON value at bytes {}..{}: {}",
+                self.span.start, self.span.end, e
+            ))
+        })
+    }
+
+    /// JSON text as it appears in `output`
+    pub fn text<'a>(&self, output: &'a str) -> &'a str {
+        &output[self.span.clone()]
+    }
+}
+
+/// Every JSON object or array in `output`, in order of appearance.
+///
+/// A fence whose whole body is valid JSON yields one fenced block, which
+/// may be any JSON value. Outside fences only objects and arrays are
+/// picked up, and arrays made up only of numbers (`[1]`, `[0, 10]`) are
+/// skipped as they are nearly always prose. Values nested in an extracted
+/// value are not reported separately.
+pub fn extract_json(output: &str) -> Vec<JsonBlock> {
+    let mut blocks = fenced_json(output);
+    let mut inline = Vec::new();
+
+    let bytes = output.as_bytes();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        if let Some(block) = blocks.iter().find(|b| b.span.contains(&pos)) {
+            pos = block.span.end;
+            continue;
+        }
+
+        if (bytes[pos] == b'{' || bytes[pos] == b'[')
+            && let Some((value, len)) = value_at(&output[pos..])
+            && is_inline_candidate(&value)
+        {
+            inline.push(JsonBlock {
+                value,
+                span: pos..pos + len,
+                fenced: false,
+            });
+            pos += len;
+            continue;
+        }
+
+        pos += 1;
+    }
+
+    blocks.extend(inline);
+    blocks.sort_by_key(|b| b.span.start);
+    blocks
+}
+
+/// Every extracted value that deserializes into `T`; others are skipped
+pub fn extract_json_as<T: DeserializeOwned>(output: &str) -> Vec<T> {
+    extract_json(output)
+        .iter()
+        .filter_map(|block| block.parse().ok())
+        .collect()
+}
+
+/// Last extracted value that deserializes into `T`. Agents usually report
+/// their final result last.
+pub fn last_json_as<T: DeserializeOwned>(output: &str) -> Result<T> {
+    extract_json(output)
+        .iter()
+        .rev()
+        .find_map(|block| block.parse().ok())
+        .ok_or_else(|| {
+            TmuxError::Parse(format!(
+                "No JSON value of type {} in output",
+                std::any::type_name::<T>()
+            ))
+        })
+}
+
+/// Value starting at the beginning of `text` and its length in bytes
+fn value_at(text: &str) -> Option<(Value, usize)> {
+    let mut stream = serde_json::Deserializer::from_str(text).into_iter::<Value>();
+    match stream.next() {
+        Some(Ok(value)) => Some((value, stream.byte_offset())),
+        _ => None,
+    }
+}
+
+fn is_inline_candidate(value: &Value) -> bool {
+    match value {
+        Value::Object(_) => true,
+        Value::Array(items) => !items.is_empty() && !items.iter().all(Value::is_number),
+        _ => false,
+    }
+}
+
+/// Fences tagged `json` or untagged whose body parses as one JSON value
+fn fenced_json(output: &str) -> Vec<JsonBlock> {
+    let mut blocks = Vec::new();
+    let mut open: Option<(bool, usize)> = None;
+    let mut offset = 0;
+
+    for line in output.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+
+        let trimmed = line.trim();
+        let Some(lang) = trimmed.strip_prefix("```") else {
+            continue;
+        };
+
+        match open {
+            None => {
+                let lang = lang.trim().to_ascii_lowercase();
+                open = Some((lang.is_empty() || lang == "json" || lang == "jsonc", offset));
+            }
+            Some((candidate, body_start)) => {
+                open = None;
+                if !candidate {
+                    continue;
+                }
+
+                let body = &output[body_start..start];
+                let leading = body.len() - body.trim_start().len();
+                let trimmed_body = body.trim();
+                if trimmed_body.is_empty() {
+                    continue;
+                }
+
+                if let Ok(value) = serde_json::from_str::<Value>(trimmed_body) {
+                    let span_start = body_start + leading;
+                    blocks.push(JsonBlock {
+                        value,
+                        span: span_start..span_start + trimmed_body.len(),
+                        fenced: true,
+                    });
+                }
+            }
+        }
+    }
+
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[test]
+    fn test_braces_inside_strings() {
+        let output = r#"Result: {"msg": "use {a, b}", "ok": true} done"#;
+        let blocks = extract_json(output);
+
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].value["msg"], "use {a, b}");
+        assert_eq!(
+            blocks[0].text(output),
+            r#"{"msg": "use {a, b}", "ok": true}"#
+        );
+    }
+
+    #[test]
+    fn test_fence_lines_not_in_span() {
+        let output = "Here:\n```json\n{\n  \"files\": 2\n}\n```\n";
+        let blocks = extract_json(output);
+
+        assert_eq!(blocks.len(), 1);
+        assert!(blocks[0].fenced);
+        assert_eq!(blocks[0].text(output), "{\n  \"files\": 2\n}");
+    }
+
+    #[test]
+    fn test_arrays_and_prose_brackets() {
+        let output = "[INFO] see [1] and [0, 10]\nchanged: [\"a.rs\", \"b.rs\"]\nfn x() { y }";
+        let blocks = extract_json(output);
+
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].value, serde_json::json!(["a.rs", "b.rs"]));
+    }
+
+    #[test]
+    fn test_fenced_scalar_and_multiple_values() {
+        let output = "```\n42\n```\n{\"a\": 1} then {\"b\": [1, {\"c\": 2}]}";
+        let blocks = extract_json(output);
+
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks[0].value, serde_json::json!(42));
+        // The nested object is part of the second value, not its own block
+        assert_eq!(blocks[2].value["b"][1]["c"], 2);
+    }
+
+    #[test]
+    fn test_invalid_fence_still_scanned() {
+        let output = "```json\n{\"a\": 1,\n// comment\n```\n";
+        assert!(extract_json(output).is_empty());
+
+        let output = "```json\nresult {\"a\": 1}\n```\n";
+        let blocks = extract_json(output);
+        assert_eq!(blocks.len(), 1);
+        assert!(!blocks[0].fenced);
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct TaskResult {
+        status: String,
+        tests_passed: u32,
+    }
+
+    #[test]
+    fn test_typed_helpers() {
+        let output = "progress {\"step\": 1}\nfinal {\"status\": \"ok\", \"tests_passed\": 12}";
+
+        let results: Vec<TaskResult> = extract_json_as(output);
+        assert_eq!(results.len(), 1);
+
+        let last: TaskResult = last_json_as(output).unwrap();
+        assert_eq!(last.tests_passed, 12);
+
+        assert!(last_json_as::<TaskResult>("no json here").is_err());
+    }
+}
@@ -0,0 +1,327 @@
+//! Log Line Index Module
+//!
+//! Sparse line index kept next to a log as `<log>.idx`. Every `stride`th
+//! line's byte offset is recorded, so seeking to line N reads at most
+//! `stride - 1` lines instead of the whole log. The index is a cache: it is
+//! extended as the log grows and rebuilt when the log is truncated or
+//! replaced.
+
+use crate::error::TmuxError;
+use std::fs::{self, File};
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// Lines between checkpoints
+pub const DEFAULT_STRIDE: u64 = 1024;
+
+const MAGIC: &[u8; 4] = b"SWLX";
+const VERSION: u32 = 1;
+/// Leading bytes fingerprinted to notice a replaced log
+const HEAD_LEN: u64 = 256;
+const CHUNK: usize = 64 * 1024;
+pub(crate) const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// Sparse line index of a log file
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineIndex {
+    stride: u64,
+    /// Byte offset of line `(i + 1) * stride`
+    checkpoints: Vec<u64>,
+    /// Bytes scanned; always the end of a complete line
+    indexed_bytes: u64,
+    /// Complete lines in the first `indexed_bytes` bytes
+    lines: u64,
+    /// Fingerprint of the first `head_len` bytes
+    head_hash: u64,
+    head_len: u64,
+}
+
+impl LineIndex {
+    /// Empty index with a checkpoint every `stride` lines
+    pub fn new(stride: u64) -> Self {
+        Self {
+            stride: stride.max(1),
+            checkpoints: Vec::new(),
+            indexed_bytes: 0,
+            lines: 0,
+            head_hash: FNV_OFFSET,
+            head_len: 0,
+        }
+    }
+
+    /// Sidecar path for a log: `session_1.log` -> `session_1.log.idx`
+    pub fn sidecar_path(log_path: &Path) -> PathBuf {
+        let mut path = log_path.as_os_str().to_owned();
+        path.push(".idx");
+        PathBuf::from(path)
+    }
+
+    /// Index for `log_path`, loaded from its sidecar and brought up to date.
+    /// The sidecar is rewritten when the index changed; failing to write it
+    /// only costs a rescan next time.
+    pub fn for_log(log_path: &Path) -> Result<Self, TmuxError> {
+        let sidecar = Self::sidecar_path(log_path);
+        let mut index = Self::load(&sidecar).unwrap_or_else(|| Self::new(DEFAULT_STRIDE));
+        if index.update(log_path)? {
+            let _ = index.save(&sidecar);
+        }
+        Ok(index)
+    }
+
+    /// Read a sidecar. A missing, corrupt or outdated file gives `None`.
+    pub fn load(path: &Path) -> Option<Self> {
+        let bytes = fs::read(path).ok()?;
+        let mut fields = bytes.strip_prefix(MAGIC.as_slice())?.chunks_exact(8);
+        let mut next = || {
+            fields
+                .next()
+                .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+        };
+
+        let header = next()?;
+        if header as u32 != VERSION {
+            return None;
+        }
+        let stride = (header >> 32).max(1);
+        let indexed_bytes = next()?;
+        let lines = next()?;
+        let head_hash = next()?;
+        let head_len = next()?;
+        let count = next()?;
+
+        let checkpoints = (0..count).map(|_| next()).collect::<Option<Vec<_>>>()?;
+        if checkpoints.len() as u64 != lines / stride {
+            return None;
+        }
+
+        Some(Self {
+            stride,
+            checkpoints,
+            indexed_bytes,
+            lines,
+            head_hash,
+            head_len,
+        })
+    }
+
+    /// Write the sidecar atomically
+    pub fn save(&self, path: &Path) -> Result<(), TmuxError> {
+        let mut bytes = Vec::with_capacity(4 + 8 * (6 + self.checkpoints.len()));
+        bytes.extend_from_slice(MAGIC);
+        let header = (self.stride << 32) | VERSION as u64;
+        for field in [
+            header,
+            self.indexed_bytes,
+            self.lines,
+            self.head_hash,
+            self.head_len,
+            self.checkpoints.len() as u64,
+        ] {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        for offset in &self.checkpoints {
+            bytes.extend_from_slice(&offset.to_le_bytes());
+        }
+
+        let tmp = path.with_extension("idx.tmp");
+        fs::write(&tmp, bytes)
+            .and_then(|_| fs::rename(&tmp, path))
+            .map_err(|e| TmuxError::Command(format!("Failed to write line index: {}", e)))
+    }
+
+    /// Scan whatever the log gained since the last update. A log that
+    /// shrank or whose first bytes changed is indexed from scratch. Returns
+    /// whether the index changed.
+    pub fn update(&mut self, log_path: &Path) -> Result<bool, TmuxError> {
+        let mut file = match File::open(log_path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let changed = self.indexed_bytes > 0;
+                *self = Self::new(self.stride);
+                return Ok(changed);
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let len = file.metadata()?.len();
+
+        let mut rebuilt = false;
+        if len < self.indexed_bytes || head_hash(&mut file, self.head_len)? != self.head_hash {
+            *self = Self::new(self.stride);
+            rebuilt = true;
+        }
+        if len == self.indexed_bytes {
+            return Ok(rebuilt);
+        }
+
+        let before = (self.indexed_bytes, self.head_len);
+        file.seek(SeekFrom::Start(self.indexed_bytes))?;
+        let mut reader = BufReader::with_capacity(CHUNK, file.take(len - self.indexed_bytes));
+        let mut buf = vec![0; CHUNK];
+        let mut pos = self.indexed_bytes;
+
+        loop {
+            let read = reader.read(&mut buf)?;
+            if read == 0 {
+                break;
+            }
+            for (i, byte) in buf[..read].iter().enumerate() {
+                if *byte == b'\n' {
+                    self.lines += 1;
+                    self.indexed_bytes = pos + i as u64 + 1;
+                    if self.lines.is_multiple_of(self.stride) {
+                        self.checkpoints.push(self.indexed_bytes);
+                    }
+                }
+            }
+            pos += read as u64;
+        }
+
+        let head_len = self.indexed_bytes.min(HEAD_LEN);
+        if head_len != self.head_len {
+            let mut file = reader.into_inner().into_inner();
+            self.head_hash = head_hash(&mut file, head_len)?;
+            self.head_len = head_len;
+        }
+
+        Ok(rebuilt || (self.indexed_bytes, self.head_len) != before)
+    }
+
+    /// Closest checkpoint at or before `line`, as `(byte offset, line)`
+    pub fn seek(&self, line: u64) -> (u64, u64) {
+        let k = (line / self.stride).min(self.checkpoints.len() as u64);
+        match k {
+            0 => (0, 0),
+            k => (self.checkpoints[k as usize - 1], k * self.stride),
+        }
+    }
+
+    /// Complete lines indexed
+    pub fn line_count(&self) -> u64 {
+        self.lines
+    }
+
+    /// End of the last complete line indexed
+    pub fn indexed_bytes(&self) -> u64 {
+        self.indexed_bytes
+    }
+
+    pub fn stride(&self) -> u64 {
+        self.stride
+    }
+}
+
+impl Default for LineIndex {
+    fn default() -> Self {
+        Self::new(DEFAULT_STRIDE)
+    }
+}
+
+/// FNV-1a of the first `len` bytes
+pub(crate) fn head_hash(file: &mut File, len: u64) -> Result<u64, TmuxError> {
+    let mut head = Vec::with_capacity(len as usize);
+    file.seek(SeekFrom::Start(0))?;
+    file.by_ref().take(len).read_to_end(&mut head)?;
+    Ok(head.iter().fold(FNV_OFFSET, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(FNV_PRIME)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn write_lines(path: &Path, from: u64, to: u64) {
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        for i in from..to {
+            writeln!(file, "line {}", i).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_checkpoints_and_incremental_update() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("session_1.log");
+        write_lines(&log, 0, 25);
+
+        let mut index = LineIndex::new(10);
+        assert!(index.update(&log).unwrap());
+        assert_eq!(index.line_count(), 25);
+        assert_eq!(index.seek(0), (0, 0));
+        assert_eq!(index.seek(9), (0, 0));
+
+        let content = fs::read_to_string(&log).unwrap();
+        let offset_of = |line: usize| {
+            content
+                .split_inclusive('\n')
+                .take(line)
+                .map(str::len)
+                .sum::<usize>() as u64
+        };
+        assert_eq!(index.seek(10), (offset_of(10), 10));
+        assert_eq!(index.seek(24), (offset_of(20), 20));
+
+        // An unterminated line is left for the next update
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&log)
+            .unwrap()
+            .write_all(b"partial")
+            .unwrap();
+        assert!(!index.update(&log).unwrap());
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&log)
+            .unwrap()
+            .write_all(b"\n")
+            .unwrap();
+        write_lines(&log, 26, 40);
+        assert!(index.update(&log).unwrap());
+        assert_eq!(index.line_count(), 40);
+        assert_eq!(index.seek(1000).1, 40);
+    }
+
+    #[test]
+    fn test_rebuilds_after_truncation_or_replacement() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("session_1.log");
+        write_lines(&log, 0, 30);
+
+        let mut index = LineIndex::new(10);
+        index.update(&log).unwrap();
+
+        fs::write(&log, "").unwrap();
+        assert!(index.update(&log).unwrap());
+        assert_eq!(index.line_count(), 0);
+
+        // Same size or larger, but different content
+        write_lines(&log, 0, 30);
+        index.update(&log).unwrap();
+        fs::write(&log, "x".repeat(400) + "\n").unwrap();
+        write_lines(&log, 0, 30);
+        assert!(index.update(&log).unwrap());
+        assert_eq!(index.line_count(), 31);
+    }
+
+    #[test]
+    fn test_sidecar_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("session_1.log");
+        write_lines(&log, 0, 3000);
+
+        let index = LineIndex::for_log(&log).unwrap();
+        let sidecar = LineIndex::sidecar_path(&log);
+        assert_eq!(sidecar.file_name().unwrap(), "session_1.log.idx");
+        assert_eq!(LineIndex::load(&sidecar), Some(index.clone()));
+        assert_eq!(index.seek(2500).1, 2048);
+
+        fs::write(&sidecar, b"SWLXgarbage").unwrap();
+        assert_eq!(LineIndex::load(&sidecar), None);
+        assert_eq!(LineIndex::for_log(&log).unwrap(), index);
+    }
+}
@@ -1,7 +1,38 @@
+pub mod dedup;
+pub mod export;
+pub mod filter;
+pub mod json;
+pub mod log_index;
+pub mod parser;
+pub mod query;
 pub mod queue;
 pub mod read;
+pub mod rotate;
+pub mod search;
 pub mod send;
+pub mod stream;
+pub mod timestamp;
+pub mod transcript;
+pub mod watch;
 
-pub use queue::{MessageQueue, QueueStats, QueuedMessage};
-pub use read::LogReader;
+pub use dedup::{Folded, NearDuplicateFolder, content_hash, normalize_content};
+pub use export::{ExportFormat, export, render_html, render_markdown};
+pub use filter::MessageFilter;
+pub use json::{JsonBlock, extract_json, extract_json_as, last_json_as};
+pub use log_index::LineIndex;
+pub use parser::OutputParser;
+pub use query::{Predicate, Query};
+pub use queue::{DeliveryFailure, DrainReport, MessageQueue, QueueStats, QueuedMessage};
+pub use read::{LogCursor, LogReader};
+pub use rotate::{
+    LogRotator, LogSegment, RotationPolicy, RotationReport, log_segments, parse_duration,
+    parse_size,
+};
+pub use search::{
+    IndexStats, LogSearch, LogSource, SearchExpr, SearchHit, SearchOptions, SearchQuery, TermIndex,
+};
 pub use send::{FileLock, MessageSender, PromptMetadata};
+pub use stream::{StreamingParser, capture_time};
+pub use timestamp::{TimestampParser, find_timestamp, now_millis, parse_timestamp};
+pub use transcript::{EntryKind, Transcript, TranscriptBuilder, TranscriptEntry, Turn};
+pub use watch::{LogLine, WatchEnd, WatchHandle, WatchOptions, WatchStart};
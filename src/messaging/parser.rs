@@ -1,35 +1,43 @@
 use crate::Result;
-use crate::opencode::{AgentResponse, MessageType, ToolCall};
-use std::collections::HashMap;
-
+use crate::messaging::json::{JsonBlock, extract_json, extract_json_as};
+use crate::messaging::stream::StreamingParser;
+use crate::messaging::timestamp::find_timestamp;
+use crate::opencode::{AgentResponse, ClassifiedError, DialectRegistry, ToolCall, scan_output};
+use serde::de::DeserializeOwned;
+
+/// Agent output parsing helpers. Classification goes through the dialect
+/// registry, so the results match `Protocol::parse_response`.
 pub struct OutputParser;
 
 impl OutputParser {
+    /// Parse output with the dialect detected from its content
     pub fn parse_agent_output(output: &str) -> Result<AgentResponse> {
-        if Self::is_error(output) {
-            return Ok(Self::parse_error(output));
-        }
-
-        if Self::is_tool_call(output) {
-            return Ok(Self::parse_tool_call(output));
-        }
-
-        if Self::is_completion(output) {
-            return Ok(Self::parse_completion(output));
-        }
+        DialectRegistry::builtin().parse(output)
+    }
 
-        Ok(Self::parse_message(output))
+    /// Parse output with a named dialect
+    pub fn parse_with_dialect(dialect: &str, output: &str) -> Result<AgentResponse> {
+        let registry = DialectRegistry::builtin();
+        let protocol = registry.get(dialect).ok_or_else(|| {
+            crate::error::TmuxError::NotFound(format!(
+                "Unknown dialect '{}' (known: {})",
+                dialect,
+                registry.names().join(", ")
+            ))
+        })?;
+        protocol.parse_response(output)
     }
 
+    /// Split complete output into segments and parse each one. Use
+    /// `StreamingParser` directly for output that is still arriving.
     pub fn parse_multiple_outputs(output: &str) -> Vec<AgentResponse> {
-        let mut responses = Vec::new();
-        
-        for segment in Self::split_into_segments(output) {
-            if let Ok(response) = Self::parse_agent_output(&segment) {
-                responses.push(response);
-            }
-        }
+        let Some(protocol) = DialectRegistry::builtin().detect(output) else {
+            return Vec::new();
+        };
 
+        let mut parser = StreamingParser::with_protocol(protocol);
+        let mut responses = parser.feed(output);
+        responses.extend(parser.finish());
         responses
     }
 
@@ -37,49 +45,40 @@ impl OutputParser {
         response.tool_calls.clone()
     }
 
+    /// Error messages in the output. Warnings and prose that mentions an
+    /// error are left out; see `classify_errors` for categories and
+    /// locations.
     pub fn extract_errors(output: &str) -> Vec<String> {
-        let mut errors = Vec::new();
-        
-        for line in output.lines() {
-            if line.contains("<error>") || line.contains("Error:") || line.contains("error:") {
-                errors.push(Self::strip_error_tags(line));
-            }
-        }
+        Self::classify_errors(output)
+            .into_iter()
+            .map(|error| error.message)
+            .collect()
+    }
 
-        errors
+    /// Classified errors in the output
+    pub fn classify_errors(output: &str) -> Vec<ClassifiedError> {
+        match DialectRegistry::builtin().detect(output) {
+            Some(protocol) => scan_output(protocol, output).errors,
+            None => Vec::new(),
+        }
     }
 
+    /// Text of every JSON value embedded in the output
     pub fn extract_json_blocks(output: &str) -> Vec<String> {
-        let mut json_blocks = Vec::new();
-        let mut in_json = false;
-        let mut current_block = String::new();
-        let mut brace_count = 0;
-
-        for line in output.lines() {
-            if line.trim().starts_with("{") || line.trim().starts_with("```json") {
-                in_json = true;
-            }
-
-            if in_json {
-                current_block.push_str(line);
-                current_block.push('\n');
-
-                brace_count += line.chars().filter(|&c| c == '{').count();
-                brace_count -= line.chars().filter(|&c| c == '}').count();
-
-                if brace_count == 0 && line.contains('}') {
-                    json_blocks.push(current_block.trim().to_string());
-                    current_block.clear();
-                    in_json = false;
-                }
-            }
+        extract_json(output)
+            .iter()
+            .map(|block| block.text(output).to_string())
+            .collect()
+    }
 
-            if line.trim().contains("```") && in_json && brace_count == 0 {
-                in_json = false;
-            }
-        }
+    /// Every JSON value embedded in the output, with byte spans
+    pub fn extract_json_values(output: &str) -> Vec<JsonBlock> {
+        extract_json(output)
+    }
 
-        json_blocks
+    /// Every embedded JSON value that deserializes into `T`
+    pub fn extract_json_as<T: DeserializeOwned>(output: &str) -> Vec<T> {
+        extract_json_as(output)
     }
 
     pub fn extract_code_blocks(output: &str) -> Vec<(String, String)> {
@@ -110,7 +109,7 @@ impl OutputParser {
 
     pub fn extract_command_line(command: &str) -> Option<(String, Vec<String>)> {
         let parts: Vec<&str> = command.split_whitespace().collect();
-        
+
         if parts.is_empty() {
             return None;
         }
@@ -121,207 +120,144 @@ impl OutputParser {
         Some((cmd, args))
     }
 
+    /// First timestamp in `line`, in seconds since the Unix epoch
     pub fn parse_timestamp(line: &str) -> Option<u64> {
-        let patterns = [
-            r"\[(\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2})\]",
-            r"\[(\d{10})\]",
-            r"T(\d{10})",
-        ];
-
-        for pattern in &patterns {
-            if let Some(captures) = regex_lite::Regex::new(pattern).ok()?.find(line) {
-                let timestamp_str = captures.as_str();
-                
-                if timestamp_str.contains('-') {
-                    if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(&format!("{}Z", timestamp_str)) {
-                        return Some(dt.timestamp() as u64);
-                    }
-                } else {
-                    let digits: String = timestamp_str.chars().filter(|c| c.is_ascii_digit()).collect();
-                    if let Ok(ts) = digits.parse::<u64>() {
-                        return Some(ts);
-                    }
-                }
-            }
-        }
-
-        None
-    }
-
-    fn is_error(output: &str) -> bool {
-        output.contains("<error>") || output.contains("Error:") || output.contains("error:")
-    }
-
-    fn is_tool_call(output: &str) -> bool {
-        output.contains("Running:") || output.contains("<tool_call>")
+        find_timestamp(line).map(|ts| ts.timestamp().max(0) as u64)
     }
+}
 
-    fn is_completion(output: &str) -> bool {
-        output.contains("<complete>") || output.contains("I'll complete") || output.contains("Done.")
+impl Default for OutputParser {
+    fn default() -> Self {
+        OutputParser
     }
+}
 
-    fn parse_error(output: &str) -> AgentResponse {
-        AgentResponse {
-            success: false,
-            message_type: MessageType::Error,
-            content: Self::strip_error_tags(output),
-            tool_calls: Vec::new(),
-            error: Some(Self::extract_error_message(output)),
-        }
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opencode::{ErrorCategory, MessageType, OpenCodeProtocol, Protocol};
+
+    #[test]
+    fn test_agrees_with_protocol() {
+        let samples = [
+            "I'll read the file\nRunning: read filePath=/tmp/a.txt",
+            "<error>permission denied</error>",
+            "I'll complete this task\n<complete>Task finished</complete>",
+            "<tool_call>bash command=ls</tool_call>",
+            "Done.",
+            "Done. Next I'll look at the tests",
+            "Handled the error: case in the lexer",
+        ];
 
-    fn parse_tool_call(output: &str) -> AgentResponse {
-        let tool_calls = Self::extract_tool_calls_from_output(output);
-        
-        AgentResponse {
-            success: true,
-            message_type: MessageType::ToolCall,
-            content: Self::extract_content_from_tool_output(output),
-            tool_calls,
-            error: None,
+        for sample in samples {
+            let ours = OutputParser::parse_agent_output(sample).unwrap();
+            let theirs = OpenCodeProtocol::new().parse_response(sample).unwrap();
+            assert_eq!(ours.message_type, theirs.message_type, "{}", sample);
+            assert_eq!(ours.content, theirs.content, "{}", sample);
         }
     }
 
-    fn parse_completion(output: &str) -> AgentResponse {
-        AgentResponse {
-            success: true,
-            message_type: MessageType::Completion,
-            content: Self::strip_completion_tags(output),
-            tool_calls: Vec::new(),
-            error: None,
-        }
-    }
+    #[test]
+    fn test_done_only_as_marker_line() {
+        let done = OutputParser::parse_agent_output("Fixed it.\nDone.").unwrap();
+        assert_eq!(done.message_type, MessageType::Completion);
+        assert_eq!(done.content, "Fixed it.");
 
-    fn parse_message(output: &str) -> AgentResponse {
-        AgentResponse {
-            success: true,
-            message_type: MessageType::Message,
-            content: output.to_string(),
-            tool_calls: Vec::new(),
-            error: None,
-        }
+        let prose = OutputParser::parse_agent_output("Done. Now the tests").unwrap();
+        assert_eq!(prose.message_type, MessageType::Message);
     }
 
-    fn strip_error_tags(output: &str) -> String {
-        output
-            .replace("<error>", "")
-            .replace("</error>", "")
-            .lines()
-            .filter(|line| !line.contains("Error:") && !line.contains("error:"))
-            .collect::<Vec<_>>()
-            .join("\n")
-            .trim()
-            .to_string()
-    }
+    #[test]
+    fn test_error_only_at_line_start() {
+        let prose = OutputParser::parse_agent_output("Handled the error: case").unwrap();
+        assert_eq!(prose.message_type, MessageType::Message);
 
-    fn extract_error_message(output: &str) -> String {
-        output
-            .lines()
-            .filter(|line| line.contains("Error:") || line.contains("error:"))
-            .collect::<Vec<_>>()
-            .join("\n")
-            .trim()
-            .to_string()
+        let error = OutputParser::parse_agent_output("error: could not compile").unwrap();
+        assert_eq!(error.message_type, MessageType::Error);
+        assert_eq!(error.error.as_deref(), Some("error: could not compile"));
     }
 
-    fn extract_tool_calls_from_output(output: &str) -> Vec<ToolCall> {
-        let mut calls = Vec::new();
-
-        for line in output.lines() {
-            if let Some(rest) = line.strip_prefix("Running: ") {
-                if let Some((tool_name, args_str)) = Self::extract_command_line(rest) {
-                    let mut args = HashMap::new();
-                    args.insert("raw_args".to_string(), args_str.join(" "));
-                    args.insert("command".to_string(), rest.to_string());
-                    
-                    calls.push(ToolCall {
-                        tool_name,
-                        arguments: args,
-                    });
-                }
-            }
-
-            if line.contains("<tool_call>") {
-                if let Some(call) = Self::parse_xml_tool_call(line) {
-                    calls.push(call);
-                }
-            }
-        }
-
-        calls
+    #[test]
+    fn test_classify_errors() {
+        let output = "\
+Running: bash cargo build
+warning: unused variable: `error`
+error[E0425]: cannot find value `x` in this scope
+ --> src/main.rs:4:5
+API Error: 429 Too Many Requests";
+
+        assert_eq!(
+            OutputParser::extract_errors(output),
+            vec![
+                "error[E0425]: cannot find value `x` in this scope",
+                "API Error: 429 Too Many Requests"
+            ]
+        );
+
+        let errors = OutputParser::classify_errors(output);
+        assert_eq!(errors[0].category, ErrorCategory::Compiler);
+        assert_eq!(
+            errors[0].location.as_ref().unwrap().to_string(),
+            "src/main.rs:4:5"
+        );
+        assert_eq!(errors[1].category, ErrorCategory::Api);
+
+        let response = OutputParser::parse_agent_output(output).unwrap();
+        assert_eq!(response.errors, errors);
+        assert_eq!(response.error_category(), Some(ErrorCategory::Compiler));
     }
 
-    fn parse_xml_tool_call(line: &str) -> Option<ToolCall> {
-        let name_start = line.find("<tool_call>")?;
-        let name_end = line.find("</tool_call>")?;
-        let content = &line[name_start + 11..name_end];
-        
-        let parts: Vec<&str> = content.split_whitespace().collect();
-        if parts.is_empty() {
-            return None;
-        }
-
-        let tool_name = parts[0].to_string();
-        let mut args = HashMap::new();
-        
-        for part in &parts[1..] {
-            if let Some((key, value)) = part.split_once('=') {
-                args.insert(key.to_string(), value.trim_matches('"').to_string());
-            }
-        }
-
-        Some(ToolCall { tool_name, arguments: args })
+    #[test]
+    fn test_multiline_tool_call_tag() {
+        let response =
+            OutputParser::parse_agent_output("<tool_call>\nedit path=src/lib.rs\n</tool_call>")
+                .unwrap();
+        assert_eq!(response.message_type, MessageType::ToolCall);
+        assert_eq!(response.tool_calls[0].tool_name, "edit");
+        assert_eq!(response.tool_calls[0].arguments["path"], "src/lib.rs");
     }
 
-    fn extract_content_from_tool_output(output: &str) -> String {
-        output
-            .lines()
-            .filter(|line| !line.starts_with("Running:") && !line.starts_with("<tool_call>"))
-            .collect::<Vec<_>>()
-            .join("\n")
-            .trim()
-            .to_string()
+    #[test]
+    fn test_parse_multiple_outputs() {
+        let output = "Looking around\nRunning: ls\nsrc\n<error>boom</error>";
+        let responses = OutputParser::parse_multiple_outputs(output);
+
+        let types: Vec<_> = responses.iter().map(|r| r.message_type).collect();
+        assert_eq!(
+            types,
+            vec![
+                MessageType::Message,
+                MessageType::ToolCall,
+                MessageType::Error
+            ]
+        );
     }
 
-    fn strip_completion_tags(output: &str) -> String {
-        output
-            .replace("<complete>", "")
-            .replace("</complete>", "")
-            .lines()
-            .filter(|line| !line.contains("I'll complete") && !line.contains("Done."))
-            .collect::<Vec<_>>()
-            .join("\n")
-            .trim()
-            .to_string()
+    #[test]
+    fn test_json_blocks_exclude_fences() {
+        let output = "```json\n{\"a\": \"}\"}\n```\nand [{\"b\": 1}]";
+        assert_eq!(
+            OutputParser::extract_json_blocks(output),
+            vec![r#"{"a": "}"}"#.to_string(), r#"[{"b": 1}]"#.to_string()]
+        );
     }
 
-    fn split_into_segments(output: &str) -> Vec<String> {
-        let mut segments = Vec::new();
-        let mut current = String::new();
-        let mut depth = 0;
-
-        for line in output.lines() {
-            if line.contains("<error>") || line.contains("<complete>") || line.contains("Running:") {
-                if !current.is_empty() {
-                    segments.push(current.clone());
-                    current.clear();
-                }
-            }
-
-            current.push_str(line);
-            current.push('\n');
-        }
-
-        if !current.is_empty() {
-            segments.push(current);
-        }
-
-        segments
+    #[test]
+    fn test_parse_timestamp_formats() {
+        assert_eq!(
+            OutputParser::parse_timestamp("[2024-01-02 03:04:05] started"),
+            Some(1704164645)
+        );
+        assert_eq!(
+            OutputParser::parse_timestamp("[1704164645123] ms"),
+            Some(1704164645)
+        );
+        assert_eq!(OutputParser::parse_timestamp("nothing"), None);
     }
-}
 
-impl Default for OutputParser {
-    fn default() -> Self {
-        OutputParser
+    #[test]
+    fn test_unknown_dialect() {
+        assert!(OutputParser::parse_with_dialect("cobol", "hi").is_err());
+        assert!(OutputParser::parse_with_dialect("shell", "$ ls").is_ok());
     }
 }
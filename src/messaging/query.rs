@@ -0,0 +1,887 @@
+//! Message Query Module
+//!
+//! Composable predicates over `AgentResponse`s, built in code or parsed
+//! from a small text syntax such as
+//! `type:tool_call tool:bash since:10m content~/cargo test/`.
+
+use crate::Result;
+use crate::error::TmuxError;
+use crate::messaging::timestamp::TimestampParser;
+use crate::opencode::{AgentResponse, ErrorCategory, MessageType, value_text};
+use chrono::{DateTime, Utc};
+use regex_lite::Regex;
+use std::iter::Peekable;
+use std::str::FromStr;
+use std::time::Duration;
+
+const KEYS: &str = "type, is, status, tool, arg, content, error, category, file, since, until";
+
+/// Condition on a single `AgentResponse`
+#[derive(Debug, Clone)]
+pub enum Predicate {
+    /// Matches every response
+    All,
+    Type(MessageType),
+    Success(bool),
+    /// Response carries an error message
+    HasError,
+    /// Some tool call has this name. `*` matches any run of characters and
+    /// case is ignored.
+    Tool(String),
+    /// Some tool call has the argument, with exactly this text if given
+    Argument(String, Option<String>),
+    /// Content contains the text
+    Content(String),
+    ContentMatches(Regex),
+    /// Error message contains the text
+    Error(String),
+    ErrorMatches(Regex),
+    /// Some error is of this category, see `classify_error`
+    Category(ErrorCategory),
+    /// Content or a tool argument mentions the path
+    File(String),
+    /// Timestamp at or after, in Unix milliseconds
+    Since(i64),
+    /// Timestamp at or before, in Unix milliseconds
+    Until(i64),
+    Not(Box<Predicate>),
+    And(Vec<Predicate>),
+    Or(Vec<Predicate>),
+}
+
+impl Predicate {
+    pub fn matches(&self, msg: &AgentResponse) -> bool {
+        match self {
+            Predicate::All => true,
+            Predicate::Type(message_type) => msg.message_type == *message_type,
+            Predicate::Success(success) => msg.success == *success,
+            Predicate::HasError => msg.error.is_some(),
+            Predicate::Tool(pattern) => msg
+                .tool_calls
+                .iter()
+                .any(|call| glob_match(pattern, &call.tool_name)),
+            Predicate::Argument(key, value) => {
+                msg.tool_calls
+                    .iter()
+                    .any(|call| match (call.arguments.get(key), value) {
+                        (Some(arg), Some(value)) => value_text(arg) == *value,
+                        (Some(_), None) => true,
+                        (None, _) => false,
+                    })
+            }
+            Predicate::Content(text) => msg.content.contains(text.as_str()),
+            Predicate::ContentMatches(re) => re.is_match(&msg.content),
+            Predicate::Error(text) => msg
+                .error
+                .as_deref()
+                .is_some_and(|error| error.contains(text.as_str())),
+            Predicate::ErrorMatches(re) => msg.error.as_deref().is_some_and(|e| re.is_match(e)),
+            Predicate::Category(category) => msg
+                .classified_errors()
+                .iter()
+                .any(|error| error.category == *category),
+            Predicate::File(path) => {
+                msg.content.contains(path.as_str())
+                    || msg.tool_calls.iter().any(|call| {
+                        call.arguments
+                            .values()
+                            .any(|v| value_text(v).contains(path.as_str()))
+                    })
+            }
+            Predicate::Since(millis) => msg
+                .timestamp()
+                .is_some_and(|ts| ts.timestamp_millis() >= *millis),
+            Predicate::Until(millis) => msg
+                .timestamp()
+                .is_some_and(|ts| ts.timestamp_millis() <= *millis),
+            Predicate::Not(inner) => !inner.matches(msg),
+            Predicate::And(all) => all.iter().all(|p| p.matches(msg)),
+            Predicate::Or(any) => any.iter().any(|p| p.matches(msg)),
+        }
+    }
+}
+
+/// Query over agent responses.
+///
+/// Builder methods narrow the query (they are ANDed together); `or` and
+/// `negate` combine whole queries:
+///
+/// ```ignore
+/// let failing_bash = Query::new().tool("bash").failed().within(Duration::from_secs(3600));
+/// let hits = failing_bash.apply(&responses);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Query {
+    predicate: Predicate,
+}
+
+impl Query {
+    /// Query matching every response
+    pub fn new() -> Self {
+        Self {
+            predicate: Predicate::All,
+        }
+    }
+
+    /// Parse the text syntax, resolving relative times against now.
+    ///
+    /// Terms are `key:value`, `key~/regex/flags` or a bare word or quoted
+    /// phrase, which matches content. Terms next to each other must all
+    /// match. `OR` (or `|`) separates alternatives, `-term`, `!term` and
+    /// `NOT term` negate, and parentheses group. Keys are `type`, `is`,
+    /// `status`, `tool`, `arg`, `content`, `error`, `category`, `file`,
+    /// `since` and `until`. Times are either a duration before now (`30s`, `10m`,
+    /// `1h30m`, `2d`, `1w`) or any timestamp `TimestampParser` accepts.
+    pub fn parse(text: &str) -> Result<Self> {
+        Self::parse_at(text, Utc::now())
+    }
+
+    /// Parse the text syntax, resolving relative times against `now`
+    pub fn parse_at(text: &str, now: DateTime<Utc>) -> Result<Self> {
+        let tokens = Lexer { text, pos: 0, now }.tokens()?;
+        if tokens.is_empty() {
+            return Ok(Self::new());
+        }
+
+        let mut parser = Parser {
+            tokens: tokens.into_iter().peekable(),
+        };
+        let predicate = parser.or()?;
+        if parser.tokens.next().is_some() {
+            return Err(TmuxError::Parse(format!(
+                "Unbalanced ')' in query '{}'",
+                text
+            )));
+        }
+
+        Ok(Self { predicate })
+    }
+
+    pub fn predicate(&self) -> &Predicate {
+        &self.predicate
+    }
+
+    /// Narrow the query with another predicate
+    pub fn with(mut self, predicate: Predicate) -> Self {
+        self.predicate = match (self.predicate, predicate) {
+            (Predicate::All, p) | (p, Predicate::All) => p,
+            (Predicate::And(mut all), p) => {
+                all.push(p);
+                Predicate::And(all)
+            }
+            (first, p) => Predicate::And(vec![first, p]),
+        };
+        self
+    }
+
+    pub fn message_type(self, message_type: MessageType) -> Self {
+        self.with(Predicate::Type(message_type))
+    }
+
+    pub fn success(self, success: bool) -> Self {
+        self.with(Predicate::Success(success))
+    }
+
+    /// Unsuccessful responses
+    pub fn failed(self) -> Self {
+        self.success(false)
+    }
+
+    pub fn has_error(self) -> Self {
+        self.with(Predicate::HasError)
+    }
+
+    /// Responses calling a tool, see `Predicate::Tool`
+    pub fn tool(self, pattern: impl Into<String>) -> Self {
+        self.with(Predicate::Tool(pattern.into()))
+    }
+
+    pub fn argument(self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.with(Predicate::Argument(key.into(), Some(value.into())))
+    }
+
+    pub fn content(self, text: impl Into<String>) -> Self {
+        self.with(Predicate::Content(text.into()))
+    }
+
+    pub fn content_matches(self, regex: &str) -> Result<Self> {
+        Ok(self.with(Predicate::ContentMatches(compile(regex)?)))
+    }
+
+    pub fn error_contains(self, text: impl Into<String>) -> Self {
+        self.with(Predicate::Error(text.into()))
+    }
+
+    pub fn error_category(self, category: ErrorCategory) -> Self {
+        self.with(Predicate::Category(category))
+    }
+
+    pub fn file(self, path: impl Into<String>) -> Self {
+        self.with(Predicate::File(path.into()))
+    }
+
+    pub fn since(self, time: DateTime<Utc>) -> Self {
+        self.with(Predicate::Since(time.timestamp_millis()))
+    }
+
+    pub fn until(self, time: DateTime<Utc>) -> Self {
+        self.with(Predicate::Until(time.timestamp_millis()))
+    }
+
+    /// Responses from the last `duration`
+    pub fn within(self, duration: Duration) -> Self {
+        let millis = i64::try_from(duration.as_millis()).unwrap_or(i64::MAX);
+        self.with(Predicate::Since(
+            Utc::now().timestamp_millis().saturating_sub(millis),
+        ))
+    }
+
+    pub fn and(self, other: Query) -> Self {
+        self.with(other.predicate)
+    }
+
+    pub fn or(self, other: Query) -> Self {
+        let predicate = match (self.predicate, other.predicate) {
+            (Predicate::Or(mut any), p) => {
+                any.push(p);
+                Predicate::Or(any)
+            }
+            (first, p) => Predicate::Or(vec![first, p]),
+        };
+        Self { predicate }
+    }
+
+    /// Responses the query does not match
+    pub fn negate(self) -> Self {
+        Self {
+            predicate: Predicate::Not(Box::new(self.predicate)),
+        }
+    }
+
+    pub fn matches(&self, msg: &AgentResponse) -> bool {
+        self.predicate.matches(msg)
+    }
+
+    /// Matching responses, lazily
+    pub fn filter<'a, I>(&'a self, messages: I) -> impl Iterator<Item = &'a AgentResponse> + 'a
+    where
+        I: IntoIterator<Item = &'a AgentResponse>,
+        I::IntoIter: 'a,
+    {
+        messages.into_iter().filter(move |msg| self.matches(msg))
+    }
+
+    /// Matching responses, in order
+    pub fn apply<'a>(&self, messages: &'a [AgentResponse]) -> Vec<&'a AgentResponse> {
+        messages.iter().filter(|msg| self.matches(msg)).collect()
+    }
+
+    pub fn count(&self, messages: &[AgentResponse]) -> usize {
+        messages.iter().filter(|msg| self.matches(msg)).count()
+    }
+}
+
+impl Default for Query {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<Predicate> for Query {
+    fn from(predicate: Predicate) -> Self {
+        Self { predicate }
+    }
+}
+
+impl FromStr for Query {
+    type Err = TmuxError;
+
+    fn from_str(text: &str) -> Result<Self> {
+        Self::parse(text)
+    }
+}
+
+/// Case-insensitive match where `*` stands for any run of characters
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase();
+    let text = text.to_ascii_lowercase();
+
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+
+    for part in middle {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+
+    rest.ends_with(last)
+}
+
+fn compile(regex: &str) -> Result<Regex> {
+    Regex::new(regex).map_err(|e| TmuxError::Parse(format!("Invalid regex '{}': {}", regex, e)))
+}
+
+enum Token {
+    LParen,
+    RParen,
+    And,
+    Or,
+    Not,
+    Term(Predicate),
+}
+
+struct Lexer<'a> {
+    text: &'a str,
+    pos: usize,
+    now: DateTime<Utc>,
+}
+
+impl Lexer<'_> {
+    fn tokens(mut self) -> Result<Vec<Token>> {
+        let mut tokens = Vec::new();
+
+        loop {
+            self.skip_whitespace();
+            let Some(c) = self.peek() else {
+                return Ok(tokens);
+            };
+
+            let token = match c {
+                '(' => {
+                    self.pos += 1;
+                    Token::LParen
+                }
+                ')' => {
+                    self.pos += 1;
+                    Token::RParen
+                }
+                '|' => {
+                    self.pos += if self.rest().starts_with("||") { 2 } else { 1 };
+                    Token::Or
+                }
+                '&' => {
+                    self.pos += if self.rest().starts_with("&&") { 2 } else { 1 };
+                    Token::And
+                }
+                '-' | '!' => {
+                    self.pos += 1;
+                    if self.peek().is_none_or(char::is_whitespace) {
+                        return Err(self.error("Nothing to negate"));
+                    }
+                    Token::Not
+                }
+                _ => self.term()?,
+            };
+            tokens.push(token);
+        }
+    }
+
+    fn term(&mut self) -> Result<Token> {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            self.pos += 1;
+        }
+        let key = &self.text[start..self.pos];
+
+        if !key.is_empty() {
+            match self.peek() {
+                Some(':') => {
+                    self.pos += 1;
+                    let value = self.value()?;
+                    return self.key_value(key, &value).map(Token::Term);
+                }
+                Some('~') => {
+                    self.pos += 1;
+                    let regex = self.regex()?;
+                    return self.key_regex(key, regex).map(Token::Term);
+                }
+                _ => {}
+            }
+        }
+
+        self.pos = start;
+        let quoted = matches!(self.peek(), Some('"' | '\''));
+        let word = self.value()?;
+        Ok(match word.as_str() {
+            "OR" if !quoted => Token::Or,
+            "AND" if !quoted => Token::And,
+            "NOT" if !quoted => Token::Not,
+            _ => Token::Term(Predicate::Content(word)),
+        })
+    }
+
+    /// Bare or quoted text up to whitespace or `)`. Quoted parts may sit
+    /// anywhere, as in `arg:command="cargo test"`.
+    fn value(&mut self) -> Result<String> {
+        let mut value = String::new();
+
+        while let Some(c) = self.peek() {
+            match c {
+                '"' | '\'' => {
+                    self.pos += 1;
+                    loop {
+                        let Some(next) = self.peek() else {
+                            return Err(self.error("Unterminated quote"));
+                        };
+                        self.pos += next.len_utf8();
+                        match next {
+                            '\\' => {
+                                if let Some(escaped) = self.peek() {
+                                    self.pos += escaped.len_utf8();
+                                    value.push(escaped);
+                                }
+                            }
+                            q if q == c => break,
+                            other => value.push(other),
+                        }
+                    }
+                }
+                c if c.is_whitespace() || c == ')' => break,
+                c => {
+                    self.pos += c.len_utf8();
+                    value.push(c);
+                }
+            }
+        }
+
+        Ok(value)
+    }
+
+    /// `/regex/flags`; `\/` is a literal slash
+    fn regex(&mut self) -> Result<Regex> {
+        if self.peek() != Some('/') {
+            return Err(self.error("Expected /regex/ after '~'"));
+        }
+        self.pos += 1;
+
+        let mut pattern = String::new();
+        loop {
+            let Some(c) = self.peek() else {
+                return Err(self.error("Unterminated regex"));
+            };
+            self.pos += c.len_utf8();
+            match c {
+                '/' => break,
+                '\\' if self.peek() == Some('/') => {
+                    self.pos += 1;
+                    pattern.push('/');
+                }
+                '\\' => {
+                    pattern.push('\\');
+                    if let Some(escaped) = self.peek() {
+                        self.pos += escaped.len_utf8();
+                        pattern.push(escaped);
+                    }
+                }
+                c => pattern.push(c),
+            }
+        }
+
+        let mut flags = String::new();
+        while let Some(c) = self.peek().filter(char::is_ascii_alphabetic) {
+            self.pos += 1;
+            match c {
+                'i' | 'm' | 's' | 'x' | 'U' => flags.push(c),
+                other => return Err(self.error(&format!("Unknown regex flag '{}'", other))),
+            }
+        }
+
+        if flags.is_empty() {
+            compile(&pattern)
+        } else {
+            compile(&format!("(?{}){}", flags, pattern))
+        }
+    }
+
+    fn key_value(&self, key: &str, value: &str) -> Result<Predicate> {
+        if value.is_empty() {
+            return Err(self.error(&format!("Missing value for '{}'", key)));
+        }
+
+        Ok(match key {
+            "type" => Predicate::Type(self.message_type(value)?),
+            "is" => match value {
+                "ok" | "success" | "successful" => Predicate::Success(true),
+                "failed" | "failure" | "failing" => Predicate::Success(false),
+                "error" => Predicate::HasError,
+                other => Predicate::Type(self.message_type(other)?),
+            },
+            "status" => match value {
+                "ok" | "success" => Predicate::Success(true),
+                "failed" | "error" => Predicate::Success(false),
+                other => return Err(self.error(&format!("Unknown status '{}'", other))),
+            },
+            "tool" => Predicate::Tool(value.to_string()),
+            "arg" => match value.split_once('=') {
+                Some((key, value)) => Predicate::Argument(key.to_string(), Some(value.to_string())),
+                None => Predicate::Argument(value.to_string(), None),
+            },
+            "content" => Predicate::Content(value.to_string()),
+            "error" => Predicate::Error(value.to_string()),
+            "category" => Predicate::Category(
+                value
+                    .parse()
+                    .map_err(|_| self.error(&format!("Unknown error category '{}'", value)))?,
+            ),
+            "file" => Predicate::File(value.to_string()),
+            "since" => Predicate::Since(self.time(value)?),
+            "until" => Predicate::Until(self.time(value)?),
+            other => {
+                return Err(self.error(&format!("Unknown query key '{}' (known: {})", other, KEYS)));
+            }
+        })
+    }
+
+    fn key_regex(&self, key: &str, regex: Regex) -> Result<Predicate> {
+        match key {
+            "content" => Ok(Predicate::ContentMatches(regex)),
+            "error" => Ok(Predicate::ErrorMatches(regex)),
+            other => Err(self.error(&format!(
+                "'{}' does not take a regex (use content~ or error~)",
+                other
+            ))),
+        }
+    }
+
+    fn message_type(&self, value: &str) -> Result<MessageType> {
+        match value.to_ascii_lowercase().as_str() {
+            "message" | "text" => Ok(MessageType::Message),
+            "tool_call" | "toolcall" | "tool" => Ok(MessageType::ToolCall),
+            "error" => Ok(MessageType::Error),
+            "completion" | "complete" | "done" => Ok(MessageType::Completion),
+            _ => Err(self.error(&format!(
+                "Unknown message type '{}' (known: message, tool_call, error, completion)",
+                value
+            ))),
+        }
+    }
+
+    /// Duration before now, such as `1h30m`, or an absolute timestamp, in
+    /// Unix milliseconds
+    fn time(&self, value: &str) -> Result<i64> {
+        if let Some(millis) = duration_millis(value) {
+            return Ok(self.now.timestamp_millis().saturating_sub(millis));
+        }
+
+        TimestampParser::new()
+            .with_reference(self.now)
+            .parse(value)
+            .map(|ts| ts.timestamp_millis())
+            .ok_or_else(|| self.error(&format!("Invalid time '{}'", value)))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn rest(&self) -> &str {
+        &self.text[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn error(&self, msg: &str) -> TmuxError {
+        TmuxError::Parse(format!(
+            "{} at offset {} in query '{}'",
+            msg, self.pos, self.text
+        ))
+    }
+}
+
+/// `30s`, `10m`, `1h30m`, `2d` or `1w` in milliseconds
+pub(crate) fn duration_millis(text: &str) -> Option<i64> {
+    let mut total: i64 = 0;
+    let mut rest = text;
+
+    while !rest.is_empty() {
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if digits == 0 {
+            return None;
+        }
+        let amount: i64 = rest[..digits].parse().ok()?;
+        rest = &rest[digits..];
+
+        let unit_len = rest.len()
+            - rest
+                .trim_start_matches(|c: char| c.is_ascii_alphabetic())
+                .len();
+        let unit: i64 = match &rest[..unit_len] {
+            "ms" => 1,
+            "s" | "sec" => 1_000,
+            "m" | "min" => 60_000,
+            "h" => 3_600_000,
+            "d" => 86_400_000,
+            "w" => 604_800_000,
+            _ => return None,
+        };
+        rest = &rest[unit_len..];
+        total = total.checked_add(amount.checked_mul(unit)?)?;
+    }
+
+    Some(total)
+}
+
+struct Parser {
+    tokens: Peekable<std::vec::IntoIter<Token>>,
+}
+
+impl Parser {
+    /// Lowest precedence: alternatives
+    fn or(&mut self) -> Result<Predicate> {
+        let mut any = vec![self.and()?];
+        while self.tokens.next_if(|t| matches!(t, Token::Or)).is_some() {
+            any.push(self.and()?);
+        }
+
+        Ok(if any.len() == 1 {
+            any.remove(0)
+        } else {
+            Predicate::Or(any)
+        })
+    }
+
+    /// Terms next to each other, with or without `AND`
+    fn and(&mut self) -> Result<Predicate> {
+        let mut all = vec![self.unary()?];
+        loop {
+            match self.tokens.peek() {
+                Some(Token::And) => {
+                    self.tokens.next();
+                    all.push(self.unary()?);
+                }
+                Some(Token::Term(_) | Token::Not | Token::LParen) => all.push(self.unary()?),
+                _ => break,
+            }
+        }
+
+        Ok(if all.len() == 1 {
+            all.remove(0)
+        } else {
+            Predicate::And(all)
+        })
+    }
+
+    fn unary(&mut self) -> Result<Predicate> {
+        match self.tokens.next() {
+            Some(Token::Not) => Ok(Predicate::Not(Box::new(self.unary()?))),
+            Some(Token::LParen) => {
+                let inner = self.or()?;
+                match self.tokens.next() {
+                    Some(Token::RParen) => Ok(inner),
+                    _ => Err(TmuxError::Parse("Unbalanced '(' in query".to_string())),
+                }
+            }
+            Some(Token::Term(predicate)) => Ok(predicate),
+            Some(Token::RParen) => Err(TmuxError::Parse("Unbalanced ')' in query".to_string())),
+            Some(Token::And | Token::Or) | None => {
+                Err(TmuxError::Parse("Expected a term in query".to_string()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opencode::ToolCall;
+    use chrono::TimeZone;
+
+    /// Tool call response; a failed one is an error response that keeps
+    /// its tool call, as the parser produces
+    fn tool(name: &str, command: &str, failed: bool, received_at: u64) -> AgentResponse {
+        let message_type = if failed {
+            MessageType::Error
+        } else {
+            MessageType::ToolCall
+        };
+        let mut msg = AgentResponse::fixture(message_type, command);
+        msg.tool_calls
+            .push(ToolCall::from_command_line(name, command, command));
+        if failed {
+            msg.error = Some(format!("{} failed", command));
+        }
+        msg.with_received_at(received_at)
+    }
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 2, 12, 0, 0).unwrap()
+    }
+
+    fn minutes_ago(minutes: i64) -> u64 {
+        (now().timestamp_millis() - minutes * 60_000) as u64
+    }
+
+    fn corpus() -> Vec<AgentResponse> {
+        vec![
+            tool("bash", "cargo test", true, minutes_ago(5)),
+            tool("bash", "cargo build", false, minutes_ago(30)),
+            tool("bash", "cargo test", true, minutes_ago(120)),
+            tool("read", "src/main.rs", false, minutes_ago(1)),
+            AgentResponse::fixture(MessageType::Completion, "All done")
+                .with_received_at(minutes_ago(1)),
+        ]
+    }
+
+    fn contents(query: &str) -> Vec<String> {
+        let messages = corpus();
+        Query::parse_at(query, now())
+            .unwrap()
+            .apply(&messages)
+            .iter()
+            .map(|m| m.content.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_builder() {
+        let messages = corpus();
+        let query = Query::new()
+            .tool("bash")
+            .failed()
+            .since(now() - chrono::Duration::hours(1));
+
+        let hits = query.apply(&messages);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].received_at, minutes_ago(5));
+
+        let either = Query::new()
+            .tool("read")
+            .or(Query::new().message_type(MessageType::Completion));
+        assert_eq!(either.count(&messages), 2);
+        assert_eq!(either.clone().negate().count(&messages), 3);
+    }
+
+    #[test]
+    fn test_text_syntax() {
+        assert_eq!(contents("tool:bash is:failed since:1h"), vec!["cargo test"]);
+        assert_eq!(contents("type:tool_call tool:bash"), vec!["cargo build"]);
+        assert_eq!(
+            contents("content~/cargo (test|build)/ -is:failed"),
+            vec!["cargo build"]
+        );
+        assert_eq!(
+            contents("tool:read OR type:completion"),
+            vec!["src/main.rs", "All done"]
+        );
+        assert_eq!(contents("\"All done\""), vec!["All done"]);
+        assert_eq!(contents("arg:raw_args=src/main.rs"), vec!["src/main.rs"]);
+        assert_eq!(contents("until:30m since:\"2024-01-02 10:30:00\"").len(), 1);
+    }
+
+    #[test]
+    fn test_parsed_output() {
+        let log = "[2024-01-02 10:00:00] Running: bash cargo test\n\
+                   [2024-01-02 10:00:01] test foo ... FAILED\n\
+                   [2024-01-02 10:00:02] error: test failed, to rerun pass `--lib`\n\
+                   [2024-01-02 11:30:00] Running: bash cargo build\n\
+                   [2024-01-02 11:30:05]    Finished dev profile\n\
+                   [2024-01-02 11:55:00] Running: bash cargo test\n\
+                   [2024-01-02 11:55:01] test foo ... FAILED\n\
+                   [2024-01-02 11:59:00] Running: read src/main.rs\n\
+                   [2024-01-02 11:59:30] Done.\n";
+        let mut parser = crate::messaging::stream::StreamingParser::new();
+        let mut messages = parser.feed(log);
+        messages.extend(parser.finish());
+
+        let hits = Query::parse_at("tool:bash is:failed", now())
+            .unwrap()
+            .apply(&messages);
+        assert_eq!(hits.len(), 2);
+        assert!(hits.iter().all(|m| m.message_type == MessageType::Error));
+
+        let recent = Query::parse_at("tool:bash is:failed since:1h category:test_failure", now())
+            .unwrap()
+            .apply(&messages);
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].received_at, minutes_ago(5));
+
+        let passed = Query::parse_at("tool:bash -is:failed", now())
+            .unwrap()
+            .apply(&messages);
+        assert_eq!(passed.len(), 1);
+        assert!(passed[0].content.contains("Finished"));
+    }
+
+    #[test]
+    fn test_precedence_and_grouping() {
+        // NOT binds tighter than AND, which binds tighter than OR
+        assert_eq!(contents("tool:read OR tool:bash content:build").len(), 2);
+        assert_eq!(contents("(tool:read OR tool:bash) content:build").len(), 1);
+        assert_eq!(contents("NOT (tool:bash OR tool:read)"), vec!["All done"]);
+        assert_eq!(contents("tool:bash AND !content:test"), vec!["cargo build"]);
+    }
+
+    #[test]
+    fn test_glob_and_regex_flags() {
+        assert_eq!(contents("tool:BA*").len(), 3);
+        assert_eq!(contents("tool:*ea*"), vec!["src/main.rs"]);
+        assert_eq!(contents("content~/ALL/i"), vec!["All done"]);
+        assert_eq!(contents("error~/test\\/?\\s+failed/").len(), 2);
+    }
+
+    #[test]
+    fn test_error_category() {
+        // "cargo test failed" carries no classification and is classified
+        // on the fly
+        assert_eq!(contents("category:test_failure").len(), 2);
+        assert_eq!(contents("category:api").len(), 0);
+
+        let mut messages = corpus();
+        messages[1]
+            .errors
+            .push(crate::opencode::classify_error("API Error: overloaded"));
+        assert_eq!(
+            Query::new()
+                .error_category(ErrorCategory::Api)
+                .count(&messages),
+            1
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        for query in [
+            "colour:red",
+            "type:banana",
+            "(tool:bash",
+            "tool:bash)",
+            "content~/[unclosed/",
+            "content~/x/q",
+            "since:yesterdayish",
+            "tool~/bash/",
+            "OR tool:bash",
+            "\"unterminated",
+            "- tool:bash",
+            "category:bogus",
+        ] {
+            assert!(
+                matches!(Query::parse_at(query, now()), Err(TmuxError::Parse(_))),
+                "{}",
+                query
+            );
+        }
+    }
+
+    #[test]
+    fn test_empty_query_matches_all() {
+        assert_eq!(contents("  ").len(), corpus().len());
+        assert_eq!(duration_millis("1h30m"), Some(5_400_000));
+        assert_eq!(duration_millis("10"), None);
+    }
+}
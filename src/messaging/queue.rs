@@ -1,4 +1,4 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::fs::{self, OpenOptions};
 use std::io::Write;
 use std::path::{Path, PathBuf};
@@ -171,19 +171,19 @@ impl MessageQueue {
             let path = entry.path();
             if path.extension().map(|e| e == "msg").unwrap_or(false) {
                 let content = fs::read_to_string(&path)?;
-                if let Ok(mut queued) = serde_json::from_str::<QueuedMessage>(&content) {
-                    if queued.retries < 3 {
-                        queued.retries += 1;
-                        let mut file = OpenOptions::new()
-                            .write(true)
-                            .create(true)
-                            .truncate(true)
-                            .open(&path)?;
-                        serde_json::to_writer_pretty(&mut file, &queued)?;
-                        file.flush()?;
-                        self.queue.lock().unwrap().push_back(queued);
-                        retry_count += 1;
-                    }
+                if let Ok(mut queued) = serde_json::from_str::<QueuedMessage>(&content)
+                    && queued.retries < 3
+                {
+                    queued.retries += 1;
+                    let mut file = OpenOptions::new()
+                        .write(true)
+                        .create(true)
+                        .truncate(true)
+                        .open(&path)?;
+                    serde_json::to_writer_pretty(&mut file, &queued)?;
+                    file.flush()?;
+                    self.queue.lock().unwrap().push_back(queued);
+                    retry_count += 1;
                 }
             }
         }
@@ -220,7 +220,7 @@ impl QueuedMessage {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct QueueStats {
     pub pending: usize,
     pub queued: usize,
@@ -237,15 +237,14 @@ impl MessageQueue {
         for entry in fs::read_dir(queue_dir)? {
             let entry = entry?;
             let path = entry.path();
-            if path.extension().map(|e| e == "msg").unwrap_or(false) {
-                if let Ok(content) = fs::read_to_string(&path) {
-                    if let Ok(queued_msg) = serde_json::from_str::<QueuedMessage>(&content) {
-                        if queued_msg.retries >= 3 {
-                            failed += 1;
-                        } else {
-                            queued += 1;
-                        }
-                    }
+            if path.extension().map(|e| e == "msg").unwrap_or(false)
+                && let Ok(content) = fs::read_to_string(&path)
+                && let Ok(queued_msg) = serde_json::from_str::<QueuedMessage>(&content)
+            {
+                if queued_msg.retries >= 3 {
+                    failed += 1;
+                } else {
+                    queued += 1;
                 }
             }
         }
@@ -256,6 +255,145 @@ impl MessageQueue {
             failed,
         })
     }
+
+    /// Messages on disk still waiting for delivery, by pane id
+    pub fn depth_by_pane(&self) -> Result<HashMap<String, usize>> {
+        let mut depth = HashMap::new();
+
+        for entry in fs::read_dir(self.queue_dir())? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|e| e != "msg") {
+                continue;
+            }
+            let Ok(content) = fs::read_to_string(&path) else {
+                continue;
+            };
+            if let Ok(queued_msg) = serde_json::from_str::<QueuedMessage>(&content)
+                && queued_msg.should_retry()
+            {
+                *depth.entry(queued_msg.message.pane_id.0).or_insert(0) += 1;
+            }
+        }
+
+        Ok(depth)
+    }
+
+    /// Every message on disk, oldest first, including failed ones
+    pub fn messages(&self) -> Result<Vec<QueuedMessage>> {
+        let mut messages = Vec::new();
+
+        for entry in fs::read_dir(self.queue_dir())? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|e| e != "msg") {
+                continue;
+            }
+            let Ok(content) = fs::read_to_string(&path) else {
+                continue;
+            };
+            if let Ok(queued_msg) = serde_json::from_str::<QueuedMessage>(&content) {
+                messages.push(queued_msg);
+            }
+        }
+
+        messages.sort_by(|a, b| {
+            a.queued_at
+                .cmp(&b.queued_at)
+                .then_with(|| a.message.id.cmp(&b.message.id))
+        });
+        Ok(messages)
+    }
+
+    /// Give messages that ran out of retries a fresh set. Returns how many
+    /// were requeued.
+    pub fn requeue_failed(&self) -> Result<usize> {
+        let mut requeued = 0;
+
+        for mut queued in self.messages()? {
+            if queued.should_retry() {
+                continue;
+            }
+            queued.retries = 0;
+            let path = self.message_file(&SessionId(queued.message.pane_id.0.clone()), queued.id());
+            let mut file = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&path)?;
+            serde_json::to_writer_pretty(&mut file, &queued)?;
+            file.flush()?;
+            self.queue.lock().unwrap().push_back(queued);
+            requeued += 1;
+        }
+
+        Ok(requeued)
+    }
+
+    /// Hand every message on disk that may still be retried to `deliver`,
+    /// oldest first. Delivered messages are removed; a failed delivery
+    /// counts as a retry and the message stays queued.
+    pub fn drain<F>(&self, mut deliver: F) -> Result<DrainReport>
+    where
+        F: FnMut(&QueuedMessage) -> Result<()>,
+    {
+        let mut report = DrainReport::default();
+
+        for mut queued in self.messages()? {
+            if !queued.should_retry() {
+                report.skipped += 1;
+                continue;
+            }
+
+            let path = self.message_file(&SessionId(queued.message.pane_id.0.clone()), queued.id());
+            match deliver(&queued) {
+                Ok(()) => {
+                    if path.exists() {
+                        fs::remove_file(&path)?;
+                    }
+                    report.delivered.push(queued.message.id.clone());
+                }
+                Err(e) => {
+                    queued.retries += 1;
+                    let mut file = OpenOptions::new()
+                        .write(true)
+                        .create(true)
+                        .truncate(true)
+                        .open(&path)?;
+                    serde_json::to_writer_pretty(&mut file, &queued)?;
+                    file.flush()?;
+                    report.failed.push(DeliveryFailure {
+                        id: queued.message.id.clone(),
+                        error: e.to_string(),
+                    });
+                }
+            }
+        }
+
+        let delivered = &report.delivered;
+        self.queue
+            .lock()
+            .unwrap()
+            .retain(|queued| !delivered.contains(&queued.message.id));
+
+        Ok(report)
+    }
+}
+
+/// Outcome of [`MessageQueue::drain`]
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct DrainReport {
+    /// Ids of delivered messages
+    pub delivered: Vec<String>,
+    /// Messages whose delivery failed this time
+    pub failed: Vec<DeliveryFailure>,
+    /// Messages left alone because they ran out of retries
+    pub skipped: usize,
+}
+
+/// A message that could not be delivered, and why
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct DeliveryFailure {
+    pub id: String,
+    pub error: String,
 }
 
 #[cfg(test)]
@@ -314,4 +452,103 @@ mod tests {
         let stats = queue.stats().unwrap();
         assert_eq!(stats.pending, 1);
     }
+
+    #[test]
+    fn test_depth_by_pane() {
+        let temp_dir = TempDir::new().unwrap();
+        let queue = MessageQueue::new(temp_dir.path()).unwrap();
+
+        for (id, pane) in [("a", "%1"), ("b", "%1"), ("c", "%2")] {
+            queue
+                .enqueue_message(Message {
+                    id: id.to_string(),
+                    pane_id: PaneId(pane.to_string()),
+                    content: "Test message".to_string(),
+                    timestamp: 0,
+                })
+                .unwrap();
+        }
+
+        let depth = queue.depth_by_pane().unwrap();
+        assert_eq!(depth.get("%1"), Some(&2));
+        assert_eq!(depth.get("%2"), Some(&1));
+        assert_eq!(depth.get("%3"), None);
+    }
+
+    #[test]
+    fn test_messages_survive_a_new_queue() {
+        let temp_dir = TempDir::new().unwrap();
+        let queue = MessageQueue::new(temp_dir.path()).unwrap();
+        queue
+            .send_message(&SessionId("work".to_string()), "first")
+            .unwrap();
+
+        let reopened = MessageQueue::new(temp_dir.path()).unwrap();
+        let messages = reopened.messages().unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].message.content, "first");
+        assert!(reopened.is_empty());
+    }
+
+    #[test]
+    fn test_drain() {
+        let temp_dir = TempDir::new().unwrap();
+        let queue = MessageQueue::new(temp_dir.path()).unwrap();
+
+        for (id, content) in [("a", "deliver me"), ("b", "fail me")] {
+            queue
+                .enqueue_message(Message {
+                    id: id.to_string(),
+                    pane_id: PaneId("%1".to_string()),
+                    content: content.to_string(),
+                    timestamp: 0,
+                })
+                .unwrap();
+        }
+
+        let report = queue
+            .drain(|queued| match queued.message.content.as_str() {
+                "deliver me" => Ok(()),
+                _ => Err(TmuxError::Command("pane gone".to_string())),
+            })
+            .unwrap();
+
+        assert_eq!(report.delivered, vec!["a".to_string()]);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].id, "b");
+
+        let left = queue.messages().unwrap();
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0].retries, 1);
+        assert_eq!(queue.len(), 1);
+    }
+
+    #[test]
+    fn test_drain_skips_exhausted_messages() {
+        let temp_dir = TempDir::new().unwrap();
+        let queue = MessageQueue::new(temp_dir.path()).unwrap();
+        queue
+            .enqueue_message(Message {
+                id: "a".to_string(),
+                pane_id: PaneId("%1".to_string()),
+                content: "never".to_string(),
+                timestamp: 0,
+            })
+            .unwrap();
+
+        for _ in 0..3 {
+            queue
+                .drain(|_| Err(TmuxError::Command("pane gone".to_string())))
+                .unwrap();
+        }
+        let report = queue.drain(|_| Ok(())).unwrap();
+
+        assert!(report.delivered.is_empty());
+        assert_eq!(report.skipped, 1);
+        assert_eq!(queue.stats().unwrap().failed, 1);
+
+        assert_eq!(queue.requeue_failed().unwrap(), 1);
+        let report = queue.drain(|_| Ok(())).unwrap();
+        assert_eq!(report.delivered, vec!["a".to_string()]);
+    }
 }
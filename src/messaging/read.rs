@@ -1,10 +1,29 @@
 use crate::error::TmuxError;
+use crate::messaging::log_index::LineIndex;
+use crate::messaging::rotate::log_segments;
+use crate::messaging::search::{LogSource, TermIndex};
+use crate::messaging::stream::StreamingParser;
+use crate::messaging::watch::{self, LogLine, WatchEnd, WatchHandle, WatchOptions, WatchStart};
+use crate::opencode::{AgentResponse, DialectRegistry};
 use crate::types::{PaneId, SessionId};
+use serde::{Deserialize, Serialize};
 use std::fs::{self, File};
-use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
-
-const LOG_DIR: &str = "/tmp/tmux_logs";
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+
+pub(crate) const LOG_DIR: &str = "/tmp/tmux_logs";
+/// Block size for reading a log backwards
+const TAIL_CHUNK: u64 = 64 * 1024;
+
+/// Position in the active log: a byte offset at the start of a line, and
+/// that line's number. Cursors are plain data, so callers can persist one
+/// and resume reading where they stopped.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogCursor {
+    pub offset: u64,
+    pub line: u64,
+}
 
 pub struct LogReader {
     log_dir: PathBuf,
@@ -21,39 +40,119 @@ impl LogReader {
         LogReader { log_dir }
     }
 
+    /// Whole log, including rotated segments
     pub fn read_log(&self, session_id: &SessionId) -> Result<String, TmuxError> {
+        let mut bytes = Vec::new();
+        self.open_history(session_id)?
+            .read_to_end(&mut bytes)
+            .map_err(|e| TmuxError::Command(format!("Failed to read log file: {}", e)))?;
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    /// Lines of the log, including rotated segments. Bytes that are not
+    /// UTF-8 become U+FFFD.
+    pub fn read_log_lines(&self, session_id: &SessionId) -> Result<Vec<String>, TmuxError> {
+        let mut reader = BufReader::new(self.open_history(session_id)?);
+        let mut buf = Vec::new();
+        let mut lines = Vec::new();
+        while reader.read_until(b'\n', &mut buf)? > 0 {
+            lines.push(line_text(&buf));
+            buf.clear();
+        }
+        Ok(lines)
+    }
+
+    /// Lines of the active log from line `offset` (0-based) on, including
+    /// an unterminated last line. Seeks through the line index instead of
+    /// reading the lines before `offset`.
+    pub fn read_log_from(
+        &self,
+        session_id: &SessionId,
+        offset: usize,
+    ) -> Result<Vec<String>, TmuxError> {
         let log_path = self.session_log_path(session_id);
 
         if !log_path.exists() {
-            return Ok(String::new());
+            return Ok(Vec::new());
         }
 
-        Ok(fs::read_to_string(&log_path)
-            .map_err(|e| TmuxError::Command(format!("Failed to read log file: {}", e)))?)
+        let cursor = match offset {
+            0 => LogCursor::default(),
+            _ => self.cursor_at_line(session_id, offset as u64)?,
+        };
+        // Past the last complete line, only an unterminated line is left
+        let skip = offset.saturating_sub(cursor.line as usize);
+        let lines = read_lines_at(&log_path, cursor.offset, true)?.0;
+        Ok(lines.into_iter().skip(skip).collect())
     }
 
-    pub fn read_log_lines(&self, session_id: &SessionId) -> Result<Vec<String>, TmuxError> {
+    /// Complete lines after `cursor`, and the cursor to resume from. An
+    /// unterminated last line is left for the next call. A cursor past the
+    /// end of the log means it was truncated, and reading restarts at the
+    /// top.
+    pub fn read_from(
+        &self,
+        session_id: &SessionId,
+        cursor: LogCursor,
+    ) -> Result<(Vec<String>, LogCursor), TmuxError> {
         let log_path = self.session_log_path(session_id);
 
         if !log_path.exists() {
-            return Ok(Vec::new());
+            return Ok((Vec::new(), LogCursor::default()));
         }
 
-        let file = File::open(&log_path)?;
-        let reader = BufReader::new(file);
-        Ok(reader
-            .lines()
-            .collect::<Result<Vec<_>, _>>()
-            .map_err(|e| TmuxError::Command(format!("Failed to read log lines: {}", e)))?)
+        let cursor = if cursor.offset > self.get_log_size(session_id)? {
+            LogCursor::default()
+        } else {
+            cursor
+        };
+
+        let (lines, end) = read_lines_at(&log_path, cursor.offset, false)?;
+        let next = LogCursor {
+            offset: end,
+            line: cursor.line + lines.len() as u64,
+        };
+        Ok((lines, next))
     }
 
-    pub fn read_log_from(
+    /// Cursor at the start of line `line` (0-based), found through the
+    /// sparse line index. Past the last complete line, the cursor is at
+    /// the end of it.
+    pub fn cursor_at_line(
         &self,
         session_id: &SessionId,
-        offset: usize,
-    ) -> Result<Vec<String>, TmuxError> {
-        let lines = self.read_log_lines(session_id)?;
-        Ok(lines.into_iter().skip(offset).collect())
+        line: u64,
+    ) -> Result<LogCursor, TmuxError> {
+        cursor_in(&self.session_log_path(session_id), line)
+    }
+
+    /// Cursor after the last complete line, to read only what is appended
+    /// from now on
+    pub fn cursor_at_end(&self, session_id: &SessionId) -> Result<LogCursor, TmuxError> {
+        self.cursor_at_line(session_id, u64::MAX)
+    }
+
+    /// Parse a whole session log into responses, detecting the dialect
+    /// unless one is given. Lines without a capture timestamp leave
+    /// `received_at` at 0, since when they were written is unknown.
+    pub fn read_responses(
+        &self,
+        session_id: &SessionId,
+        dialect: Option<&str>,
+    ) -> Result<Vec<AgentResponse>, TmuxError> {
+        let output = self.read_log(session_id)?;
+        let parser = match dialect {
+            Some(name) => StreamingParser::for_dialect(name)?,
+            None => match DialectRegistry::builtin().detect(&output) {
+                Some(protocol) => StreamingParser::with_protocol(protocol),
+                None => return Ok(Vec::new()),
+            },
+        };
+
+        let mut parser = parser.with_received_at(0);
+        let mut responses = parser.feed(&output);
+        responses.extend(parser.finish());
+        Ok(responses)
     }
 
     pub fn read_pane_output(&self, pane_id: &PaneId) -> Result<String, TmuxError> {
@@ -63,40 +162,57 @@ impl LogReader {
             return Ok(String::new());
         }
 
-        Ok(fs::read_to_string(&pane_log_path)
-            .map_err(|e| TmuxError::Command(format!("Failed to read pane log: {}", e)))?)
+        fs::read(&pane_log_path)
+            .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+            .map_err(|e| TmuxError::Command(format!("Failed to read pane log: {}", e)))
     }
 
-    pub fn watch_log<F>(&self, session_id: &SessionId, callback: F) -> Result<(), TmuxError>
+    /// Follow the log on a background thread, like `tail -F`. Complete
+    /// lines go to `callback` with their offsets; the log may not exist
+    /// yet, and truncation, rotation and replacement are followed. Cancel
+    /// through the returned handle, or set a timeout in `options`.
+    pub fn watch_log<F>(
+        &self,
+        session_id: &SessionId,
+        options: WatchOptions,
+        callback: F,
+    ) -> Result<WatchHandle, TmuxError>
     where
-        F: Fn(&str) + Send + Sync,
+        F: FnMut(LogLine) + Send + 'static,
     {
-        let log_path = self.session_log_path(session_id);
-
-        if !log_path.exists() {
-            return Err(TmuxError::NotFound(format!(
-                "Log file does not exist: {:?}",
-                log_path
-            )));
-        }
+        self.watch(&LogSource::Session(session_id.clone()), options, callback)
+    }
 
-        let file = File::open(&log_path)?;
-        let mut reader = BufReader::new(file);
+    /// Like [`watch_log`](Self::watch_log), for a session or pane log
+    pub fn watch<F>(
+        &self,
+        source: &LogSource,
+        options: WatchOptions,
+        callback: F,
+    ) -> Result<WatchHandle, TmuxError>
+    where
+        F: FnMut(LogLine) + Send + 'static,
+    {
+        let log_path = self.log_path(source);
+        let start = watch_start(&log_path, &options)?;
+        WatchHandle::spawn(log_path, start, options, callback)
+    }
 
-        loop {
-            let mut line = String::new();
-            match reader.read_line(&mut line) {
-                Ok(0) => {
-                    std::thread::sleep(std::time::Duration::from_millis(100));
-                }
-                Ok(_) => {
-                    callback(&line);
-                }
-                Err(e) => {
-                    return Err(TmuxError::Command(format!("Failed to read log: {}", e)));
-                }
-            }
-        }
+    /// Follow the log on the calling thread until `stop` is set or the
+    /// timeout in `options` elapses
+    pub fn follow_log<F>(
+        &self,
+        session_id: &SessionId,
+        options: &WatchOptions,
+        stop: &AtomicBool,
+        callback: F,
+    ) -> Result<WatchEnd, TmuxError>
+    where
+        F: FnMut(LogLine),
+    {
+        let log_path = self.session_log_path(session_id);
+        let start = watch_start(&log_path, options)?;
+        watch::follow(&log_path, start, options, stop, callback)
     }
 
     pub fn get_log_size(&self, session_id: &SessionId) -> Result<u64, TmuxError> {
@@ -106,10 +222,10 @@ impl LogReader {
             return Ok(0);
         }
 
-        Ok(log_path
+        log_path
             .metadata()
             .map(|m| m.len())
-            .map_err(|e| TmuxError::Command(format!("Failed to get log size: {}", e)))?)
+            .map_err(|e| TmuxError::Command(format!("Failed to get log size: {}", e)))
     }
 
     pub fn get_log_timestamp(&self, session_id: &SessionId) -> Result<u64, TmuxError> {
@@ -129,15 +245,49 @@ impl LogReader {
             .map(|d| d.as_secs())
     }
 
+    /// Last `n` lines. The active log is read backwards from the end, so
+    /// the cost depends on `n` and not on the size of the log. Rotated
+    /// segments are only read when the active log is shorter than `n`
+    /// lines.
     pub fn tail_log(&self, session_id: &SessionId, n: usize) -> Result<Vec<String>, TmuxError> {
-        let lines = self.read_log_lines(session_id)?;
-        let len = lines.len();
+        self.tail(&LogSource::Session(session_id.clone()), n)
+    }
+
+    /// Like [`tail_log`](Self::tail_log), for a session or pane log
+    pub fn tail(&self, source: &LogSource, n: usize) -> Result<Vec<String>, TmuxError> {
+        let log_path = self.log_path(source);
 
-        if n >= len {
+        if n == 0 {
+            return Ok(Vec::new());
+        }
+
+        let lines = match log_path.exists() {
+            true => read_lines_at(&log_path, tail_start(&log_path, n)?, true)?.0,
+            false => Vec::new(),
+        };
+        let segments = log_segments(&log_path)?;
+        if lines.len() >= n || segments.is_empty() {
             return Ok(lines);
         }
 
-        Ok(lines.into_iter().skip(len - n).collect())
+        // Prepend segments, newest first, until the first of the last `n`
+        // lines is known to start after a line break
+        let mut bytes = match log_path.exists() {
+            true => fs::read(&log_path)?,
+            false => Vec::new(),
+        };
+        for segment in &segments {
+            let mut older = segment.read()?;
+            older.extend_from_slice(&bytes);
+            bytes = older;
+            let body = bytes.strip_suffix(b"\n").unwrap_or(&bytes);
+            if body.iter().filter(|b| **b == b'\n').count() >= n {
+                break;
+            }
+        }
+
+        let lines = split_lines(&bytes);
+        Ok(lines[lines.len().saturating_sub(n)..].to_vec())
     }
 
     pub fn search_log(
@@ -145,16 +295,24 @@ impl LogReader {
         session_id: &SessionId,
         pattern: &str,
     ) -> Result<Vec<String>, TmuxError> {
-        let lines = self.read_log_lines(session_id)?;
+        let mut reader = BufReader::new(self.open_history(session_id)?);
+        let mut buf = Vec::new();
+        let mut matches = Vec::new();
+        while reader.read_until(b'\n', &mut buf)? > 0 {
+            let line = line_text(&buf);
+            if line.contains(pattern) {
+                matches.push(line);
+            }
+            buf.clear();
+        }
 
-        Ok(lines
-            .into_iter()
-            .filter(|line| line.contains(pattern))
-            .collect())
+        Ok(matches)
     }
 
+    /// Empty the active log and remove its rotated segments
     pub fn clear_log(&self, session_id: &SessionId) -> Result<(), TmuxError> {
         let log_path = self.session_log_path(session_id);
+        self.remove_history(&log_path)?;
 
         fs::write(&log_path, "")
             .map_err(|e| TmuxError::Command(format!("Failed to clear log: {}", e)))
@@ -162,6 +320,7 @@ impl LogReader {
 
     pub fn delete_log(&self, session_id: &SessionId) -> Result<(), TmuxError> {
         let log_path = self.session_log_path(session_id);
+        self.remove_history(&log_path)?;
 
         if !log_path.exists() {
             return Ok(());
@@ -182,16 +341,17 @@ impl LogReader {
         let mut sessions = Vec::new();
 
         for entry in entries.flatten() {
-            if let Ok(name) = entry.file_name().into_string() {
-                if name.starts_with("session_") && name.ends_with(".log") {
-                    let session_id = name
-                        .strip_prefix("session_")
-                        .and_then(|s| s.strip_suffix(".log"))
-                        .map(String::from);
-
-                    if let Some(id) = session_id {
-                        sessions.push(SessionId(id));
-                    }
+            if let Ok(name) = entry.file_name().into_string()
+                && name.starts_with("session_")
+                && name.ends_with(".log")
+            {
+                let session_id = name
+                    .strip_prefix("session_")
+                    .and_then(|s| s.strip_suffix(".log"))
+                    .map(String::from);
+
+                if let Some(id) = session_id {
+                    sessions.push(SessionId(id));
                 }
             }
         }
@@ -199,13 +359,49 @@ impl LogReader {
         Ok(sessions)
     }
 
-    fn session_log_path(&self, session_id: &SessionId) -> PathBuf {
+    /// Rotated segments, oldest first, then the active log, as one stream
+    fn open_history(&self, session_id: &SessionId) -> Result<Box<dyn Read>, TmuxError> {
+        let log_path = self.session_log_path(session_id);
+        let mut stream: Box<dyn Read> = Box::new(io::empty());
+
+        for segment in log_segments(&log_path)?.iter().rev() {
+            stream = Box::new(stream.chain(segment.open()?));
+        }
+        if log_path.exists() {
+            stream = Box::new(stream.chain(File::open(&log_path)?));
+        }
+
+        Ok(stream)
+    }
+
+    /// Remove a log's rotated segments and its indexes
+    fn remove_history(&self, log_path: &Path) -> Result<(), TmuxError> {
+        let _ = fs::remove_file(LineIndex::sidecar_path(log_path));
+        let _ = fs::remove_file(TermIndex::sidecar_path(log_path));
+        for segment in log_segments(log_path)? {
+            fs::remove_file(&segment.path)
+                .map_err(|e| TmuxError::Command(format!("Failed to remove log segment: {}", e)))?;
+            let _ = fs::remove_file(TermIndex::sidecar_path(&segment.path));
+        }
+        Ok(())
+    }
+
+    /// Path of a session's active log
+    pub fn session_log_path(&self, session_id: &SessionId) -> PathBuf {
         self.log_dir.join(format!("session_{}.log", session_id.0))
     }
 
     fn pane_log_path(&self, pane_id: &PaneId) -> PathBuf {
         self.log_dir.join(format!("pane_{}.log", pane_id.0))
     }
+
+    /// Path of a session or pane log
+    pub fn log_path(&self, source: &LogSource) -> PathBuf {
+        match source {
+            LogSource::Session(id) => self.session_log_path(id),
+            LogSource::Pane(id) => self.pane_log_path(id),
+        }
+    }
 }
 
 impl Default for LogReader {
@@ -213,3 +409,282 @@ impl Default for LogReader {
         Self::new()
     }
 }
+
+/// Cursor at the start of line `line` of the log at `log_path`, found
+/// through its sparse line index
+fn cursor_in(log_path: &Path, line: u64) -> Result<LogCursor, TmuxError> {
+    if !log_path.exists() {
+        return Ok(LogCursor::default());
+    }
+
+    let index = LineIndex::for_log(log_path)?;
+    if line >= index.line_count() {
+        return Ok(LogCursor {
+            offset: index.indexed_bytes(),
+            line: index.line_count(),
+        });
+    }
+
+    let (mut offset, mut current) = index.seek(line);
+    let mut reader = BufReader::new(File::open(log_path)?);
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut buf = Vec::new();
+    while current < line {
+        buf.clear();
+        offset += reader.read_until(b'\n', &mut buf)? as u64;
+        current += 1;
+    }
+
+    Ok(LogCursor {
+        offset,
+        line: current,
+    })
+}
+
+fn watch_start(log_path: &Path, options: &WatchOptions) -> Result<LogCursor, TmuxError> {
+    match options.start {
+        WatchStart::Beginning => Ok(LogCursor::default()),
+        WatchStart::End => cursor_in(log_path, u64::MAX),
+        WatchStart::Tail(n) => {
+            if !log_path.exists() {
+                return Ok(LogCursor::default());
+            }
+            let lines = LineIndex::for_log(log_path)?.line_count();
+            cursor_in(log_path, lines.saturating_sub(n))
+        }
+        WatchStart::Cursor(cursor) => Ok(cursor),
+    }
+}
+
+/// Byte offset where the last `n` lines of a log start. A trailing
+/// newline ends the last line rather than starting an empty one.
+fn tail_start(path: &Path, n: usize) -> Result<u64, TmuxError> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut buf = vec![0; TAIL_CHUNK as usize];
+    let mut end = len;
+    let mut newlines = 0;
+    let mut skip_final = true;
+
+    while end > 0 {
+        let start = end.saturating_sub(TAIL_CHUNK);
+        let block = &mut buf[..(end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(block)?;
+
+        for (i, byte) in block.iter().enumerate().rev() {
+            if *byte != b'\n' {
+                skip_final = false;
+                continue;
+            }
+            if std::mem::take(&mut skip_final) {
+                continue;
+            }
+            newlines += 1;
+            if newlines == n {
+                return Ok(start + i as u64 + 1);
+            }
+        }
+        end = start;
+    }
+
+    Ok(0)
+}
+
+/// Lines from byte `offset` to the end, and the offset after the last line
+/// returned. An unterminated last line is only included when `partial` is
+/// set.
+fn read_lines_at(path: &Path, offset: u64, partial: bool) -> Result<(Vec<String>, u64), TmuxError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    reader.seek(SeekFrom::Start(offset))?;
+
+    let mut lines = Vec::new();
+    let mut end = offset;
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        let read = reader.read_until(b'\n', &mut buf)?;
+        if read == 0 || (!partial && !buf.ends_with(b"\n")) {
+            break;
+        }
+        end += read as u64;
+        lines.push(line_text(&buf));
+    }
+
+    Ok((lines, end))
+}
+
+/// Lines of a whole buffer, as `str::lines` splits them
+fn split_lines(bytes: &[u8]) -> Vec<String> {
+    let body = bytes.strip_suffix(b"\n").unwrap_or(bytes);
+    if body.is_empty() && bytes.is_empty() {
+        return Vec::new();
+    }
+    body.split(|b| *b == b'\n').map(line_text).collect()
+}
+
+/// Text of a raw line without its line ending. Bytes that are not UTF-8
+/// become U+FFFD.
+pub(crate) fn line_text(bytes: &[u8]) -> String {
+    let bytes = bytes.strip_suffix(b"\n").unwrap_or(bytes);
+    let bytes = bytes.strip_suffix(b"\r").unwrap_or(bytes);
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn reader_with(content: &[u8]) -> (tempfile::TempDir, LogReader, SessionId) {
+        let dir = tempfile::tempdir().unwrap();
+        let session_id = SessionId("$1".to_string());
+        let reader = LogReader::with_dir(dir.path().to_path_buf());
+        fs::write(reader.session_log_path(&session_id), content).unwrap();
+        (dir, reader, session_id)
+    }
+
+    fn numbered(count: usize) -> String {
+        (0..count).map(|i| format!("line {}\n", i)).collect()
+    }
+
+    #[test]
+    fn test_tail_reads_backwards() {
+        // Several read blocks, with and without a trailing newline
+        let content = numbered(20_000);
+        let (_dir, reader, id) = reader_with(content.as_bytes());
+        assert_eq!(
+            reader.tail_log(&id, 2).unwrap(),
+            vec!["line 19998", "line 19999"]
+        );
+        assert_eq!(reader.tail_log(&id, 15_000).unwrap()[0], "line 5000");
+        assert_eq!(reader.tail_log(&id, 50_000).unwrap().len(), 20_000);
+        assert!(reader.tail_log(&id, 0).unwrap().is_empty());
+
+        let (_dir, reader, id) = reader_with(b"a\r\nb\r\nunterminated");
+        assert_eq!(reader.tail_log(&id, 2).unwrap(), vec!["b", "unterminated"]);
+    }
+
+    #[test]
+    fn test_read_from_line_uses_index() {
+        let (_dir, reader, id) = reader_with(numbered(5000).as_bytes());
+        let log_path = reader.session_log_path(&id);
+
+        let lines = reader.read_log_from(&id, 4098).unwrap();
+        assert_eq!(lines.len(), 902);
+        assert_eq!(lines[0], "line 4098");
+        assert!(LineIndex::sidecar_path(&log_path).exists());
+        assert!(reader.read_log_from(&id, 5000).unwrap().is_empty());
+
+        let cursor = reader.cursor_at_line(&id, 3000).unwrap();
+        assert_eq!(cursor.line, 3000);
+        assert_eq!(cursor.offset, numbered(3000).len() as u64);
+
+        reader.delete_log(&id).unwrap();
+        assert!(!LineIndex::sidecar_path(&log_path).exists());
+    }
+
+    #[test]
+    fn test_cursor_resumes_and_survives_truncation() {
+        let (_dir, reader, id) = reader_with(b"one\ntwo\npart");
+        let log_path = reader.session_log_path(&id);
+
+        let (lines, cursor) = reader.read_from(&id, LogCursor::default()).unwrap();
+        assert_eq!(lines, vec!["one", "two"]);
+        assert_eq!(cursor, LogCursor { offset: 8, line: 2 });
+
+        // Cursors round-trip through JSON for persisting between runs
+        let saved = serde_json::to_string(&cursor).unwrap();
+        let cursor: LogCursor = serde_json::from_str(&saved).unwrap();
+
+        let mut file = fs::OpenOptions::new().append(true).open(&log_path).unwrap();
+        file.write_all(b"ial\nthree\n").unwrap();
+        let (lines, cursor) = reader.read_from(&id, cursor).unwrap();
+        assert_eq!(lines, vec!["partial", "three"]);
+        assert_eq!(cursor.line, 4);
+        assert_eq!(reader.cursor_at_end(&id).unwrap(), cursor);
+
+        reader.clear_log(&id).unwrap();
+        fs::write(&log_path, "fresh\n").unwrap();
+        let (lines, cursor) = reader.read_from(&id, cursor).unwrap();
+        assert_eq!(lines, vec!["fresh"]);
+        assert_eq!(cursor, LogCursor { offset: 6, line: 1 });
+    }
+
+    #[test]
+    fn test_reads_across_rotated_segments() {
+        use crate::messaging::rotate::{LogRotator, RotationPolicy};
+
+        let (dir, reader, id) = reader_with(b"one\ntwo\nthr");
+        let log_path = reader.session_log_path(&id);
+        let rotator = LogRotator::with_dir(dir.path().to_path_buf(), RotationPolicy::new());
+        rotator.rotate(&id).unwrap();
+        fs::write(&log_path, "ee\nfour\n").unwrap();
+        let plain = RotationPolicy::new().with_compress(false);
+        LogRotator::with_dir(dir.path().to_path_buf(), plain)
+            .rotate(&id)
+            .unwrap();
+        fs::write(&log_path, "five\n").unwrap();
+
+        // A line split by rotation is joined again
+        assert_eq!(
+            reader.read_log(&id).unwrap(),
+            "one\ntwo\nthree\nfour\nfive\n"
+        );
+        assert_eq!(reader.read_log_lines(&id).unwrap().len(), 5);
+        assert_eq!(reader.search_log(&id, "thr").unwrap(), vec!["three"]);
+        assert_eq!(reader.tail_log(&id, 1).unwrap(), vec!["five"]);
+        assert_eq!(
+            reader.tail_log(&id, 3).unwrap(),
+            vec!["three", "four", "five"]
+        );
+        assert_eq!(reader.tail_log(&id, 10).unwrap().len(), 5);
+
+        // The active log alone for line numbers and cursors
+        assert_eq!(reader.read_log_from(&id, 0).unwrap(), vec!["five"]);
+
+        reader.clear_log(&id).unwrap();
+        assert!(reader.read_log(&id).unwrap().is_empty());
+        assert!(
+            crate::messaging::rotate::log_segments(&log_path)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_tolerates_invalid_utf8() {
+        let (_dir, reader, id) = reader_with(b"ok\nbad \xff\xfe byte\n\x1b[31mred\x1b[0m\n");
+
+        let lines = reader.read_log_lines(&id).unwrap();
+        assert_eq!(lines[1], "bad \u{fffd}\u{fffd} byte");
+        assert_eq!(reader.search_log(&id, "byte").unwrap().len(), 1);
+        assert_eq!(reader.tail_log(&id, 1).unwrap(), vec!["\x1b[31mred\x1b[0m"]);
+        assert!(reader.read_log(&id).unwrap().contains("bad \u{fffd}"));
+    }
+
+    #[test]
+    fn test_watch_pane_log_from_tail() {
+        let dir = tempfile::tempdir().unwrap();
+        let reader = LogReader::with_dir(dir.path().to_path_buf());
+        let source = LogSource::Pane(PaneId("%3".to_string()));
+        fs::write(reader.log_path(&source), numbered(300) + "partial").unwrap();
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let options = WatchOptions::new()
+            .from_tail(2)
+            .with_timeout(std::time::Duration::from_millis(300));
+        let handle = reader
+            .watch(&source, options, move |line| {
+                sender.send(line.text).unwrap()
+            })
+            .unwrap();
+        let end = handle.join().unwrap();
+
+        // Complete lines only; the partial one is still being written
+        let lines: Vec<String> = receiver.iter().collect();
+        assert_eq!(lines, vec!["line 298", "line 299"]);
+        assert!(end.timed_out);
+        assert_eq!(end.cursor.line, 300);
+    }
+}
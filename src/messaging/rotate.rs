@@ -0,0 +1,555 @@
+//! Log Rotation Module
+//!
+//! Size- and time-based rotation of session logs into numbered segments
+//! (`session_<id>.log.1.gz` is the newest), and retention by segment count,
+//! age or total size. Rotation copies the active log and truncates it in
+//! place, like logrotate's `copytruncate`, so a writer that keeps the log
+//! open in append mode (tmux `pipe-pane 'cat >> ...'`) carries on writing
+//! to the same file. Logs are rotated when `maintain`, `maintain_all` or
+//! `rotate` is called: once by `output rotate`, and after every pass by
+//! `sync --daemon` given rotation limits. Each new segment gets its own term
+//! index (see `TermIndex::for_segment`), so searches do not decompress it
+//! again.
+
+use crate::error::TmuxError;
+use crate::messaging::log_index::LineIndex;
+use crate::messaging::query::duration_millis;
+use crate::messaging::read::LogReader;
+use crate::messaging::search::TermIndex;
+use crate::types::SessionId;
+use flate2::Compression;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// When to rotate a log and which rotated segments to keep. Every limit is
+/// off by default; rotated segments are gzip-compressed unless
+/// `with_compress(false)` is set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RotationPolicy {
+    /// Rotate once the active log reaches this many bytes
+    pub max_size: Option<u64>,
+    /// Rotate once the active log has been written for this long
+    pub max_age: Option<Duration>,
+    pub compress: bool,
+    /// Rotated segments to keep
+    pub max_segments: Option<usize>,
+    /// Remove rotated segments older than this
+    pub retain_for: Option<Duration>,
+    /// Remove the oldest segments until the active log and its segments fit
+    pub max_total_size: Option<u64>,
+}
+
+impl RotationPolicy {
+    pub fn new() -> Self {
+        Self {
+            max_size: None,
+            max_age: None,
+            compress: true,
+            max_segments: None,
+            retain_for: None,
+            max_total_size: None,
+        }
+    }
+
+    pub fn with_max_size(mut self, bytes: u64) -> Self {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    pub fn with_max_age(mut self, age: Duration) -> Self {
+        self.max_age = Some(age);
+        self
+    }
+
+    pub fn with_compress(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
+    pub fn with_max_segments(mut self, count: usize) -> Self {
+        self.max_segments = Some(count);
+        self
+    }
+
+    pub fn with_retention(mut self, age: Duration) -> Self {
+        self.retain_for = Some(age);
+        self
+    }
+
+    pub fn with_max_total_size(mut self, bytes: u64) -> Self {
+        self.max_total_size = Some(bytes);
+        self
+    }
+}
+
+impl Default for RotationPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Rotated segment of a log
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogSegment {
+    pub path: PathBuf,
+    /// 1 is the most recently rotated segment
+    pub number: u32,
+    pub compressed: bool,
+    pub size: u64,
+    pub modified: SystemTime,
+}
+
+impl LogSegment {
+    /// Decompressed content, read as a stream
+    pub fn open(&self) -> io::Result<Box<dyn Read>> {
+        let file = File::open(&self.path)?;
+        Ok(if self.compressed {
+            Box::new(MultiGzDecoder::new(file))
+        } else {
+            Box::new(file)
+        })
+    }
+
+    /// Decompressed content
+    pub fn read(&self) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        self.open()?.read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+/// Rotated segments of `log_path`, newest first. Gaps in the numbering are
+/// allowed.
+pub fn log_segments(log_path: &Path) -> Result<Vec<LogSegment>, TmuxError> {
+    let (Some(dir), Some(name)) = (log_path.parent(), log_path.file_name()) else {
+        return Ok(Vec::new());
+    };
+    let Some(prefix) = name.to_str().map(|name| format!("{}.", name)) else {
+        return Ok(Vec::new());
+    };
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => {
+            return Err(TmuxError::Command(format!(
+                "Failed to read log directory: {}",
+                e
+            )));
+        }
+    };
+
+    let mut segments = Vec::new();
+    for entry in entries.flatten() {
+        let file_name = entry.file_name();
+        let Some(suffix) = file_name.to_str().and_then(|n| n.strip_prefix(&prefix)) else {
+            continue;
+        };
+        let (number, compressed) = match suffix.strip_suffix(".gz") {
+            Some(number) => (number, true),
+            None => (suffix, false),
+        };
+        let Ok(number) = number.parse::<u32>() else {
+            continue;
+        };
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+
+        segments.push(LogSegment {
+            path: entry.path(),
+            number,
+            compressed,
+            size: metadata.len(),
+            modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+        });
+    }
+
+    segments.sort_by_key(|segment| segment.number);
+    Ok(segments)
+}
+
+/// What a rotation pass did
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RotationReport {
+    /// New segments
+    pub rotated: Vec<PathBuf>,
+    /// Segments removed by retention
+    pub removed: Vec<PathBuf>,
+}
+
+impl RotationReport {
+    fn merge(&mut self, other: RotationReport) {
+        self.rotated.extend(other.rotated);
+        self.removed.extend(other.removed);
+    }
+}
+
+/// Rotates session logs and enforces retention
+pub struct LogRotator {
+    reader: LogReader,
+    policy: RotationPolicy,
+}
+
+impl LogRotator {
+    /// Rotator for the default log directory
+    pub fn new(policy: RotationPolicy) -> Self {
+        Self {
+            reader: LogReader::new(),
+            policy,
+        }
+    }
+
+    pub fn with_dir(log_dir: PathBuf, policy: RotationPolicy) -> Self {
+        Self {
+            reader: LogReader::with_dir(log_dir),
+            policy,
+        }
+    }
+
+    pub fn policy(&self) -> &RotationPolicy {
+        &self.policy
+    }
+
+    /// Whether the active log has reached the size or age limit. The age
+    /// counts from the last rotation, or from when the log was created if
+    /// it was never rotated.
+    pub fn needs_rotation(&self, session_id: &SessionId) -> Result<bool, TmuxError> {
+        let log_path = self.reader.session_log_path(session_id);
+        let Ok(metadata) = log_path.metadata() else {
+            return Ok(false);
+        };
+        if metadata.len() == 0 {
+            return Ok(false);
+        }
+
+        if self
+            .policy
+            .max_size
+            .is_some_and(|max| metadata.len() >= max)
+        {
+            return Ok(true);
+        }
+
+        let Some(max_age) = self.policy.max_age else {
+            return Ok(false);
+        };
+        let started = match log_segments(&log_path)?.first() {
+            Some(newest) => Some(newest.modified),
+            None => metadata.created().ok(),
+        };
+        Ok(started
+            .and_then(|started| started.elapsed().ok())
+            .is_some_and(|age| age >= max_age))
+    }
+
+    /// Rotate the active log now, whatever its size or age. Returns the new
+    /// segment, or `None` when the log is missing or empty.
+    ///
+    /// As with `copytruncate`, lines appended after the copy reaches the end
+    /// of the log and before the log is truncated are lost. The log is
+    /// truncated right after that copy, so the window is one `ftruncate`;
+    /// compressing and syncing the segment come after it. If writing the
+    /// segment fails, the copied lines stay in `<log>.1.tmp`.
+    pub fn rotate(&self, session_id: &SessionId) -> Result<Option<PathBuf>, TmuxError> {
+        let log_path = self.reader.session_log_path(session_id);
+        let mut active = match OpenOptions::new().read(true).write(true).open(&log_path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        if active.metadata()?.len() == 0 {
+            return Ok(None);
+        }
+
+        for segment in log_segments(&log_path)?.iter().rev() {
+            let renamed = segment_path(&log_path, segment.number + 1, segment.compressed);
+            fs::rename(&segment.path, &renamed)
+                .map_err(|e| TmuxError::Command(format!("Failed to shift log segment: {}", e)))?;
+            // A missing index is rebuilt by the next search
+            let _ = fs::rename(
+                TermIndex::sidecar_path(&segment.path),
+                TermIndex::sidecar_path(&renamed),
+            );
+        }
+
+        let segment = segment_path(&log_path, 1, self.policy.compress);
+        let copy = tmp_path(&segment_path(&log_path, 1, false));
+        let copied = copy_log(&mut active, &copy).and_then(|_| active.set_len(0));
+        if let Err(e) = copied {
+            let _ = fs::remove_file(&copy);
+            return Err(TmuxError::Command(format!("Failed to rotate log: {}", e)));
+        }
+        write_segment(&copy, &segment, self.policy.compress).map_err(|e| {
+            TmuxError::Command(format!(
+                "Failed to write log segment, its lines are in {}: {}",
+                copy.display(),
+                e
+            ))
+        })?;
+
+        let _ = fs::remove_file(LineIndex::sidecar_path(&log_path));
+        let _ = fs::remove_file(TermIndex::sidecar_path(&log_path));
+        if let Some(newest) = log_segments(&log_path)?.first() {
+            let _ = TermIndex::for_segment(newest);
+        }
+        Ok(Some(segment))
+    }
+
+    /// Remove rotated segments beyond the count, age and total size limits.
+    /// The active log is never removed.
+    pub fn apply_retention(&self, session_id: &SessionId) -> Result<Vec<PathBuf>, TmuxError> {
+        let log_path = self.reader.session_log_path(session_id);
+        let mut total = log_path.metadata().map(|m| m.len()).unwrap_or(0);
+        let mut over_budget = false;
+        let mut removed = Vec::new();
+
+        for (kept, segment) in log_segments(&log_path)?.into_iter().enumerate() {
+            let too_many = self.policy.max_segments.is_some_and(|max| kept >= max);
+            let too_old = self
+                .policy
+                .retain_for
+                .is_some_and(|max| segment.modified.elapsed().is_ok_and(|age| age > max));
+            // Once a segment does not fit, neither do the older ones
+            over_budget |= self
+                .policy
+                .max_total_size
+                .is_some_and(|max| total + segment.size > max);
+
+            if too_many || too_old || over_budget {
+                fs::remove_file(&segment.path).map_err(|e| {
+                    TmuxError::Command(format!("Failed to remove log segment: {}", e))
+                })?;
+                let _ = fs::remove_file(TermIndex::sidecar_path(&segment.path));
+                removed.push(segment.path);
+            } else {
+                total += segment.size;
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// Rotate the log if the policy says so, then apply retention
+    pub fn maintain(&self, session_id: &SessionId) -> Result<RotationReport, TmuxError> {
+        let mut report = RotationReport::default();
+        if self.needs_rotation(session_id)?
+            && let Some(segment) = self.rotate(session_id)?
+        {
+            report.rotated.push(segment);
+        }
+        report.removed = self.apply_retention(session_id)?;
+        Ok(report)
+    }
+
+    /// `maintain` every session log in the directory
+    pub fn maintain_all(&self) -> Result<RotationReport, TmuxError> {
+        let mut report = RotationReport::default();
+        for session_id in self.reader.list_session_logs()? {
+            report.merge(self.maintain(&session_id)?);
+        }
+        Ok(report)
+    }
+}
+
+/// `session_1.log` -> `session_1.log.3` or `session_1.log.3.gz`
+fn segment_path(log_path: &Path, number: u32, compressed: bool) -> PathBuf {
+    let mut path = log_path.as_os_str().to_owned();
+    path.push(format!(".{}", number));
+    if compressed {
+        path.push(".gz");
+    }
+    PathBuf::from(path)
+}
+
+/// `session_1.log.1.gz` -> `session_1.log.1.gz.tmp`
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    PathBuf::from(tmp)
+}
+
+/// Copy the whole active log, including anything appended while copying.
+/// Nothing else is done here, to keep the time before truncation short.
+fn copy_log(active: &mut File, target: &Path) -> io::Result<()> {
+    let mut out = File::create(target)?;
+    io::copy(active, &mut out)?;
+    out.flush()
+}
+
+/// Turn the copied log into the durable segment, compressing it if asked
+fn write_segment(copy: &Path, segment: &Path, compress: bool) -> io::Result<()> {
+    if !compress {
+        File::open(copy)?.sync_all()?;
+        return fs::rename(copy, segment);
+    }
+
+    let tmp = tmp_path(segment);
+    let result = File::create(&tmp).and_then(|out| {
+        let mut encoder = GzEncoder::new(out, Compression::default());
+        io::copy(&mut File::open(copy)?, &mut encoder)?;
+        encoder.finish()?.sync_all()?;
+        fs::rename(&tmp, segment)
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result?;
+    let _ = fs::remove_file(copy);
+    Ok(())
+}
+
+/// Byte size such as `500`, `64K`, `100MB` or `1GiB`. Units are binary.
+pub fn parse_size(text: &str) -> Option<u64> {
+    let text = text.trim();
+    let digits = text.len() - text.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let amount: u64 = text[..digits].parse().ok()?;
+
+    let unit = text[digits..].trim().to_ascii_uppercase();
+    let unit = unit
+        .strip_suffix("IB")
+        .or_else(|| unit.strip_suffix('B'))
+        .unwrap_or(&unit);
+    let multiplier: u64 = match unit {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return None,
+    };
+    amount.checked_mul(multiplier)
+}
+
+/// Duration in the query syntax: `30s`, `10m`, `1h30m`, `2d` or `1w`
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    duration_millis(text.trim()).map(|millis| Duration::from_millis(millis as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup(policy: RotationPolicy) -> (tempfile::TempDir, LogRotator, SessionId, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let rotator = LogRotator::with_dir(dir.path().to_path_buf(), policy);
+        let session_id = SessionId("$1".to_string());
+        let log_path = dir.path().join("session_$1.log");
+        (dir, rotator, session_id, log_path)
+    }
+
+    fn append(path: &Path, text: &str) {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn test_rotates_by_size_into_numbered_gz_segments() {
+        let (_dir, rotator, id, log) = setup(RotationPolicy::new().with_max_size(10));
+
+        // The writer keeps its append handle across rotations
+        let mut writer = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log)
+            .unwrap();
+        writer.write_all(b"first\n").unwrap();
+        assert!(!rotator.needs_rotation(&id).unwrap());
+        writer.write_all(b"second\n").unwrap();
+
+        let report = rotator.maintain(&id).unwrap();
+        assert_eq!(report.rotated, vec![segment_path(&log, 1, true)]);
+        assert_eq!(fs::metadata(&log).unwrap().len(), 0);
+
+        writer.write_all(b"third line\n").unwrap();
+        rotator.maintain(&id).unwrap();
+        writer.write_all(b"fourth\n").unwrap();
+
+        let segments = log_segments(&log).unwrap();
+        assert_eq!(
+            segments.iter().map(|s| s.number).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert_eq!(segments[0].read().unwrap(), b"third line\n");
+        assert_eq!(segments[1].read().unwrap(), b"first\nsecond\n");
+        assert_eq!(fs::read_to_string(&log).unwrap(), "fourth\n");
+
+        // The uncompressed copies taken before truncating are gone
+        let leftovers: Vec<_> = fs::read_dir(log.parent().unwrap())
+            .unwrap()
+            .flatten()
+            .filter(|entry| entry.file_name().to_string_lossy().ends_with(".tmp"))
+            .collect();
+        assert!(leftovers.is_empty(), "{:?}", leftovers);
+    }
+
+    #[test]
+    fn test_rotates_by_age_and_skips_empty_logs() {
+        let (_dir, rotator, id, log) = setup(RotationPolicy::new().with_max_age(Duration::ZERO));
+        assert_eq!(rotator.rotate(&id).unwrap(), None);
+
+        fs::write(&log, "").unwrap();
+        assert!(!rotator.needs_rotation(&id).unwrap());
+
+        append(&log, "hello\n");
+        if log.metadata().unwrap().created().is_ok() {
+            assert!(rotator.needs_rotation(&id).unwrap());
+        }
+
+        let (_dir, rotator, id, log) =
+            setup(RotationPolicy::new().with_max_age(Duration::from_secs(3600)));
+        append(&log, "hello\n");
+        assert!(!rotator.needs_rotation(&id).unwrap());
+    }
+
+    #[test]
+    fn test_retention_by_count_and_total_size() {
+        let policy = RotationPolicy::new()
+            .with_compress(false)
+            .with_max_segments(2);
+        let (_dir, rotator, id, log) = setup(policy);
+        for i in 0..4 {
+            append(&log, &format!("batch {}\n", i));
+            rotator.rotate(&id).unwrap();
+        }
+
+        let removed = rotator.apply_retention(&id).unwrap();
+        assert_eq!(removed.len(), 2);
+        let segments = log_segments(&log).unwrap();
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].read().unwrap(), b"batch 3\n");
+        assert!(!segments[0].compressed);
+
+        // Active log (8 bytes) plus the newest segment fit in 16 bytes
+        append(&log, "batch 4\n");
+        let rotator = LogRotator::with_dir(
+            log.parent().unwrap().to_path_buf(),
+            RotationPolicy::new().with_max_total_size(16),
+        );
+        assert_eq!(
+            rotator.apply_retention(&id).unwrap(),
+            vec![segments[1].path.clone()]
+        );
+        assert!(log.exists());
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("500"), Some(500));
+        assert_eq!(parse_size("64K"), Some(65_536));
+        assert_eq!(parse_size("100MB"), Some(100 << 20));
+        assert_eq!(parse_size("1GiB"), Some(1 << 30));
+        assert_eq!(parse_size("2 g"), Some(2 << 30));
+        assert_eq!(parse_size("lots"), None);
+        assert_eq!(parse_size("5X"), None);
+        assert_eq!(parse_duration("1d"), Some(Duration::from_secs(86_400)));
+        assert_eq!(parse_duration("soon"), None);
+    }
+}
@@ -0,0 +1,1368 @@
+//! Log Search Module
+//!
+//! Full-text search over every session and pane log in the log directory.
+//! Each log gets a term index kept next to it as `<log>.terms`, recording
+//! for every word the blocks of `BLOCK_LINES` lines it occurs in. A search
+//! reads only the blocks the index allows and checks each of their lines
+//! against the query, so the index narrows the scan but never decides a
+//! match. Indexes are brought up to date before each search by reading
+//! only what the logs gained since. A rotated segment never changes, so it
+//! is indexed once, when it is rotated, into its own `<segment>.terms`;
+//! segments are searched oldest first, before the active log.
+//!
+//! Queries combine terms with `AND` (or by juxtaposition), `OR`, `NOT`
+//! (or `-`) and parentheses:
+//! - `word` or `"a phrase"`: the line contains the text, ignoring ASCII case
+//! - `/regex/flags`: the line matches the regex
+//! - `session:<glob>` and `pane:<glob>`: the line is from a matching log
+
+use crate::Result;
+use crate::error::TmuxError;
+use crate::messaging::log_index::{FNV_OFFSET, head_hash};
+use crate::messaging::query::glob_match;
+use crate::messaging::read::{LOG_DIR, line_text};
+use crate::messaging::rotate::{LogSegment, log_segments};
+use crate::messaging::timestamp::find_timestamp;
+use crate::types::{PaneId, SessionId};
+use chrono::{DateTime, Utc};
+use regex_lite::Regex;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Cursor, Read, Seek, SeekFrom};
+use std::iter::Peekable;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Lines per index block
+pub const BLOCK_LINES: u64 = 128;
+
+const MAGIC: &[u8; 4] = b"SWTI";
+const VERSION: u32 = 1;
+/// Leading bytes fingerprinted to notice a replaced log
+const HEAD_LEN: u64 = 256;
+const NO_TIMESTAMP: i64 = i64::MIN;
+
+/// Start of a block of lines
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Block {
+    offset: u64,
+    /// Last timestamp before the block, in Unix milliseconds
+    timestamp: Option<i64>,
+}
+
+/// Term index of one log: which blocks each word occurs in
+#[derive(Debug, Clone, PartialEq)]
+pub struct TermIndex {
+    blocks: Vec<Block>,
+    /// Lowercased word -> ascending block numbers
+    terms: HashMap<String, Vec<u32>>,
+    /// Bytes scanned; always the end of a complete line
+    indexed_bytes: u64,
+    lines: u64,
+    /// Last timestamp in the indexed lines
+    timestamp: Option<i64>,
+    head_hash: u64,
+    head_len: u64,
+}
+
+impl TermIndex {
+    pub fn new() -> Self {
+        Self {
+            blocks: Vec::new(),
+            terms: HashMap::new(),
+            indexed_bytes: 0,
+            lines: 0,
+            timestamp: None,
+            head_hash: FNV_OFFSET,
+            head_len: 0,
+        }
+    }
+
+    /// Sidecar path for a log: `session_1.log` -> `session_1.log.terms`
+    pub fn sidecar_path(log_path: &Path) -> PathBuf {
+        let mut path = log_path.as_os_str().to_owned();
+        path.push(".terms");
+        PathBuf::from(path)
+    }
+
+    /// Index for `log_path`, loaded from its sidecar and brought up to date.
+    /// The sidecar is rewritten when the index changed; failing to write it
+    /// only costs a rescan next time.
+    pub fn for_log(log_path: &Path) -> Result<Self> {
+        let sidecar = Self::sidecar_path(log_path);
+        let mut index = Self::load(&sidecar).unwrap_or_default();
+        if index.update(log_path)? {
+            let _ = index.save(&sidecar);
+        }
+        Ok(index)
+    }
+
+    /// Index for a rotated segment, loaded from its sidecar or built from
+    /// the whole segment and saved. Segments do not change once rotated, so
+    /// an existing sidecar is used as is.
+    pub fn for_segment(segment: &LogSegment) -> Result<Self> {
+        let sidecar = Self::sidecar_path(&segment.path);
+        if let Some(index) = Self::load(&sidecar) {
+            return Ok(index);
+        }
+        let index = Self::build(segment.open()?)?;
+        let _ = index.save(&sidecar);
+        Ok(index)
+    }
+
+    /// Index every line of `reader`, including a last line without a newline
+    fn build(reader: impl Read) -> Result<Self> {
+        let mut index = Self::new();
+        let mut reader = BufReader::new(reader);
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            let read = reader.read_until(b'\n', &mut buf)?;
+            if read == 0 {
+                break;
+            }
+            index.add_line(&line_text(&buf));
+            index.indexed_bytes += read as u64;
+        }
+        Ok(index)
+    }
+
+    /// Read a sidecar. A missing, corrupt or outdated file gives `None`.
+    pub fn load(path: &Path) -> Option<Self> {
+        let bytes = fs::read(path).ok()?;
+        let mut input = Decoder(bytes.strip_prefix(MAGIC.as_slice())?);
+
+        let header = input.u64()?;
+        if header != (BLOCK_LINES << 32) | VERSION as u64 {
+            return None;
+        }
+        let indexed_bytes = input.u64()?;
+        let lines = input.u64()?;
+        let timestamp = stamp(input.u64()? as i64);
+        let head_hash = input.u64()?;
+        let head_len = input.u64()?;
+
+        let block_count = input.u64()?;
+        if block_count != lines.div_ceil(BLOCK_LINES) {
+            return None;
+        }
+        let blocks = (0..block_count)
+            .map(|_| {
+                Some(Block {
+                    offset: input.u64()?,
+                    timestamp: stamp(input.u64()? as i64),
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        let term_count = input.varint()?;
+        let mut terms = HashMap::with_capacity(term_count.min(1 << 20) as usize);
+        for _ in 0..term_count {
+            let len = input.varint()? as usize;
+            let word = String::from_utf8(input.bytes(len)?.to_vec()).ok()?;
+            let mut block = 0u64;
+            let postings = (0..input.varint()?)
+                .map(|_| {
+                    block += input.varint()?;
+                    u32::try_from(block).ok()
+                })
+                .collect::<Option<Vec<_>>>()?;
+            terms.insert(word, postings);
+        }
+
+        Some(Self {
+            blocks,
+            terms,
+            indexed_bytes,
+            lines,
+            timestamp,
+            head_hash,
+            head_len,
+        })
+    }
+
+    /// Write the sidecar atomically
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut out = MAGIC.to_vec();
+        for field in [
+            (BLOCK_LINES << 32) | VERSION as u64,
+            self.indexed_bytes,
+            self.lines,
+            self.timestamp.unwrap_or(NO_TIMESTAMP) as u64,
+            self.head_hash,
+            self.head_len,
+            self.blocks.len() as u64,
+        ] {
+            out.extend_from_slice(&field.to_le_bytes());
+        }
+        for block in &self.blocks {
+            out.extend_from_slice(&block.offset.to_le_bytes());
+            out.extend_from_slice(&(block.timestamp.unwrap_or(NO_TIMESTAMP) as u64).to_le_bytes());
+        }
+
+        put_varint(&mut out, self.terms.len() as u64);
+        for (word, postings) in &self.terms {
+            put_varint(&mut out, word.len() as u64);
+            out.extend_from_slice(word.as_bytes());
+            put_varint(&mut out, postings.len() as u64);
+            let mut last = 0;
+            for block in postings {
+                put_varint(&mut out, (block - last) as u64);
+                last = *block;
+            }
+        }
+
+        let tmp = path.with_extension("terms.tmp");
+        fs::write(&tmp, out)
+            .and_then(|_| fs::rename(&tmp, path))
+            .map_err(|e| TmuxError::Command(format!("Failed to write term index: {}", e)))
+    }
+
+    /// Index whatever the log gained since the last update. A log that
+    /// shrank or whose first bytes changed is indexed from scratch. Returns
+    /// whether the index changed.
+    pub fn update(&mut self, log_path: &Path) -> Result<bool> {
+        let mut file = match File::open(log_path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let changed = self.indexed_bytes > 0;
+                *self = Self::new();
+                return Ok(changed);
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let len = file.metadata()?.len();
+
+        let mut rebuilt = false;
+        if len < self.indexed_bytes || head_hash(&mut file, self.head_len)? != self.head_hash {
+            *self = Self::new();
+            rebuilt = true;
+        }
+        if len == self.indexed_bytes {
+            return Ok(rebuilt);
+        }
+
+        let before = self.indexed_bytes;
+        file.seek(SeekFrom::Start(self.indexed_bytes))?;
+        let mut reader = BufReader::new(file);
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            let read = reader.read_until(b'\n', &mut buf)?;
+            if read == 0 || !buf.ends_with(b"\n") {
+                break;
+            }
+            self.add_line(&line_text(&buf));
+            self.indexed_bytes += read as u64;
+        }
+
+        let head_len = self.indexed_bytes.min(HEAD_LEN);
+        if head_len != self.head_len {
+            self.head_hash = head_hash(reader.get_mut(), head_len)?;
+            self.head_len = head_len;
+        }
+
+        Ok(rebuilt || self.indexed_bytes != before)
+    }
+
+    fn add_line(&mut self, text: &str) {
+        if self.lines.is_multiple_of(BLOCK_LINES) {
+            self.blocks.push(Block {
+                offset: self.indexed_bytes,
+                timestamp: self.timestamp,
+            });
+        }
+        let block = (self.lines / BLOCK_LINES) as u32;
+
+        for word in words(text) {
+            let word = word.to_ascii_lowercase();
+            match self.terms.get_mut(&word) {
+                Some(postings) if postings.last() == Some(&block) => {}
+                Some(postings) => postings.push(block),
+                None => {
+                    self.terms.insert(word, vec![block]);
+                }
+            }
+        }
+        if let Some(ts) = line_timestamp(text) {
+            self.timestamp = Some(ts.timestamp_millis());
+        }
+        self.lines += 1;
+    }
+
+    /// Blocks holding a word that contains `word`
+    pub fn blocks_containing(&self, word: &str) -> BTreeSet<u32> {
+        let word = word.to_ascii_lowercase();
+        self.terms
+            .iter()
+            .filter(|(term, _)| term.contains(&word))
+            .flat_map(|(_, postings)| postings.iter().copied())
+            .collect()
+    }
+
+    /// Start of the block holding `line`, as `(byte offset, line,
+    /// timestamp)`. Past the indexed lines, the end of the indexed bytes.
+    fn seek(&self, line: u64) -> (u64, u64, Option<i64>) {
+        let block = line / BLOCK_LINES;
+        match self.blocks.get(block as usize) {
+            Some(start) if line < self.lines => {
+                (start.offset, block * BLOCK_LINES, start.timestamp)
+            }
+            _ => (self.indexed_bytes, self.lines, self.timestamp),
+        }
+    }
+
+    /// Complete lines indexed
+    pub fn line_count(&self) -> u64 {
+        self.lines
+    }
+
+    /// Distinct words indexed
+    pub fn term_count(&self) -> usize {
+        self.terms.len()
+    }
+}
+
+impl Default for TermIndex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Runs of letters, digits and underscores
+fn words(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|word| !word.is_empty())
+}
+
+/// Capture timestamp of a line, if it has one
+fn line_timestamp(text: &str) -> Option<DateTime<Utc>> {
+    if text.bytes().any(|b| b.is_ascii_digit()) {
+        find_timestamp(text)
+    } else {
+        None
+    }
+}
+
+fn stamp(millis: i64) -> Option<i64> {
+    (millis != NO_TIMESTAMP).then_some(millis)
+}
+
+fn put_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+struct Decoder<'a>(&'a [u8]);
+
+impl Decoder<'_> {
+    fn bytes(&mut self, len: usize) -> Option<&[u8]> {
+        if len > self.0.len() {
+            return None;
+        }
+        let (head, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(head)
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.bytes(8)?.try_into().ok()?))
+    }
+
+    fn varint(&mut self) -> Option<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = *self.bytes(1)?.first()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+}
+
+/// Log a hit came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogSource {
+    Session(SessionId),
+    Pane(PaneId),
+}
+
+impl LogSource {
+    /// Source of a log file named `session_<id>.log` or `pane_<id>.log`
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.strip_suffix(".log")?;
+        if let Some(id) = name.strip_prefix("session_") {
+            Some(LogSource::Session(SessionId(id.to_string())))
+        } else {
+            name.strip_prefix("pane_")
+                .map(|id| LogSource::Pane(PaneId(id.to_string())))
+        }
+    }
+
+    pub fn session(&self) -> Option<&SessionId> {
+        match self {
+            LogSource::Session(id) => Some(id),
+            LogSource::Pane(_) => None,
+        }
+    }
+
+    pub fn pane(&self) -> Option<&PaneId> {
+        match self {
+            LogSource::Pane(id) => Some(id),
+            LogSource::Session(_) => None,
+        }
+    }
+
+    /// The session or pane id
+    pub fn id(&self) -> &str {
+        match self {
+            LogSource::Session(id) => &id.0,
+            LogSource::Pane(id) => &id.0,
+        }
+    }
+
+    /// Whether the id matches a `*` glob, with or without its `$`/`%` sigil
+    pub fn matches_glob(&self, pattern: &str) -> bool {
+        let id = self.id();
+        glob_match(pattern, id) || glob_match(pattern, id.trim_start_matches(['$', '%']))
+    }
+}
+
+impl fmt::Display for LogSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogSource::Session(id) => write!(f, "session {}", id.0),
+            LogSource::Pane(id) => write!(f, "pane {}", id.0),
+        }
+    }
+}
+
+/// A matching line and its context
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    pub source: LogSource,
+    /// Active log or rotated segment the line is in
+    pub path: PathBuf,
+    /// Line number (0-based) within `path`
+    pub line: u64,
+    /// Byte offset of the line within `path`, decompressed
+    pub offset: u64,
+    pub text: String,
+    /// Capture timestamp of the line, or the nearest one before it
+    pub timestamp: Option<DateTime<Utc>>,
+    /// Lines before the hit, oldest first
+    pub before: Vec<String>,
+    /// Lines after the hit
+    pub after: Vec<String>,
+    /// Byte ranges of `text` that matched, for highlighting
+    pub spans: Vec<Range<usize>>,
+}
+
+/// Search expression over single log lines
+#[derive(Debug, Clone)]
+pub enum SearchExpr {
+    /// Line contains the text, ignoring ASCII case. Stored lowercased.
+    Text(String),
+    Regex(Regex),
+    /// Session log whose id matches the glob
+    Session(String),
+    /// Pane log whose id matches the glob
+    Pane(String),
+    Not(Box<SearchExpr>),
+    And(Vec<SearchExpr>),
+    Or(Vec<SearchExpr>),
+}
+
+impl SearchExpr {
+    /// Whether `text` (and `lowered`, its ASCII-lowercased copy) from
+    /// `source` matches
+    pub fn matches(&self, source: &LogSource, text: &str, lowered: &str) -> bool {
+        match self {
+            SearchExpr::Text(needle) => lowered.contains(needle.as_str()),
+            SearchExpr::Regex(regex) => regex.is_match(text),
+            SearchExpr::Session(_) | SearchExpr::Pane(_) => self.accepts(source),
+            SearchExpr::Not(inner) => !inner.matches(source, text, lowered),
+            SearchExpr::And(all) => all.iter().all(|e| e.matches(source, text, lowered)),
+            SearchExpr::Or(any) => any.iter().any(|e| e.matches(source, text, lowered)),
+        }
+    }
+
+    /// Whether any line of `source` could match
+    fn accepts(&self, source: &LogSource) -> bool {
+        match (self, source) {
+            (SearchExpr::Session(pattern), LogSource::Session(_))
+            | (SearchExpr::Pane(pattern), LogSource::Pane(_)) => source.matches_glob(pattern),
+            (SearchExpr::Session(_) | SearchExpr::Pane(_), _) => false,
+            (SearchExpr::And(all), _) => all.iter().all(|e| e.accepts(source)),
+            (SearchExpr::Or(any), _) => any.iter().any(|e| e.accepts(source)),
+            _ => true,
+        }
+    }
+
+    /// Blocks of `index` that can hold a match; `None` for all of them
+    fn candidates(&self, index: &TermIndex) -> Option<BTreeSet<u32>> {
+        match self {
+            SearchExpr::Text(needle) => words(needle)
+                .map(|word| index.blocks_containing(word))
+                .reduce(|a, b| a.intersection(&b).copied().collect()),
+            SearchExpr::And(all) => all
+                .iter()
+                .filter_map(|e| e.candidates(index))
+                .reduce(|a, b| a.intersection(&b).copied().collect()),
+            SearchExpr::Or(any) => any.iter().try_fold(BTreeSet::new(), |mut blocks, e| {
+                blocks.extend(e.candidates(index)?);
+                Some(blocks)
+            }),
+            _ => None,
+        }
+    }
+
+    /// Ranges of the line matched by positive text and regex terms
+    fn spans(&self, text: &str, lowered: &str, out: &mut Vec<Range<usize>>) {
+        match self {
+            SearchExpr::Text(needle) if !needle.is_empty() => out.extend(
+                lowered
+                    .match_indices(needle.as_str())
+                    .map(|(at, found)| at..at + found.len()),
+            ),
+            SearchExpr::Regex(regex) => out.extend(
+                regex
+                    .find_iter(text)
+                    .filter(|m| !m.is_empty())
+                    .map(|m| m.range()),
+            ),
+            SearchExpr::And(all) | SearchExpr::Or(all) => {
+                all.iter().for_each(|e| e.spans(text, lowered, out))
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Parsed search query
+#[derive(Debug, Clone)]
+pub struct SearchQuery {
+    expr: SearchExpr,
+}
+
+impl SearchQuery {
+    pub fn parse(text: &str) -> Result<Self> {
+        let tokens = Lexer { text, pos: 0 }.tokens()?;
+        if tokens.is_empty() {
+            return Err(TmuxError::Parse("Empty search query".to_string()));
+        }
+
+        let mut parser = Parser {
+            tokens: tokens.into_iter().peekable(),
+        };
+        let expr = parser.or()?;
+        if parser.tokens.next().is_some() {
+            return Err(TmuxError::Parse(
+                "Unbalanced ')' in search query".to_string(),
+            ));
+        }
+        Ok(Self { expr })
+    }
+
+    pub fn expr(&self) -> &SearchExpr {
+        &self.expr
+    }
+
+    /// Whether a line from `source` matches
+    pub fn matches(&self, source: &LogSource, text: &str) -> bool {
+        self.expr.matches(source, text, &text.to_ascii_lowercase())
+    }
+}
+
+impl From<SearchExpr> for SearchQuery {
+    fn from(expr: SearchExpr) -> Self {
+        Self { expr }
+    }
+}
+
+impl FromStr for SearchQuery {
+    type Err = TmuxError;
+
+    fn from_str(text: &str) -> Result<Self> {
+        Self::parse(text)
+    }
+}
+
+enum Token {
+    LParen,
+    RParen,
+    And,
+    Or,
+    Not,
+    Term(SearchExpr),
+}
+
+struct Lexer<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl Lexer<'_> {
+    fn tokens(mut self) -> Result<Vec<Token>> {
+        let mut tokens = Vec::new();
+
+        loop {
+            let rest = self.rest();
+            self.pos += rest.len() - rest.trim_start().len();
+            let Some(c) = self.peek() else {
+                return Ok(tokens);
+            };
+
+            let token = match c {
+                '(' => {
+                    self.pos += 1;
+                    Token::LParen
+                }
+                ')' => {
+                    self.pos += 1;
+                    Token::RParen
+                }
+                '-' | '!' => {
+                    self.pos += 1;
+                    if self.peek().is_none_or(char::is_whitespace) {
+                        return Err(self.error("Nothing to negate"));
+                    }
+                    Token::Not
+                }
+                '/' => Token::Term(SearchExpr::Regex(self.regex()?)),
+                _ => self.term()?,
+            };
+            tokens.push(token);
+        }
+    }
+
+    fn term(&mut self) -> Result<Token> {
+        for (key, make) in [
+            ("session:", SearchExpr::Session as fn(String) -> SearchExpr),
+            ("pane:", SearchExpr::Pane),
+        ] {
+            if self.rest().starts_with(key) {
+                self.pos += key.len();
+                let value = self.value()?;
+                if value.is_empty() {
+                    return Err(self.error(&format!("Missing value for '{}'", key)));
+                }
+                return Ok(Token::Term(make(value)));
+            }
+        }
+
+        let quoted = matches!(self.peek(), Some('"' | '\''));
+        let word = self.value()?;
+        Ok(match word.as_str() {
+            "OR" if !quoted => Token::Or,
+            "AND" if !quoted => Token::And,
+            "NOT" if !quoted => Token::Not,
+            _ => Token::Term(SearchExpr::Text(word.to_ascii_lowercase())),
+        })
+    }
+
+    /// Bare or quoted text up to whitespace or `)`
+    fn value(&mut self) -> Result<String> {
+        let mut value = String::new();
+
+        while let Some(c) = self.peek() {
+            match c {
+                '"' | '\'' => {
+                    self.pos += 1;
+                    loop {
+                        let Some(next) = self.peek() else {
+                            return Err(self.error("Unterminated quote"));
+                        };
+                        self.pos += next.len_utf8();
+                        match next {
+                            '\\' => {
+                                if let Some(escaped) = self.peek() {
+                                    self.pos += escaped.len_utf8();
+                                    value.push(escaped);
+                                }
+                            }
+                            q if q == c => break,
+                            other => value.push(other),
+                        }
+                    }
+                }
+                c if c.is_whitespace() || c == ')' => break,
+                c => {
+                    self.pos += c.len_utf8();
+                    value.push(c);
+                }
+            }
+        }
+
+        Ok(value)
+    }
+
+    /// `/regex/flags`; `\/` is a literal slash
+    fn regex(&mut self) -> Result<Regex> {
+        self.pos += 1;
+        let mut pattern = String::new();
+        loop {
+            let Some(c) = self.peek() else {
+                return Err(self.error("Unterminated regex"));
+            };
+            self.pos += c.len_utf8();
+            match c {
+                '/' => break,
+                '\\' if self.peek() == Some('/') => {
+                    self.pos += 1;
+                    pattern.push('/');
+                }
+                '\\' => {
+                    pattern.push('\\');
+                    if let Some(escaped) = self.peek() {
+                        self.pos += escaped.len_utf8();
+                        pattern.push(escaped);
+                    }
+                }
+                c => pattern.push(c),
+            }
+        }
+
+        let mut flags = String::new();
+        while let Some(c) = self.peek().filter(char::is_ascii_alphabetic) {
+            self.pos += 1;
+            match c {
+                'i' | 'm' | 's' | 'x' | 'U' => flags.push(c),
+                other => return Err(self.error(&format!("Unknown regex flag '{}'", other))),
+            }
+        }
+        if !flags.is_empty() {
+            pattern = format!("(?{}){}", flags, pattern);
+        }
+
+        Regex::new(&pattern)
+            .map_err(|e| TmuxError::Parse(format!("Invalid regex '{}': {}", pattern, e)))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn rest(&self) -> &str {
+        &self.text[self.pos..]
+    }
+
+    fn error(&self, msg: &str) -> TmuxError {
+        TmuxError::Parse(format!(
+            "{} at offset {} in search query '{}'",
+            msg, self.pos, self.text
+        ))
+    }
+}
+
+struct Parser {
+    tokens: Peekable<std::vec::IntoIter<Token>>,
+}
+
+impl Parser {
+    fn or(&mut self) -> Result<SearchExpr> {
+        let mut any = vec![self.and()?];
+        while self.tokens.next_if(|t| matches!(t, Token::Or)).is_some() {
+            any.push(self.and()?);
+        }
+        Ok(if any.len() == 1 {
+            any.remove(0)
+        } else {
+            SearchExpr::Or(any)
+        })
+    }
+
+    fn and(&mut self) -> Result<SearchExpr> {
+        let mut all = vec![self.unary()?];
+        loop {
+            match self.tokens.peek() {
+                Some(Token::And) => {
+                    self.tokens.next();
+                    all.push(self.unary()?);
+                }
+                Some(Token::Term(_) | Token::Not | Token::LParen) => all.push(self.unary()?),
+                _ => break,
+            }
+        }
+        Ok(if all.len() == 1 {
+            all.remove(0)
+        } else {
+            SearchExpr::And(all)
+        })
+    }
+
+    fn unary(&mut self) -> Result<SearchExpr> {
+        match self.tokens.next() {
+            Some(Token::Not) => Ok(SearchExpr::Not(Box::new(self.unary()?))),
+            Some(Token::LParen) => {
+                let inner = self.or()?;
+                match self.tokens.next() {
+                    Some(Token::RParen) => Ok(inner),
+                    _ => Err(TmuxError::Parse(
+                        "Unbalanced '(' in search query".to_string(),
+                    )),
+                }
+            }
+            Some(Token::Term(expr)) => Ok(expr),
+            Some(Token::RParen) => Err(TmuxError::Parse(
+                "Unbalanced ')' in search query".to_string(),
+            )),
+            Some(Token::And | Token::Or) | None => Err(TmuxError::Parse(
+                "Expected a term in search query".to_string(),
+            )),
+        }
+    }
+}
+
+/// Context and limits for a search
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchOptions {
+    /// Lines of context before each hit
+    pub before: usize,
+    /// Lines of context after each hit
+    pub after: usize,
+    /// Stop after this many hits
+    pub limit: Option<usize>,
+}
+
+impl SearchOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_context(self, lines: usize) -> Self {
+        self.with_before(lines).with_after(lines)
+    }
+
+    pub fn with_before(mut self, lines: usize) -> Self {
+        self.before = lines;
+        self
+    }
+
+    pub fn with_after(mut self, lines: usize) -> Self {
+        self.after = lines;
+        self
+    }
+
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+}
+
+/// Totals after bringing the indexes up to date
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IndexStats {
+    pub logs: usize,
+    pub lines: u64,
+    pub terms: usize,
+}
+
+/// Searches every log in a log directory
+pub struct LogSearch {
+    log_dir: PathBuf,
+}
+
+impl LogSearch {
+    pub fn new() -> Self {
+        Self::with_dir(PathBuf::from(LOG_DIR))
+    }
+
+    pub fn with_dir(log_dir: PathBuf) -> Self {
+        Self { log_dir }
+    }
+
+    /// Session and pane logs in the directory, by file name
+    pub fn logs(&self) -> Result<Vec<(LogSource, PathBuf)>> {
+        if !self.log_dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let entries = fs::read_dir(&self.log_dir)
+            .map_err(|e| TmuxError::Command(format!("Failed to read log directory: {}", e)))?;
+        let mut logs: Vec<_> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter_map(|path| LogSource::from_path(&path).map(|source| (source, path)))
+            .collect();
+        logs.sort_by(|a, b| a.1.cmp(&b.1));
+        Ok(logs)
+    }
+
+    /// Bring every log's term index up to date
+    pub fn update_index(&self) -> Result<IndexStats> {
+        let mut stats = IndexStats::default();
+        for (_, path) in self.logs()? {
+            let index = TermIndex::for_log(&path)?;
+            stats.logs += 1;
+            stats.lines += index.line_count();
+            stats.terms += index.term_count();
+        }
+        Ok(stats)
+    }
+
+    /// Matching lines across all logs, in file name then line order. A
+    /// log's rotated segments come before it, oldest first.
+    pub fn search(&self, query: &SearchQuery, options: &SearchOptions) -> Result<Vec<SearchHit>> {
+        let mut hits = Vec::new();
+        let full = |hits: &[SearchHit]| options.limit.is_some_and(|limit| hits.len() >= limit);
+
+        for (source, path) in self.logs()? {
+            if full(&hits) {
+                break;
+            }
+            if !query.expr.accepts(&source) {
+                continue;
+            }
+
+            for segment in log_segments(&path)?.iter().rev() {
+                if full(&hits) {
+                    break;
+                }
+                let index = match TermIndex::for_segment(segment) {
+                    Ok(index) => index,
+                    Err(TmuxError::Process(std::io::ErrorKind::NotFound, _)) => continue,
+                    Err(e) => return Err(e),
+                };
+                let blocks = query.expr.candidates(&index);
+                Scan {
+                    query,
+                    options,
+                    source: &source,
+                    path: &segment.path,
+                    index: &index,
+                    blocks: blocks.as_ref(),
+                    tail: false,
+                }
+                .run_segment(segment, &mut hits)?;
+            }
+            if full(&hits) {
+                break;
+            }
+
+            let index = TermIndex::for_log(&path)?;
+            let blocks = query.expr.candidates(&index);
+            Scan {
+                query,
+                options,
+                source: &source,
+                path: &path,
+                index: &index,
+                blocks: blocks.as_ref(),
+                tail: true,
+            }
+            .run(&mut hits)?;
+        }
+
+        Ok(hits)
+    }
+}
+
+impl Default for LogSearch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Search of one log, reading only the candidate blocks and their context
+struct Scan<'a> {
+    query: &'a SearchQuery,
+    options: &'a SearchOptions,
+    source: &'a LogSource,
+    path: &'a Path,
+    index: &'a TermIndex,
+    /// `None` reads every block
+    blocks: Option<&'a BTreeSet<u32>>,
+    /// Whether lines past the indexed ones are read: an active log's
+    /// unindexed tail. A rotated segment is indexed to its end.
+    tail: bool,
+}
+
+impl Scan<'_> {
+    fn run(&self, hits: &mut Vec<SearchHit>) -> Result<()> {
+        let file = match File::open(self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        self.read_ranges(file, hits)
+    }
+
+    /// Search a rotated segment. One whose index rules out every block is
+    /// not opened; a compressed one is decompressed only when some block
+    /// may match.
+    fn run_segment(&self, segment: &LogSegment, hits: &mut Vec<SearchHit>) -> Result<()> {
+        if self.ranges().is_empty() {
+            return Ok(());
+        }
+        if !segment.compressed {
+            return self.run(hits);
+        }
+        let bytes = match segment.read() {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        self.read_ranges(Cursor::new(bytes), hits)
+    }
+
+    fn read_ranges(&self, mut file: impl Read + Seek, hits: &mut Vec<SearchHit>) -> Result<()> {
+        for range in self.ranges() {
+            let (offset, line, timestamp) = self.index.seek(range.start);
+            file.seek(SeekFrom::Start(offset))?;
+            let done = self.scan(&mut file, range, (offset, line), timestamp, hits)?;
+            if done {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    // Without candidate blocks the whole log is one range: a one-element
+    // Vec is intended
+    /// Line ranges to read: candidate blocks widened by the context, and
+    /// the unindexed tail of the log
+    #[allow(clippy::single_range_in_vec_init)]
+    fn ranges(&self) -> Vec<Range<u64>> {
+        let Some(blocks) = self.blocks else {
+            return vec![0..u64::MAX];
+        };
+        let before = self.options.before as u64;
+        let after = self.options.after as u64;
+
+        let mut ranges: Vec<Range<u64>> = Vec::new();
+        let wanted = blocks
+            .iter()
+            .map(|b| *b as u64 * BLOCK_LINES..(*b as u64 + 1) * BLOCK_LINES)
+            .chain(self.tail.then(|| self.index.line_count()..u64::MAX));
+        for block in wanted {
+            let range = block.start.saturating_sub(before)..block.end.saturating_add(after);
+            match ranges.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => ranges.push(range),
+            }
+        }
+        ranges
+    }
+
+    fn is_candidate(&self, line: u64) -> bool {
+        line >= self.index.line_count()
+            || self
+                .blocks
+                .is_none_or(|blocks| blocks.contains(&((line / BLOCK_LINES) as u32)))
+    }
+
+    /// Read `range` starting from `at`, a line start at or before it.
+    /// Returns whether the hit limit was reached.
+    fn scan(
+        &self,
+        file: impl Read,
+        range: Range<u64>,
+        at: (u64, u64),
+        timestamp: Option<i64>,
+        hits: &mut Vec<SearchHit>,
+    ) -> Result<bool> {
+        let (mut offset, mut line) = at;
+        let mut timestamp = timestamp.and_then(DateTime::from_timestamp_millis);
+        let mut context: VecDeque<String> = VecDeque::with_capacity(self.options.before + 1);
+        // Hits still collecting lines after them
+        let mut open: Vec<usize> = Vec::new();
+        let mut full = false;
+
+        let mut reader = BufReader::new(file);
+        let mut buf = Vec::new();
+        while line < range.end {
+            buf.clear();
+            let read = reader.read_until(b'\n', &mut buf)?;
+            if read == 0 {
+                break;
+            }
+            let text = line_text(&buf);
+            if let Some(ts) = line_timestamp(&text) {
+                timestamp = Some(ts);
+            }
+
+            if line >= range.start {
+                open.retain(|&i| {
+                    hits[i].after.push(text.clone());
+                    hits[i].after.len() < self.options.after
+                });
+
+                if !full && self.is_candidate(line) {
+                    let lowered = text.to_ascii_lowercase();
+                    if self.query.expr.matches(self.source, &text, &lowered) {
+                        let mut spans = Vec::new();
+                        self.query.expr.spans(&text, &lowered, &mut spans);
+                        if self.options.after > 0 {
+                            open.push(hits.len());
+                        }
+                        hits.push(SearchHit {
+                            source: self.source.clone(),
+                            path: self.path.to_path_buf(),
+                            line,
+                            offset,
+                            text: text.clone(),
+                            timestamp,
+                            before: context.iter().cloned().collect(),
+                            after: Vec::new(),
+                            spans: merge_spans(spans),
+                        });
+                        full = self.options.limit.is_some_and(|limit| hits.len() >= limit);
+                    }
+                }
+
+                if full && open.is_empty() {
+                    return Ok(true);
+                }
+                if self.options.before > 0 {
+                    if context.len() == self.options.before {
+                        context.pop_front();
+                    }
+                    context.push_back(text);
+                }
+            }
+
+            offset += read as u64;
+            line += 1;
+        }
+
+        Ok(full)
+    }
+}
+
+/// Sorted, non-overlapping spans
+fn merge_spans(mut spans: Vec<Range<usize>>) -> Vec<Range<usize>> {
+    spans.sort_by_key(|span| span.start);
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(spans.len());
+    for span in spans {
+        match merged.last_mut() {
+            Some(last) if span.start <= last.end => last.end = last.end.max(span.end),
+            _ => merged.push(span),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn search_dir(logs: &[(&str, &str)]) -> (tempfile::TempDir, LogSearch) {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, content) in logs {
+            fs::write(dir.path().join(name), content).unwrap();
+        }
+        let search = LogSearch::with_dir(dir.path().to_path_buf());
+        (dir, search)
+    }
+
+    fn hits(search: &LogSearch, query: &str) -> Vec<(String, u64)> {
+        search
+            .search(&query.parse().unwrap(), &SearchOptions::new())
+            .unwrap()
+            .into_iter()
+            .map(|hit| (hit.source.to_string(), hit.line))
+            .collect()
+    }
+
+    #[test]
+    fn test_boolean_and_regex_queries() {
+        let (_dir, search) = search_dir(&[
+            (
+                "session_1.log",
+                "edit src/main.rs\nerror[E0308]: mismatched types\ncargo test passed\n",
+            ),
+            ("session_2.log", "read src/lib.rs\nError: rate limit\n"),
+            ("pane_%3.log", "edit src/main.rs again\n"),
+            ("session_1.log.idx", "not a log"),
+        ]);
+
+        assert_eq!(
+            hits(&search, "src/main.rs"),
+            vec![("pane %3".to_string(), 0), ("session 1".to_string(), 0)]
+        );
+        assert_eq!(hits(&search, "ERROR -rate").len(), 1);
+        assert_eq!(hits(&search, "error AND rate").len(), 1);
+        assert_eq!(hits(&search, "mismatched OR \"rate limit\"").len(), 2);
+        assert_eq!(hits(&search, r"/E\d{4}/").len(), 1);
+        assert_eq!(hits(&search, "/^error/i").len(), 2);
+        assert_eq!(hits(&search, "edit (session:1 OR pane:3)").len(), 2);
+        assert_eq!(hits(&search, "session:2").len(), 2);
+        assert_eq!(hits(&search, "edit -pane:*").len(), 1);
+
+        for bad in ["", "(edit", "edit)", "/unterminated", "session:", "- edit"] {
+            assert!(SearchQuery::parse(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_context_offsets_and_spans() {
+        let (_dir, search) = search_dir(&[(
+            "session_1.log",
+            "[2024-01-02 03:04:05] start\nfirst\nneedle here\nafter 1\nafter 2\nneedle tail",
+        )]);
+
+        let options = SearchOptions::new().with_before(2).with_after(1);
+        let found = search.search(&"needle".parse().unwrap(), &options).unwrap();
+        assert_eq!(found.len(), 2);
+
+        let hit = &found[0];
+        assert_eq!((hit.line, hit.offset), (2, 34));
+        assert_eq!(hit.before, vec!["[2024-01-02 03:04:05] start", "first"]);
+        assert_eq!(hit.after, vec!["after 1"]);
+        assert_eq!(hit.spans, vec![0..6]);
+        assert_eq!(hit.source.session(), Some(&SessionId("1".to_string())));
+        assert_eq!(
+            hit.timestamp.unwrap().to_rfc3339(),
+            "2024-01-02T03:04:05+00:00"
+        );
+
+        // An unterminated last line is searched too
+        assert_eq!(found[1].text, "needle tail");
+        assert_eq!(found[1].before, vec!["after 1", "after 2"]);
+        assert!(found[1].after.is_empty());
+
+        let limited = search
+            .search(&"needle".parse().unwrap(), &options.with_limit(1))
+            .unwrap();
+        assert_eq!(limited.len(), 1);
+        assert_eq!(limited[0].after, vec!["after 1"]);
+    }
+
+    #[test]
+    fn test_index_narrows_to_blocks_and_stays_exact() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("session_1.log");
+        let mut file = File::create(&log).unwrap();
+        for i in 0..1000 {
+            let extra = if i == 700 { " unusual_word" } else { "" };
+            writeln!(file, "line {}{}", i, extra).unwrap();
+        }
+
+        let index = TermIndex::for_log(&log).unwrap();
+        assert_eq!(index.line_count(), 1000);
+        assert_eq!(
+            index.blocks_containing("UNUSUAL"),
+            BTreeSet::from([(700 / BLOCK_LINES) as u32])
+        );
+
+        // Context reaches into blocks that are not candidates
+        let search = LogSearch::with_dir(dir.path().to_path_buf());
+        let options = SearchOptions::new().with_context(1);
+        let found = search
+            .search(&"unusual".parse().unwrap(), &options)
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].line, 700);
+        assert_eq!(found[0].before, vec!["line 699"]);
+
+        let at_boundary = search
+            .search(&"\"line 767\"".parse().unwrap(), &options)
+            .unwrap();
+        assert_eq!(at_boundary[0].after, vec!["line 768"]);
+
+        // Lines appended after indexing are found, and indexed next time
+        writeln!(file, "late unusual_word").unwrap();
+        let found = search
+            .search(&"unusual".parse().unwrap(), &options)
+            .unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(TermIndex::for_log(&log).unwrap().line_count(), 1001);
+    }
+
+    #[test]
+    fn test_sidecar_round_trip_and_rebuild() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("session_1.log");
+        fs::write(&log, "alpha beta\ngamma [2024-01-02 03:04:05]\n").unwrap();
+
+        let index = TermIndex::for_log(&log).unwrap();
+        let sidecar = TermIndex::sidecar_path(&log);
+        assert_eq!(sidecar.file_name().unwrap(), "session_1.log.terms");
+        assert_eq!(TermIndex::load(&sidecar), Some(index.clone()));
+        assert!(index.timestamp.is_some());
+
+        fs::write(&sidecar, b"SWTIgarbage").unwrap();
+        assert_eq!(TermIndex::load(&sidecar), None);
+
+        // Replaced log is indexed from scratch
+        fs::write(&log, "delta\n").unwrap();
+        let index = TermIndex::for_log(&log).unwrap();
+        assert_eq!(index.line_count(), 1);
+        assert!(index.blocks_containing("alpha").is_empty());
+        assert_eq!(index.blocks_containing("delta").len(), 1);
+    }
+
+    #[test]
+    fn test_rotated_segments_are_searched() {
+        let (dir, search) = search_dir(&[
+            ("session_1.log", "needle now\n"),
+            ("session_1.log.1", "skip\nneedle recent\n"),
+        ]);
+        let mut encoder = flate2::write::GzEncoder::new(
+            File::create(dir.path().join("session_1.log.2.gz")).unwrap(),
+            flate2::Compression::default(),
+        );
+        encoder.write_all(b"needle oldest\n").unwrap();
+        encoder.finish().unwrap();
+
+        let found = search
+            .search(&"needle".parse().unwrap(), &SearchOptions::new())
+            .unwrap();
+        let texts: Vec<&str> = found.iter().map(|hit| hit.text.as_str()).collect();
+        assert_eq!(texts, vec!["needle oldest", "needle recent", "needle now"]);
+        assert_eq!(found[1].path, dir.path().join("session_1.log.1"));
+        assert_eq!((found[1].line, found[1].offset), (1, 5));
+        assert!(found.iter().all(|hit| hit.source.session().is_some()));
+
+        let options = SearchOptions::new().with_limit(1);
+        let found = search.search(&"needle".parse().unwrap(), &options).unwrap();
+        assert_eq!(found.len(), 1);
+    }
+
+    #[test]
+    fn test_rotated_segments_are_indexed_once() {
+        use crate::messaging::rotate::{LogRotator, RotationPolicy};
+
+        let (dir, search) = search_dir(&[("session_1.log", "needle first\n")]);
+        let rotator = LogRotator::with_dir(dir.path().to_path_buf(), RotationPolicy::new());
+        let session = SessionId("1".to_string());
+        rotator.rotate(&session).unwrap();
+        fs::write(dir.path().join("session_1.log"), "haystack second\n").unwrap();
+        rotator.rotate(&session).unwrap();
+
+        let oldest = dir.path().join("session_1.log.2.gz");
+        let sidecar = TermIndex::sidecar_path(&oldest);
+        assert!(sidecar.exists());
+        assert!(TermIndex::sidecar_path(&dir.path().join("session_1.log.1.gz")).exists());
+
+        // The index alone rules the corrupted segment out
+        fs::write(&oldest, b"not gzip").unwrap();
+        let found = search
+            .search(&"haystack".parse().unwrap(), &SearchOptions::new())
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        assert!(
+            search
+                .search(&"needle".parse().unwrap(), &SearchOptions::new())
+                .is_err()
+        );
+
+        let removed = LogRotator::with_dir(
+            dir.path().to_path_buf(),
+            RotationPolicy::new().with_max_segments(1),
+        )
+        .apply_retention(&session)
+        .unwrap();
+        assert_eq!(removed, vec![oldest]);
+        assert!(!sidecar.exists());
+    }
+}
@@ -74,7 +74,7 @@ impl MessageSender {
         writeln!(file, "# {}", metadata.session_id.0)?;
         writeln!(file, "# timestamp: {}", metadata.timestamp)?;
         writeln!(file, "# agent: {}", metadata.agent)?;
-        writeln!(file, "")?;
+        writeln!(file)?;
         writeln!(file, "{}", prompt)?;
         file.flush()?;
 
@@ -138,7 +138,11 @@ impl FileLock {
             fs::create_dir_all(parent)?;
         }
 
-        let file = OpenOptions::new().write(true).create(true).open(path)?;
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
 
         #[cfg(unix)]
         {
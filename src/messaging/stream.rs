@@ -0,0 +1,499 @@
+//! Streaming Parser Module
+//!
+//! Parses agent output as it arrives from a pane. Chunks may end anywhere:
+//! mid-line, mid-tag, mid-fence or mid UTF-8 sequence. A segment is emitted
+//! as an `AgentResponse` once it is known to be complete.
+
+use crate::Result;
+use crate::error::TmuxError;
+use crate::messaging::timestamp::{TimestampParser, now_millis};
+use crate::opencode::{
+    AgentResponse, Block, DialectRegistry, LineKind, Protocol, block_after, find_open_tag, is_fence,
+};
+use chrono::{DateTime, Utc};
+use regex_lite::Regex;
+use std::sync::OnceLock;
+
+/// Stateful, chunk-at-a-time agent output parser.
+///
+/// Segments follow the same rules as `OutputParser::parse_multiple_outputs`:
+/// a tool call, error or markup block starts a new segment, and a closed
+/// markup block or completion marker ends one. Errors right after a tool
+/// call line are its output and stay in its segment, so the response
+/// carries both. Lines are only looked at
+/// once their newline has arrived, and nothing inside an open markup block
+/// or code fence is classified.
+pub struct StreamingParser<'a> {
+    protocol: &'a (dyn Protocol + Send + Sync),
+    /// Incomplete trailing line
+    pending: String,
+    /// Incomplete trailing UTF-8 sequence from `feed_bytes`
+    partial_utf8: Vec<u8>,
+    /// Complete lines of the current segment
+    segment: String,
+    /// Capture time of the segment's first line, in Unix milliseconds
+    segment_received_at: u64,
+    /// Whether the segment was started by a tool call line
+    segment_tool_call: bool,
+    /// Capture time of the line being processed
+    line_received_at: u64,
+    /// Capture time of lines without a capture prefix; the time they are
+    /// fed if `None`
+    fallback_received_at: Option<u64>,
+    block: Option<Block>,
+    in_fence: bool,
+}
+
+impl StreamingParser<'static> {
+    /// Parser for the OpenCode dialect
+    pub fn new() -> Self {
+        Self::for_dialect("opencode").expect("opencode dialect is built in")
+    }
+
+    /// Parser for a built-in dialect
+    pub fn for_dialect(name: &str) -> Result<Self> {
+        let registry = DialectRegistry::builtin();
+        registry.get(name).map(Self::with_protocol).ok_or_else(|| {
+            TmuxError::NotFound(format!(
+                "Unknown dialect '{}' (known: {})",
+                name,
+                registry.names().join(", ")
+            ))
+        })
+    }
+}
+
+impl Default for StreamingParser<'static> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> StreamingParser<'a> {
+    /// Parser for any dialect
+    pub fn with_protocol(protocol: &'a (dyn Protocol + Send + Sync)) -> Self {
+        Self {
+            protocol,
+            pending: String::new(),
+            partial_utf8: Vec::new(),
+            segment: String::new(),
+            segment_received_at: 0,
+            segment_tool_call: false,
+            line_received_at: 0,
+            fallback_received_at: None,
+            block: None,
+            in_fence: false,
+        }
+    }
+
+    /// Stamp lines without a capture prefix with `received_at` instead of
+    /// the time they are fed, e.g. with 0 when parsing a saved log, whose
+    /// lines were not fed as they were captured
+    pub fn with_received_at(mut self, received_at: u64) -> Self {
+        self.fallback_received_at = Some(received_at);
+        self
+    }
+
+    /// Dialect in use
+    pub fn dialect(&self) -> &str {
+        self.protocol.name()
+    }
+
+    /// Feed a chunk of text, returning the segments it completed
+    pub fn feed(&mut self, chunk: &str) -> Vec<AgentResponse> {
+        let mut responses = Vec::new();
+        self.pending.push_str(chunk);
+
+        while let Some(newline) = self.pending.find('\n') {
+            let line: String = self.pending.drain(..=newline).collect();
+            let line = line.trim_end_matches(['\n', '\r']);
+            self.process_stamped_line(line, &mut responses);
+        }
+
+        responses
+    }
+
+    /// Feed raw pane bytes. A UTF-8 sequence split across chunks is held
+    /// back until it completes; invalid bytes become U+FFFD.
+    pub fn feed_bytes(&mut self, chunk: &[u8]) -> Vec<AgentResponse> {
+        let mut bytes = std::mem::take(&mut self.partial_utf8);
+        bytes.extend_from_slice(chunk);
+
+        let mut text = String::new();
+        let mut rest = bytes.as_slice();
+        loop {
+            match std::str::from_utf8(rest) {
+                Ok(valid) => {
+                    text.push_str(valid);
+                    break;
+                }
+                Err(e) => {
+                    let (valid, after) = rest.split_at(e.valid_up_to());
+                    text.push_str(std::str::from_utf8(valid).unwrap());
+                    match e.error_len() {
+                        Some(len) => {
+                            text.push(char::REPLACEMENT_CHARACTER);
+                            rest = &after[len..];
+                        }
+                        None => {
+                            self.partial_utf8 = after.to_vec();
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        self.feed(&text)
+    }
+
+    /// Emit the current segment if it is complete so far, e.g. after the
+    /// pane has been idle. Segments inside an open markup block or code
+    /// fence are kept, as is an unterminated last line.
+    pub fn flush(&mut self) -> Vec<AgentResponse> {
+        let mut responses = Vec::new();
+        if self.block.is_none() && !self.in_fence {
+            self.close_segment(&mut responses);
+        }
+        responses
+    }
+
+    /// End of input: the last line, open blocks and fences are closed and
+    /// everything left is emitted
+    pub fn finish(&mut self) -> Vec<AgentResponse> {
+        let mut responses = Vec::new();
+
+        if !self.partial_utf8.is_empty() {
+            let bytes = std::mem::take(&mut self.partial_utf8);
+            self.pending.push_str(&String::from_utf8_lossy(&bytes));
+        }
+
+        if !self.pending.is_empty() {
+            let line = std::mem::take(&mut self.pending);
+            self.process_stamped_line(line.trim_end_matches('\r'), &mut responses);
+        }
+
+        self.block = None;
+        self.in_fence = false;
+        self.close_segment(&mut responses);
+        responses
+    }
+
+    /// Bytes buffered and not yet emitted
+    pub fn buffered(&self) -> usize {
+        self.pending.len() + self.partial_utf8.len() + self.segment.len()
+    }
+
+    /// Strip a leading capture timestamp and process the rest of the line
+    fn process_stamped_line(&mut self, line: &str, responses: &mut Vec<AgentResponse>) {
+        match split_line_timestamp(line) {
+            Some((received_at, rest)) => {
+                self.line_received_at = received_at;
+                self.process_line(rest, responses);
+            }
+            None => {
+                self.line_received_at = self.fallback_received_at.unwrap_or_else(now_millis);
+                self.process_line(line, responses);
+            }
+        }
+    }
+
+    fn process_line(&mut self, line: &str, responses: &mut Vec<AgentResponse>) {
+        if let Some(block) = self.block {
+            self.push_line(line);
+            self.block = block_after(line, Some(block));
+            if self.block.is_none() {
+                self.close_segment(responses);
+            }
+            return;
+        }
+
+        if self.in_fence || is_fence(line) {
+            self.in_fence ^= is_fence(line);
+            self.push_line(line);
+            return;
+        }
+
+        if find_open_tag(line).is_some() {
+            self.close_segment(responses);
+            self.push_line(line);
+            self.block = block_after(line, None);
+            if self.block.is_none() {
+                self.close_segment(responses);
+            }
+            return;
+        }
+
+        match self.protocol.classify_line(line) {
+            LineKind::ToolCall(_) => {
+                self.close_segment(responses);
+                self.push_line(line);
+                self.segment_tool_call = true;
+            }
+            LineKind::Error(_) if self.segment_tool_call => self.push_line(line),
+            LineKind::Error(_) => {
+                self.close_segment(responses);
+                self.push_line(line);
+            }
+            LineKind::Completion => {
+                self.push_line(line);
+                self.close_segment(responses);
+            }
+            LineKind::Text => self.push_line(line),
+        }
+    }
+
+    fn push_line(&mut self, line: &str) {
+        if self.segment.is_empty() {
+            self.segment_received_at = self.line_received_at;
+        }
+        self.segment.push_str(line);
+        self.segment.push('\n');
+    }
+
+    fn close_segment(&mut self, responses: &mut Vec<AgentResponse>) {
+        self.segment_tool_call = false;
+        let segment = std::mem::take(&mut self.segment);
+        if segment.trim().is_empty() {
+            return;
+        }
+
+        if let Ok(response) = self.protocol.parse_response(&segment) {
+            responses.push(response.with_received_at(self.segment_received_at));
+        }
+    }
+}
+
+/// Split the capture prefix a timestamping logger writes,
+/// `[2024-01-02 03:04:05] `, off a log line. It records when the line was
+/// captured, which is what `received_at` means. Only that bracketed form
+/// counts: a line that starts with a bare epoch, a syslog date or an
+/// unbracketed ISO timestamp is output, and is kept whole.
+fn split_line_timestamp(line: &str) -> Option<(u64, &str)> {
+    let found = capture_prefix().captures(line)?;
+    let timestamp = TimestampParser::new().parse(found.get(1)?.as_str())?;
+    let rest = &line[found.get(0)?.end()..];
+    Some((timestamp.timestamp_millis().max(0) as u64, rest))
+}
+
+/// Capture time a timestamping logger wrote at the start of `line`, if any
+pub fn capture_time(line: &str) -> Option<DateTime<Utc>> {
+    let (millis, _) = split_line_timestamp(line)?;
+    DateTime::from_timestamp_millis(millis as i64)
+}
+
+fn capture_prefix() -> &'static Regex {
+    static PREFIX: OnceLock<Regex> = OnceLock::new();
+    PREFIX.get_or_init(|| {
+        Regex::new(
+            r"^\[(\d{4}-\d{2}-\d{2}[ T]\d{2}:\d{2}:\d{2}(?:\.\d+)?(?:Z|[+-]\d{2}:?\d{2})?)\] ?",
+        )
+        .unwrap()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opencode::MessageType;
+
+    fn types(responses: &[AgentResponse]) -> Vec<MessageType> {
+        responses.iter().map(|r| r.message_type).collect()
+    }
+
+    #[test]
+    fn test_emits_on_next_segment() {
+        let mut parser = StreamingParser::new();
+
+        assert!(parser.feed("Looking around\n").is_empty());
+        let responses = parser.feed("Running: ls\n");
+        assert_eq!(types(&responses), vec![MessageType::Message]);
+
+        let responses = parser.finish();
+        assert_eq!(types(&responses), vec![MessageType::ToolCall]);
+    }
+
+    #[test]
+    fn test_tool_call_tag_split_across_chunks() {
+        let mut parser = StreamingParser::new();
+        let mut responses = Vec::new();
+
+        for chunk in [
+            "Let me check.\n<tool",
+            "_call>bash comm",
+            "and=ls\n",
+            "dir=src</tool_",
+            "call>\n",
+        ] {
+            responses.extend(parser.feed(chunk));
+        }
+
+        assert_eq!(
+            types(&responses),
+            vec![MessageType::Message, MessageType::ToolCall]
+        );
+        assert_eq!(responses[1].tool_calls[0].tool_name, "bash");
+        assert_eq!(responses[1].tool_calls[0].arguments["dir"], "src");
+        assert_eq!(parser.buffered(), 0);
+    }
+
+    #[test]
+    fn test_code_fence_split_across_chunks() {
+        let mut parser = StreamingParser::new();
+        let mut responses = Vec::new();
+
+        for chunk in [
+            "Example:\n`",
+            "``sh\nRunning: rm -rf /\nerror: nope\n`",
+            "``\nDone.\n",
+        ] {
+            responses.extend(parser.feed(chunk));
+        }
+
+        // Nothing inside the fence is a tool call or an error
+        assert_eq!(types(&responses), vec![MessageType::Completion]);
+        assert!(responses[0].content.contains("Running: rm -rf /"));
+    }
+
+    #[test]
+    fn test_flush_keeps_open_blocks() {
+        let mut parser = StreamingParser::new();
+
+        parser.feed("<error>first line\n");
+        assert!(parser.flush().is_empty());
+
+        let responses = parser.feed("second line</error>\n");
+        assert_eq!(types(&responses), vec![MessageType::Error]);
+        assert_eq!(
+            responses[0].error.as_deref(),
+            Some("first line\nsecond line")
+        );
+    }
+
+    #[test]
+    fn test_finish_takes_unterminated_line() {
+        let mut parser = StreamingParser::new();
+        assert!(parser.feed("Done.").is_empty());
+        assert_eq!(types(&parser.finish()), vec![MessageType::Completion]);
+    }
+
+    #[test]
+    fn test_utf8_split_across_chunks() {
+        let mut parser = StreamingParser::for_dialect("claude").unwrap();
+        let bytes = "⏺ Bash(cargo test)\n".as_bytes();
+
+        assert!(parser.feed_bytes(&bytes[..1]).is_empty());
+        parser.feed_bytes(&bytes[1..]);
+        let responses = parser.finish();
+
+        assert_eq!(types(&responses), vec![MessageType::ToolCall]);
+        assert_eq!(responses[0].tool_calls[0].tool_name, "bash");
+    }
+
+    #[test]
+    fn test_received_at() {
+        let mut parser = StreamingParser::new();
+        let before = now_millis();
+
+        // A leading capture timestamp is stripped before classification
+        parser.feed("[2024-01-02 03:04:05] Running: ls\n");
+        let responses = parser.feed("Running: pwd\n");
+        assert_eq!(types(&responses), vec![MessageType::ToolCall]);
+        assert_eq!(responses[0].received_at, 1704164645000);
+        assert!(!responses[0].content.contains("2024"));
+
+        // Timestamps later in a line are content
+        parser.feed("Fixed on 2023-05-06 07:08:09 as planned\n");
+        let responses = parser.finish();
+        assert_eq!(responses.len(), 1);
+        assert!(responses[0].received_at >= before);
+
+        // A fallback replaces the feed time, not a capture prefix
+        let mut parser = StreamingParser::new().with_received_at(42);
+        let mut responses = parser.feed("Looking around\n[2024-01-02 03:04:05] Running: ls\n");
+        responses.extend(parser.finish());
+        assert_eq!(responses[0].received_at, 42);
+        assert_eq!(responses[1].received_at, 1704164645000);
+    }
+
+    #[test]
+    fn test_only_capture_prefix_is_stripped() {
+        let lines = [
+            "1704164645 files checked",
+            "1704164645123 bytes copied",
+            "Jan  2 03:04:05 host sshd[42]: session opened",
+            "2024-01-02T03:04:05Z build started",
+            "[Jan  2 03:04:05] not a capture prefix",
+        ];
+        for line in lines {
+            let mut parser = StreamingParser::new();
+            parser.feed(&format!("{}\n", line));
+            let responses = parser.finish();
+            assert_eq!(responses[0].content, line);
+            assert_ne!(responses[0].received_at, 1704164645000);
+            assert_eq!(capture_time(line), None);
+        }
+        assert_eq!(
+            capture_time("[2024-01-02 03:04:05] Running: ls").map(|t| t.timestamp_millis()),
+            Some(1704164645000)
+        );
+
+        let mut parser = StreamingParser::new();
+        parser.feed("[2024-01-02T03:04:05.250+01:00]   indented\n");
+        let responses = parser.finish();
+        assert_eq!(responses[0].received_at, 1704161045250);
+        assert_eq!(responses[0].content, "indented");
+    }
+
+    #[test]
+    fn test_matches_whole_string_parse() {
+        let output = "Plan\nRunning: read a.rs\nRunning: bash cargo test\nerror: failed\n<complete>ok</complete>\ntrailing";
+
+        let mut parser = StreamingParser::new();
+        let mut streamed = Vec::new();
+        for chunk in output.as_bytes().chunks(3) {
+            streamed.extend(parser.feed_bytes(chunk));
+        }
+        streamed.extend(parser.finish());
+
+        let mut whole = StreamingParser::new();
+        let mut expected = whole.feed(output);
+        expected.extend(whole.finish());
+
+        assert_eq!(types(&streamed), types(&expected));
+        assert_eq!(
+            types(&streamed),
+            vec![
+                MessageType::Message,
+                MessageType::ToolCall,
+                MessageType::Error,
+                MessageType::Completion,
+                MessageType::Message,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_errors_stay_with_their_tool_call() {
+        let mut parser = StreamingParser::new();
+        let mut responses =
+            parser.feed("Running: bash cargo test\ntest foo ... FAILED\nerror: test failed\n");
+        responses.extend(parser.feed("error: could not compile\n"));
+        responses.extend(parser.finish());
+
+        assert_eq!(types(&responses), vec![MessageType::Error]);
+        assert!(!responses[0].success);
+        assert_eq!(responses[0].tool_calls[0].tool_name, "bash");
+        assert_eq!(responses[0].errors.len(), 3);
+
+        // Without a tool call each error is still its own segment
+        let mut parser = StreamingParser::new();
+        let mut responses = parser.feed("error: first\nerror: second\n");
+        responses.extend(parser.finish());
+        assert_eq!(
+            types(&responses),
+            vec![MessageType::Error, MessageType::Error]
+        );
+        assert!(responses.iter().all(|r| r.tool_calls.is_empty()));
+    }
+}
@@ -0,0 +1,409 @@
+//! Timestamp Parsing Module
+//!
+//! Parses the timestamp formats that show up in agent output and pane
+//! logs: ISO 8601 / RFC 3339, RFC 2822, Unix epochs in seconds through
+//! nanoseconds, syslog (`Jan  2 03:04:05`) and the ctime and status-line
+//! formats tmux prints.
+
+use chrono::{
+    DateTime, Datelike, FixedOffset, Local, NaiveDate, NaiveDateTime, Offset, TimeZone, Utc,
+};
+use regex_lite::Regex;
+use std::sync::OnceLock;
+
+/// Epoch values outside 2001-09-09..2100-01-01 are treated as plain numbers
+const EPOCH_SECS_MIN: i64 = 1_000_000_000;
+const EPOCH_SECS_MAX: i64 = 4_102_444_800;
+
+/// Milliseconds since the Unix epoch, now
+pub fn now_millis() -> u64 {
+    Utc::now().timestamp_millis().max(0) as u64
+}
+
+/// Timestamp parser. Timestamps without a zone are read in `offset`, and
+/// syslog timestamps, which have no year, get the year of `reference`.
+#[derive(Debug, Clone, Copy)]
+pub struct TimestampParser {
+    offset: FixedOffset,
+    reference: DateTime<Utc>,
+}
+
+impl TimestampParser {
+    /// Parser that reads zone-less timestamps as UTC
+    pub fn new() -> Self {
+        Self {
+            offset: Utc.fix(),
+            reference: Utc::now(),
+        }
+    }
+
+    /// Parser that reads zone-less timestamps in the local time zone
+    pub fn local() -> Self {
+        Self::new().with_offset(Local::now().offset().fix())
+    }
+
+    /// Read zone-less timestamps in `offset`
+    pub fn with_offset(mut self, offset: FixedOffset) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Resolve year-less timestamps relative to `reference`. A syslog date
+    /// more than a day after `reference` is taken to be from the year
+    /// before, so logs read just after New Year land in the right year.
+    pub fn with_reference(mut self, reference: DateTime<Utc>) -> Self {
+        self.reference = reference;
+        self
+    }
+
+    /// Parse `text` as a whole timestamp
+    pub fn parse(&self, text: &str) -> Option<DateTime<Utc>> {
+        let text = text.trim();
+        if text.is_empty() {
+            return None;
+        }
+
+        if let Some(dt) = parse_epoch(text) {
+            return Some(dt);
+        }
+
+        if let Ok(dt) = DateTime::parse_from_rfc3339(text) {
+            return Some(dt.with_timezone(&Utc));
+        }
+
+        if let Ok(dt) = DateTime::parse_from_rfc2822(text) {
+            return Some(dt.with_timezone(&Utc));
+        }
+
+        let (body, offset) = split_zone(text);
+        let offset = offset.unwrap_or(self.offset);
+
+        const WITH_DATE: [&str; 8] = [
+            "%Y-%m-%dT%H:%M:%S%.f",
+            "%Y-%m-%d %H:%M:%S%.f",
+            "%Y-%m-%dT%H:%M",
+            "%Y-%m-%d %H:%M",
+            "%Y/%m/%d %H:%M:%S%.f",
+            // ctime, as printed by tmux for #{t:...} and `date`
+            "%a %b %e %H:%M:%S %Y",
+            // tmux's default status-right
+            "%H:%M %d-%b-%y",
+            "%d/%b/%Y:%H:%M:%S",
+        ];
+        for format in WITH_DATE {
+            if let Ok(naive) = NaiveDateTime::parse_from_str(body, format) {
+                return self.resolve(naive, offset);
+            }
+        }
+
+        if let Ok(date) = NaiveDate::parse_from_str(body, "%Y-%m-%d") {
+            return self.resolve(date.and_hms_opt(0, 0, 0)?, offset);
+        }
+
+        self.parse_syslog(body, offset)
+    }
+
+    /// Find the first timestamp embedded in `line`, e.g. `[2024-01-02 ...]`,
+    /// `ts=1704164645123` or a syslog prefix
+    pub fn find(&self, line: &str) -> Option<DateTime<Utc>> {
+        self.find_with_span(line).map(|(dt, _)| dt)
+    }
+
+    /// Like `find`, also returning the byte range of the match
+    pub fn find_with_span(&self, line: &str) -> Option<(DateTime<Utc>, std::ops::Range<usize>)> {
+        let mut best: Option<(DateTime<Utc>, std::ops::Range<usize>)> = None;
+
+        for re in patterns() {
+            for found in re.find_iter(line) {
+                if best
+                    .as_ref()
+                    .is_some_and(|(_, span)| span.start <= found.start())
+                {
+                    break;
+                }
+                if let Some(dt) = self.parse(found.as_str()) {
+                    best = Some((dt, found.range()));
+                    break;
+                }
+            }
+        }
+
+        best
+    }
+
+    fn resolve(&self, naive: NaiveDateTime, offset: FixedOffset) -> Option<DateTime<Utc>> {
+        offset
+            .from_local_datetime(&naive)
+            .single()
+            .map(|dt| dt.with_timezone(&Utc))
+    }
+
+    /// `Jan  2 03:04:05`, optionally with fractional seconds. The year is
+    /// the latest one in which the date exists and is not after the
+    /// reference, so `Feb 29` goes back to the last leap year.
+    fn parse_syslog(&self, text: &str, offset: FixedOffset) -> Option<DateTime<Utc>> {
+        let year = self.reference.with_timezone(&offset).year();
+
+        // Leap years are at most eight years apart
+        for year in (year - 8..=year).rev() {
+            let candidate = format!("{} {}", year, text);
+            let Ok(naive) = NaiveDateTime::parse_from_str(&candidate, "%Y %b %e %H:%M:%S%.f")
+            else {
+                continue;
+            };
+            let Some(dt) = self.resolve(naive, offset) else {
+                continue;
+            };
+            if dt <= self.reference + chrono::Duration::days(1) {
+                return Some(dt);
+            }
+        }
+
+        None
+    }
+}
+
+impl Default for TimestampParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Parse `text` as a whole timestamp, reading zone-less ones as UTC
+pub fn parse_timestamp(text: &str) -> Option<DateTime<Utc>> {
+    TimestampParser::new().parse(text)
+}
+
+/// First timestamp embedded in `line`, reading zone-less ones as UTC
+pub fn find_timestamp(line: &str) -> Option<DateTime<Utc>> {
+    TimestampParser::new().find(line)
+}
+
+/// Epoch seconds, milliseconds, microseconds or nanoseconds, told apart by
+/// digit count. Fractional seconds (`1704164645.25`) are accepted.
+fn parse_epoch(text: &str) -> Option<DateTime<Utc>> {
+    let (whole, fraction) = match text.split_once('.') {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (text, None),
+    };
+    if whole.is_empty() || !whole.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let value: i64 = whole.parse().ok()?;
+    let nanos_per_unit: i64 = match whole.len() {
+        10 => 1_000_000_000,
+        13 if fraction.is_none() => 1_000_000,
+        16 if fraction.is_none() => 1_000,
+        19 if fraction.is_none() => 1,
+        _ => return None,
+    };
+
+    let secs = value / (1_000_000_000 / nanos_per_unit);
+    if !(EPOCH_SECS_MIN..EPOCH_SECS_MAX).contains(&secs) {
+        return None;
+    }
+
+    let mut nanos = (value % (1_000_000_000 / nanos_per_unit)) * nanos_per_unit;
+    if let Some(fraction) = fraction {
+        if fraction.is_empty() || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let digits: String = fraction
+            .chars()
+            .chain("000000000".chars())
+            .take(9)
+            .collect();
+        nanos = digits.parse().ok()?;
+    }
+
+    DateTime::from_timestamp(secs, nanos as u32)
+}
+
+/// Split a trailing zone (`Z`, `UTC`, `GMT`, `+02:00`, `+0200`) off `text`
+fn split_zone(text: &str) -> (&str, Option<FixedOffset>) {
+    for suffix in ["Z", " UTC", " GMT", " Z"] {
+        if let Some(body) = text.strip_suffix(suffix) {
+            return (body.trim_end(), Some(Utc.fix()));
+        }
+    }
+
+    let bytes = text.as_bytes();
+    for len in [6, 5] {
+        if bytes.len() <= len || !text.is_char_boundary(text.len() - len) {
+            continue;
+        }
+        let zone = &text[text.len() - len..];
+        let sign = match zone.as_bytes()[0] {
+            b'+' => 1,
+            b'-' => -1,
+            _ => continue,
+        };
+        let digits: String = zone[1..].chars().filter(|c| *c != ':').collect();
+        if digits.len() != 4 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            continue;
+        }
+        if len == 6 && zone.as_bytes()[3] != b':' {
+            continue;
+        }
+        // `2024-01-02` ends in `-01-02`; a zone must follow a time
+        let body = text[..text.len() - len].trim_end();
+        if !body.ends_with(|c: char| c.is_ascii_digit()) || !body.contains(':') {
+            continue;
+        }
+
+        // Both are two ASCII digits, checked above
+        let hours: i32 = digits[..2].parse().unwrap_or(0);
+        let minutes: i32 = digits[2..].parse().unwrap_or(0);
+        let offset = FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60));
+        return (body.trim_end(), offset);
+    }
+
+    (text, None)
+}
+
+/// Patterns for timestamps embedded in a line, most specific first
+fn patterns() -> &'static [Regex] {
+    static PATTERNS: OnceLock<Vec<Regex>> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        [
+            // ISO 8601 / RFC 3339
+            r"\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}(?::\d{2}(?:[.,]\d+)?)?(?:Z|[+-]\d{2}:?\d{2}| UTC)?",
+            // RFC 2822
+            r"[A-Z][a-z]{2}, \d{1,2} [A-Z][a-z]{2} \d{4} \d{2}:\d{2}:\d{2} [+-]\d{4}",
+            // ctime
+            r"[A-Z][a-z]{2} [A-Z][a-z]{2} [ \d]\d \d{2}:\d{2}:\d{2} \d{4}",
+            // syslog
+            r"[A-Z][a-z]{2} [ \d]\d \d{2}:\d{2}:\d{2}(?:\.\d+)?",
+            // tmux status line
+            r"\d{2}:\d{2} \d{2}-[A-Z][a-z]{2}-\d{2}",
+            // Unix epoch in s, ms, us or ns
+            r"\b(?:\d{19}|\d{16}|\d{13}|\d{10}(?:\.\d+)?)\b",
+        ]
+        .iter()
+        .map(|p| Regex::new(p).expect("valid timestamp pattern"))
+        .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_iso_formats_and_zones() {
+        assert_eq!(
+            parse_timestamp("2024-01-02T03:04:05Z"),
+            Some(utc("2024-01-02T03:04:05Z"))
+        );
+        assert_eq!(
+            parse_timestamp("2024-01-02T05:04:05.5+02:00"),
+            Some(utc("2024-01-02T03:04:05.5Z"))
+        );
+        assert_eq!(
+            parse_timestamp("2024-01-02 05:04:05 +0200"),
+            Some(utc("2024-01-02T03:04:05Z"))
+        );
+        assert_eq!(
+            parse_timestamp("2024-01-02 03:04:05"),
+            Some(utc("2024-01-02T03:04:05Z"))
+        );
+        assert_eq!(
+            parse_timestamp("2024-01-02"),
+            Some(utc("2024-01-02T00:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn test_default_offset_for_naive_timestamps() {
+        let parser = TimestampParser::new().with_offset(FixedOffset::east_opt(3600).unwrap());
+        assert_eq!(
+            parser.parse("2024-01-02 04:04:05"),
+            Some(utc("2024-01-02T03:04:05Z"))
+        );
+        // An explicit zone wins
+        assert_eq!(
+            parser.parse("2024-01-02 03:04:05Z"),
+            Some(utc("2024-01-02T03:04:05Z"))
+        );
+    }
+
+    #[test]
+    fn test_epoch_units() {
+        let expected = utc("2024-01-02T03:04:05Z");
+        assert_eq!(parse_timestamp("1704164645"), Some(expected));
+        assert_eq!(parse_timestamp("1704164645000"), Some(expected));
+        assert_eq!(parse_timestamp("1704164645000000"), Some(expected));
+        assert_eq!(parse_timestamp("1704164645000000000"), Some(expected));
+        assert_eq!(
+            parse_timestamp("1704164645.25"),
+            Some(utc("2024-01-02T03:04:05.25Z"))
+        );
+        assert_eq!(parse_timestamp("12345"), None);
+    }
+
+    #[test]
+    fn test_syslog_and_tmux_formats() {
+        let parser = TimestampParser::new().with_reference(utc("2024-06-01T00:00:00Z"));
+        assert_eq!(
+            parser.parse("Jan  2 03:04:05"),
+            Some(utc("2024-01-02T03:04:05Z"))
+        );
+        // December seen in June is this year, but seen in January it is last year's
+        let january = TimestampParser::new().with_reference(utc("2024-01-01T00:10:00Z"));
+        assert_eq!(
+            january.parse("Dec 31 23:59:00"),
+            Some(utc("2023-12-31T23:59:00Z"))
+        );
+        // Feb 29 is from the last leap year
+        let later = TimestampParser::new().with_reference(utc("2027-03-01T00:00:00Z"));
+        assert_eq!(
+            later.parse("Feb 29 12:00:00"),
+            Some(utc("2024-02-29T12:00:00Z"))
+        );
+        assert_eq!(
+            january.parse("Feb 29 12:00:00"),
+            Some(utc("2020-02-29T12:00:00Z"))
+        );
+        assert_eq!(parser.parse("Feb 30 12:00:00"), None);
+
+        assert_eq!(
+            parse_timestamp("Tue Jan  2 03:04:05 2024"),
+            Some(utc("2024-01-02T03:04:05Z"))
+        );
+        assert_eq!(
+            parse_timestamp("03:04 02-Jan-24"),
+            Some(utc("2024-01-02T03:04:00Z"))
+        );
+        assert_eq!(
+            parse_timestamp("Tue, 2 Jan 2024 04:04:05 +0100"),
+            Some(utc("2024-01-02T03:04:05Z"))
+        );
+    }
+
+    #[test]
+    fn test_find_embedded() {
+        let expected = utc("2024-01-02T03:04:05Z");
+        assert_eq!(
+            find_timestamp("[2024-01-02 03:04:05] build ok"),
+            Some(expected)
+        );
+        assert_eq!(
+            find_timestamp("event ts=1704164645123 done"),
+            Some(utc("2024-01-02T03:04:05.123Z"))
+        );
+        assert_eq!(find_timestamp("[1704164645] started"), Some(expected));
+        assert_eq!(find_timestamp("no time here, just 42 and 2024"), None);
+
+        let (_, span) = TimestampParser::new()
+            .find_with_span("at 2024-01-02T03:04:05Z then 1704164645")
+            .unwrap();
+        assert_eq!(span, 3..23);
+    }
+}
@@ -0,0 +1,648 @@
+//! Transcript Module
+//!
+//! The conversation of one session or pane in order: prompts sent through
+//! `MessageSender` and `MessageQueue` interleaved with the responses
+//! parsed from the session log.
+
+use crate::Result;
+use crate::error::TmuxError;
+use crate::messaging::parser::OutputParser;
+use crate::messaging::query::Query;
+use crate::messaging::queue::QueuedMessage;
+use crate::messaging::read::LogReader;
+use crate::messaging::timestamp::parse_timestamp;
+use crate::opencode::{AgentResponse, SessionMessage};
+use crate::types::{Message, PaneId, SessionId};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+const QUEUE_DIR: &str = ".opencode/queue";
+const PROMPT_DIR: &str = ".opencode/prompts";
+
+/// What a transcript entry holds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EntryKind {
+    /// Prompt sent to the agent
+    Prompt {
+        content: String,
+        #[serde(default)]
+        agent: Option<String>,
+        #[serde(default)]
+        retries: u32,
+    },
+    /// Parsed agent output
+    Response { response: AgentResponse },
+}
+
+/// One prompt or response in a transcript
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptEntry {
+    /// Message id for prompts; `<session>:r<n>` for log responses
+    pub id: String,
+    /// When the entry was sent or captured, in Unix milliseconds. 0 if
+    /// unknown.
+    pub timestamp: u64,
+    #[serde(default)]
+    pub pane_id: Option<PaneId>,
+    #[serde(flatten)]
+    pub kind: EntryKind,
+}
+
+impl TranscriptEntry {
+    pub fn is_prompt(&self) -> bool {
+        matches!(self.kind, EntryKind::Prompt { .. })
+    }
+
+    pub fn response(&self) -> Option<&AgentResponse> {
+        match &self.kind {
+            EntryKind::Response { response } => Some(response),
+            EntryKind::Prompt { .. } => None,
+        }
+    }
+
+    /// Prompt text or response content
+    pub fn text(&self) -> &str {
+        match &self.kind {
+            EntryKind::Prompt { content, .. } => content,
+            EntryKind::Response { response } => &response.content,
+        }
+    }
+}
+
+/// A prompt and the responses up to the next prompt
+#[derive(Debug, Clone)]
+pub struct Turn<'a> {
+    /// None for output that arrived before the first prompt
+    pub prompt: Option<&'a TranscriptEntry>,
+    pub responses: Vec<&'a TranscriptEntry>,
+}
+
+impl Turn<'_> {
+    /// Whether any response in the turn failed
+    pub fn failed(&self) -> bool {
+        self.responses
+            .iter()
+            .filter_map(|entry| entry.response())
+            .any(|response| !response.success)
+    }
+}
+
+/// Ordered conversation of a session or pane
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transcript {
+    pub session_id: SessionId,
+    #[serde(default)]
+    pub pane_id: Option<PaneId>,
+    entries: Vec<TranscriptEntry>,
+}
+
+impl Transcript {
+    pub fn new(session_id: SessionId) -> Self {
+        Self {
+            session_id,
+            pane_id: None,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Transcript limited to one pane of the session
+    pub fn for_pane(session_id: SessionId, pane_id: PaneId) -> Self {
+        Self {
+            pane_id: Some(pane_id),
+            ..Self::new(session_id)
+        }
+    }
+
+    /// Builder that loads prompts and responses from disk
+    pub fn builder(session_id: SessionId) -> TranscriptBuilder {
+        TranscriptBuilder::new(session_id)
+    }
+
+    /// Entries in order
+    pub fn entries(&self) -> &[TranscriptEntry] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, id: &str) -> Option<&TranscriptEntry> {
+        self.entries.iter().find(|entry| entry.id == id)
+    }
+
+    /// Add an entry in timestamp order. Entries with equal timestamps keep
+    /// the order they were added in. Entries without a timestamp go at the
+    /// end, and later entries are never moved in front of them.
+    pub fn push(&mut self, entry: TranscriptEntry) {
+        if entry.timestamp == 0 {
+            self.entries.push(entry);
+            return;
+        }
+
+        let at = self
+            .entries
+            .iter()
+            .rposition(|e| e.timestamp <= entry.timestamp)
+            .map_or(0, |last| last + 1);
+        self.entries.insert(at, entry);
+    }
+
+    /// Add a prompt from `MessageQueue::send_message` or `Message` directly
+    pub fn push_message(&mut self, message: &Message) {
+        self.push(TranscriptEntry {
+            id: message.id.clone(),
+            timestamp: message.timestamp.saturating_mul(1000),
+            pane_id: Some(message.pane_id.clone()),
+            kind: EntryKind::Prompt {
+                content: message.content.clone(),
+                agent: None,
+                retries: 0,
+            },
+        });
+    }
+
+    /// Add a queued prompt, timed by when it was queued
+    pub fn push_queued(&mut self, queued: &QueuedMessage) {
+        self.push(TranscriptEntry {
+            id: queued.message.id.clone(),
+            timestamp: queued.queued_at,
+            pane_id: Some(queued.message.pane_id.clone()),
+            kind: EntryKind::Prompt {
+                content: queued.message.content.clone(),
+                agent: None,
+                retries: queued.retries,
+            },
+        });
+    }
+
+    /// Add a parsed response. Its id is `<session>:r<n>`, numbered in the
+    /// order responses are added.
+    pub fn push_response(&mut self, response: AgentResponse) {
+        let n = self.entries.iter().filter(|e| !e.is_prompt()).count();
+        self.push(TranscriptEntry {
+            id: format!("{}:r{}", self.session_id.0, n),
+            timestamp: response.received_at,
+            pane_id: self.pane_id.clone(),
+            kind: EntryKind::Response { response },
+        });
+    }
+
+    /// Add an OpenCode session message: `user` messages are prompts and
+    /// everything else is parsed as agent output
+    pub fn push_session_message(&mut self, message: &SessionMessage) -> Result<()> {
+        let timestamp = parse_timestamp(&message.timestamp)
+            .map(|ts| ts.timestamp_millis().max(0) as u64)
+            .unwrap_or(0);
+
+        let kind = if message.role == "user" {
+            EntryKind::Prompt {
+                content: message.content.clone(),
+                agent: None,
+                retries: 0,
+            }
+        } else {
+            let response = OutputParser::parse_agent_output(&message.content)?;
+            EntryKind::Response {
+                response: response.with_received_at(timestamp),
+            }
+        };
+
+        self.push(TranscriptEntry {
+            id: message.id.clone(),
+            timestamp,
+            pane_id: self.pane_id.clone(),
+            kind,
+        });
+        Ok(())
+    }
+
+    pub fn prompts(&self) -> impl Iterator<Item = &TranscriptEntry> {
+        self.entries.iter().filter(|entry| entry.is_prompt())
+    }
+
+    pub fn responses(&self) -> impl Iterator<Item = &AgentResponse> {
+        self.entries.iter().filter_map(TranscriptEntry::response)
+    }
+
+    /// Prompts with the responses that followed each
+    pub fn turns(&self) -> Vec<Turn<'_>> {
+        let mut turns: Vec<Turn<'_>> = Vec::new();
+
+        for entry in &self.entries {
+            if entry.is_prompt() {
+                turns.push(Turn {
+                    prompt: Some(entry),
+                    responses: Vec::new(),
+                });
+                continue;
+            }
+
+            match turns.last_mut() {
+                Some(turn) => turn.responses.push(entry),
+                None => turns.push(Turn {
+                    prompt: None,
+                    responses: vec![entry],
+                }),
+            }
+        }
+
+        turns
+    }
+
+    /// Response entries matching `query`
+    pub fn query(&self, query: &Query) -> Vec<&TranscriptEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.response().is_some_and(|r| query.matches(r)))
+            .collect()
+    }
+
+    /// Turns with at least one response matching `query`, so each hit is
+    /// shown with the prompt that led to it
+    pub fn turns_matching(&self, query: &Query) -> Vec<Turn<'_>> {
+        self.turns()
+            .into_iter()
+            .filter(|turn| {
+                turn.responses
+                    .iter()
+                    .any(|entry| entry.response().is_some_and(|r| query.matches(r)))
+            })
+            .collect()
+    }
+
+    /// Write one JSON entry per line
+    pub fn write_jsonl<W: Write>(&self, mut writer: W) -> Result<()> {
+        for entry in &self.entries {
+            serde_json::to_writer(&mut writer, entry)?;
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Read entries written by `write_jsonl`
+    pub fn read_jsonl<R: BufRead>(session_id: SessionId, reader: R) -> Result<Self> {
+        let mut transcript = Self::new(session_id);
+        for (n, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry = serde_json::from_str(&line)
+                .map_err(|e| TmuxError::Parse(format!("Transcript line {}: {}", n + 1, e)))?;
+            transcript.entries.push(entry);
+        }
+        Ok(transcript)
+    }
+}
+
+/// Loads a `Transcript` from the files the messaging layer writes: queued
+/// messages and prompt files under the base path, and the session log.
+pub struct TranscriptBuilder {
+    session_id: SessionId,
+    pane_id: Option<PaneId>,
+    base_path: Option<PathBuf>,
+    log_reader: Option<LogReader>,
+    dialect: Option<String>,
+}
+
+impl TranscriptBuilder {
+    fn new(session_id: SessionId) -> Self {
+        Self {
+            session_id,
+            pane_id: None,
+            base_path: None,
+            log_reader: None,
+            dialect: None,
+        }
+    }
+
+    /// Only prompts sent to this pane
+    pub fn pane(mut self, pane_id: PaneId) -> Self {
+        self.pane_id = Some(pane_id);
+        self
+    }
+
+    /// Base path given to `MessageSender` and `MessageQueue`
+    pub fn base_path<P: AsRef<Path>>(mut self, base_path: P) -> Self {
+        self.base_path = Some(base_path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Log reader for responses; the default log directory if unset
+    pub fn log_reader(mut self, log_reader: LogReader) -> Self {
+        self.log_reader = Some(log_reader);
+        self
+    }
+
+    /// Dialect of the session log; detected if unset
+    pub fn dialect(mut self, dialect: impl Into<String>) -> Self {
+        self.dialect = Some(dialect.into());
+        self
+    }
+
+    pub fn build(self) -> Result<Transcript> {
+        let mut transcript = match &self.pane_id {
+            Some(pane_id) => Transcript::for_pane(self.session_id.clone(), pane_id.clone()),
+            None => Transcript::new(self.session_id.clone()),
+        };
+
+        if let Some(base_path) = &self.base_path {
+            let queued = self.queued_messages(base_path)?;
+            for message in &queued {
+                transcript.push_queued(message);
+            }
+            if let Some(entry) = self.prompt_file_entry(base_path)? {
+                // The queue already has prompts sent through it
+                if !queued
+                    .iter()
+                    .any(|q| q.message.content.trim() == entry.text().trim())
+                {
+                    transcript.push(entry);
+                }
+            }
+        }
+
+        let log_reader = self.log_reader.unwrap_or_default();
+        for response in log_reader.read_responses(&self.session_id, self.dialect.as_deref())? {
+            transcript.push_response(response);
+        }
+
+        Ok(transcript)
+    }
+
+    fn targets(&self, pane_id: &PaneId) -> bool {
+        match &self.pane_id {
+            Some(wanted) => wanted == pane_id,
+            None => {
+                pane_id.0 == self.session_id.0
+                    || pane_id.0.starts_with(&format!("{}:", self.session_id.0))
+            }
+        }
+    }
+
+    /// Queued messages for the session, sent or not, oldest first
+    fn queued_messages(&self, base_path: &Path) -> Result<Vec<QueuedMessage>> {
+        let queue_dir = base_path.join(QUEUE_DIR);
+        if !queue_dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut messages = Vec::new();
+        for entry in fs::read_dir(queue_dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|e| e != "msg") {
+                continue;
+            }
+            let content = fs::read_to_string(&path)?;
+            if let Ok(queued) = serde_json::from_str::<QueuedMessage>(&content)
+                && self.targets(&queued.message.pane_id)
+            {
+                messages.push(queued);
+            }
+        }
+
+        messages.sort_by_key(|q| q.queued_at);
+        Ok(messages)
+    }
+
+    /// Last prompt written by `MessageSender` for the pane. Only the
+    /// latest prompt is kept on disk.
+    fn prompt_file_entry(&self, base_path: &Path) -> Result<Option<TranscriptEntry>> {
+        let pane_id = self
+            .pane_id
+            .clone()
+            .unwrap_or_else(|| PaneId(format!("{}:0.0", self.session_id.0)));
+        let path = base_path
+            .join(PROMPT_DIR)
+            .join(format!("{}.prompt.input", pane_id.0));
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let text = fs::read_to_string(&path)?;
+        let mut timestamp = 0;
+        let mut agent = None;
+        let mut body = text.as_str();
+
+        // Header written by `send_prompt_with_metadata`
+        if body
+            .lines()
+            .nth(1)
+            .is_some_and(|l| l.starts_with("# timestamp: "))
+        {
+            let mut rest = body;
+            while let Some(line) = rest.lines().next().filter(|l| l.starts_with("# ")) {
+                if let Some(secs) = line.strip_prefix("# timestamp: ") {
+                    timestamp = secs.trim().parse::<u64>().unwrap_or(0).saturating_mul(1000);
+                } else if let Some(name) = line.strip_prefix("# agent: ") {
+                    agent = Some(name.trim().to_string());
+                }
+                rest = rest[line.len()..].trim_start_matches(['\r', '\n']);
+            }
+            body = rest;
+        }
+
+        let modified = fs::metadata(&path)?
+            .modified()
+            .ok()
+            .and_then(|m| m.duration_since(std::time::UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_millis() as u64);
+
+        Ok(Some(TranscriptEntry {
+            id: format!("{}:prompt", pane_id.0),
+            timestamp: if timestamp == 0 { modified } else { timestamp },
+            pane_id: Some(pane_id),
+            kind: EntryKind::Prompt {
+                content: body.trim_end().to_string(),
+                agent,
+                retries: 0,
+            },
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messaging::queue::MessageQueue;
+    use crate::messaging::send::{MessageSender, PromptMetadata};
+    use tempfile::TempDir;
+
+    fn response(content: &str, received_at: u64) -> AgentResponse {
+        OutputParser::parse_agent_output(content)
+            .unwrap()
+            .with_received_at(received_at)
+    }
+
+    fn prompt(id: &str, content: &str, timestamp: u64) -> TranscriptEntry {
+        TranscriptEntry {
+            id: id.to_string(),
+            timestamp,
+            pane_id: None,
+            kind: EntryKind::Prompt {
+                content: content.to_string(),
+                agent: None,
+                retries: 0,
+            },
+        }
+    }
+
+    #[test]
+    fn test_interleaves_by_timestamp() {
+        let mut transcript = Transcript::new(SessionId("s".to_string()));
+        transcript.push_response(response("Running: bash cargo test", 3_000));
+        transcript.push(prompt("p1", "run the tests", 1_000));
+        transcript.push_response(response("error: 2 tests failed", 4_000));
+        transcript.push(prompt("p2", "fix them", 5_000));
+        transcript.push_response(response("Done.", 0));
+
+        let ids: Vec<&str> = transcript.entries().iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(ids, vec!["p1", "s:r0", "s:r1", "p2", "s:r2"]);
+
+        let turns = transcript.turns();
+        assert_eq!(turns.len(), 2);
+        assert_eq!(turns[0].prompt.unwrap().text(), "run the tests");
+        assert_eq!(turns[0].responses.len(), 2);
+        assert!(turns[0].failed());
+        assert!(!turns[1].failed());
+    }
+
+    #[test]
+    fn test_equal_timestamps_keep_insertion_order() {
+        let mut transcript = Transcript::new(SessionId("s".to_string()));
+        transcript.push(prompt("a", "first", 1_000));
+        transcript.push(prompt("b", "second", 1_000));
+        transcript.push(prompt("c", "earlier", 500));
+
+        let ids: Vec<&str> = transcript.entries().iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(ids, vec!["c", "a", "b"]);
+    }
+
+    #[test]
+    fn test_query_and_turns_matching() {
+        let mut transcript = Transcript::new(SessionId("s".to_string()));
+        transcript.push(prompt("p1", "build", 1_000));
+        transcript.push_response(response("Running: bash cargo build", 2_000));
+        transcript.push(prompt("p2", "test", 3_000));
+        transcript.push_response(response("error: test failed", 4_000));
+
+        let query = Query::parse("type:error").unwrap();
+        assert_eq!(transcript.query(&query).len(), 1);
+
+        let turns = transcript.turns_matching(&query);
+        assert_eq!(turns.len(), 1);
+        assert_eq!(turns[0].prompt.unwrap().id, "p2");
+    }
+
+    #[test]
+    fn test_jsonl_round_trip() {
+        let mut transcript = Transcript::new(SessionId("s".to_string()));
+        transcript.push(prompt("p1", "hello", 1_000));
+        transcript.push_response(response("Running: read a.rs", 2_000));
+
+        let mut out = Vec::new();
+        transcript.write_jsonl(&mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(text.lines().count(), 2);
+        assert!(text.lines().next().unwrap().contains("\"kind\":\"prompt\""));
+
+        let read = Transcript::read_jsonl(SessionId("s".to_string()), text.as_bytes()).unwrap();
+        assert_eq!(read.len(), 2);
+        assert_eq!(
+            read.responses().next().unwrap().tool_calls[0].tool_name,
+            "read"
+        );
+
+        assert!(Transcript::read_jsonl(SessionId("s".to_string()), "{oops\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_builder_loads_queue_prompt_file_and_log() {
+        let base = TempDir::new().unwrap();
+        let logs = TempDir::new().unwrap();
+        let session = SessionId("swarm".to_string());
+
+        let queue = MessageQueue::new(base.path()).unwrap();
+        let id = queue.send_message(&session, "run the tests").unwrap();
+        queue
+            .send_message(&SessionId("other".to_string()), "not ours")
+            .unwrap();
+
+        let sender = MessageSender::new(base.path());
+        let metadata = PromptMetadata {
+            timestamp: 4_000_000_000,
+            ..PromptMetadata::new(session.clone(), "claude".to_string())
+        };
+        sender
+            .send_prompt_with_metadata(&PaneId("swarm:0.0".to_string()), "fix them", &metadata)
+            .unwrap();
+
+        fs::write(
+            logs.path().join("session_swarm.log"),
+            "[2100-01-01 00:00:00] Running: bash cargo test\n",
+        )
+        .unwrap();
+
+        let transcript = Transcript::builder(session)
+            .base_path(base.path())
+            .log_reader(LogReader::with_dir(logs.path().to_path_buf()))
+            .build()
+            .unwrap();
+
+        let texts: Vec<&str> = transcript.entries().iter().map(|e| e.text()).collect();
+        assert_eq!(texts, vec!["run the tests", "fix them", ""]);
+        assert_eq!(transcript.entries()[0].id, id);
+        assert_eq!(transcript.entries()[2].id, "swarm:r0");
+
+        let agent = transcript.prompts().find_map(|e| match &e.kind {
+            EntryKind::Prompt { agent, .. } => agent.clone(),
+            _ => None,
+        });
+        assert_eq!(agent.as_deref(), Some("claude"));
+    }
+
+    #[test]
+    fn test_builder_keeps_unprefixed_responses_untimed() {
+        let base = TempDir::new().unwrap();
+        let logs = TempDir::new().unwrap();
+        let session = SessionId("swarm".to_string());
+
+        let queue = MessageQueue::new(base.path()).unwrap();
+        let first = queue.send_message(&session, "run the tests").unwrap();
+        let second = queue.send_message(&session, "fix them").unwrap();
+
+        fs::write(
+            logs.path().join("session_swarm.log"),
+            "Running: bash cargo test\nerror: 2 tests failed\n",
+        )
+        .unwrap();
+
+        let transcript = Transcript::builder(session)
+            .base_path(base.path())
+            .log_reader(LogReader::with_dir(logs.path().to_path_buf()))
+            .build()
+            .unwrap();
+
+        // Untimed responses go after the timed prompts rather than before
+        let mut prompts: Vec<&str> = transcript.entries()[..2]
+            .iter()
+            .map(|e| e.id.as_str())
+            .collect();
+        prompts.sort();
+        let mut expected = vec![first.as_str(), second.as_str()];
+        expected.sort();
+        assert_eq!(prompts, expected);
+        assert_eq!(transcript.len(), 3);
+        assert_eq!(transcript.entries()[2].id, "swarm:r0");
+        assert!(transcript.prompts().all(|e| e.timestamp > 0));
+        assert!(transcript.responses().all(|r| r.received_at == 0));
+    }
+}
@@ -0,0 +1,784 @@
+//! Log Watch Module
+//!
+//! Follows a session log by name, like `tail -F`. The watcher waits for a
+//! log that does not exist yet, starts over when the log is truncated or
+//! replaced, and picks up the lines a rotation copied away before it read
+//! them. Only complete lines are delivered, each with its byte offset and
+//! line number. On Linux the watcher sleeps on inotify; elsewhere, or when
+//! inotify is unavailable, it polls.
+
+use crate::error::TmuxError;
+use crate::messaging::read::{LogCursor, line_text};
+use crate::messaging::rotate::log_segments;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
+
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(200);
+/// Leading bytes compared to notice a log rewritten in place
+const HEAD_LEN: usize = 64;
+const CHUNK: usize = 64 * 1024;
+/// Allowance for coarse file timestamps when matching a rotated segment
+const MTIME_SLACK: Duration = Duration::from_secs(1);
+
+/// A complete line from a watched log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogLine {
+    /// Text without the line ending. Bytes that are not UTF-8 become U+FFFD.
+    pub text: String,
+    /// Byte offset of the line in the log it was read from
+    pub offset: u64,
+    /// Line number (0-based) in the log it was read from
+    pub line: u64,
+}
+
+impl LogLine {
+    /// Cursor at the start of this line
+    pub fn cursor(&self) -> LogCursor {
+        LogCursor {
+            offset: self.offset,
+            line: self.line,
+        }
+    }
+}
+
+/// Where in the log a watch starts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WatchStart {
+    /// Deliver the whole log, then follow it
+    #[default]
+    Beginning,
+    /// Deliver only lines written from now on
+    End,
+    /// Deliver the last `n` complete lines of the active log, then follow it
+    Tail(u64),
+    /// Resume from a cursor. A cursor past the end of the log starts over.
+    Cursor(LogCursor),
+}
+
+/// Options for watching a log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchOptions {
+    pub start: WatchStart,
+    /// Stop after this long
+    pub timeout: Option<Duration>,
+    /// Check interval when polling. Also bounds how long a cancel takes.
+    pub poll_interval: Duration,
+    /// Poll even where inotify is available
+    pub force_polling: bool,
+}
+
+impl WatchOptions {
+    pub fn new() -> Self {
+        Self {
+            start: WatchStart::Beginning,
+            timeout: None,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            force_polling: false,
+        }
+    }
+
+    pub fn from_end(mut self) -> Self {
+        self.start = WatchStart::End;
+        self
+    }
+
+    pub fn from_tail(mut self, lines: u64) -> Self {
+        self.start = WatchStart::Tail(lines);
+        self
+    }
+
+    pub fn from_cursor(mut self, cursor: LogCursor) -> Self {
+        self.start = WatchStart::Cursor(cursor);
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn with_poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval.max(Duration::from_millis(1));
+        self
+    }
+
+    pub fn with_polling(mut self) -> Self {
+        self.force_polling = true;
+        self
+    }
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// How a watch ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchEnd {
+    /// Cursor after the last line delivered, to resume from
+    pub cursor: LogCursor,
+    /// Whether the timeout elapsed, rather than the watch being cancelled
+    pub timed_out: bool,
+}
+
+/// A watch running on a background thread. Dropping the handle cancels
+/// the watch and waits for the thread.
+pub struct WatchHandle {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<Result<WatchEnd, TmuxError>>>,
+}
+
+impl WatchHandle {
+    pub(crate) fn spawn<F>(
+        path: PathBuf,
+        start: LogCursor,
+        options: WatchOptions,
+        callback: F,
+    ) -> Result<Self, TmuxError>
+    where
+        F: FnMut(LogLine) + Send + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let stop = stop.clone();
+            std::thread::Builder::new()
+                .name("log-watch".to_string())
+                .spawn(move || follow(&path, start, &options, &stop, callback))?
+        };
+
+        Ok(Self {
+            stop,
+            handle: Some(handle),
+        })
+    }
+
+    /// Ask the watch to stop. It finishes within one poll interval.
+    pub fn cancel(&self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether the watch has stopped: cancelled, timed out or failed
+    pub fn is_finished(&self) -> bool {
+        self.handle.as_ref().is_none_or(JoinHandle::is_finished)
+    }
+
+    /// Wait for the watch to end
+    pub fn join(mut self) -> Result<WatchEnd, TmuxError> {
+        self.wait()
+    }
+
+    /// Cancel the watch and wait for it to end
+    pub fn stop(self) -> Result<WatchEnd, TmuxError> {
+        self.cancel();
+        self.join()
+    }
+
+    fn wait(&mut self) -> Result<WatchEnd, TmuxError> {
+        match self.handle.take() {
+            Some(handle) => handle
+                .join()
+                .unwrap_or_else(|_| Err(TmuxError::Command("Log watcher panicked".to_string()))),
+            None => Err(TmuxError::InvalidState("Watch already joined".to_string())),
+        }
+    }
+}
+
+impl Drop for WatchHandle {
+    fn drop(&mut self) {
+        self.cancel();
+        if self.handle.is_some() {
+            let _ = self.wait();
+        }
+    }
+}
+
+/// Follow `path` from `start` on the calling thread until `stop` is set
+/// or the timeout elapses
+pub(crate) fn follow<F>(
+    path: &Path,
+    start: LogCursor,
+    options: &WatchOptions,
+    stop: &AtomicBool,
+    mut callback: F,
+) -> Result<WatchEnd, TmuxError>
+where
+    F: FnMut(LogLine),
+{
+    let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
+    let mut waiter = Waiter::new(path, options.force_polling);
+    let mut follower = Follower::new(path, start);
+
+    loop {
+        follower.poll(&mut callback)?;
+
+        let end = |timed_out| WatchEnd {
+            cursor: follower.cursor,
+            timed_out,
+        };
+        if stop.load(Ordering::SeqCst) {
+            return Ok(end(false));
+        }
+        let wait = match deadline {
+            Some(deadline) => {
+                let left = deadline.saturating_duration_since(Instant::now());
+                if left.is_zero() {
+                    return Ok(end(true));
+                }
+                left.min(options.poll_interval)
+            }
+            None => options.poll_interval,
+        };
+        waiter.wait(wait);
+    }
+}
+
+/// Read side of a watch: the open log and the partial line after the cursor
+struct Follower {
+    path: PathBuf,
+    file: Option<File>,
+    /// Device and inode of the open file
+    id: (u64, u64),
+    /// Start of `pending` in the open file
+    cursor: LogCursor,
+    pending: Vec<u8>,
+    /// Start of a line split by rotation, continued in the new log
+    carry: Vec<u8>,
+    /// Leading bytes of the open file, as last seen
+    head: Vec<u8>,
+    /// The first open honours the start cursor; later ones start at 0
+    opened: bool,
+    last_poll: SystemTime,
+}
+
+impl Follower {
+    fn new(path: &Path, start: LogCursor) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            file: None,
+            id: (0, 0),
+            cursor: start,
+            pending: Vec::new(),
+            carry: Vec::new(),
+            head: Vec::new(),
+            opened: false,
+            last_poll: SystemTime::now(),
+        }
+    }
+
+    /// Deliver whatever the log gained since the last poll
+    fn poll<F: FnMut(LogLine)>(&mut self, emit: &mut F) -> Result<(), TmuxError> {
+        let started = SystemTime::now();
+        let meta = match fs::metadata(&self.path) {
+            Ok(meta) => Some(meta),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        // Removed or replaced: finish the old file, then follow the name
+        let replaced = meta.as_ref().is_none_or(|m| file_id(m) != self.id);
+        if self.file.is_some() && replaced {
+            self.drain(emit)?;
+            self.flush(emit);
+            self.reset();
+            self.file = None;
+        }
+
+        if self.file.is_none() {
+            if meta.is_none() || !self.open()? {
+                self.last_poll = started;
+                return Ok(());
+            }
+        } else if self.truncated()? {
+            self.recover_rotated(emit)?;
+            self.reset();
+        }
+
+        self.drain(emit)?;
+        self.last_poll = started;
+        Ok(())
+    }
+
+    /// Open the log by name. Returns false if it vanished meanwhile.
+    fn open(&mut self) -> Result<bool, TmuxError> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+        let meta = file.metadata()?;
+        self.id = file_id(&meta);
+
+        if self.opened || self.cursor.offset > meta.len() {
+            self.reset();
+        }
+        self.opened = true;
+        self.pending.clear();
+        self.head = read_head(&file)?;
+        self.file = Some(file);
+        Ok(true)
+    }
+
+    /// Whether the open file shrank below what was read, or was rewritten
+    /// in place
+    fn truncated(&self) -> Result<bool, TmuxError> {
+        let Some(file) = &self.file else {
+            return Ok(false);
+        };
+        if file.metadata()?.len() < self.position() {
+            return Ok(true);
+        }
+        let mut head = vec![0; self.head.len()];
+        let read = read_at(file, &mut head, 0)?;
+        Ok(read < head.len() || head != self.head)
+    }
+
+    /// After copytruncate rotation, the lines written between the last
+    /// poll and the copy are only in the newest segment. Read them from
+    /// there, if that segment is this log's fresh copy.
+    fn recover_rotated<F: FnMut(LogLine)>(&mut self, emit: &mut F) -> Result<(), TmuxError> {
+        let position = self.position();
+        let Some(segment) = log_segments(&self.path)?.into_iter().next() else {
+            return Ok(());
+        };
+        let fresh = segment
+            .modified
+            .checked_add(MTIME_SLACK)
+            .is_some_and(|modified| modified >= self.last_poll);
+        if !fresh || segment.size < position && !segment.compressed {
+            return Ok(());
+        }
+
+        let mut reader = segment.open()?;
+        let mut head = vec![0; self.head.len()];
+        if reader.read_exact(&mut head).is_err() || head != self.head {
+            return Ok(());
+        }
+        let skip = position - head.len() as u64;
+        if io::copy(&mut reader.by_ref().take(skip), &mut io::sink())? < skip {
+            return Ok(());
+        }
+
+        let mut buf = vec![0; CHUNK];
+        loop {
+            let read = reader.read(&mut buf)?;
+            if read == 0 {
+                break;
+            }
+            self.pending.extend_from_slice(&buf[..read]);
+            self.emit_lines(emit);
+        }
+        // The rest of a split line is at the start of the new log
+        let mut split = std::mem::take(&mut self.carry);
+        split.append(&mut self.pending);
+        self.carry = split;
+        Ok(())
+    }
+
+    /// Read the open file to its end, delivering complete lines
+    fn drain<F: FnMut(LogLine)>(&mut self, emit: &mut F) -> Result<(), TmuxError> {
+        let position = self.position();
+        let Some(file) = self.file.as_mut() else {
+            return Ok(());
+        };
+        file.seek(SeekFrom::Start(position))?;
+
+        let mut buf = vec![0; CHUNK];
+        loop {
+            let read = self.file.as_mut().unwrap().read(&mut buf)?;
+            if read == 0 {
+                break;
+            }
+            self.pending.extend_from_slice(&buf[..read]);
+            self.emit_lines(emit);
+        }
+
+        if self.head.len() < HEAD_LEN && self.position() > self.head.len() as u64 {
+            self.head = read_head(self.file.as_ref().unwrap())?;
+        }
+        Ok(())
+    }
+
+    fn emit_lines<F: FnMut(LogLine)>(&mut self, emit: &mut F) {
+        let mut start = 0;
+        while let Some(end) = self.pending[start..].iter().position(|b| *b == b'\n') {
+            let end = start + end + 1;
+            let text = if self.carry.is_empty() {
+                line_text(&self.pending[start..end])
+            } else {
+                let mut joined = std::mem::take(&mut self.carry);
+                joined.extend_from_slice(&self.pending[start..end]);
+                line_text(&joined)
+            };
+            emit(LogLine {
+                text,
+                offset: self.cursor.offset,
+                line: self.cursor.line,
+            });
+            self.cursor.offset += (end - start) as u64;
+            self.cursor.line += 1;
+            start = end;
+        }
+        self.pending.drain(..start);
+    }
+
+    /// Deliver an unterminated last line of a log that will not grow
+    fn flush<F: FnMut(LogLine)>(&mut self, emit: &mut F) {
+        if !self.pending.is_empty() || !self.carry.is_empty() {
+            self.pending.push(b'\n');
+            self.emit_lines(emit);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.cursor = LogCursor::default();
+        self.pending.clear();
+        self.head.clear();
+    }
+
+    /// Bytes of the open file read so far
+    fn position(&self) -> u64 {
+        self.cursor.offset + self.pending.len() as u64
+    }
+}
+
+fn read_head(file: &File) -> Result<Vec<u8>, TmuxError> {
+    let mut head = vec![0; HEAD_LEN];
+    let read = read_at(file, &mut head, 0)?;
+    head.truncate(read);
+    Ok(head)
+}
+
+/// Identity of the file behind the log's name, to notice a replacement
+#[cfg(unix)]
+fn file_id(meta: &fs::Metadata) -> (u64, u64) {
+    use std::os::unix::fs::MetadataExt;
+    (meta.dev(), meta.ino())
+}
+
+/// Without inode numbers, a file recreated under the same name is told
+/// apart by its creation time
+#[cfg(not(unix))]
+fn file_id(meta: &fs::Metadata) -> (u64, u64) {
+    let created = meta
+        .created()
+        .ok()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    (created.as_secs(), u64::from(created.subsec_nanos()))
+}
+
+#[cfg(unix)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    use std::os::unix::fs::FileExt;
+    file.read_at(buf, offset)
+}
+
+/// Moves the file's cursor, which is fine: `drain` seeks before it reads
+#[cfg(not(unix))]
+fn read_at(mut file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    file.seek(SeekFrom::Start(offset))?;
+    file.read(buf)
+}
+
+/// Sleeps between polls, waking early when the log changes
+enum Waiter {
+    #[cfg(target_os = "linux")]
+    Inotify(inotify::Inotify),
+    Poll,
+}
+
+impl Waiter {
+    fn new(path: &Path, force_polling: bool) -> Self {
+        #[cfg(target_os = "linux")]
+        if !force_polling && let Some(inotify) = inotify::Inotify::watch(path) {
+            return Waiter::Inotify(inotify);
+        }
+        let _ = (path, force_polling);
+        Waiter::Poll
+    }
+
+    fn wait(&mut self, timeout: Duration) {
+        match self {
+            #[cfg(target_os = "linux")]
+            Waiter::Inotify(inotify) => inotify.wait(timeout),
+            Waiter::Poll => std::thread::sleep(timeout),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod inotify {
+    use std::ffi::{CString, OsString};
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+    use std::time::{Duration, Instant};
+
+    const HEADER: usize = std::mem::size_of::<libc::inotify_event>();
+
+    /// inotify watch on a log's directory. The log itself may be created,
+    /// removed or replaced, so the directory is what gets watched.
+    pub struct Inotify {
+        fd: OwnedFd,
+        name: OsString,
+    }
+
+    impl Inotify {
+        pub fn watch(path: &Path) -> Option<Self> {
+            let name = path.file_name()?.to_owned();
+            let dir = match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+            let dir = CString::new(dir.as_os_str().as_bytes()).ok()?;
+
+            let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+            if fd < 0 {
+                return None;
+            }
+            let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+            let mask = libc::IN_MODIFY
+                | libc::IN_ATTRIB
+                | libc::IN_CLOSE_WRITE
+                | libc::IN_CREATE
+                | libc::IN_DELETE
+                | libc::IN_MOVED_FROM
+                | libc::IN_MOVED_TO
+                | libc::IN_DELETE_SELF
+                | libc::IN_MOVE_SELF;
+            if unsafe { libc::inotify_add_watch(fd.as_raw_fd(), dir.as_ptr(), mask) } < 0 {
+                return None;
+            }
+
+            Some(Self { fd, name })
+        }
+
+        /// Wait up to `timeout` for an event about the log, its segments
+        /// or the directory
+        pub fn wait(&mut self, timeout: Duration) {
+            let deadline = Instant::now() + timeout;
+            loop {
+                let left = deadline.saturating_duration_since(Instant::now());
+                let mut pollfd = libc::pollfd {
+                    fd: self.fd.as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                };
+                let millis = left.as_millis().min(i32::MAX as u128) as i32;
+                // Timed out, or interrupted by a signal
+                if unsafe { libc::poll(&mut pollfd, 1, millis) } <= 0 {
+                    return;
+                }
+                if self.read_events() || left.is_zero() {
+                    return;
+                }
+            }
+        }
+
+        /// Consume queued events. Returns whether any concerned the log.
+        fn read_events(&mut self) -> bool {
+            let mut buf = [0u8; 4096];
+            let mut relevant = false;
+            loop {
+                let read =
+                    unsafe { libc::read(self.fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) };
+                if read <= 0 {
+                    return relevant;
+                }
+
+                let mut at = 0;
+                while at + HEADER <= read as usize {
+                    let event: libc::inotify_event =
+                        unsafe { std::ptr::read_unaligned(buf[at..].as_ptr().cast()) };
+                    let name = &buf[at + HEADER..at + HEADER + event.len as usize];
+                    let name = name.split(|b| *b == 0).next().unwrap_or_default();
+                    relevant |= self.concerns(name);
+                    at += HEADER + event.len as usize;
+                }
+            }
+        }
+
+        /// Events on the directory itself, the log, or `<log>.*` siblings
+        fn concerns(&self, name: &[u8]) -> bool {
+            let log = self.name.as_bytes();
+            name.is_empty()
+                || name
+                    .strip_prefix(log)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with(b"."))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messaging::rotate::{LogRotator, RotationPolicy};
+    use crate::types::SessionId;
+    use std::io::Write;
+    use std::sync::mpsc;
+
+    fn append(path: &Path, text: &str) {
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap()
+            .write_all(text.as_bytes())
+            .unwrap();
+    }
+
+    /// Drive a follower by hand, without threads or timing
+    fn collect(follower: &mut Follower) -> Vec<(String, u64, u64)> {
+        let mut lines = Vec::new();
+        follower
+            .poll(&mut |line: LogLine| lines.push((line.text, line.offset, line.line)))
+            .unwrap();
+        lines
+    }
+
+    fn texts(follower: &mut Follower) -> Vec<String> {
+        collect(follower)
+            .into_iter()
+            .map(|(text, ..)| text)
+            .collect()
+    }
+
+    #[test]
+    fn test_complete_lines_with_offsets() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("session_1.log");
+        let mut follower = Follower::new(&log, LogCursor::default());
+
+        // Missing log is waited for
+        assert!(collect(&mut follower).is_empty());
+
+        append(&log, "one\ntw");
+        assert_eq!(collect(&mut follower), vec![("one".to_string(), 0, 0)]);
+        append(&log, "o\r\nthree\n");
+        assert_eq!(
+            collect(&mut follower),
+            vec![("two".to_string(), 4, 1), ("three".to_string(), 9, 2)]
+        );
+        assert_eq!(
+            follower.cursor,
+            LogCursor {
+                offset: 15,
+                line: 3
+            }
+        );
+    }
+
+    #[test]
+    fn test_resumes_from_cursor() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("session_1.log");
+        append(&log, "one\ntwo\nthree\n");
+
+        let mut follower = Follower::new(&log, LogCursor { offset: 8, line: 2 });
+        assert_eq!(collect(&mut follower), vec![("three".to_string(), 8, 2)]);
+
+        // A cursor past the end means the log was truncated since
+        let mut follower = Follower::new(
+            &log,
+            LogCursor {
+                offset: 99,
+                line: 9,
+            },
+        );
+        assert_eq!(texts(&mut follower), vec!["one", "two", "three"]);
+    }
+
+    #[test]
+    fn test_survives_truncation_and_replacement() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("session_1.log");
+        append(&log, "one\ntwo\n");
+        let mut follower = Follower::new(&log, LogCursor::default());
+        assert_eq!(texts(&mut follower), vec!["one", "two"]);
+
+        // Truncated, as by clear_log
+        fs::write(&log, "").unwrap();
+        assert!(texts(&mut follower).is_empty());
+        append(&log, "three\n");
+        assert_eq!(collect(&mut follower), vec![("three".to_string(), 0, 0)]);
+
+        // Rewritten in place to a longer log
+        fs::write(&log, "a much longer first line\nb\n").unwrap();
+        assert_eq!(texts(&mut follower), vec!["a much longer first line", "b"]);
+
+        // Replaced by rename, with an unterminated line left in the old file
+        append(&log, "partial");
+        let next = dir.path().join("next.log");
+        fs::write(&next, "fresh\n").unwrap();
+        fs::rename(&next, &log).unwrap();
+        assert_eq!(texts(&mut follower), vec!["partial", "fresh"]);
+
+        // Removed, then created again
+        fs::remove_file(&log).unwrap();
+        assert!(texts(&mut follower).is_empty());
+        append(&log, "back\n");
+        assert_eq!(texts(&mut follower), vec!["back"]);
+    }
+
+    #[test]
+    fn test_picks_up_lines_copied_away_by_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let id = SessionId("1".to_string());
+        let log = dir.path().join("session_1.log");
+        append(&log, "one\n");
+        let mut follower = Follower::new(&log, LogCursor::default());
+        assert_eq!(texts(&mut follower), vec!["one"]);
+
+        // Written and rotated away between polls, with a line split across
+        append(&log, "two\nthr");
+        let rotator = LogRotator::with_dir(dir.path().to_path_buf(), RotationPolicy::new());
+        rotator.rotate(&id).unwrap();
+        append(&log, "ee\nfour\n");
+
+        assert_eq!(
+            collect(&mut follower),
+            vec![
+                ("two".to_string(), 4, 1),
+                ("three".to_string(), 0, 0),
+                ("four".to_string(), 3, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_handle_cancel_and_timeout() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("session_1.log");
+
+        let (tx, rx) = mpsc::channel();
+        let options = WatchOptions::new().with_poll_interval(Duration::from_millis(10));
+        let handle = WatchHandle::spawn(log.clone(), LogCursor::default(), options, move |line| {
+            tx.send(line).unwrap();
+        })
+        .unwrap();
+
+        append(&log, "hello\n");
+        let line = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!((line.text.as_str(), line.offset), ("hello", 0));
+        assert!(!handle.is_finished());
+
+        let end = handle.stop().unwrap();
+        assert!(!end.timed_out);
+        assert_eq!(end.cursor, LogCursor { offset: 6, line: 1 });
+
+        let options = WatchOptions::new()
+            .with_polling()
+            .with_timeout(Duration::from_millis(50));
+        let handle = WatchHandle::spawn(log, end.cursor, options, |_| {}).unwrap();
+        let end = handle.join().unwrap();
+        assert!(end.timed_out);
+        assert_eq!(end.cursor.line, 1);
+    }
+}
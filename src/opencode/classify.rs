@@ -0,0 +1,653 @@
+//! Error Classification Module
+//!
+//! Sorts the error reports agents print into categories (compiler, test,
+//! crash, provider, permission, refusal) with a hint for each, and picks
+//! up the source location and code a diagnostic points at.
+
+use crate::Result;
+use crate::error::TmuxError;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// What kind of failure an error report describes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCategory {
+    /// Compiler or type checker diagnostic (rustc, tsc, gcc, go, ...)
+    Compiler,
+    /// A test or test suite failed
+    TestFailure,
+    /// A tool or program crashed: panics, tracebacks, signals
+    ToolCrash,
+    /// Model provider error: rate limits, overload, quota, API failures
+    Api,
+    /// File system or sandbox permission denial
+    Permission,
+    /// The agent declined the request
+    Refusal,
+    Other,
+}
+
+impl ErrorCategory {
+    pub const ALL: [ErrorCategory; 7] = [
+        ErrorCategory::Compiler,
+        ErrorCategory::TestFailure,
+        ErrorCategory::ToolCrash,
+        ErrorCategory::Api,
+        ErrorCategory::Permission,
+        ErrorCategory::Refusal,
+        ErrorCategory::Other,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCategory::Compiler => "compiler",
+            ErrorCategory::TestFailure => "test_failure",
+            ErrorCategory::ToolCrash => "tool_crash",
+            ErrorCategory::Api => "api",
+            ErrorCategory::Permission => "permission",
+            ErrorCategory::Refusal => "refusal",
+            ErrorCategory::Other => "other",
+        }
+    }
+
+    /// Suggested next step for the operator or the agent
+    pub fn hint(&self) -> &'static str {
+        match self {
+            ErrorCategory::Compiler => "Fix the reported diagnostic and rebuild",
+            ErrorCategory::TestFailure => "Re-run the failing test on its own to see its output",
+            ErrorCategory::ToolCrash => {
+                "Read the backtrace; the tool crashed instead of reporting an error"
+            }
+            ErrorCategory::Api => "Wait and retry; the model provider is rate limiting or down",
+            ErrorCategory::Permission => "Check file permissions or the agent's sandbox settings",
+            ErrorCategory::Refusal => "Rephrase the prompt or split the task into smaller steps",
+            ErrorCategory::Other => "Read the full error output",
+        }
+    }
+
+    /// Whether sending the same prompt again may succeed
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, ErrorCategory::Api)
+    }
+}
+
+impl fmt::Display for ErrorCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ErrorCategory {
+    type Err = TmuxError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().replace('-', "_").as_str() {
+            "compiler" | "compile" | "build" => Ok(ErrorCategory::Compiler),
+            "test_failure" | "test" | "tests" => Ok(ErrorCategory::TestFailure),
+            "tool_crash" | "crash" | "panic" => Ok(ErrorCategory::ToolCrash),
+            "api" | "rate_limit" => Ok(ErrorCategory::Api),
+            "permission" | "permissions" => Ok(ErrorCategory::Permission),
+            "refusal" | "refused" => Ok(ErrorCategory::Refusal),
+            "other" => Ok(ErrorCategory::Other),
+            _ => Err(TmuxError::Parse(format!(
+                "Unknown error category '{}' (known: {})",
+                s,
+                ErrorCategory::ALL.map(|c| c.as_str()).join(", ")
+            ))),
+        }
+    }
+}
+
+/// Position in a source file reported alongside an error
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.file)?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+            if let Some(column) = self.column {
+                write!(f, ":{}", column)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Error report with its category and, where the output names one, the
+/// diagnostic code and source location
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClassifiedError {
+    pub category: ErrorCategory,
+    /// Error text as reported
+    pub message: String,
+    /// Diagnostic code such as `E0425` or `TS2322`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<SourceLocation>,
+}
+
+impl ClassifiedError {
+    /// Suggested next step, see `ErrorCategory::hint`
+    pub fn hint(&self) -> &'static str {
+        self.category.hint()
+    }
+}
+
+impl fmt::Display for ClassifiedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}]", self.category)?;
+        if let Some(location) = &self.location {
+            write!(f, " {}:", location)?;
+        }
+        write!(f, " {}", self.message)
+    }
+}
+
+/// Classify an error report. Every line is looked at, so a multi-line
+/// report such as a rustc diagnostic with its ` --> file:line:col` line
+/// gets a location.
+pub fn classify_error(message: &str) -> ClassifiedError {
+    let message = message.trim();
+    let category = message
+        .lines()
+        .find_map(error_category)
+        .unwrap_or_else(|| infer_category(message));
+
+    ClassifiedError {
+        category,
+        message: message.to_string(),
+        code: message.lines().find_map(diagnostic_code),
+        location: message.lines().find_map(line_location),
+    }
+}
+
+/// Every error in `output`, with locations picked up from the lines that
+/// follow an error line (rustc's ` --> file:line:col`, Python's
+/// `File "...", line N`).
+pub fn find_errors(output: &str) -> Vec<ClassifiedError> {
+    let mut errors: Vec<ClassifiedError> = Vec::new();
+    let mut attached = false;
+
+    for line in output.lines() {
+        if error_category(line).is_some() {
+            errors.push(classify_error(line));
+            attached = true;
+        } else if line.trim().is_empty() {
+            attached = false;
+        } else if attached && let Some(last) = errors.last_mut() {
+            attach_context(last, line);
+        }
+    }
+
+    errors
+}
+
+/// Fill in a location or code for `error` from a line that follows it
+pub(crate) fn attach_context(error: &mut ClassifiedError, line: &str) {
+    if error.location.is_none() {
+        error.location = line_location(line);
+    }
+    if error.code.is_none() {
+        error.code = diagnostic_code(line);
+    }
+}
+
+/// Category of `line` if it reports an error on its own. Only the start
+/// of the line and well-known tool formats count: prose that mentions an
+/// error and warnings are not errors.
+pub fn error_category(line: &str) -> Option<ErrorCategory> {
+    let line = line.trim();
+    let lower = line.to_ascii_lowercase();
+
+    if lower.starts_with("warning") || lower.starts_with("note:") || lower.starts_with("help:") {
+        return None;
+    }
+
+    if is_compiler_diagnostic(line) {
+        return Some(ErrorCategory::Compiler);
+    }
+
+    if is_test_failure(line) {
+        return Some(ErrorCategory::TestFailure);
+    }
+
+    if (line.starts_with("thread '") && line.contains("' panicked at"))
+        || line.starts_with("Traceback (most recent call last)")
+        || line.starts_with("panic: ")
+        || line.starts_with("fatal runtime error:")
+        || is_signal_report(line)
+    {
+        return Some(ErrorCategory::ToolCrash);
+    }
+
+    if line.starts_with("API Error") {
+        return Some(ErrorCategory::Api);
+    }
+
+    if line == "Permission denied"
+        || line.starts_with("Permission denied (")
+        || line.starts_with("Permission denied:")
+        || line.ends_with(": Permission denied")
+        || line.contains(": Permission denied (os error")
+        || line.contains("EACCES:")
+        || line.ends_with(": Operation not permitted")
+    {
+        return Some(ErrorCategory::Permission);
+    }
+
+    if is_refusal(line) {
+        return Some(ErrorCategory::Refusal);
+    }
+
+    let rest = line
+        .strip_prefix("Error:")
+        .or_else(|| line.strip_prefix("error:"))
+        .or_else(|| line.strip_prefix("ERROR:"))?;
+    Some(infer_category(rest))
+}
+
+/// Category from the words in an error message whose format says nothing
+/// about its source, such as `Error: ...` or an `<error>` block
+fn infer_category(message: &str) -> ErrorCategory {
+    let lower = message.to_ascii_lowercase();
+
+    if lower.contains("permission denied")
+        || lower.contains("operation not permitted")
+        || lower.contains("eacces")
+        || lower.contains("not allowed to")
+    {
+        ErrorCategory::Permission
+    } else if is_api_failure(&lower) || lower.contains("overloaded") || lower.contains("api error")
+    {
+        ErrorCategory::Api
+    } else if lower.contains("panicked")
+        || lower.contains("traceback")
+        || lower.contains("segmentation fault")
+    {
+        ErrorCategory::ToolCrash
+    } else if lower.contains("could not compile")
+        || lower.contains("compilation failed")
+        || lower.contains("build failed")
+    {
+        ErrorCategory::Compiler
+    } else if lower.contains("test failed") || lower.contains("tests failed") {
+        ErrorCategory::TestFailure
+    } else if is_refusal(message) {
+        ErrorCategory::Refusal
+    } else {
+        ErrorCategory::Other
+    }
+}
+
+/// `error[E0425]: ...`, `error: could not compile ...`,
+/// `src/a.ts(3,5): error TS2322: ...` and `a.c:3:5: error: ...`
+fn is_compiler_diagnostic(line: &str) -> bool {
+    if line.starts_with("error[") && line.contains("]:") {
+        return true;
+    }
+    if line.starts_with("error: could not compile") || line.starts_with("error: aborting due to") {
+        return true;
+    }
+    if let Some((location, _)) = line.split_once("): error TS") {
+        return location.contains('(');
+    }
+
+    [": error:", ": fatal error:"]
+        .iter()
+        .filter_map(|marker| line.split_once(marker))
+        .any(|(location, _)| parse_file_position(location).is_some_and(|l| l.line.is_some()))
+}
+
+/// `test foo ... FAILED`, `test result: FAILED`, Go's `--- FAIL:`, pytest's
+/// `FAILED tests/a.py::test_x` and Jest's `FAIL src/a.test.ts`
+fn is_test_failure(line: &str) -> bool {
+    (line.starts_with("test ") && line.ends_with("... FAILED"))
+        || line.starts_with("test result: FAILED")
+        || line.starts_with("--- FAIL: ")
+        || line.starts_with("error: test failed")
+        || (line.starts_with("FAILED ") && line.contains("::"))
+        || line
+            .strip_prefix("FAIL ")
+            .is_some_and(|path| !path.contains(char::is_whitespace) && path.contains('.'))
+}
+
+/// A shell reporting a child killed by a signal: `Segmentation fault
+/// (core dumped)` or `Aborted (core dumped)` on its own line, or after a
+/// `bash: line 1: 4242 ` or `zsh: ` prefix
+fn is_signal_report(line: &str) -> bool {
+    let report = ["bash: ", "sh: ", "zsh: "]
+        .iter()
+        .find_map(|shell| line.strip_prefix(shell))
+        .map(|rest| {
+            let rest = rest
+                .strip_prefix("line ")
+                .and_then(|rest| rest.split_once(": "))
+                .map_or(rest, |(_, rest)| rest);
+            rest.trim_start_matches(|c: char| c.is_ascii_digit() || c == ' ')
+        })
+        .unwrap_or(line);
+
+    report.starts_with("Segmentation fault")
+        || report.starts_with("segmentation fault")
+        || report.starts_with("Aborted (core dumped)")
+}
+
+fn is_api_failure(lower: &str) -> bool {
+    lower.contains("rate limit exceeded")
+        || lower.contains("rate_limit_error")
+        || lower.contains("rate limit reached")
+        || lower.contains("429 too many requests")
+        || lower.contains("overloaded_error")
+        || lower.contains("insufficient_quota")
+        || lower.contains("quota exceeded")
+}
+
+const REFUSALS: [&str; 9] = [
+    "i can't help with",
+    "i cannot help with",
+    "i can't assist with",
+    "i cannot assist with",
+    "i'm not able to help with",
+    "i'm unable to help with",
+    "i won't be able to help with",
+    "i must decline",
+    "i'm sorry, but i can't",
+];
+
+fn is_refusal(line: &str) -> bool {
+    let lower = line.trim().to_lowercase().replace('\u{2019}', "'");
+    REFUSALS.iter().any(|phrase| lower.starts_with(phrase))
+}
+
+/// `E0425` from `error[E0425]:` or `TS2322` from `error TS2322:`
+fn diagnostic_code(line: &str) -> Option<String> {
+    let line = line.trim();
+    if let Some(rest) = line.strip_prefix("error[") {
+        let code = &rest[..rest.find(']')?];
+        return Some(code.to_string()).filter(|c| !c.is_empty());
+    }
+
+    let (_, rest) = line.split_once("error TS")?;
+    let digits: String = rest.chars().take_while(char::is_ascii_digit).collect();
+    (!digits.is_empty()).then(|| format!("TS{}", digits))
+}
+
+/// Source location named on a single line of compiler, test or crash output
+fn line_location(line: &str) -> Option<SourceLocation> {
+    let trimmed = line.trim();
+
+    // rustc: ` --> src/main.rs:4:5`
+    if let Some(rest) = trimmed.strip_prefix("--> ") {
+        return parse_file_position(rest.trim());
+    }
+
+    // Rust panic: `thread 'main' panicked at src/main.rs:4:5:`
+    // (older releases: `panicked at 'boom', src/main.rs:4:5`)
+    if let Some((_, rest)) = trimmed.split_once("panicked at ") {
+        return rest.split_whitespace().find_map(|token| {
+            parse_file_position(token.trim_matches([',', ':', '\''])).filter(|l| l.line.is_some())
+        });
+    }
+
+    // Python: `File "app.py", line 3, in <module>`
+    if let Some(rest) = trimmed.strip_prefix("File \"") {
+        let (file, rest) = rest.split_once('"')?;
+        let line = rest
+            .trim_start_matches(", line ")
+            .split(',')
+            .next()
+            .and_then(|n| n.trim().parse().ok());
+        return Some(SourceLocation {
+            file: file.to_string(),
+            line,
+            column: None,
+        });
+    }
+
+    // tsc: `src/a.ts(3,5): error TS2322: ...`
+    if let Some((location, _)) = trimmed.split_once("): ")
+        && let Some((file, position)) = location.split_once('(')
+    {
+        let (line, column) = position.split_once(',').unwrap_or((position, ""));
+        if let Ok(line) = line.parse() {
+            return Some(SourceLocation {
+                file: file.to_string(),
+                line: Some(line),
+                column: column.parse().ok(),
+            });
+        }
+    }
+
+    // pytest: `FAILED tests/test_a.py::test_x - assert 1 == 2`
+    if let Some(rest) = trimmed.strip_prefix("FAILED ")
+        && let Some((file, _)) = rest.split_once("::")
+    {
+        return looks_like_path(file).then(|| SourceLocation {
+            file: file.to_string(),
+            line: None,
+            column: None,
+        });
+    }
+
+    // gcc, go, eslint and friends: `file:line[:col]: message`
+    let mut parts = trimmed.splitn(4, ':');
+    let file = parts.next()?;
+    let line = parts.next()?.trim().parse().ok()?;
+    let column = parts.next().and_then(|c| c.trim().parse().ok());
+    looks_like_path(file).then(|| SourceLocation {
+        file: file.to_string(),
+        line: Some(line),
+        column,
+    })
+}
+
+/// `file`, `file:line` or `file:line:col`
+fn parse_file_position(text: &str) -> Option<SourceLocation> {
+    let mut parts = text.trim().splitn(3, ':');
+    let file = parts.next()?;
+    if !looks_like_path(file) {
+        return None;
+    }
+
+    let line = parts.next().and_then(|l| l.trim().parse().ok());
+    let column = parts.next().and_then(|c| c.trim().parse().ok());
+    Some(SourceLocation {
+        file: file.to_string(),
+        line,
+        column: line.and(column),
+    })
+}
+
+fn looks_like_path(text: &str) -> bool {
+    !text.is_empty()
+        && !text.contains(char::is_whitespace)
+        && (text.contains('.') || text.contains('/'))
+        && !text.contains("://")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn location(file: &str, line: u32, column: u32) -> Option<SourceLocation> {
+        Some(SourceLocation {
+            file: file.to_string(),
+            line: Some(line),
+            column: Some(column),
+        })
+    }
+
+    #[test]
+    fn test_rustc_diagnostic() {
+        let output = "\
+warning: unused variable: `error`
+error[E0425]: cannot find value `x` in this scope
+ --> src/main.rs:4:5
+  |
+4 |     x
+  |     ^ not found in this scope
+
+error: could not compile `demo` (bin \"demo\") due to 1 previous error";
+
+        let errors = find_errors(output);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].category, ErrorCategory::Compiler);
+        assert_eq!(errors[0].code.as_deref(), Some("E0425"));
+        assert_eq!(errors[0].location, location("src/main.rs", 4, 5));
+        assert_eq!(errors[1].category, ErrorCategory::Compiler);
+        assert_eq!(errors[1].location, None);
+
+        let block = classify_error("error[E0308]: mismatched types\n --> src/lib.rs:10:9");
+        assert_eq!(block.code.as_deref(), Some("E0308"));
+        assert_eq!(block.location, location("src/lib.rs", 10, 9));
+    }
+
+    #[test]
+    fn test_other_compilers() {
+        let tsc = classify_error("src/app.ts(12,7): error TS2322: Type 'string' is not assignable");
+        assert_eq!(tsc.category, ErrorCategory::Compiler);
+        assert_eq!(tsc.code.as_deref(), Some("TS2322"));
+        assert_eq!(tsc.location, location("src/app.ts", 12, 7));
+
+        let gcc = classify_error("main.c:3:10: fatal error: stdio.hh: No such file or directory");
+        assert_eq!(gcc.category, ErrorCategory::Compiler);
+        assert_eq!(gcc.location, location("main.c", 3, 10));
+
+        let go = classify_error("./main.go:8:2: error: undefined: foo");
+        assert_eq!(go.location, location("./main.go", 8, 2));
+    }
+
+    #[test]
+    fn test_test_failures() {
+        for line in [
+            "test parser::tests::test_split ... FAILED",
+            "test result: FAILED. 3 passed; 1 failed",
+            "--- FAIL: TestParse (0.00s)",
+            "FAIL src/app.test.ts",
+        ] {
+            assert_eq!(
+                error_category(line),
+                Some(ErrorCategory::TestFailure),
+                "{}",
+                line
+            );
+        }
+
+        let pytest = classify_error("FAILED tests/test_api.py::test_login - assert 401 == 200");
+        assert_eq!(pytest.category, ErrorCategory::TestFailure);
+        assert_eq!(pytest.location.unwrap().file, "tests/test_api.py");
+
+        assert_eq!(error_category("test result: ok. 12 passed; 0 failed"), None);
+    }
+
+    #[test]
+    fn test_crashes() {
+        let panic = classify_error("thread 'main' panicked at src/main.rs:4:5:");
+        assert_eq!(panic.category, ErrorCategory::ToolCrash);
+        assert_eq!(panic.location, location("src/main.rs", 4, 5));
+
+        let traceback = "\
+Traceback (most recent call last):
+  File \"app.py\", line 3, in <module>
+    main()
+ZeroDivisionError: division by zero";
+        let errors = find_errors(traceback);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].category, ErrorCategory::ToolCrash);
+        assert_eq!(errors[0].location.as_ref().unwrap().file, "app.py");
+        assert_eq!(errors[0].location.as_ref().unwrap().line, Some(3));
+
+        for line in [
+            "bash: line 1: 4242 Segmentation fault (core dumped) ./a.out",
+            "zsh: segmentation fault (core dumped)  ./a.out",
+            "Segmentation fault (core dumped)",
+            "Aborted (core dumped)",
+        ] {
+            assert_eq!(
+                error_category(line),
+                Some(ErrorCategory::ToolCrash),
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn test_api_permission_and_refusal() {
+        let api = classify_error("API Error: 529 overloaded");
+        assert_eq!(api.category, ErrorCategory::Api);
+        assert!(api.category.is_retryable());
+        assert_eq!(
+            error_category("Error: 429 Too Many Requests"),
+            Some(ErrorCategory::Api)
+        );
+        assert_eq!(
+            error_category("Error: rate limit exceeded"),
+            Some(ErrorCategory::Api)
+        );
+
+        assert_eq!(
+            error_category("cat: /etc/shadow: Permission denied"),
+            Some(ErrorCategory::Permission)
+        );
+        assert_eq!(
+            classify_error("Failed to read file: permission denied").category,
+            ErrorCategory::Permission
+        );
+
+        assert_eq!(
+            error_category("I can\u{2019}t help with bypassing the license check."),
+            Some(ErrorCategory::Refusal)
+        );
+        assert!(!ErrorCategory::Refusal.is_retryable());
+    }
+
+    #[test]
+    fn test_prose_and_warnings_are_not_errors() {
+        for line in [
+            "The parser now handles the error: prefix used by rustc.",
+            "warning: field `error` is never read",
+            "warning[E0001]: error: this is only a warning",
+            "note: errors are reported at the end",
+            "We should add rate limit handling to the client.",
+            "Permission denied errors are common on NFS.",
+            "I fixed the Segmentation fault in the parser.",
+            "We hit rate limit exceeded earlier, so I added backoff.",
+            "The build (core dumped) issue is resolved.",
+        ] {
+            assert_eq!(error_category(line), None, "{}", line);
+        }
+
+        assert_eq!(
+            classify_error("Error: something odd").category,
+            ErrorCategory::Other
+        );
+    }
+
+    #[test]
+    fn test_category_names() {
+        for category in ErrorCategory::ALL {
+            assert_eq!(
+                category.as_str().parse::<ErrorCategory>().unwrap(),
+                category
+            );
+        }
+        assert_eq!(
+            "rate-limit".parse::<ErrorCategory>().unwrap(),
+            ErrorCategory::Api
+        );
+        assert!("nonsense".parse::<ErrorCategory>().is_err());
+
+        let json = serde_json::to_string(&ErrorCategory::TestFailure).unwrap();
+        assert_eq!(json, "\"test_failure\"");
+    }
+}
@@ -4,13 +4,219 @@
 //! Uses OpenCode's HTTP protocol to send messages and retrieve session data.
 
 use crate::Result;
+use crate::error::TmuxError;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Default time allowed to establish a connection
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Default time allowed between reads; prompts can take a while to answer
+const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Environment variable holding the password of a protected OpenCode server
+const PASSWORD_ENV: &str = "OPENCODE_SERVER_PASSWORD";
+
+/// Environment variable overriding the basic-auth username
+const USERNAME_ENV: &str = "OPENCODE_SERVER_USERNAME";
+
+/// Username OpenCode expects when only a password is configured
+const DEFAULT_USERNAME: &str = "opencode";
 
 /// OpenCode client for Session API
 pub struct OpenCodeClient {
     server_url: String,
     client: reqwest::Client,
+    auth: Option<Auth>,
+    retry: RetryPolicy,
+}
+
+/// Credentials sent with every request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Auth {
+    Basic { username: String, password: String },
+    Bearer(String),
+}
+
+impl Auth {
+    /// Basic auth from `OPENCODE_SERVER_PASSWORD` (and optional
+    /// `OPENCODE_SERVER_USERNAME`), if the password is set
+    pub fn from_env() -> Option<Self> {
+        let password = std::env::var(PASSWORD_ENV).ok().filter(|p| !p.is_empty())?;
+        let username = std::env::var(USERNAME_ENV)
+            .ok()
+            .filter(|u| !u.is_empty())
+            .unwrap_or_else(|| DEFAULT_USERNAME.to_string());
+
+        Some(Auth::Basic { username, password })
+    }
+}
+
+/// Retry policy for idempotent requests.
+/// Delays use exponential backoff with full jitter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt
+    pub max_retries: u32,
+    /// Backoff ceiling for the first retry
+    pub base_delay: Duration,
+    /// Upper bound for any single delay
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(200),
+            max_delay: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Never retry
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Self::default()
+        }
+    }
+
+    /// Delay before retry number `retry` (0-based), drawn uniformly
+    /// from zero up to the exponential backoff ceiling
+    pub fn delay_for(&self, retry: u32) -> Duration {
+        let ceiling = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_delay);
+        let ceiling_ms = ceiling.as_millis() as u64;
+
+        if ceiling_ms == 0 {
+            return Duration::ZERO;
+        }
+
+        let random = uuid::Uuid::new_v4().as_u128() as u64;
+        Duration::from_millis(random % (ceiling_ms + 1))
+    }
+}
+
+/// Builder for `OpenCodeClient`
+#[derive(Debug, Clone)]
+pub struct OpenCodeClientBuilder {
+    server_url: String,
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    auth: Option<Auth>,
+    retry: RetryPolicy,
+    unix_socket: Option<PathBuf>,
+}
+
+impl OpenCodeClientBuilder {
+    /// Create builder with default timeouts and retry policy
+    pub fn new(server_url: &str) -> Self {
+        Self {
+            server_url: server_url.to_string(),
+            connect_timeout: Some(DEFAULT_CONNECT_TIMEOUT),
+            read_timeout: Some(DEFAULT_READ_TIMEOUT),
+            auth: None,
+            retry: RetryPolicy::default(),
+            unix_socket: None,
+        }
+    }
+
+    /// Time allowed to establish a connection
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Time allowed between reads of a response
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Disable connect and read timeouts
+    pub fn no_timeouts(mut self) -> Self {
+        self.connect_timeout = None;
+        self.read_timeout = None;
+        self
+    }
+
+    /// Use basic auth
+    pub fn basic_auth(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        self.auth = Some(Auth::Basic {
+            username: username.into(),
+            password: password.into(),
+        });
+        self
+    }
+
+    /// Use these credentials
+    pub fn auth(mut self, auth: Auth) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Use bearer token auth
+    pub fn bearer_auth(mut self, token: impl Into<String>) -> Self {
+        self.auth = Some(Auth::Bearer(token.into()));
+        self
+    }
+
+    /// Pick up credentials from the environment if no auth was set
+    pub fn auth_from_env(mut self) -> Self {
+        if self.auth.is_none() {
+            self.auth = Auth::from_env();
+        }
+        self
+    }
+
+    /// Retry policy for idempotent requests
+    pub fn retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Connect through a unix socket instead of TCP.
+    /// The server URL still supplies scheme and path, its host is ignored.
+    #[cfg(unix)]
+    pub fn unix_socket(mut self, path: impl Into<PathBuf>) -> Self {
+        self.unix_socket = Some(path.into());
+        self
+    }
+
+    /// Build the client
+    pub fn build(self) -> Result<OpenCodeClient> {
+        let mut builder = reqwest::Client::builder();
+
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+
+        if let Some(timeout) = self.read_timeout {
+            builder = builder.read_timeout(timeout);
+        }
+
+        #[cfg(unix)]
+        if let Some(path) = self.unix_socket {
+            builder = builder.unix_socket(path);
+        }
+
+        let client = builder
+            .build()
+            .map_err(|e| TmuxError::Transport(format!("Failed to build HTTP client: {}", e)))?;
+
+        Ok(OpenCodeClient {
+            server_url: self.server_url,
+            client,
+            auth: self.auth,
+            retry: self.retry,
+        })
+    }
 }
 
 /// Session info from OpenCode
@@ -40,164 +246,154 @@ pub struct SessionMessage {
     pub timestamp: String,
 }
 
+#[derive(Serialize)]
+struct ContentRequest<'a> {
+    content: &'a str,
+}
+
 impl OpenCodeClient {
-    /// Create new OpenCode client
+    /// Create new OpenCode client with default timeouts and retries, and
+    /// credentials from the environment if `OPENCODE_SERVER_PASSWORD` is set
     pub fn new(server_url: &str) -> Self {
-        Self {
-            server_url: server_url.to_string(),
-            client: reqwest::Client::new(),
-        }
+        Self::builder(server_url)
+            .auth_from_env()
+            .build()
+            .expect("default OpenCode client configuration is valid")
+    }
+
+    /// Start building a client
+    pub fn builder(server_url: &str) -> OpenCodeClientBuilder {
+        OpenCodeClientBuilder::new(server_url)
     }
 
     /// Send a prompt to OpenCode session
     pub async fn session_prompt(&self, session_id: &str, prompt: &str) -> Result<()> {
-        let url = format!(
-            "{}/session/{}/prompt",
-            self.server_url.trim_end_matches('/'),
-            session_id
-        );
-
-        #[derive(Serialize)]
-        struct PromptRequest {
-            content: String,
-        }
-
-        let body = PromptRequest {
-            content: prompt.to_string(),
-        };
+        let url = self.url(&format!("session/{}/prompt", session_id));
+        let body = ContentRequest { content: prompt };
 
         let response = self
-            .client
-            .post(&url)
-            .json(&body)
-            .send()
-            .await
-            .map_err(|e| crate::error::TmuxError::Command(format!("HTTP request failed: {}", e)))?;
-
-        if response.status().is_success() {
-            Ok(())
-        } else {
-            let status = response.status();
-            let error_text = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Unknown error".to_string());
-            Err(crate::error::TmuxError::Command(format!(
-                "OpenCode API error: {} - {}",
-                status, error_text
-            )))
-        }
+            .execute(false, || self.client.post(&url).json(&body))
+            .await?;
+        Self::check_status(response).await?;
+        Ok(())
     }
 
     /// Send a message to OpenCode session
     pub async fn session_message(&self, session_id: &str, message: &str) -> Result<()> {
-        let url = format!(
-            "{}/session/{}/message",
-            self.server_url.trim_end_matches('/'),
-            session_id
-        );
-
-        #[derive(Serialize)]
-        struct MessageRequest {
-            content: String,
-        }
-
-        let body = MessageRequest {
-            content: message.to_string(),
-        };
+        let url = self.url(&format!("session/{}/message", session_id));
+        let body = ContentRequest { content: message };
 
         let response = self
-            .client
-            .post(&url)
-            .json(&body)
-            .send()
-            .await
-            .map_err(|e| crate::error::TmuxError::Command(format!("HTTP request failed: {}", e)))?;
-
-        if response.status().is_success() {
-            Ok(())
-        } else {
-            let status = response.status();
-            let error_text = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Unknown error".to_string());
-            Err(crate::error::TmuxError::Command(format!(
-                "OpenCode API error: {} - {}",
-                status, error_text
-            )))
-        }
+            .execute(false, || self.client.post(&url).json(&body))
+            .await?;
+        Self::check_status(response).await?;
+        Ok(())
     }
 
     /// List all OpenCode sessions
     pub async fn list_sessions(&self) -> Result<Vec<SessionInfo>> {
-        let url = format!("{}/sessions", self.server_url.trim_end_matches('/'));
-
-        let response =
-            self.client.get(&url).send().await.map_err(|e| {
-                crate::error::TmuxError::Command(format!("HTTP request failed: {}", e))
-            })?;
-
-        if response.status().is_success() {
-            let sessions: Vec<SessionInfo> = response.json().await.map_err(|e| {
-                crate::error::TmuxError::Command(format!("Failed to parse JSON: {}", e))
-            })?;
-            Ok(sessions)
-        } else {
-            let status = response.status();
-            let error_text = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Unknown error".to_string());
-            Err(crate::error::TmuxError::Command(format!(
-                "OpenCode API error: {} - {}",
-                status, error_text
-            )))
-        }
+        self.get_json("sessions").await
     }
 
     /// Get session status
     pub async fn get_session_status(&self, session_id: &str) -> Result<SessionStatus> {
-        let url = format!(
-            "{}/session/{}/status",
-            self.server_url.trim_end_matches('/'),
-            session_id
-        );
-
-        let response =
-            self.client.get(&url).send().await.map_err(|e| {
-                crate::error::TmuxError::Command(format!("HTTP request failed: {}", e))
-            })?;
-
-        if response.status().is_success() {
-            let status: SessionStatus = response.json().await.map_err(|e| {
-                crate::error::TmuxError::Command(format!("Failed to parse JSON: {}", e))
-            })?;
-            Ok(status)
-        } else {
-            let status = response.status();
-            let error_text = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Unknown error".to_string());
-            Err(crate::error::TmuxError::Command(format!(
-                "OpenCode API error: {} - {}",
-                status, error_text
-            )))
-        }
+        self.get_json(&format!("session/{}/status", session_id))
+            .await
     }
 
     /// Check if OpenCode server is available
     pub async fn health_check(&self) -> Result<bool> {
-        let url = format!("{}/health", self.server_url.trim_end_matches('/'));
-
-        let response =
-            self.client.get(&url).send().await.map_err(|e| {
-                crate::error::TmuxError::Command(format!("HTTP request failed: {}", e))
-            })?;
+        let url = self.url("health");
 
+        let response = self.execute(true, || self.client.get(&url)).await?;
         Ok(response.status().is_success())
     }
+
+    /// Server URL the client talks to
+    pub fn server_url(&self) -> &str {
+        &self.server_url
+    }
+
+    /// GET a path and decode its JSON body, retrying as an idempotent request
+    pub(crate) async fn get_json<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        let url = self.url(path);
+
+        let response = self.execute(true, || self.client.get(&url)).await?;
+        Self::decode(Self::check_status(response).await?).await
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.server_url.trim_end_matches('/'), path)
+    }
+
+    fn authorize(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.auth {
+            Some(Auth::Basic { username, password }) => {
+                request.basic_auth(username, Some(password))
+            }
+            Some(Auth::Bearer(token)) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    /// Send a request, retrying transport failures and retryable statuses
+    /// when the request is idempotent
+    async fn execute<F>(&self, idempotent: bool, build: F) -> Result<reqwest::Response>
+    where
+        F: Fn() -> reqwest::RequestBuilder,
+    {
+        let max_attempts = if idempotent {
+            self.retry.max_retries + 1
+        } else {
+            1
+        };
+        let mut attempt = 0;
+
+        loop {
+            let result = self.authorize(build()).send().await;
+            attempt += 1;
+
+            let retryable = match &result {
+                Ok(response) => is_retryable_status(response.status()),
+                Err(e) => e.is_connect() || e.is_timeout(),
+            };
+
+            if !retryable || attempt >= max_attempts {
+                return result
+                    .map_err(|e| TmuxError::Transport(format!("HTTP request failed: {}", e)));
+            }
+
+            tokio::time::sleep(self.retry.delay_for(attempt - 1)).await;
+        }
+    }
+
+    async fn check_status(response: reqwest::Response) -> Result<reqwest::Response> {
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let error_text = response
+            .text()
+            .await
+            .unwrap_or_else(|_| "Unknown error".to_string());
+        Err(TmuxError::Http(status.as_u16(), error_text))
+    }
+
+    async fn decode<T: DeserializeOwned>(response: reqwest::Response) -> Result<T> {
+        response
+            .json()
+            .await
+            .map_err(|e| TmuxError::Decode(format!("Failed to parse JSON: {}", e)))
+    }
+}
+
+/// Statuses worth retrying: throttling and transient server failures
+fn is_retryable_status(status: reqwest::StatusCode) -> bool {
+    status == reqwest::StatusCode::TOO_MANY_REQUESTS
+        || status == reqwest::StatusCode::BAD_GATEWAY
+        || status == reqwest::StatusCode::SERVICE_UNAVAILABLE
+        || status == reqwest::StatusCode::GATEWAY_TIMEOUT
 }
 
 #[cfg(test)]
@@ -208,6 +404,84 @@ mod tests {
     fn test_client_creation() {
         let client = OpenCodeClient::new("http://localhost:4096");
         assert_eq!(client.server_url, "http://localhost:4096");
+        assert_eq!(client.retry, RetryPolicy::default());
+        assert_eq!(client.auth, Auth::from_env());
+    }
+
+    #[test]
+    fn test_builder_options() {
+        let client = OpenCodeClient::builder("http://localhost:4096/")
+            .connect_timeout(Duration::from_secs(1))
+            .read_timeout(Duration::from_secs(2))
+            .bearer_auth("secret")
+            .retry_policy(RetryPolicy::none())
+            .build()
+            .unwrap();
+
+        assert_eq!(client.auth, Some(Auth::Bearer("secret".to_string())));
+        assert_eq!(client.retry.max_retries, 0);
+        assert_eq!(client.url("health"), "http://localhost:4096/health");
+    }
+
+    #[test]
+    fn test_auth_headers() {
+        let client = OpenCodeClient::builder("http://localhost:4096")
+            .basic_auth("opencode", "hunter2")
+            .build()
+            .unwrap();
+
+        let request = client
+            .authorize(client.client.get(client.url("sessions")))
+            .build()
+            .unwrap();
+        let header = request
+            .headers()
+            .get(reqwest::header::AUTHORIZATION)
+            .unwrap();
+        assert!(header.to_str().unwrap().starts_with("Basic "));
+
+        let client = OpenCodeClient::builder("http://localhost:4096")
+            .bearer_auth("token123")
+            .build()
+            .unwrap();
+
+        let request = client
+            .authorize(client.client.get(client.url("sessions")))
+            .build()
+            .unwrap();
+        let header = request
+            .headers()
+            .get(reqwest::header::AUTHORIZATION)
+            .unwrap();
+        assert_eq!(header.to_str().unwrap(), "Bearer token123");
+    }
+
+    #[test]
+    fn test_retry_delay_is_bounded() {
+        let policy = RetryPolicy {
+            max_retries: 5,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(500),
+        };
+
+        for retry in 0..8 {
+            let ceiling = (100u64 << retry).min(500);
+            for _ in 0..20 {
+                assert!(policy.delay_for(retry) <= Duration::from_millis(ceiling));
+            }
+        }
+    }
+
+    #[test]
+    fn test_retryable_statuses() {
+        assert!(is_retryable_status(
+            reqwest::StatusCode::SERVICE_UNAVAILABLE
+        ));
+        assert!(is_retryable_status(reqwest::StatusCode::TOO_MANY_REQUESTS));
+        assert!(!is_retryable_status(reqwest::StatusCode::NOT_FOUND));
+        assert!(!is_retryable_status(
+            reqwest::StatusCode::INTERNAL_SERVER_ERROR
+        ));
     }
 
     #[test]
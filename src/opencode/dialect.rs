@@ -0,0 +1,311 @@
+//! Agent Dialect Module
+//!
+//! Dialects for the agents swarm_test drives besides OpenCode, and a
+//! registry that picks the right one for a piece of captured output. All
+//! dialects parse through the shared pipeline in `protocol`.
+
+use crate::Result;
+use crate::opencode::protocol::{
+    AgentResponse, LineKind, OpenCodeProtocol, Protocol, ToolCall, is_error_line,
+};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Claude-style TUI dialect: `⏺ Bash(cargo test)` tool lines and
+/// `⎿  Error: ...` tool results
+pub struct ClaudeProtocol;
+
+impl Protocol for ClaudeProtocol {
+    fn name(&self) -> &str {
+        "claude"
+    }
+
+    fn classify_line(&self, line: &str) -> LineKind {
+        let trimmed = line.trim();
+
+        if let Some(rest) = trimmed
+            .strip_prefix('⏺')
+            .or_else(|| trimmed.strip_prefix('●'))
+            && let Some(call) = parse_claude_tool(rest.trim())
+        {
+            return LineKind::ToolCall(call);
+        }
+
+        let result = trimmed.strip_prefix('⎿').map(str::trim).unwrap_or(trimmed);
+        if is_error_line(result) || result.starts_with("API Error:") {
+            return LineKind::Error(result.to_string());
+        }
+
+        LineKind::Text
+    }
+}
+
+/// `Bash(cargo test)` -> tool `bash` with raw args `cargo test`
+fn parse_claude_tool(text: &str) -> Option<ToolCall> {
+    let open = text.find('(')?;
+    let name = &text[..open];
+    if name.is_empty()
+        || !name.starts_with(|c: char| c.is_ascii_uppercase())
+        || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        || !text.ends_with(')')
+    {
+        return None;
+    }
+
+    let args = &text[open + 1..text.len() - 1];
+    Some(ToolCall::raw(name.to_lowercase(), args, text))
+}
+
+/// Aider dialect: `Applied edit to <path>`, `Running <command>` and
+/// `Commit <hash> <message>` actions. The `Tokens: ...` summary line ends
+/// a turn.
+pub struct AiderProtocol;
+
+impl Protocol for AiderProtocol {
+    fn name(&self) -> &str {
+        "aider"
+    }
+
+    fn classify_line(&self, line: &str) -> LineKind {
+        let trimmed = line.trim();
+
+        if let Some(path) = trimmed.strip_prefix("Applied edit to ") {
+            let mut call = ToolCall::raw("edit", path, trimmed);
+            call.arguments
+                .insert("path".to_string(), Value::String(path.trim().to_string()));
+            return LineKind::ToolCall(call);
+        }
+
+        if let Some(command) = trimmed.strip_prefix("Running ") {
+            return LineKind::ToolCall(ToolCall::raw("bash", command, command));
+        }
+
+        if let Some(rest) = trimmed.strip_prefix("Commit ")
+            && let Some((hash, message)) = rest.split_once(' ')
+            && hash.len() >= 7
+            && hash.chars().all(|c| c.is_ascii_hexdigit())
+        {
+            let mut call = ToolCall::raw("commit", rest, trimmed);
+            call.arguments
+                .insert("hash".to_string(), Value::String(hash.to_string()));
+            call.arguments.insert(
+                "message".to_string(),
+                Value::String(message.trim().to_string()),
+            );
+            return LineKind::ToolCall(call);
+        }
+
+        if is_error_line(trimmed)
+            || trimmed.starts_with("The LLM did not conform to the edit format")
+        {
+            return LineKind::Error(trimmed.to_string());
+        }
+
+        if trimmed.starts_with("Tokens: ") && trimmed.contains(" sent") {
+            return LineKind::Completion;
+        }
+
+        LineKind::Text
+    }
+}
+
+/// Plain shell dialect: prompt lines (`$ cmd`, `user@host:~/dir$ cmd`) are
+/// tool calls, and common shell and compiler failures are errors
+pub struct ShellProtocol;
+
+impl Protocol for ShellProtocol {
+    fn name(&self) -> &str {
+        "shell"
+    }
+
+    fn classify_line(&self, line: &str) -> LineKind {
+        if let Some(command) = shell_command(line) {
+            return LineKind::ToolCall(ToolCall::raw("shell", command, command));
+        }
+
+        let trimmed = line.trim();
+        if is_error_line(trimmed)
+            || trimmed.starts_with("fatal:")
+            || trimmed.ends_with(": command not found")
+            || trimmed.ends_with(": No such file or directory")
+            || trimmed.ends_with(": Permission denied")
+            || (trimmed.starts_with("thread '") && trimmed.contains("' panicked at"))
+        {
+            return LineKind::Error(trimmed.to_string());
+        }
+
+        LineKind::Text
+    }
+}
+
+/// Command typed at a shell prompt, if `line` is a prompt line
+fn shell_command(line: &str) -> Option<&str> {
+    if let Some(command) = line.strip_prefix("$ ") {
+        return Some(command.trim()).filter(|c| !c.is_empty());
+    }
+
+    // user@host:path$ command
+    let (prompt, command) = line.split_once("$ ")?;
+    let (user_host, _path) = prompt.split_once(':')?;
+    if user_host.contains('@') && !prompt.contains(char::is_whitespace) {
+        Some(command.trim()).filter(|c| !c.is_empty())
+    } else {
+        None
+    }
+}
+
+/// Known agent dialects, looked up by name or detected from output
+pub struct DialectRegistry {
+    dialects: Vec<Box<dyn Protocol + Send + Sync>>,
+}
+
+impl DialectRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self {
+            dialects: Vec::new(),
+        }
+    }
+
+    /// Registry with the built-in dialects. OpenCode comes first and wins
+    /// when no dialect recognises the output.
+    pub fn with_builtin() -> Self {
+        let mut registry = Self::new();
+        registry.register(Box::new(OpenCodeProtocol::new()));
+        registry.register(Box::new(ClaudeProtocol));
+        registry.register(Box::new(AiderProtocol));
+        registry.register(Box::new(ShellProtocol));
+        registry
+    }
+
+    /// Shared registry with the built-in dialects
+    pub fn builtin() -> &'static DialectRegistry {
+        static BUILTIN: OnceLock<DialectRegistry> = OnceLock::new();
+        BUILTIN.get_or_init(DialectRegistry::with_builtin)
+    }
+
+    /// Add a dialect, replacing any dialect with the same name
+    pub fn register(&mut self, dialect: Box<dyn Protocol + Send + Sync>) {
+        match self
+            .dialects
+            .iter()
+            .position(|d| d.name() == dialect.name())
+        {
+            Some(index) => self.dialects[index] = dialect,
+            None => self.dialects.push(dialect),
+        }
+    }
+
+    /// Dialect by name
+    pub fn get(&self, name: &str) -> Option<&(dyn Protocol + Send + Sync)> {
+        self.dialects
+            .iter()
+            .find(|d| d.name() == name)
+            .map(|d| d.as_ref())
+    }
+
+    /// Registered dialect names, in registration order
+    pub fn names(&self) -> Vec<&str> {
+        self.dialects.iter().map(|d| d.name()).collect()
+    }
+
+    /// Dialect that recognises the most lines of `output`. Ties go to the
+    /// dialect registered first.
+    pub fn detect(&self, output: &str) -> Option<&(dyn Protocol + Send + Sync)> {
+        let mut best: Option<(&(dyn Protocol + Send + Sync), usize)> = None;
+
+        for dialect in &self.dialects {
+            let score = dialect.detect(output);
+            if best.is_none_or(|(_, best_score)| score > best_score) {
+                best = Some((dialect.as_ref(), score));
+            }
+        }
+
+        best.map(|(dialect, _)| dialect)
+    }
+
+    /// Parse `output` with the detected dialect
+    pub fn parse(&self, output: &str) -> Result<AgentResponse> {
+        match self.detect(output) {
+            Some(dialect) => dialect.parse_response(output),
+            None => OpenCodeProtocol::new().parse_response(output),
+        }
+    }
+
+    /// Score of every dialect for `output`, for diagnostics
+    pub fn scores(&self, output: &str) -> HashMap<String, usize> {
+        self.dialects
+            .iter()
+            .map(|d| (d.name().to_string(), d.detect(output)))
+            .collect()
+    }
+}
+
+impl Default for DialectRegistry {
+    fn default() -> Self {
+        Self::with_builtin()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opencode::protocol::MessageType;
+
+    #[test]
+    fn test_builtin_names() {
+        let registry = DialectRegistry::with_builtin();
+        assert_eq!(
+            registry.names(),
+            vec!["opencode", "claude", "aider", "shell"]
+        );
+    }
+
+    #[test]
+    fn test_register_replaces_same_name() {
+        let mut registry = DialectRegistry::with_builtin();
+        registry.register(Box::new(ShellProtocol));
+        assert_eq!(registry.names().len(), 4);
+    }
+
+    #[test]
+    fn test_detect_prefers_recognising_dialect() {
+        let registry = DialectRegistry::builtin();
+
+        let claude = "⏺ Bash(cargo test)\n  ⎿  test result: ok";
+        assert_eq!(registry.detect(claude).unwrap().name(), "claude");
+
+        let shell = "$ ls\nsrc tests\n$ cat missing\ncat: missing: No such file or directory";
+        assert_eq!(registry.detect(shell).unwrap().name(), "shell");
+
+        // Nothing recognised: OpenCode is the default
+        assert_eq!(registry.detect("hello").unwrap().name(), "opencode");
+    }
+
+    #[test]
+    fn test_claude_tool_line() {
+        let response = ClaudeProtocol
+            .parse_response("⏺ Read(src/lib.rs)\n  ⎿  Read 20 lines")
+            .unwrap();
+        assert_eq!(response.message_type, MessageType::ToolCall);
+        assert_eq!(response.tool_calls[0].tool_name, "read");
+        assert_eq!(response.tool_calls[0].arguments["raw_args"], "src/lib.rs");
+    }
+
+    #[test]
+    fn test_aider_commit() {
+        let response = AiderProtocol
+            .parse_response("Commit 1a2b3c4 fix: handle empty input")
+            .unwrap();
+        assert_eq!(response.tool_calls[0].tool_name, "commit");
+        assert_eq!(response.tool_calls[0].arguments["hash"], "1a2b3c4");
+    }
+
+    #[test]
+    fn test_shell_prompt_forms() {
+        assert_eq!(shell_command("$ cargo build"), Some("cargo build"));
+        assert_eq!(shell_command("dev@box:~/crate$ ls -la"), Some("ls -la"));
+        assert_eq!(shell_command("costs $ 5"), None);
+    }
+}
@@ -3,7 +3,6 @@
 //! Discovers OpenCode server availability and configuration.
 //! Provides health checking and automatic server detection.
 
-use crate::Result;
 use std::time::Duration;
 
 /// OpenCode server status
@@ -44,9 +43,7 @@ impl Default for OpenCodeDiscovery {
 impl OpenCodeDiscovery {
     /// Create new discovery service
     pub fn new(default_server_url: String) -> Self {
-        Self {
-            default_server_url,
-        }
+        Self { default_server_url }
     }
 
     /// Check if OpenCode server is running on default URL
@@ -72,7 +69,7 @@ impl OpenCodeDiscovery {
         {
             Ok(client) => {
                 let health_url = format!("{}/health", server_url.trim_end_matches('/'));
-                
+
                 match client.get(&health_url).send().await {
                     Ok(response) => {
                         let status = if response.status().is_success() {
@@ -107,18 +104,18 @@ impl OpenCodeDiscovery {
     pub async fn discover(&self) -> OpenCodeStatus {
         // Check default location first
         let default_status = self.check_default_server().await;
-        
+
         if default_status.running {
             return default_status;
         }
 
         // Check environment variable
-        if let Ok(env_url) = std::env::var("OPENCODE_SERVER_URL") {
-            if !env_url.is_empty() {
-                let env_status = self.check_server(&env_url).await;
-                if env_status.running {
-                    return env_status;
-                }
+        if let Ok(env_url) = std::env::var("OPENCODE_SERVER_URL")
+            && !env_url.is_empty()
+        {
+            let env_status = self.check_server(&env_url).await;
+            if env_status.running {
+                return env_status;
             }
         }
 
@@ -143,18 +140,18 @@ impl OpenCodeDiscovery {
     /// Returns configured URL if available, or discovered URL, or None
     pub async fn get_server_url(&self, configured_url: Option<String>) -> Option<String> {
         // Try configured URL first
-        if let Some(url) = configured_url {
-            if !url.is_empty() {
-                let status = self.check_server(&url).await;
-                if status.running {
-                    return Some(url);
-                }
+        if let Some(url) = configured_url
+            && !url.is_empty()
+        {
+            let status = self.check_server(&url).await;
+            if status.running {
+                return Some(url);
             }
         }
 
         // Discover automatically
         let discovered = self.discover().await;
-        
+
         if discovered.running {
             discovered.url
         } else {
@@ -180,7 +177,7 @@ mod tests {
             url: Some("http://localhost:4096".to_string()),
             status: ServerStatus::Available,
         };
-        
+
         assert!(status.running);
         assert_eq!(status.url, Some("http://localhost:4096".to_string()));
         assert_eq!(status.status, ServerStatus::Available);
@@ -0,0 +1,540 @@
+//! Mock OpenCode Server Module
+//!
+//! Minimal in-process HTTP server implementing the OpenCode endpoints this
+//! crate talks to. Used by tests and by `swarm_test mock-opencode` for
+//! offline development. Supports scripted responses, latency injection and
+//! failure injection.
+
+use crate::Result;
+use crate::error::TmuxError;
+use crate::opencode::client::{SessionInfo, SessionStatus};
+use crate::opencode::reader::{OpenCodeMessage, SessionOutput};
+use std::collections::{HashMap, VecDeque};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Canned HTTP response
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    pub status: u16,
+    pub body: String,
+    /// Extra delay before this response is written
+    pub delay: Option<Duration>,
+}
+
+impl MockResponse {
+    /// JSON response with given status
+    pub fn json(status: u16, body: impl Into<String>) -> Self {
+        Self {
+            status,
+            body: body.into(),
+            delay: None,
+        }
+    }
+
+    /// Delay this response
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Self::json(status, serde_json::json!({ "error": message }).to_string())
+    }
+}
+
+/// Failure injected into the next request, whatever its route
+#[derive(Debug, Clone)]
+pub enum Failure {
+    /// Answer with this status and an error body
+    Status(u16),
+    /// Close the connection without answering
+    Disconnect,
+}
+
+/// Prompt or message received by the mock
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReceivedPrompt {
+    pub session_id: String,
+    /// `prompt` or `message`, the endpoint that was hit
+    pub endpoint: String,
+    pub content: String,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    healthy: bool,
+    sessions: Vec<SessionInfo>,
+    messages: HashMap<String, Vec<OpenCodeMessage>>,
+    received: Vec<ReceivedPrompt>,
+    scripted: HashMap<String, VecDeque<MockResponse>>,
+    failures: VecDeque<Failure>,
+    latency: Duration,
+    auto_reply: Option<String>,
+    /// `Authorization` header every request must carry
+    required_auth: Option<String>,
+    events: Vec<String>,
+    request_count: usize,
+    next_message_id: usize,
+}
+
+/// Mock OpenCode server running on a background thread
+pub struct MockOpenCodeServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<MockState>>,
+    shutdown: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl MockOpenCodeServer {
+    /// Start on an ephemeral localhost port
+    pub fn start() -> Result<Self> {
+        Self::bind("127.0.0.1:0")
+    }
+
+    /// Start on a specific address
+    pub fn bind(addr: &str) -> Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+
+        let state = Arc::new(Mutex::new(MockState {
+            healthy: true,
+            ..MockState::default()
+        }));
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let handle = {
+            let state = state.clone();
+            let shutdown = shutdown.clone();
+            std::thread::spawn(move || accept_loop(listener, state, shutdown))
+        };
+
+        Ok(Self {
+            addr,
+            state,
+            shutdown,
+            handle: Some(handle),
+        })
+    }
+
+    /// Base URL, e.g. `http://127.0.0.1:40123`
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Bound address
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Register a session returned by `/sessions`
+    pub fn add_session(&self, id: &str, name: &str) {
+        let now = chrono::Utc::now().to_rfc3339();
+        let mut state = self.state.lock().unwrap();
+        state.sessions.retain(|s| s.id != id);
+        state.sessions.push(SessionInfo {
+            id: id.to_string(),
+            name: name.to_string(),
+            created_at: now.clone(),
+            updated_at: now,
+        });
+        state.messages.entry(id.to_string()).or_default();
+    }
+
+    /// Remove a session and its messages
+    pub fn remove_session(&self, id: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.sessions.retain(|s| s.id != id);
+        state.messages.remove(id);
+    }
+
+    /// Append a message to a session's history
+    pub fn push_message(&self, session_id: &str, role: &str, content: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.append_message(session_id, role, content);
+    }
+
+    /// Reply to every prompt with this assistant message
+    pub fn set_auto_reply(&self, reply: Option<&str>) {
+        self.state.lock().unwrap().auto_reply = reply.map(String::from);
+    }
+
+    /// Answer 401 to requests whose `Authorization` header is not exactly
+    /// this value, e.g. `Bearer secret`
+    pub fn require_auth(&self, authorization: Option<&str>) {
+        self.state.lock().unwrap().required_auth = authorization.map(String::from);
+    }
+
+    /// Make `/health` report success or failure
+    pub fn set_healthy(&self, healthy: bool) {
+        self.state.lock().unwrap().healthy = healthy;
+    }
+
+    /// Delay every response by this much
+    pub fn set_latency(&self, latency: Duration) {
+        self.state.lock().unwrap().latency = latency;
+    }
+
+    /// Queue a one-shot response for `method path`, taking precedence over
+    /// the built-in route. Queued responses are served in order.
+    pub fn script(&self, method: &str, path: &str, response: MockResponse) {
+        let key = format!("{} {}", method.to_uppercase(), path);
+        self.state
+            .lock()
+            .unwrap()
+            .scripted
+            .entry(key)
+            .or_default()
+            .push_back(response);
+    }
+
+    /// Fail the next request, whatever its route
+    pub fn inject_failure(&self, failure: Failure) {
+        self.state.lock().unwrap().failures.push_back(failure);
+    }
+
+    /// Fail the next `count` requests the same way
+    pub fn inject_failures(&self, failure: Failure, count: usize) {
+        let mut state = self.state.lock().unwrap();
+        for _ in 0..count {
+            state.failures.push_back(failure.clone());
+        }
+    }
+
+    /// Queue an event for the `/event` stream
+    pub fn push_event(&self, event: serde_json::Value) {
+        self.state.lock().unwrap().events.push(event.to_string());
+    }
+
+    /// Prompts and messages received so far
+    pub fn received(&self) -> Vec<ReceivedPrompt> {
+        self.state.lock().unwrap().received.clone()
+    }
+
+    /// Number of requests handled, including injected failures
+    pub fn request_count(&self) -> usize {
+        self.state.lock().unwrap().request_count
+    }
+
+    /// Stop accepting connections and wait for the accept loop to exit
+    pub fn shutdown(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+
+    /// Block the calling thread until the server is shut down
+    pub fn wait(mut self) {
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for MockOpenCodeServer {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+impl MockState {
+    fn append_message(&mut self, session_id: &str, role: &str, content: &str) {
+        self.next_message_id += 1;
+        let message = OpenCodeMessage {
+            id: format!("msg_{}", self.next_message_id),
+            session_id: session_id.to_string(),
+            role: role.to_string(),
+            content: content.to_string(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            tool_calls: Vec::new(),
+        };
+        self.messages
+            .entry(session_id.to_string())
+            .or_default()
+            .push(message);
+    }
+
+    fn has_session(&self, session_id: &str) -> bool {
+        self.sessions.iter().any(|s| s.id == session_id)
+    }
+
+    fn route(&mut self, method: &str, path: &str, body: &str) -> MockResponse {
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+        match (method, segments.as_slice()) {
+            ("GET", ["health"]) => {
+                if self.healthy {
+                    MockResponse::json(200, r#"{"healthy":true}"#)
+                } else {
+                    MockResponse::error(503, "unhealthy")
+                }
+            }
+            ("GET", ["sessions"]) => json_response(&self.sessions),
+            ("GET", ["event"]) => {
+                let body: String = self
+                    .events
+                    .iter()
+                    .map(|e| format!("data: {}\n\n", e))
+                    .collect();
+                MockResponse::json(200, body)
+            }
+            ("GET", ["session", id]) => match self.session_output(id) {
+                Some(output) => json_response(&output),
+                None => MockResponse::error(404, "session not found"),
+            },
+            ("GET", ["session", id, "messages"]) => match self.session_output(id) {
+                Some(output) => json_response(&output),
+                None => MockResponse::error(404, "session not found"),
+            },
+            ("GET", ["session", id, "status"]) => {
+                if !self.has_session(id) {
+                    return MockResponse::error(404, "session not found");
+                }
+                json_response(&SessionStatus {
+                    session_id: id.to_string(),
+                    status: "idle".to_string(),
+                    message_count: self.messages.get(*id).map(Vec::len).unwrap_or(0),
+                })
+            }
+            ("POST", ["session", id, endpoint @ ("prompt" | "message")]) => {
+                if !self.has_session(id) {
+                    return MockResponse::error(404, "session not found");
+                }
+                let content = serde_json::from_str::<serde_json::Value>(body)
+                    .ok()
+                    .and_then(|v| v.get("content").and_then(|c| c.as_str()).map(String::from));
+                let content = match content {
+                    Some(content) => content,
+                    None => return MockResponse::error(400, "missing content"),
+                };
+
+                self.received.push(ReceivedPrompt {
+                    session_id: id.to_string(),
+                    endpoint: endpoint.to_string(),
+                    content: content.clone(),
+                });
+                self.append_message(id, "user", &content);
+                if let Some(reply) = self.auto_reply.clone() {
+                    self.append_message(id, "assistant", &reply);
+                }
+
+                MockResponse::json(200, "{}")
+            }
+            _ => MockResponse::error(404, "no such route"),
+        }
+    }
+
+    fn session_output(&self, session_id: &str) -> Option<SessionOutput> {
+        if !self.has_session(session_id) {
+            return None;
+        }
+        let messages = self.messages.get(session_id).cloned().unwrap_or_default();
+        let last_activity = messages
+            .last()
+            .map(|m| m.timestamp.clone())
+            .unwrap_or_default();
+
+        Some(SessionOutput {
+            session_id: session_id.to_string(),
+            messages,
+            last_activity,
+        })
+    }
+}
+
+fn json_response<T: serde::Serialize>(value: &T) -> MockResponse {
+    match serde_json::to_string(value) {
+        Ok(body) => MockResponse::json(200, body),
+        Err(e) => MockResponse::error(500, &e.to_string()),
+    }
+}
+
+fn accept_loop(listener: TcpListener, state: Arc<Mutex<MockState>>, shutdown: Arc<AtomicBool>) {
+    while !shutdown.load(Ordering::SeqCst) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                let state = state.clone();
+                std::thread::spawn(move || {
+                    let _ = handle_connection(stream, &state);
+                });
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                std::thread::sleep(Duration::from_millis(5));
+            }
+            Err(_) => break,
+        }
+    }
+}
+
+fn handle_connection(stream: TcpStream, state: &Mutex<MockState>) -> Result<()> {
+    stream.set_nonblocking(false)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default();
+    let path = target.split('?').next().unwrap_or_default().to_string();
+
+    let mut content_length = 0;
+    let mut authorization = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let name = name.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = value
+                .trim()
+                .parse()
+                .map_err(|_| TmuxError::Parse("Invalid Content-Length".to_string()))?;
+        } else if name.eq_ignore_ascii_case("authorization") {
+            authorization = Some(value.trim().to_string());
+        }
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    let body = String::from_utf8_lossy(&body);
+
+    // Decide under the lock, sleep and write without it
+    let (response, latency) = {
+        let mut state = state.lock().unwrap();
+        state.request_count += 1;
+
+        let scripted_key = format!("{} {}", method, path);
+        let authorized = state.required_auth.is_none() || state.required_auth == authorization;
+        let response = if !authorized {
+            Some(MockResponse::error(401, "unauthorized"))
+        } else {
+            match state.failures.pop_front() {
+                Some(Failure::Disconnect) => None,
+                Some(Failure::Status(status)) => {
+                    Some(MockResponse::error(status, "injected failure"))
+                }
+                None => Some(
+                    match state
+                        .scripted
+                        .get_mut(&scripted_key)
+                        .and_then(VecDeque::pop_front)
+                    {
+                        Some(response) => response,
+                        None => state.route(&method, &path, &body),
+                    },
+                ),
+            }
+        };
+        (response, state.latency)
+    };
+
+    let response = match response {
+        Some(response) => response,
+        None => {
+            let _ = stream.shutdown(std::net::Shutdown::Both);
+            return Ok(());
+        }
+    };
+
+    std::thread::sleep(latency + response.delay.unwrap_or_default());
+
+    let content_type = if path == "/event" {
+        "text/event-stream"
+    } else {
+        "application/json"
+    };
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        reason_phrase(response.status),
+        content_type,
+        response.body.len(),
+        response.body
+    )?;
+    stream.flush()?;
+
+    Ok(())
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        _ => "Unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get(server: &MockOpenCodeServer, path: &str) -> String {
+        let mut stream = TcpStream::connect(server.addr()).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_health_and_sessions() {
+        let server = MockOpenCodeServer::start().unwrap();
+        server.add_session("ses_1", "agent-1");
+
+        assert!(get(&server, "/health").starts_with("HTTP/1.1 200"));
+        assert!(get(&server, "/sessions").contains("ses_1"));
+
+        server.set_healthy(false);
+        assert!(get(&server, "/health").starts_with("HTTP/1.1 503"));
+    }
+
+    #[test]
+    fn test_scripted_then_default() {
+        let server = MockOpenCodeServer::start().unwrap();
+        server.script("get", "/sessions", MockResponse::json(500, "{}"));
+
+        assert!(get(&server, "/sessions").starts_with("HTTP/1.1 500"));
+        assert!(get(&server, "/sessions").starts_with("HTTP/1.1 200"));
+    }
+
+    #[test]
+    fn test_injected_failures() {
+        let server = MockOpenCodeServer::start().unwrap();
+        server.inject_failure(Failure::Status(502));
+        server.inject_failure(Failure::Disconnect);
+
+        assert!(get(&server, "/health").starts_with("HTTP/1.1 502"));
+        assert!(get(&server, "/health").is_empty());
+        assert!(get(&server, "/health").starts_with("HTTP/1.1 200"));
+        assert_eq!(server.request_count(), 3);
+    }
+
+    #[test]
+    fn test_event_stream() {
+        let server = MockOpenCodeServer::start().unwrap();
+        server.push_event(serde_json::json!({ "type": "session.idle" }));
+
+        let response = get(&server, "/event");
+        assert!(response.contains("text/event-stream"));
+        assert!(response.contains("data: {\"type\":\"session.idle\"}"));
+    }
+}
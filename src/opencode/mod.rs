@@ -1,13 +1,21 @@
+mod classify;
 mod client;
+mod dialect;
 mod discovery;
+mod mock;
 mod protocol;
 mod reader;
 mod sender;
 mod session;
+mod tool_args;
 
+pub use classify::*;
 pub use client::*;
+pub use dialect::*;
 pub use discovery::*;
+pub use mock::*;
 pub use protocol::*;
 pub use reader::*;
 pub use sender::*;
 pub use session::*;
+pub use tool_args::*;
@@ -1,11 +1,43 @@
 use crate::Result;
+use crate::messaging::timestamp::find_timestamp;
+use crate::opencode::classify::{
+    ClassifiedError, ErrorCategory, attach_context, classify_error, error_category,
+};
+use crate::opencode::tool_args::{ToolArguments, parse_arguments, parse_tool_call};
 use crate::types::{Command, CommandTarget, PaneId};
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde_json::Value;
 
+/// Agent output dialect.
+///
+/// Every dialect shares one parsing pipeline (`scan_output`): the
+/// `<error>`, `<tool_call>` and `<complete>` blocks are handled for all
+/// dialects, and every other line is handed to `classify_line`.
 pub trait Protocol {
-    fn format_command(&self, command: &Command) -> String;
-    fn parse_response(&self, output: &str) -> Result<AgentResponse>;
+    /// Dialect name, used as the registry key
+    fn name(&self) -> &str;
+
+    /// Classify a single line that is not part of a shared markup block
+    fn classify_line(&self, line: &str) -> LineKind;
+
+    fn format_command(&self, command: &Command) -> String {
+        format_tmux_command(command)
+    }
+
+    fn parse_response(&self, output: &str) -> Result<AgentResponse> {
+        Ok(scan_output(self, output).into_response())
+    }
+
+    /// How many lines of `output` this dialect recognises. Used by the
+    /// registry to pick a dialect for unlabelled output.
+    fn detect(&self, output: &str) -> usize {
+        output
+            .lines()
+            .filter(|line| self.classify_line(line) != LineKind::Text)
+            .count()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -15,9 +47,69 @@ pub struct AgentResponse {
     pub content: String,
     pub tool_calls: Vec<ToolCall>,
     pub error: Option<String>,
+    /// `error` split into classified reports
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<ClassifiedError>,
+    /// When the output was captured, in milliseconds since the Unix epoch.
+    /// 0 if unknown.
+    #[serde(default)]
+    pub received_at: u64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+impl AgentResponse {
+    /// Set the capture time, in milliseconds since the Unix epoch
+    pub fn with_received_at(mut self, received_at: u64) -> Self {
+        self.received_at = received_at;
+        self
+    }
+
+    /// Capture time, if known
+    pub fn received(&self) -> Option<DateTime<Utc>> {
+        if self.received_at == 0 {
+            return None;
+        }
+        DateTime::from_timestamp_millis(self.received_at as i64)
+    }
+
+    /// Category of the first error, if any
+    pub fn error_category(&self) -> Option<ErrorCategory> {
+        self.errors.first().map(|e| e.category)
+    }
+
+    /// Classified errors, classifying `error` for responses that predate
+    /// classification
+    pub fn classified_errors(&self) -> Vec<ClassifiedError> {
+        match (&self.error, self.errors.is_empty()) {
+            (Some(error), true) => vec![classify_error(error)],
+            _ => self.errors.clone(),
+        }
+    }
+
+    /// Capture time, or the first timestamp in the content when the capture
+    /// time is unknown
+    pub fn timestamp(&self) -> Option<DateTime<Utc>> {
+        self.received().or_else(|| find_timestamp(&self.content))
+    }
+}
+
+#[cfg(test)]
+impl AgentResponse {
+    /// Response of `message_type` with `content` and nothing else; it
+    /// succeeds unless it is an error
+    pub(crate) fn fixture(message_type: MessageType, content: &str) -> Self {
+        Self {
+            success: message_type != MessageType::Error,
+            message_type,
+            content: content.to_string(),
+            tool_calls: Vec::new(),
+            error: None,
+            errors: Vec::new(),
+            received_at: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum MessageType {
     Message,
     ToolCall,
@@ -25,126 +117,376 @@ pub enum MessageType {
     Completion,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ToolCall {
     pub tool_name: String,
-    pub arguments: HashMap<String, String>,
+    pub arguments: ToolArguments,
 }
 
-pub struct OpenCodeProtocol;
+impl ToolCall {
+    /// Tool call carrying its raw argument string
+    pub fn raw(tool_name: impl Into<String>, raw_args: &str, command: &str) -> Self {
+        let mut arguments = ToolArguments::new();
+        arguments.insert(
+            "raw_args".to_string(),
+            Value::String(raw_args.trim().to_string()),
+        );
+        arguments.insert(
+            "command".to_string(),
+            Value::String(command.trim().to_string()),
+        );
+        ToolCall {
+            tool_name: tool_name.into(),
+            arguments,
+        }
+    }
 
-impl Protocol for OpenCodeProtocol {
-    fn format_command(&self, command: &Command) -> String {
-        match &command.target {
-            CommandTarget::Server => format!("{} {}", command.command, command.args.join(" ")),
-            CommandTarget::Session(session_id) => {
-                format!(
-                    "{} -t {} {}",
-                    command.command,
-                    session_id.0,
-                    command.args.join(" ")
-                )
-            }
-            CommandTarget::Window(window_id) => {
-                format!(
-                    "{} -t {} {}",
-                    command.command,
-                    window_id.0,
-                    command.args.join(" ")
-                )
-            }
-            CommandTarget::Pane(pane_id) => {
-                format!(
-                    "{} -t {} {}",
-                    command.command,
-                    pane_id.0,
-                    command.args.join(" ")
-                )
-            }
+    /// Tool call from a command line such as `read filePath=/a.rs`. The
+    /// arguments are parsed with `parse_arguments`; `raw_args` and
+    /// `command` keep the original text.
+    pub fn from_command_line(tool_name: impl Into<String>, raw_args: &str, command: &str) -> Self {
+        let mut call = Self::raw(tool_name, raw_args, command);
+        for (key, value) in parse_arguments(raw_args) {
+            call.arguments.entry(key).or_insert(value);
         }
+        call
     }
 
-    fn parse_response(&self, output: &str) -> Result<AgentResponse> {
-        if output.contains("<error>") {
-            return Ok(AgentResponse {
+    /// String argument
+    pub fn argument_str(&self, key: &str) -> Option<&str> {
+        self.arguments.get(key).and_then(Value::as_str)
+    }
+
+    /// Argument deserialized into `T`
+    pub fn argument<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        self.arguments
+            .get(key)
+            .and_then(|value| T::deserialize(value).ok())
+    }
+
+    /// Argument rendered as text: strings as-is, other values as JSON
+    pub fn argument_text(&self, key: &str) -> Option<String> {
+        self.arguments.get(key).map(value_text)
+    }
+}
+
+/// Text of an argument value: strings as-is, other values as JSON
+pub fn value_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// How a dialect reads one line of output
+#[derive(Debug, Clone, PartialEq)]
+pub enum LineKind {
+    /// Ordinary text, kept in the response content
+    Text,
+    /// Tool invocation; the line is dropped from the content
+    ToolCall(ToolCall),
+    /// Error report with its message
+    Error(String),
+    /// Completion marker; the line is dropped from the content
+    Completion,
+}
+
+/// Everything the pipeline found in a piece of output
+#[derive(Debug, Clone, Default)]
+pub struct ScannedOutput {
+    /// Lines that are neither markers nor markup
+    pub text: Vec<String>,
+    pub tool_calls: Vec<ToolCall>,
+    pub errors: Vec<ClassifiedError>,
+    pub completed: bool,
+}
+
+impl ScannedOutput {
+    /// Classify the scan. Errors win over tool calls, tool calls over
+    /// completion markers; a failed response keeps the tool calls whose
+    /// output reported the errors. `received_at` is left at 0: the scan
+    /// does not know when its output was captured.
+    pub fn into_response(self) -> AgentResponse {
+        let content = self.text.join("\n").trim().to_string();
+
+        if !self.errors.is_empty() {
+            let error = self
+                .errors
+                .iter()
+                .map(|e| e.message.as_str())
+                .collect::<Vec<_>>()
+                .join("\n");
+            return AgentResponse {
                 success: false,
                 message_type: MessageType::Error,
-                content: extract_error_content(output),
-                tool_calls: Vec::new(),
-                error: Some(output.to_string()),
-            });
-        }
-
-        let tool_calls = extract_tool_calls(output);
-
-        if !tool_calls.is_empty() {
-            Ok(AgentResponse {
-                success: true,
-                message_type: MessageType::ToolCall,
-                content: extract_content(output),
-                tool_calls,
-                error: None,
-            })
-        } else if output.contains("<complete>") || output.contains("I'll complete this") {
-            Ok(AgentResponse {
-                success: true,
-                message_type: MessageType::Completion,
-                content: extract_content(output),
-                tool_calls,
-                error: None,
-            })
+                content: if content.is_empty() {
+                    error.clone()
+                } else {
+                    content
+                },
+                tool_calls: self.tool_calls,
+                error: Some(error),
+                errors: self.errors,
+                received_at: 0,
+            };
+        }
+
+        let message_type = if !self.tool_calls.is_empty() {
+            MessageType::ToolCall
+        } else if self.completed {
+            MessageType::Completion
         } else {
-            Ok(AgentResponse {
-                success: true,
-                message_type: MessageType::Message,
-                content: output.to_string(),
-                tool_calls,
-                error: None,
-            })
+            MessageType::Message
+        };
+
+        AgentResponse {
+            success: true,
+            message_type,
+            content,
+            tool_calls: self.tool_calls,
+            error: None,
+            errors: Vec::new(),
+            received_at: 0,
         }
     }
 }
 
-fn extract_tool_calls(output: &str) -> Vec<ToolCall> {
-    let mut calls = Vec::new();
+/// Markup blocks understood by every dialect
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Block {
+    Error,
+    ToolCall,
+    Complete,
+}
+
+impl Block {
+    const ALL: [Block; 3] = [Block::Error, Block::ToolCall, Block::Complete];
+
+    fn open_tag(self) -> &'static str {
+        match self {
+            Block::Error => "<error>",
+            Block::ToolCall => "<tool_call>",
+            Block::Complete => "<complete>",
+        }
+    }
+
+    fn close_tag(self) -> &'static str {
+        match self {
+            Block::Error => "</error>",
+            Block::ToolCall => "</tool_call>",
+            Block::Complete => "</complete>",
+        }
+    }
+}
+
+/// Run `output` through the shared pipeline using `protocol` for the lines
+/// outside of markup blocks. Blocks may span several lines; an unclosed
+/// block runs to the end of the output. Lines inside ``` fences are
+/// literal text. Text lines right after an error line may give its
+/// location, as rustc's ` --> file:line:col` does.
+pub fn scan_output<P: Protocol + ?Sized>(protocol: &P, output: &str) -> ScannedOutput {
+    let mut scanned = ScannedOutput::default();
+    let mut open: Option<(Block, String)> = None;
+    let mut in_fence = false;
+    let mut after_error = false;
 
     for line in output.lines() {
-        if line.starts_with("Running: ") {
-            if let Some(rest) = line.strip_prefix("Running: ") {
-                if let Some(tool_name) = rest.split_whitespace().next() {
-                    let args_str = rest.trim_start_matches(tool_name).trim();
-                    let mut args = HashMap::new();
-                    args.insert("raw_args".to_string(), args_str.to_string());
-                    calls.push(ToolCall {
-                        tool_name: tool_name.to_string(),
-                        arguments: args,
-                    });
+        if open.is_none() && (in_fence || is_fence(line)) {
+            in_fence ^= is_fence(line);
+            scanned.text.push(line.to_string());
+            continue;
+        }
+
+        if open.is_none() && find_open_tag(line).is_none() {
+            match protocol.classify_line(line) {
+                LineKind::Text => {
+                    after_error &= !line.trim().is_empty();
+                    if after_error && let Some(error) = scanned.errors.last_mut() {
+                        attach_context(error, line);
+                    }
+                    scanned.text.push(line.to_string());
+                }
+                LineKind::ToolCall(call) => {
+                    after_error = false;
+                    scanned.tool_calls.push(call);
+                }
+                LineKind::Error(message) => {
+                    after_error = true;
+                    scanned.errors.push(classify_error(&message));
+                }
+                LineKind::Completion => {
+                    after_error = false;
+                    scanned.completed = true;
                 }
             }
+            continue;
+        }
+
+        let mut rest = line;
+        let mut text = String::new();
+        loop {
+            if let Some((block, body)) = open.as_mut() {
+                match rest.find(block.close_tag()) {
+                    Some(end) => {
+                        body.push_str(&rest[..end]);
+                        rest = &rest[end + block.close_tag().len()..];
+                        let (block, body) = open.take().unwrap();
+                        close_block(&mut scanned, block, &body);
+                    }
+                    None => {
+                        body.push_str(rest);
+                        body.push('\n');
+                        break;
+                    }
+                }
+            } else {
+                match find_open_tag(rest) {
+                    Some((start, block)) => {
+                        text.push_str(&rest[..start]);
+                        rest = &rest[start + block.open_tag().len()..];
+                        open = Some((block, String::new()));
+                    }
+                    None => {
+                        text.push_str(rest);
+                        break;
+                    }
+                }
+            }
+        }
+
+        if !text.trim().is_empty() {
+            scanned.text.push(text.trim_end().to_string());
         }
     }
 
-    calls
+    if let Some((block, body)) = open {
+        close_block(&mut scanned, block, &body);
+    }
+
+    scanned
+}
+
+/// Whether `line` opens or closes a ``` code fence
+pub(crate) fn is_fence(line: &str) -> bool {
+    line.trim_start().starts_with("```")
 }
 
-fn extract_content(output: &str) -> String {
-    output
-        .lines()
-        .filter(|line| {
-            !line.starts_with("Running: ") && !line.starts_with("<") && !line.starts_with("</")
-        })
-        .collect::<Vec<_>>()
-        .join("\n")
-        .trim()
-        .to_string()
+/// Markup block still open at the end of `line`, given the block open at
+/// its start
+pub(crate) fn block_after(line: &str, mut open: Option<Block>) -> Option<Block> {
+    let mut rest = line;
+    loop {
+        match open {
+            Some(block) => match rest.find(block.close_tag()) {
+                Some(end) => {
+                    rest = &rest[end + block.close_tag().len()..];
+                    open = None;
+                }
+                None => return open,
+            },
+            None => match find_open_tag(rest) {
+                Some((start, block)) => {
+                    rest = &rest[start + block.open_tag().len()..];
+                    open = Some(block);
+                }
+                None => return None,
+            },
+        }
+    }
 }
 
-fn extract_error_content(output: &str) -> String {
-    output
-        .replace("<error>", "")
-        .replace("</error>", "")
-        .trim()
-        .to_string()
+pub(crate) fn find_open_tag(line: &str) -> Option<(usize, Block)> {
+    Block::ALL
+        .iter()
+        .filter_map(|block| line.find(block.open_tag()).map(|i| (i, *block)))
+        .min_by_key(|(i, _)| *i)
+}
+
+fn close_block(scanned: &mut ScannedOutput, block: Block, body: &str) {
+    let body = body.trim();
+    match block {
+        Block::Error => scanned.errors.push(classify_error(body)),
+        Block::Complete => {
+            scanned.completed = true;
+            if !body.is_empty() {
+                scanned.text.push(body.to_string());
+            }
+        }
+        Block::ToolCall => match parse_tool_call(body) {
+            Some(call) => scanned.tool_calls.push(call),
+            None => scanned.text.push(body.to_string()),
+        },
+    }
+}
+
+/// Error line shared by all dialects: `Error:` or `error:` at the start of
+/// the line, or a compiler diagnostic, test failure, crash, API error,
+/// permission denial or refusal that `error_category` recognises. The same
+/// words later in a line are prose, and warnings are never errors.
+pub fn is_error_line(line: &str) -> bool {
+    error_category(line).is_some()
+}
+
+/// tmux command line for `command`
+pub fn format_tmux_command(command: &Command) -> String {
+    match &command.target {
+        CommandTarget::Server => format!("{} {}", command.command, command.args.join(" ")),
+        CommandTarget::Session(session_id) => {
+            format!(
+                "{} -t {} {}",
+                command.command,
+                session_id.0,
+                command.args.join(" ")
+            )
+        }
+        CommandTarget::Window(window_id) => {
+            format!(
+                "{} -t {} {}",
+                command.command,
+                window_id.0,
+                command.args.join(" ")
+            )
+        }
+        CommandTarget::Pane(pane_id) => {
+            format!(
+                "{} -t {} {}",
+                command.command,
+                pane_id.0,
+                command.args.join(" ")
+            )
+        }
+    }
+}
+
+/// OpenCode TUI dialect: `Running: <tool> <args>` tool lines, and a bare
+/// `Done.` line or `I'll complete ...` as completion markers
+pub struct OpenCodeProtocol;
+
+impl Protocol for OpenCodeProtocol {
+    fn name(&self) -> &str {
+        "opencode"
+    }
+
+    fn classify_line(&self, line: &str) -> LineKind {
+        let trimmed = line.trim();
+
+        if let Some(rest) = trimmed.strip_prefix("Running: ")
+            && let Some(tool_name) = rest.split_whitespace().next()
+        {
+            let args = rest[tool_name.len()..].trim();
+            return LineKind::ToolCall(ToolCall::from_command_line(tool_name, args, rest));
+        }
+
+        if is_error_line(trimmed) {
+            return LineKind::Error(trimmed.to_string());
+        }
+
+        if trimmed == "Done." || trimmed.starts_with("I'll complete") {
+            return LineKind::Completion;
+        }
+
+        LineKind::Text
+    }
 }
 
 impl OpenCodeProtocol {
@@ -183,3 +525,115 @@ impl Default for OpenCodeProtocol {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classify(line: &str) -> LineKind {
+        OpenCodeProtocol.classify_line(line)
+    }
+
+    #[test]
+    fn test_classify_line() {
+        match classify("  Running: bash cargo test --lib") {
+            LineKind::ToolCall(call) => {
+                assert_eq!(call.tool_name, "bash");
+                assert_eq!(call.argument_str("raw_args"), Some("cargo test --lib"));
+            }
+            other => panic!("expected a tool call, got {:?}", other),
+        }
+        assert_eq!(
+            classify("Error: connection refused"),
+            LineKind::Error("Error: connection refused".to_string())
+        );
+        assert_eq!(classify("Done."), LineKind::Completion);
+        assert_eq!(classify("I'll complete the task now"), LineKind::Completion);
+
+        // Markers later in the line are prose
+        assert_eq!(classify("The Error: prefix is gone"), LineKind::Text);
+        assert_eq!(classify("Done. Next I'll refactor"), LineKind::Text);
+        assert_eq!(classify("Running:"), LineKind::Text);
+        assert_eq!(classify("warning: unused variable"), LineKind::Text);
+    }
+
+    #[test]
+    fn test_scan_output_lines() {
+        let scanned = scan_output(
+            &OpenCodeProtocol,
+            "Looking at it\nRunning: read src/lib.rs\nerror: boom\nDone.",
+        );
+        assert_eq!(scanned.text, vec!["Looking at it"]);
+        assert_eq!(scanned.tool_calls.len(), 1);
+        assert_eq!(scanned.tool_calls[0].tool_name, "read");
+        assert_eq!(scanned.errors.len(), 1);
+        assert_eq!(scanned.errors[0].message, "error: boom");
+        assert!(scanned.completed);
+    }
+
+    #[test]
+    fn test_scan_output_blocks() {
+        let output = "before <tool_call>read path=src/lib.rs</tool_call> after\n\
+                      <error>first line\nsecond line</error>\n\
+                      <complete>all done</complete>";
+        let scanned = scan_output(&OpenCodeProtocol, output);
+
+        assert_eq!(scanned.tool_calls[0].tool_name, "read");
+        assert_eq!(
+            scanned.tool_calls[0].argument_str("path"),
+            Some("src/lib.rs")
+        );
+        assert_eq!(scanned.errors[0].message, "first line\nsecond line");
+        assert!(scanned.completed);
+        assert_eq!(scanned.text, vec!["before  after", "all done"]);
+    }
+
+    #[test]
+    fn test_scan_output_unclosed_block_runs_to_end() {
+        let scanned = scan_output(&OpenCodeProtocol, "<error>out of memory\nDone.");
+        assert_eq!(scanned.errors[0].message, "out of memory\nDone.");
+        assert!(!scanned.completed);
+        assert!(scanned.text.is_empty());
+    }
+
+    #[test]
+    fn test_scan_output_fences_are_text() {
+        let output = "```\nError: example\nRunning: bash ls\n```\nDone.";
+        let scanned = scan_output(&OpenCodeProtocol, output);
+        assert!(scanned.errors.is_empty());
+        assert!(scanned.tool_calls.is_empty());
+        assert_eq!(scanned.text.len(), 4);
+        assert!(scanned.completed);
+    }
+
+    #[test]
+    fn test_scan_output_error_location() {
+        let output =
+            "error[E0425]: cannot find value `x`\n --> src/main.rs:4:13\n\nsrc/other.rs:1:1";
+        let scanned = scan_output(&OpenCodeProtocol, output);
+
+        let error = &scanned.errors[0];
+        assert_eq!(error.category, ErrorCategory::Compiler);
+        assert_eq!(error.code.as_deref(), Some("E0425"));
+        let location = error.location.as_ref().unwrap();
+        assert_eq!(location.file, "src/main.rs");
+        assert_eq!(location.line, Some(4));
+        assert_eq!(location.column, Some(13));
+    }
+
+    #[test]
+    fn test_into_response_prefers_errors() {
+        let mut scanned = scan_output(&OpenCodeProtocol, "Running: bash ls\nDone.");
+        assert_eq!(
+            scanned.clone().into_response().message_type,
+            MessageType::ToolCall
+        );
+
+        scanned.errors.push(classify_error("Error: disk full"));
+        let response = scanned.into_response();
+        assert!(!response.success);
+        assert_eq!(response.message_type, MessageType::Error);
+        assert_eq!(response.error.as_deref(), Some("Error: disk full"));
+        assert_eq!(response.tool_calls[0].tool_name, "bash");
+    }
+}
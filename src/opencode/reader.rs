@@ -4,6 +4,7 @@
 //! Provides methods for retrieving session data in various formats.
 
 use crate::Result;
+use crate::opencode::client::OpenCodeClient;
 use serde::{Deserialize, Serialize};
 
 /// Message from OpenCode session
@@ -14,12 +15,12 @@ pub struct OpenCodeMessage {
     pub role: String,
     pub content: String,
     pub timestamp: String,
-    pub tool_calls: Vec<ToolCall>,
+    pub tool_calls: Vec<OpenCodeToolCall>,
 }
 
 /// Tool call within a message
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ToolCall {
+pub struct OpenCodeToolCall {
     pub tool: String,
     pub input: serde_json::Value,
 }
@@ -34,47 +35,34 @@ pub struct SessionOutput {
 
 /// OpenCode output reader
 pub struct OpenCodeReader {
-    server_url: String,
+    client: OpenCodeClient,
 }
 
 impl OpenCodeReader {
-    /// Create new output reader
+    /// Create new output reader with default timeouts, retries and
+    /// credentials from the environment
     pub fn new(server_url: String) -> Self {
-        Self {
-            server_url,
-        }
+        Self::with_client(OpenCodeClient::new(&server_url))
     }
 
-    /// Get all messages from a session
-    pub async fn get_messages(&self, session_id: &str, limit: Option<usize>) -> Result<Vec<OpenCodeMessage>> {
-        use reqwest::Client;
-        
-        let url = format!("{}/session/{}/messages", self.server_url.trim_end_matches('/'), session_id);
-        let mut client = Client::new();
-
-        let response = client
-            .get(&url)
-            .send()
-            .await
-            .map_err(|e| crate::error::TmuxError::Command(format!("HTTP request failed: {}", e)))?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(crate::error::TmuxError::Command(format!(
-                "OpenCode API error: {} - {}",
-                status,
-                error_text
-            )));
-        }
+    /// Create output reader over a configured client, e.g. one with auth
+    pub fn with_client(client: OpenCodeClient) -> Self {
+        Self { client }
+    }
 
-        let session_output: SessionOutput = response
-            .json()
-            .await
-            .map_err(|e| crate::error::TmuxError::Command(format!("Failed to parse JSON: {}", e)))?;
+    /// Get all messages from a session
+    pub async fn get_messages(
+        &self,
+        session_id: &str,
+        limit: Option<usize>,
+    ) -> Result<Vec<OpenCodeMessage>> {
+        let session_output: SessionOutput = self
+            .client
+            .get_json(&format!("session/{}/messages", session_id))
+            .await?;
 
         let mut messages = session_output.messages;
-        
+
         // Apply limit if specified
         if let Some(n) = limit {
             messages.truncate(n);
@@ -93,15 +81,13 @@ impl OpenCodeReader {
     where
         F: FnMut(&OpenCodeMessage) + Send,
     {
-        use reqwest::Client;
         use std::time::Duration;
-        
+
         let mut last_message_id = String::new();
-        let mut client = Client::new();
 
         loop {
             let messages = self.get_messages(session_id, None).await?;
-            
+
             for msg in &messages {
                 // Only process new messages
                 if msg.id != last_message_id {
@@ -117,33 +103,9 @@ impl OpenCodeReader {
 
     /// Get session output summary
     pub async fn get_session_summary(&self, session_id: &str) -> Result<SessionOutput> {
-        use reqwest::Client;
-        
-        let url = format!("{}/session/{}", self.server_url.trim_end_matches('/'), session_id);
-        let mut client = Client::new();
-
-        let response = client
-            .get(&url)
-            .send()
+        self.client
+            .get_json(&format!("session/{}", session_id))
             .await
-            .map_err(|e| crate::error::TmuxError::Command(format!("HTTP request failed: {}", e)))?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(crate::error::TmuxError::Command(format!(
-                "OpenCode API error: {} - {}",
-                status,
-                error_text
-            )));
-        }
-
-        let session_output: SessionOutput = response
-            .json()
-            .await
-            .map_err(|e| crate::error::TmuxError::Command(format!("Failed to parse JSON: {}", e)))?;
-
-        Ok(session_output)
     }
 }
 
@@ -154,7 +116,7 @@ mod tests {
     #[test]
     fn test_reader_creation() {
         let reader = OpenCodeReader::new("http://localhost:4096".to_string());
-        assert_eq!(reader.server_url, "http://localhost:4096");
+        assert_eq!(reader.client.server_url(), "http://localhost:4096");
     }
 
     #[test]
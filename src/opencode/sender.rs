@@ -1,115 +1,209 @@
 //! OpenCode Message Sender Module
 //!
-//! Sends messages via OpenCode protocol when available,
-//! with fallback to direct tmux send-keys.
+//! Sends messages via OpenCode protocol when available, falling back to
+//! direct tmux send-keys and finally to the file-based prompt queue.
 
-use crate::config::Config;
-use crate::error::TmuxError;
 use crate::Result;
+use crate::config::Config;
 use crate::config::MessageMode;
-use crate::opencode::client::OpenCodeClient;
-use crate::session_mapping::SessionMappingStore;
+use crate::messaging::queue::MessageQueue;
 use crate::messaging::send::MessageSender as TmuxMessageSender;
+use crate::opencode::client::{Auth, OpenCodeClient};
+use crate::session_mapping::SessionMappingStore;
+use crate::tmux::pane;
+use crate::types::{Pane, PaneId, SessionId};
+use std::fmt;
+use std::path::PathBuf;
+
+/// Transport a message can be delivered through, in fallback order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    /// OpenCode HTTP Session API
+    OpenCode,
+    /// Direct tmux send-keys into the agent pane
+    Tmux,
+    /// File-based prompt (`MessageSender`) or queue (`MessageQueue`)
+    File,
+}
+
+impl fmt::Display for Transport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Transport::OpenCode => write!(f, "opencode"),
+            Transport::Tmux => write!(f, "tmux"),
+            Transport::File => write!(f, "file"),
+        }
+    }
+}
+
+/// Outcome of a single transport attempt
+#[derive(Debug, Clone)]
+pub struct DeliveryAttempt {
+    pub transport: Transport,
+    /// Why the transport failed, `None` if it delivered the message
+    pub error: Option<String>,
+}
+
+/// Result of walking the fallback chain for one message
+#[derive(Debug, Clone, Default)]
+pub struct DeliveryReport {
+    /// Transport that delivered the message, if any did
+    pub delivered_via: Option<Transport>,
+    /// Every transport tried, in order
+    pub attempts: Vec<DeliveryAttempt>,
+}
+
+impl DeliveryReport {
+    /// Whether any transport delivered the message
+    pub fn delivered(&self) -> bool {
+        self.delivered_via.is_some()
+    }
+
+    /// Transports that failed, with the reason for each
+    pub fn failures(&self) -> Vec<(Transport, &str)> {
+        self.attempts
+            .iter()
+            .filter_map(|a| a.error.as_deref().map(|e| (a.transport, e)))
+            .collect()
+    }
+
+    fn succeeded(&mut self, transport: Transport) {
+        self.attempts.push(DeliveryAttempt {
+            transport,
+            error: None,
+        });
+        self.delivered_via = Some(transport);
+    }
+
+    fn failed(&mut self, transport: Transport, error: impl Into<String>) {
+        self.attempts.push(DeliveryAttempt {
+            transport,
+            error: Some(error.into()),
+        });
+    }
+}
+
+/// OpenCode endpoint used for the HTTP leg of the chain
+#[derive(Debug, Clone, Copy)]
+enum Endpoint {
+    Prompt,
+    Message,
+}
 
 /// Unified message sender with OpenCode integration
 pub struct OpenCodeSender {
     /// OpenCode client (if available)
     opencode_client: Option<OpenCodeClient>,
-    
-    /// Tmux-based message sender (fallback)
+
+    /// Credentials for the OpenCode server, `None` to read them from the
+    /// environment
+    auth: Option<Auth>,
+
+    /// File-based message sender (last-resort fallback)
     tmux_sender: TmuxMessageSender,
-    
+
     /// Session mapping store
     session_mappings: SessionMappingStore,
-    
+
+    /// Finds the agent pane of a tmux session by name
+    pane_lookup: fn(&str) -> Result<Option<Pane>>,
+
     /// Configuration
     config: Config,
 }
 
 impl OpenCodeSender {
-    /// Create new OpenCode sender
-    pub fn new(config: Config) -> Self {
-        Self {
+    /// Create new OpenCode sender with the default session mapping store.
+    /// Fails if that store cannot be loaded, rather than writing links to
+    /// some other file.
+    pub fn new(config: Config) -> Result<Self> {
+        Self::with_store(config, SessionMappingStore::new()?)
+    }
+
+    /// Create new OpenCode sender over a specific session mapping store
+    pub fn with_store(config: Config, session_mappings: SessionMappingStore) -> Result<Self> {
+        Ok(Self {
             opencode_client: None,
-            tmux_sender: TmuxMessageSender::new(&std::env::current_dir().unwrap()),
-            session_mappings: SessionMappingStore::new().unwrap_or_else(|_| {
-                eprintln!("Warning: Failed to initialize session mapping store");
-                SessionMappingStore {
-                    mappings: std::collections::HashMap::new(),
-                    storage_path: std::path::PathBuf::from(".swarm_test/sessions.json"),
-                }
-            }),
+            auth: None,
+            tmux_sender: TmuxMessageSender::new(&std::env::current_dir()?),
+            session_mappings,
+            pane_lookup: pane::find_pane_by_session_name,
             config,
-        }
+        })
+    }
+
+    /// Use a different base path for the file-based fallback
+    pub fn with_base_path(mut self, base_path: impl Into<PathBuf>) -> Self {
+        self.tmux_sender = TmuxMessageSender::new(base_path.into());
+        self
+    }
+
+    /// Use a different lookup for the tmux leg, e.g. one that does not
+    /// ask the running tmux server
+    pub fn with_pane_lookup(mut self, lookup: fn(&str) -> Result<Option<Pane>>) -> Self {
+        self.pane_lookup = lookup;
+        self
+    }
+
+    /// Authenticate to the OpenCode server with these credentials instead
+    /// of the ones in the environment
+    pub fn with_auth(mut self, auth: Auth) -> Self {
+        self.auth = Some(auth);
+        self
     }
 
     /// Initialize OpenCode client if available
     pub async fn initialize(&mut self, server_url: Option<String>) -> Result<()> {
-        if server_url.is_none() {
-            // Try to auto-discover
-            let discovery = crate::opencode::discovery::OpenCodeDiscovery::new(
-                self.config.get_opencode_server_url()
-            );
-            
-            if self.config.auto_detect_opencode {
-                let status = discovery.discover().await;
-                if status.running {
-                    self.opencode_client = Some(OpenCodeClient::new(&status.url.unwrap()));
-                    println!("{}OpenCode server detected at: {}{}", 
-                        "\x1b[32m", 
-                        "\x1b[0m",
-                        status.url.unwrap()
-                    );
-                } else {
-                    println!("{}OpenCode server not available, using direct tmux mode{}", 
-                        "\x1b[33m", 
-                        "\x1b[0m"
-                    );
+        match server_url {
+            // Use configured URL
+            Some(url) => {
+                self.opencode_client = Some(self.client_for(&url)?);
+            }
+            None => {
+                // Try to auto-discover
+                let discovery = crate::opencode::discovery::OpenCodeDiscovery::new(
+                    self.config.get_opencode_server_url(),
+                );
+
+                if self.config.auto_detect_opencode {
+                    let status = discovery.discover().await;
+                    match status.url {
+                        Some(url) if status.running => {
+                            println!("\x1b[32mOpenCode server detected at: {}\x1b[0m", url);
+                            self.opencode_client = Some(self.client_for(&url)?);
+                        }
+                        _ => {
+                            println!(
+                                "\x1b[33mOpenCode server not available, using direct tmux mode\x1b[0m"
+                            );
+                        }
+                    }
                 }
             }
-        } else {
-            // Use configured URL
-            let url = server_url.unwrap();
-            self.opencode_client = Some(OpenCodeClient::new(&url));
         }
-        
+
         Ok(())
     }
 
+    fn client_for(&self, url: &str) -> Result<OpenCodeClient> {
+        let builder = OpenCodeClient::builder(url);
+        match &self.auth {
+            Some(auth) => builder.auth(auth.clone()),
+            None => builder.auth_from_env(),
+        }
+        .build()
+    }
+
     /// Send a prompt to session
-    /// Tries OpenCode first, falls back to direct tmux
+    /// Tries OpenCode, then tmux send-keys, then the file-based queue
     pub async fn send_prompt(
         &mut self,
         session_id: &str,
         mode: MessageMode,
         prompt: &str,
-    ) -> Result<bool> {
-        // Try OpenCode first (if available and mode permits)
-        if mode == MessageMode::Auto || mode == MessageMode::Opencode {
-            if let Some(client) = &mut self.opencode_client {
-                if let Ok(_) = client.session_prompt(session_id, prompt).await {
-                    return Ok(true);
-                }
-            }
-        }
-        
-        // Fallback to direct tmux
-        match mode {
-            MessageMode::Auto => {
-                println!("{}OpenCode unavailable, sending via direct tmux{}", 
-                    "\x1b[33m", 
-                    "\x1b[0m"
-                );
-            }
-            _ => {}
-        }
-        
-        // Map session for future reference
-        if let Ok(pane_id) = crate::tmux::pane::find_pane_by_session_name(session_id) {
-            self.session_mappings.insert(session_id.to_string(), pane_id.to_string())?;
-        }
-        
-        Ok(false)
+    ) -> Result<DeliveryReport> {
+        self.deliver(session_id, mode, prompt, Endpoint::Prompt)
+            .await
     }
 
     /// Send a message to session
@@ -119,60 +213,203 @@ impl OpenCodeSender {
         session_id: &str,
         mode: MessageMode,
         message: &str,
-    ) -> Result<bool> {
-        // Try OpenCode first (if available and mode permits)
+    ) -> Result<DeliveryReport> {
+        self.deliver(session_id, mode, message, Endpoint::Message)
+            .await
+    }
+
+    /// Check if OpenCode integration is active
+    pub fn is_opencode_active(&self) -> bool {
+        self.opencode_client.is_some()
+    }
+
+    /// Walk the fallback chain until one transport accepts the content.
+    /// `Opencode` mode only tries HTTP; every other mode falls through to
+    /// tmux and then to files.
+    async fn deliver(
+        &mut self,
+        session_id: &str,
+        mode: MessageMode,
+        content: &str,
+        endpoint: Endpoint,
+    ) -> Result<DeliveryReport> {
+        let mut report = DeliveryReport::default();
+
         if mode == MessageMode::Auto || mode == MessageMode::Opencode {
-            if let Some(client) = &mut self.opencode_client {
-                if let Ok(_) = client.session_message(session_id, message).await {
-                    return Ok(true);
+            match &self.opencode_client {
+                Some(client) => {
+                    let result = match endpoint {
+                        Endpoint::Prompt => client.session_prompt(session_id, content).await,
+                        Endpoint::Message => client.session_message(session_id, content).await,
+                    };
+                    match result {
+                        Ok(()) => {
+                            report.succeeded(Transport::OpenCode);
+                            return Ok(report);
+                        }
+                        Err(e) => report.failed(Transport::OpenCode, e.to_string()),
+                    }
                 }
+                None => report.failed(Transport::OpenCode, "OpenCode client not initialized"),
+            }
+
+            if mode == MessageMode::Opencode {
+                return Ok(report);
             }
         }
-        
-        // Fallback to direct tmux
-        match mode {
-            MessageMode::Auto => {
-                println!("{}OpenCode unavailable, sending via direct tmux{}", 
-                    "\x1b[33m", 
-                    "\x1b[0m"
+
+        let agent_pane = match self.resolve_agent_pane(session_id) {
+            Ok(Some(pane_id)) => match pane::send_literal_enter(&pane_id, content) {
+                Ok(()) => {
+                    report.succeeded(Transport::Tmux);
+                    return Ok(report);
+                }
+                Err(e) => {
+                    report.failed(Transport::Tmux, e.to_string());
+                    Some(pane_id)
+                }
+            },
+            Ok(None) => {
+                report.failed(
+                    Transport::Tmux,
+                    format!("No tmux pane mapped to session '{}'", session_id),
                 );
+                None
             }
-            _ => {}
-        }
-        
-        // Map session for future reference
-        if let Ok(pane_id) = crate::tmux::pane::find_pane_by_session_name(session_id) {
-            self.session_mappings.insert(session_id.to_string(), pane_id.to_string())?;
+            Err(e) => {
+                report.failed(Transport::Tmux, e.to_string());
+                None
+            }
+        };
+
+        // With a known pane the prompt file is picked up by that agent;
+        // otherwise queue it for whoever serves the session
+        let file_result = match agent_pane {
+            Some(pane_id) => self.tmux_sender.send_prompt(&pane_id, content),
+            None => MessageQueue::new(self.tmux_sender.base_path())
+                .and_then(|queue| queue.send_message(&SessionId(session_id.to_string()), content))
+                .map(|_| ()),
+        };
+
+        match file_result {
+            Ok(()) => report.succeeded(Transport::File),
+            Err(e) => report.failed(Transport::File, e.to_string()),
         }
-        
-        Ok(false)
+
+        Ok(report)
     }
 
-    /// Check if OpenCode integration is active
-    pub fn is_opencode_active(&self) -> bool {
-        self.opencode_client.is_some()
+    /// Find the agent pane for a session, recording the tmux session name
+    /// in the mapping store the first time it is resolved
+    fn resolve_agent_pane(&mut self, session_id: &str) -> Result<Option<PaneId>> {
+        let mapped = self.session_mappings.lookup_tmux(session_id);
+        let tmux_name = mapped.clone().unwrap_or_else(|| session_id.to_string());
+
+        let pane = match (self.pane_lookup)(&tmux_name)? {
+            Some(pane) => pane,
+            None => return Ok(None),
+        };
+
+        if mapped.is_none() {
+            self.session_mappings
+                .insert(session_id.to_string(), tmux_name)?;
+        }
+
+        Ok(Some(pane.id))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tempfile::TempDir;
+
+    /// Store that is never saved, so tests leave `$HOME` alone
+    fn test_store() -> SessionMappingStore {
+        SessionMappingStore::empty(std::env::temp_dir().join("swarm_test_unused_sessions.json"))
+    }
 
     #[test]
     fn test_sender_creation() {
         let config = crate::config::Config::default();
-        let sender = OpenCodeSender::new(config);
+        let sender = OpenCodeSender::with_store(config, test_store()).unwrap();
         assert!(!sender.is_opencode_active());
     }
 
     #[test]
     fn test_initialize_without_auto_detect() {
-        let mut config = crate::config::Config::default();
-        config.auto_detect_opencode = false;
-        let mut sender = OpenCodeSender::new(config);
-        
+        let config = crate::config::Config {
+            auto_detect_opencode: false,
+            ..Default::default()
+        };
+        let sender = OpenCodeSender::with_store(config, test_store()).unwrap();
+
         // In test environment, just check no crash
         // In real usage, would need async runtime
         assert!(!sender.is_opencode_active());
     }
+
+    #[test]
+    fn test_delivery_report_failures() {
+        let mut report = DeliveryReport::default();
+        report.failed(Transport::OpenCode, "connection refused");
+        report.failed(Transport::Tmux, "no pane");
+        report.succeeded(Transport::File);
+
+        assert!(report.delivered());
+        assert_eq!(report.delivered_via, Some(Transport::File));
+        assert_eq!(
+            report.failures(),
+            vec![
+                (Transport::OpenCode, "connection refused"),
+                (Transport::Tmux, "no pane")
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_to_file_queue() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = crate::config::Config::default();
+        let store = SessionMappingStore::empty(temp_dir.path().join("sessions.json"));
+        let mut sender = OpenCodeSender::with_store(config, store)
+            .unwrap()
+            .with_base_path(temp_dir.path())
+            .with_pane_lookup(|_| Ok(None));
+
+        let report = sender
+            .send_prompt("no_such_session_026", MessageMode::Auto, "hello")
+            .await
+            .unwrap();
+
+        assert_eq!(report.delivered_via, Some(Transport::File));
+        assert_eq!(report.attempts.len(), 3);
+        assert!(
+            report
+                .failures()
+                .iter()
+                .any(|(t, _)| *t == Transport::OpenCode)
+        );
+        assert!(report.failures().iter().any(|(t, _)| *t == Transport::Tmux));
+        assert!(temp_dir.path().join(".opencode/queue").exists());
+    }
+
+    #[tokio::test]
+    async fn test_opencode_mode_does_not_fall_back() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = crate::config::Config::default();
+        let store = SessionMappingStore::empty(temp_dir.path().join("sessions.json"));
+        let mut sender = OpenCodeSender::with_store(config, store)
+            .unwrap()
+            .with_base_path(temp_dir.path());
+
+        let report = sender
+            .send_message("no_such_session_026", MessageMode::Opencode, "hello")
+            .await
+            .unwrap();
+
+        assert!(!report.delivered());
+        assert_eq!(report.attempts.len(), 1);
+        assert_eq!(report.attempts[0].transport, Transport::OpenCode);
+    }
 }
@@ -86,6 +86,10 @@ mod tests {
         assert_eq!(response.message_type, MessageType::ToolCall);
         assert_eq!(response.tool_calls.len(), 1);
         assert_eq!(response.tool_calls[0].tool_name, "read");
+        assert_eq!(
+            response.tool_calls[0].argument_str("filePath"),
+            Some("/path/to/file.txt")
+        );
     }
 
     #[test]
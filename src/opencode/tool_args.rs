@@ -0,0 +1,519 @@
+//! Tool Call Argument Parsing Module
+//!
+//! Parses the tool-call syntaxes agents print: `key=value` lists with
+//! quoted strings, JSON argument bodies, and XML-ish parameter tags such as
+//! `<parameter name="command">cargo test</parameter>`.
+
+use crate::opencode::protocol::ToolCall;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Tool call arguments by name
+pub type ToolArguments = HashMap<String, Value>;
+
+/// Key under which positional arguments are collected
+pub const POSITIONAL_KEY: &str = "args";
+
+/// Parse the body of a `<tool_call>` block. Accepted forms:
+///
+/// - `name key="value" flag=true n=3 positional`
+/// - `name {"key": "value"}`
+/// - `{"name": "bash", "arguments": {...}}` (also `tool`/`input`)
+/// - `name` or `<name>bash</name>` or `<invoke name="bash">` followed by
+///   `<parameter name="key">value</parameter>` or `<key>value</key>` tags
+///
+/// Bodies may span several lines.
+pub fn parse_tool_call(body: &str) -> Option<ToolCall> {
+    let body = body.trim();
+    if body.is_empty() {
+        return None;
+    }
+
+    if body.starts_with('{') {
+        return parse_json_tool_call(body);
+    }
+
+    if find_element(body).is_some() {
+        return parse_tagged_tool_call(body);
+    }
+
+    let (name, rest) = split_name(body)?;
+    Some(ToolCall {
+        tool_name: name.to_string(),
+        arguments: parse_arguments(rest),
+    })
+}
+
+/// Parse an argument list: `key=value` pairs, positional words and JSON
+/// objects, in any order. Quoted values are always strings; bare values
+/// become booleans, null or numbers when they look like one. Positional
+/// words are collected under `args`.
+pub fn parse_arguments(text: &str) -> ToolArguments {
+    let mut arguments = ToolArguments::new();
+    let mut positional = Vec::new();
+    let mut lexer = Lexer::new(text);
+
+    while let Some(token) = lexer.next_token() {
+        match token {
+            Token::Pair(key, value) => {
+                arguments.insert(key, value);
+            }
+            Token::Json(Value::Object(map)) => arguments.extend(map),
+            Token::Json(value) => positional.push(value),
+            Token::Word(word) => positional.push(Value::String(word)),
+        }
+    }
+
+    if !positional.is_empty() {
+        arguments.insert(POSITIONAL_KEY.to_string(), Value::Array(positional));
+    }
+
+    arguments
+}
+
+/// Typed value for bare text: JSON literals, numbers, arrays and objects
+/// keep their type, anything else is a string
+pub fn typed_value(text: &str) -> Value {
+    let trimmed = text.trim();
+    match serde_json::from_str::<Value>(trimmed) {
+        Ok(value) if !value.is_string() => value,
+        _ => Value::String(trimmed.to_string()),
+    }
+}
+
+fn split_name(body: &str) -> Option<(&str, &str)> {
+    let end = body
+        .find(|c: char| c.is_whitespace() || c == '{' || c == '(')
+        .unwrap_or(body.len());
+    let name = &body[..end];
+    if name.is_empty() {
+        None
+    } else {
+        Some((name, &body[end..]))
+    }
+}
+
+fn parse_json_tool_call(body: &str) -> Option<ToolCall> {
+    let Value::Object(mut object) = serde_json::from_str::<Value>(body).ok()? else {
+        return None;
+    };
+
+    let name = ["name", "tool", "tool_name"]
+        .iter()
+        .find_map(|key| object.get(*key).and_then(Value::as_str).map(str::to_string))?;
+
+    let arguments = ["arguments", "input", "parameters", "args"]
+        .iter()
+        .find_map(|key| object.remove(*key))
+        .map(|value| match value {
+            // Some agents double-encode the arguments
+            Value::String(text) => serde_json::from_str(&text).unwrap_or(Value::String(text)),
+            value => value,
+        });
+
+    let arguments = match arguments {
+        Some(Value::Object(map)) => map.into_iter().collect(),
+        Some(Value::Null) | None => ToolArguments::new(),
+        Some(other) => ToolArguments::from([(POSITIONAL_KEY.to_string(), other)]),
+    };
+
+    Some(ToolCall {
+        tool_name: name,
+        arguments,
+    })
+}
+
+fn parse_tagged_tool_call(body: &str) -> Option<ToolCall> {
+    let mut name = None;
+    let mut arguments = ToolArguments::new();
+    let mut loose_text = String::new();
+
+    collect_elements(body, &mut name, &mut arguments, &mut loose_text);
+
+    // Text outside of tags: the tool name followed by `key=value` pairs
+    let loose = loose_text.trim();
+    let rest = match name {
+        Some(_) => loose,
+        None => {
+            let (first, rest) = split_name(loose)?;
+            name = Some(first.to_string());
+            rest
+        }
+    };
+    for (key, value) in parse_arguments(rest) {
+        arguments.entry(key).or_insert(value);
+    }
+
+    Some(ToolCall {
+        tool_name: name?,
+        arguments,
+    })
+}
+
+fn collect_elements(
+    text: &str,
+    name: &mut Option<String>,
+    arguments: &mut ToolArguments,
+    loose_text: &mut String,
+) {
+    let mut rest = text;
+
+    while let Some((start, element, end)) = find_element(rest) {
+        loose_text.push_str(&rest[..start]);
+        loose_text.push(' ');
+
+        match element.tag {
+            "invoke" | "tool_use" | "function_call" => {
+                if let Some(tool) = element.attr("name") {
+                    *name = Some(tool.to_string());
+                }
+                collect_elements(element.body, name, arguments, loose_text);
+            }
+            "name" | "tool" | "tool_name" => *name = Some(element.body.trim().to_string()),
+            "parameter" | "param" | "arg" | "argument" => {
+                if let Some(key) = element.attr("name") {
+                    arguments.insert(key.to_string(), tag_value(element.body));
+                }
+            }
+            "arguments" | "parameters" | "input" | "args" => match typed_value(element.body) {
+                Value::Object(map) => arguments.extend(map),
+                _ => collect_elements(element.body, name, arguments, loose_text),
+            },
+            tag => {
+                arguments.insert(tag.to_string(), tag_value(element.body));
+            }
+        }
+
+        rest = &rest[end..];
+    }
+
+    loose_text.push_str(rest);
+}
+
+/// Tag bodies keep inner newlines; only the line breaks that wrap the
+/// value are dropped
+fn tag_value(body: &str) -> Value {
+    let trimmed = body.trim_matches(|c| c == '\n' || c == '\r');
+    match typed_value(trimmed) {
+        Value::String(_) => Value::String(trimmed.to_string()),
+        value => value,
+    }
+}
+
+struct Element<'a> {
+    tag: &'a str,
+    attrs: Vec<(String, String)>,
+    body: &'a str,
+}
+
+impl Element<'_> {
+    fn attr(&self, key: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// First element in `text`: start offset, element, end offset
+fn find_element(text: &str) -> Option<(usize, Element<'_>, usize)> {
+    let mut search = 0;
+
+    while let Some(found) = text[search..].find('<') {
+        let start = search + found;
+        search = start + 1;
+
+        let after = &text[start + 1..];
+        let tag_len = after
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+            .unwrap_or(after.len());
+        if tag_len == 0 || !after.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            continue;
+        }
+        let tag = &after[..tag_len];
+
+        let Some(open_end) = after.find('>') else {
+            continue;
+        };
+        let attr_text = &after[tag_len..open_end];
+        let attrs = parse_attributes(attr_text.trim_end_matches('/'));
+        let body_start = start + 1 + open_end + 1;
+
+        if attr_text.trim_end().ends_with('/') {
+            let element = Element {
+                tag,
+                attrs,
+                body: "",
+            };
+            return Some((start, element, body_start));
+        }
+
+        if let Some((body_end, end)) = find_close(text, body_start, tag) {
+            let element = Element {
+                tag,
+                attrs,
+                body: &text[body_start..body_end],
+            };
+            return Some((start, element, end));
+        }
+    }
+
+    None
+}
+
+/// Matching `</tag>` for an element whose body starts at `from`, allowing
+/// nested elements with the same tag
+fn find_close(text: &str, from: usize, tag: &str) -> Option<(usize, usize)> {
+    let open = format!("<{}", tag);
+    let close = format!("</{}>", tag);
+    let mut depth = 0;
+    let mut pos = from;
+
+    loop {
+        let next_close = pos + text[pos..].find(&close)?;
+        let next_open = text[pos..next_close]
+            .match_indices(&open)
+            .map(|(i, _)| pos + i)
+            .find(|&i| {
+                text[i + open.len()..]
+                    .starts_with(|c: char| c == '>' || c == '/' || c.is_whitespace())
+            });
+
+        match next_open {
+            Some(i) => {
+                depth += 1;
+                pos = i + open.len();
+            }
+            None if depth == 0 => return Some((next_close, next_close + close.len())),
+            None => {
+                depth -= 1;
+                pos = next_close + close.len();
+            }
+        }
+    }
+}
+
+fn parse_attributes(text: &str) -> Vec<(String, String)> {
+    let mut attrs = Vec::new();
+    let mut lexer = Lexer::new(text);
+
+    while let Some(token) = lexer.next_token() {
+        if let Token::Pair(key, value) = token {
+            let value = match value {
+                Value::String(s) => s,
+                other => other.to_string(),
+            };
+            attrs.push((key, value));
+        }
+    }
+
+    attrs
+}
+
+enum Token {
+    Pair(String, Value),
+    Json(Value),
+    Word(String),
+}
+
+/// Shell-like tokenizer for argument lists
+struct Lexer<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(text: &'a str) -> Self {
+        Self { text, pos: 0 }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn next_token(&mut self) -> Option<Token> {
+        let rest = self.rest();
+        let skipped = rest.len() - rest.trim_start().len();
+        self.pos += skipped;
+        let rest = self.rest();
+        if rest.is_empty() {
+            return None;
+        }
+
+        if let Some(key_len) = key_length(rest) {
+            let key = rest[..key_len].to_string();
+            self.pos += key_len + 1;
+            let value = self.value();
+            return Some(Token::Pair(key, value));
+        }
+
+        Some(match self.value() {
+            Value::String(word) => Token::Word(word),
+            value => Token::Json(value),
+        })
+    }
+
+    /// Quoted string, JSON value or bare word
+    fn value(&mut self) -> Value {
+        let rest = self.rest();
+
+        if let Some(quote) = rest.chars().next().filter(|c| *c == '"' || *c == '\'') {
+            let (value, len) = unquote(rest, quote);
+            self.pos += len;
+            return Value::String(value);
+        }
+
+        if rest.starts_with('{') || rest.starts_with('[') {
+            let mut stream = serde_json::Deserializer::from_str(rest).into_iter::<Value>();
+            if let Some(Ok(value)) = stream.next() {
+                self.pos += stream.byte_offset();
+                return value;
+            }
+        }
+
+        let len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        self.pos += len;
+        let word = &rest[..len];
+        match word {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            "null" => Value::Null,
+            _ => match serde_json::from_str::<serde_json::Number>(word) {
+                Ok(number) => Value::Number(number),
+                Err(_) => Value::String(word.to_string()),
+            },
+        }
+    }
+}
+
+/// Length of `key` if `text` starts with `key=`
+fn key_length(text: &str) -> Option<usize> {
+    let len = text
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.'))
+        .unwrap_or(text.len());
+    let starts_ok = text.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_');
+    (len > 0 && starts_ok && text[len..].starts_with('=')).then_some(len)
+}
+
+/// Decode a quoted string starting at `text[0]`. Returns the value and the
+/// number of bytes consumed; an unterminated string runs to the end.
+fn unquote(text: &str, quote: char) -> (String, usize) {
+    let mut value = String::new();
+    let mut chars = text.char_indices().skip(1);
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            c if c == quote => return (value, i + c.len_utf8()),
+            '\\' => match chars.next() {
+                Some((_, 'n')) => value.push('\n'),
+                Some((_, 't')) => value.push('\t'),
+                Some((_, 'r')) => value.push('\r'),
+                Some((_, c @ ('\\' | '"' | '\''))) => value.push(c),
+                Some((_, c)) => {
+                    value.push('\\');
+                    value.push(c);
+                }
+                None => value.push('\\'),
+            },
+            c => value.push(c),
+        }
+    }
+
+    (value, text.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_quoted_values_with_spaces_and_escapes() {
+        let call = parse_tool_call(
+            r#"bash command="cargo test -- --nocapture" note='it\'s "fine"' path=src/lib.rs"#,
+        )
+        .unwrap();
+
+        assert_eq!(call.tool_name, "bash");
+        assert_eq!(call.arguments["command"], "cargo test -- --nocapture");
+        assert_eq!(call.arguments["note"], r#"it's "fine""#);
+        assert_eq!(call.arguments["path"], "src/lib.rs");
+    }
+
+    #[test]
+    fn test_bare_values_are_typed() {
+        let args = parse_arguments(r#"timeout=30 ratio=0.5 force=true stdin=null label="30""#);
+
+        assert_eq!(args["timeout"], json!(30));
+        assert_eq!(args["ratio"], json!(0.5));
+        assert_eq!(args["force"], json!(true));
+        assert_eq!(args["stdin"], Value::Null);
+        assert_eq!(args["label"], json!("30"));
+    }
+
+    #[test]
+    fn test_positional_and_json_arguments() {
+        let args = parse_arguments(r#"cargo "build --release" {"env": {"RUST_LOG": "debug"}}"#);
+
+        assert_eq!(args[POSITIONAL_KEY], json!(["cargo", "build --release"]));
+        assert_eq!(args["env"]["RUST_LOG"], "debug");
+    }
+
+    #[test]
+    fn test_json_body() {
+        let call = parse_tool_call(r#"{"name": "edit", "arguments": {"path": "a.rs", "line": 3}}"#)
+            .unwrap();
+        assert_eq!(call.tool_name, "edit");
+        assert_eq!(call.arguments["line"], json!(3));
+
+        let call =
+            parse_tool_call(r#"{"tool": "bash", "input": "{\"command\": \"ls\"}"}"#).unwrap();
+        assert_eq!(call.arguments["command"], "ls");
+
+        let call = parse_tool_call(r#"write {"path": "x.txt", "content": "a b"}"#).unwrap();
+        assert_eq!(call.arguments["content"], "a b");
+    }
+
+    #[test]
+    fn test_parameter_tags() {
+        let body = r#"
+<invoke name="write">
+<parameter name="path">notes.md</parameter>
+<parameter name="content">line one
+line two</parameter>
+<parameter name="append">true</parameter>
+</invoke>
+"#;
+        let call = parse_tool_call(body).unwrap();
+
+        assert_eq!(call.tool_name, "write");
+        assert_eq!(call.arguments["content"], "line one\nline two");
+        assert_eq!(call.arguments["append"], json!(true));
+    }
+
+    #[test]
+    fn test_child_tags_and_leading_name() {
+        let call = parse_tool_call("grep\n<pattern>fn main</pattern>\n<path>src</path>").unwrap();
+        assert_eq!(call.tool_name, "grep");
+        assert_eq!(call.arguments["pattern"], "fn main");
+
+        let call = parse_tool_call("<name>read</name><arguments>{\"path\": \"a.rs\"}</arguments>")
+            .unwrap();
+        assert_eq!(call.tool_name, "read");
+        assert_eq!(call.arguments["path"], "a.rs");
+    }
+
+    #[test]
+    fn test_nested_same_tag() {
+        let body = r#"<invoke name="outer"><arg name="x"><arg>inner</arg></arg></invoke>"#;
+        let call = parse_tool_call(body).unwrap();
+        assert_eq!(call.arguments["x"], "<arg>inner</arg>");
+    }
+
+    #[test]
+    fn test_multiline_key_values() {
+        let call = parse_tool_call("bash\n  command=\"echo one\necho two\"\n  timeout=5").unwrap();
+        assert_eq!(call.arguments["command"], "echo one\necho two");
+        assert_eq!(call.arguments["timeout"], json!(5));
+    }
+}
@@ -1,159 +1,597 @@
 //! Session ID Mapping Module
 //!
-//! Manages mappings between OpenCode session IDs and tmux session names.
-//! Enables bridging between OpenCode protocol and tmux process control.
+//! Manages mappings between OpenCode session IDs and tmux sessions, windows
+//! and panes. Enables bridging between OpenCode protocol and tmux process
+//! control.
+//!
+//! The store is a versioned JSON document. Older layouts are migrated on
+//! load, and every write happens under a `FileLock`: the current file is
+//! re-read, the change applied and the result atomically renamed into place.
 
+use crate::Result;
+use crate::error::TmuxError;
+use crate::messaging::send::FileLock;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
-use std::time::SystemTime;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Current on-disk schema version
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Attempts to take the store lock before giving up. With the retry delay
+/// this waits about five seconds: every write holds the lock across an
+/// fsync, and a queue of those on a busy disk easily outlasts one second,
+/// while a CLI command that hangs much longer looks stuck.
+const LOCK_ATTEMPTS: u32 = 250;
+
+/// Pause between lock attempts
+const LOCK_RETRY_DELAY: Duration = Duration::from_millis(20);
 
-/// Mapping entry linking OpenCode session to tmux session
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Link between an OpenCode session and a tmux session, optionally narrowed
+/// to a window or pane. An OpenCode session may have several links and a
+/// tmux session may be linked to several OpenCode sessions.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SessionMapping {
     /// OpenCode session ID
     pub opencode_session_id: String,
-    
+
     /// Corresponding tmux session name
     pub tmux_session_name: String,
-    
-    /// Timestamp when mapping was created
+
+    /// tmux window ID (e.g. `@3`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tmux_window_id: Option<String>,
+
+    /// tmux pane ID (e.g. `%7`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tmux_pane_id: Option<String>,
+
+    /// Name of the agent running in the pane
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent_name: Option<String>,
+
+    /// Project directory the agent works in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project_dir: Option<PathBuf>,
+
+    /// RFC3339 timestamp when mapping was created
     pub created_at: String,
+
+    /// RFC3339 timestamp when mapping was last changed
+    pub updated_at: String,
+}
+
+impl SessionMapping {
+    /// Session-level link stamped with the current time
+    pub fn new(
+        opencode_session_id: impl Into<String>,
+        tmux_session_name: impl Into<String>,
+    ) -> Self {
+        let now = now_rfc3339();
+        Self {
+            opencode_session_id: opencode_session_id.into(),
+            tmux_session_name: tmux_session_name.into(),
+            tmux_window_id: None,
+            tmux_pane_id: None,
+            agent_name: None,
+            project_dir: None,
+            created_at: now.clone(),
+            updated_at: now,
+        }
+    }
+
+    /// Narrow the link to a window
+    pub fn with_window(mut self, window_id: impl Into<String>) -> Self {
+        self.tmux_window_id = Some(window_id.into());
+        self
+    }
+
+    /// Narrow the link to a pane
+    pub fn with_pane(mut self, pane_id: impl Into<String>) -> Self {
+        self.tmux_pane_id = Some(pane_id.into());
+        self
+    }
+
+    /// Record the agent name
+    pub fn with_agent(mut self, agent_name: impl Into<String>) -> Self {
+        self.agent_name = Some(agent_name.into());
+        self
+    }
+
+    /// Record the project directory
+    pub fn with_project_dir(mut self, project_dir: impl Into<PathBuf>) -> Self {
+        self.project_dir = Some(project_dir.into());
+        self
+    }
+
+    /// Whether two links point at the same OpenCode session and tmux target
+    fn same_link(&self, other: &SessionMapping) -> bool {
+        self.opencode_session_id == other.opencode_session_id
+            && self.tmux_session_name == other.tmux_session_name
+            && self.tmux_window_id == other.tmux_window_id
+            && self.tmux_pane_id == other.tmux_pane_id
+    }
+}
+
+/// On-disk document
+#[derive(Debug, Serialize, Deserialize)]
+struct StoreFile {
+    version: u32,
+    mappings: Vec<SessionMapping>,
+}
+
+/// Version 0 entry: a map keyed by OpenCode ID with a `Debug`-printed
+/// `SystemTime` as `created_at`
+#[derive(Debug, Deserialize)]
+struct LegacyMapping {
+    opencode_session_id: String,
+    tmux_session_name: String,
+    created_at: String,
 }
 
 /// Session mapping store
 pub struct SessionMappingStore {
-    mappings: HashMap<String, SessionMapping>,
+    mappings: Vec<SessionMapping>,
     storage_path: PathBuf,
 }
 
 impl SessionMappingStore {
-    /// Create or load session mapping store
+    /// Create or load the store at `~/.swarm_test/sessions.json`
     pub fn new() -> Result<Self> {
-        let storage_dir = dirs::home_dir()
-            .ok_or_else(|| "Cannot find home directory".to_string())?
-            .join(".swarm_test");
-        
-        fs::create_dir_all(&storage_dir)?;
-        
-        let storage_path = storage_dir.join("sessions.json");
-        let mappings = if storage_path.exists() {
-            let content = fs::read_to_string(&storage_path)?;
-            serde_json::from_str(&content).unwrap_or_else(|_| HashMap::new())
-        } else {
-            HashMap::new()
-        };
-        
+        Self::with_path(Self::default_path()?)
+    }
+
+    /// Create or load the store at a specific path
+    pub fn with_path(storage_path: impl Into<PathBuf>) -> Result<Self> {
+        let storage_path = storage_path.into();
+        let mappings = load(&storage_path)?;
+
         Ok(Self {
             mappings,
             storage_path,
         })
     }
 
-    /// Insert a new session mapping
+    /// Store at `storage_path` that starts empty without reading the file.
+    /// Writes still merge with whatever is on disk.
+    pub fn empty(storage_path: impl Into<PathBuf>) -> Self {
+        Self {
+            mappings: Vec::new(),
+            storage_path: storage_path.into(),
+        }
+    }
+
+    /// Default storage location
+    pub fn default_path() -> Result<PathBuf> {
+        dirs::home_dir()
+            .map(|home| home.join(".swarm_test").join("sessions.json"))
+            .ok_or_else(|| TmuxError::NotFound("Cannot find home directory".to_string()))
+    }
+
+    /// Path of the backing file
+    pub fn storage_path(&self) -> &Path {
+        &self.storage_path
+    }
+
+    /// Insert a session-level mapping, replacing any existing session-level
+    /// link for the same pair
     pub fn insert(&mut self, opencode_id: String, tmux_name: String) -> Result<()> {
-        let mapping = SessionMapping {
-            opencode_session_id: opencode_id.clone(),
-            tmux_session_name: tmux_name,
-            created_at: format!("{:?}", SystemTime::now()),
-        };
-        
-        self.mappings.insert(opencode_id, mapping);
-        self.save()?;
-        Ok(())
+        self.add_link(SessionMapping::new(opencode_id, tmux_name))
+    }
+
+    /// Add or update a link. A link with the same OpenCode session and tmux
+    /// target keeps its `created_at`.
+    pub fn add_link(&mut self, mapping: SessionMapping) -> Result<()> {
+        self.update(
+            |mappings| match mappings.iter_mut().find(|m| m.same_link(&mapping)) {
+                Some(existing) => {
+                    let created_at = existing.created_at.clone();
+                    *existing = SessionMapping {
+                        created_at,
+                        updated_at: now_rfc3339(),
+                        ..mapping
+                    };
+                }
+                None => mappings.push(mapping),
+            },
+        )
     }
 
-    /// Lookup tmux session by OpenCode session ID
+    /// Lookup tmux session by OpenCode session ID (first link)
     pub fn lookup_tmux(&self, opencode_id: &str) -> Option<String> {
-        self.mappings.get(opencode_id)
+        self.links_for_opencode(opencode_id)
+            .first()
             .map(|m| m.tmux_session_name.clone())
     }
 
-    /// Lookup OpenCode session ID by tmux session name
+    /// Lookup OpenCode session ID by tmux session name (first link)
     pub fn lookup_opencode(&self, tmux_name: &str) -> Option<String> {
+        self.links_for_tmux(tmux_name)
+            .first()
+            .map(|m| m.opencode_session_id.clone())
+    }
+
+    /// Every link of an OpenCode session
+    pub fn links_for_opencode(&self, opencode_id: &str) -> Vec<&SessionMapping> {
+        self.mappings
+            .iter()
+            .filter(|m| m.opencode_session_id == opencode_id)
+            .collect()
+    }
+
+    /// Every link into a tmux session
+    pub fn links_for_tmux(&self, tmux_name: &str) -> Vec<&SessionMapping> {
         self.mappings
             .iter()
-            .find(|(_, m)| m.tmux_session_name == tmux_name)
-            .map(|(k, _)| k.clone())
+            .filter(|m| m.tmux_session_name == tmux_name)
+            .collect()
     }
 
-    /// Remove a mapping
+    /// Every link into a tmux pane
+    pub fn links_for_pane(&self, pane_id: &str) -> Vec<&SessionMapping> {
+        self.mappings
+            .iter()
+            .filter(|m| m.tmux_pane_id.as_deref() == Some(pane_id))
+            .collect()
+    }
+
+    /// Remove every link of an OpenCode session
     pub fn remove(&mut self, opencode_id: &str) -> Result<()> {
-        self.mappings.remove(opencode_id);
-        self.save()?;
-        Ok(())
+        self.update(|mappings| mappings.retain(|m| m.opencode_session_id != opencode_id))
+    }
+
+    /// Remove every link between an OpenCode session and a tmux session
+    pub fn remove_link(&mut self, opencode_id: &str, tmux_name: &str) -> Result<()> {
+        self.update(|mappings| {
+            mappings.retain(|m| {
+                m.opencode_session_id != opencode_id || m.tmux_session_name != tmux_name
+            })
+        })
     }
 
     /// List all mappings
     pub fn list(&self) -> Vec<SessionMapping> {
-        self.mappings.values().cloned().collect()
+        self.mappings.clone()
     }
 
-    /// Save mappings to disk
-    fn save(&self) -> Result<()> {
-        let content = serde_json::to_string_pretty(&self.mappings)
-            .map_err(|e| format!("Failed to serialize mappings: {}", e))?;
-        
-        fs::write(&self.storage_path, content)
-            .map_err(|e| format!("Failed to write mappings: {}", e))?;
-        
+    /// Re-read the backing file
+    pub fn reload(&mut self) -> Result<()> {
+        self.mappings = load(&self.storage_path)?;
         Ok(())
     }
 
     /// Clear all mappings
     pub fn clear(&mut self) -> Result<()> {
-        self.mappings.clear();
-        self.save()?;
+        self.update(|mappings| mappings.clear())
+    }
+
+    /// Apply a change under the file lock: reload, mutate, write atomically
+    fn update<F>(&mut self, change: F) -> Result<()>
+    where
+        F: FnOnce(&mut Vec<SessionMapping>),
+    {
+        if let Some(parent) = self.storage_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let _lock = acquire_lock(&lock_path(&self.storage_path))?;
+
+        let mut mappings = load(&self.storage_path)?;
+        change(&mut mappings);
+        save(&self.storage_path, &mappings)?;
+
+        self.mappings = mappings;
         Ok(())
     }
 }
 
+fn now_rfc3339() -> String {
+    chrono::Utc::now().to_rfc3339()
+}
+
+fn lock_path(storage_path: &Path) -> PathBuf {
+    let mut name = storage_path.as_os_str().to_owned();
+    name.push(".lock");
+    PathBuf::from(name)
+}
+
+/// `FileLock::acquire` does not block, so retry for a short while
+fn acquire_lock(path: &Path) -> Result<FileLock> {
+    let mut attempt = 0;
+    loop {
+        match FileLock::acquire(path) {
+            Ok(lock) => return Ok(lock),
+            Err(TmuxError::Process(std::io::ErrorKind::WouldBlock, _))
+                if attempt + 1 < LOCK_ATTEMPTS =>
+            {
+                attempt += 1;
+                std::thread::sleep(LOCK_RETRY_DELAY);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn load(storage_path: &Path) -> Result<Vec<SessionMapping>> {
+    if !storage_path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let content = fs::read_to_string(storage_path)?;
+    if content.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+
+    parse_store(&content).map_err(|e| {
+        TmuxError::Parse(format!(
+            "Corrupt session mapping file {}: {}",
+            storage_path.display(),
+            e
+        ))
+    })
+}
+
+/// Parse any known schema version, migrating to the current one
+fn parse_store(content: &str) -> Result<Vec<SessionMapping>> {
+    let value: serde_json::Value = serde_json::from_str(content)?;
+
+    let version = match value.get("version") {
+        Some(version) => version
+            .as_u64()
+            .ok_or_else(|| TmuxError::Parse("Schema version is not a number".to_string()))?
+            as u32,
+        None => 0,
+    };
+
+    match version {
+        0 => {
+            let legacy: HashMap<String, LegacyMapping> = serde_json::from_value(value)?;
+            Ok(migrate_v0(legacy))
+        }
+        SCHEMA_VERSION => {
+            let file: StoreFile = serde_json::from_value(value)?;
+            Ok(file.mappings)
+        }
+        newer => Err(TmuxError::Parse(format!(
+            "Schema version {} is newer than supported version {}",
+            newer, SCHEMA_VERSION
+        ))),
+    }
+}
+
+fn migrate_v0(legacy: HashMap<String, LegacyMapping>) -> Vec<SessionMapping> {
+    let mut mappings: Vec<SessionMapping> = legacy
+        .into_values()
+        .map(|old| {
+            let created_at = parse_debug_system_time(&old.created_at).unwrap_or_else(now_rfc3339);
+            SessionMapping {
+                created_at: created_at.clone(),
+                updated_at: created_at,
+                ..SessionMapping::new(old.opencode_session_id, old.tmux_session_name)
+            }
+        })
+        .collect();
+
+    mappings.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+    mappings
+}
+
+/// Recover the time from `SystemTime { tv_sec: 1700000000, tv_nsec: 5 }`
+fn parse_debug_system_time(debug: &str) -> Option<String> {
+    let re = regex_lite::Regex::new(r"tv_sec:\s*(\d+)").ok()?;
+    let secs: i64 = re.captures(debug)?.get(1)?.as_str().parse().ok()?;
+    chrono::DateTime::from_timestamp(secs, 0).map(|dt| dt.to_rfc3339())
+}
+
+fn save(storage_path: &Path, mappings: &[SessionMapping]) -> Result<()> {
+    let file = StoreFile {
+        version: SCHEMA_VERSION,
+        mappings: mappings.to_vec(),
+    };
+    let content = serde_json::to_string_pretty(&file)?;
+
+    let mut tmp_name = storage_path.as_os_str().to_owned();
+    tmp_name.push(format!(".{}.tmp", std::process::id()));
+    let tmp_path = PathBuf::from(tmp_name);
+
+    {
+        let mut tmp = fs::File::create(&tmp_path)?;
+        tmp.write_all(content.as_bytes())?;
+        tmp.sync_all()?;
+    }
+
+    fs::rename(&tmp_path, storage_path).map_err(|e| {
+        let _ = fs::remove_file(&tmp_path);
+        TmuxError::from(e)
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tempfile::TempDir;
+
+    fn temp_store() -> (TempDir, SessionMappingStore) {
+        let temp_dir = TempDir::new().unwrap();
+        let store = SessionMappingStore::with_path(temp_dir.path().join("sessions.json")).unwrap();
+        (temp_dir, store)
+    }
 
     #[test]
     fn test_store_creation() {
-        let store = SessionMappingStore::new();
+        let (_dir, store) = temp_store();
         assert_eq!(store.list().len(), 0);
     }
 
     #[test]
     fn test_insert_and_lookup() {
-        let mut store = SessionMappingStore::new().unwrap();
-        store.insert("session_abc".to_string(), "tmux_session_1".to_string()).unwrap();
-        
-        assert_eq!(store.lookup_tmux("session_abc"), Some("tmux_session_1".to_string()));
-        assert_eq!(store.lookup_opencode("tmux_session_1"), Some("session_abc".to_string()));
+        let (_dir, mut store) = temp_store();
+        store
+            .insert("session_abc".to_string(), "tmux_session_1".to_string())
+            .unwrap();
+
+        assert_eq!(
+            store.lookup_tmux("session_abc"),
+            Some("tmux_session_1".to_string())
+        );
+        assert_eq!(
+            store.lookup_opencode("tmux_session_1"),
+            Some("session_abc".to_string())
+        );
     }
 
     #[test]
     fn test_remove() {
-        let mut store = SessionMappingStore::new().unwrap();
-        store.insert("session_xyz".to_string(), "tmux_session_2".to_string()).unwrap();
+        let (_dir, mut store) = temp_store();
+        store
+            .insert("session_xyz".to_string(), "tmux_session_2".to_string())
+            .unwrap();
         store.remove("session_xyz").unwrap();
-        
+
         assert_eq!(store.lookup_tmux("session_xyz"), None);
     }
 
     #[test]
     fn test_list() {
-        let mut store = SessionMappingStore::new().unwrap();
-        store.insert("session_1".to_string(), "tmux_1".to_string()).unwrap();
-        store.insert("session_2".to_string(), "tmux_2".to_string()).unwrap();
-        
+        let (_dir, mut store) = temp_store();
+        store
+            .insert("session_1".to_string(), "tmux_1".to_string())
+            .unwrap();
+        store
+            .insert("session_2".to_string(), "tmux_2".to_string())
+            .unwrap();
+
         let mappings = store.list();
         assert_eq!(mappings.len(), 2);
-        assert!(mappings.iter().any(|m| m.opencode_session_id == "session_1"));
+        assert!(
+            mappings
+                .iter()
+                .any(|m| m.opencode_session_id == "session_1")
+        );
     }
 
     #[test]
     fn test_clear() {
-        let mut store = SessionMappingStore::new().unwrap();
-        store.insert("session_test".to_string(), "tmux_test".to_string()).unwrap();
+        let (_dir, mut store) = temp_store();
+        store
+            .insert("session_test".to_string(), "tmux_test".to_string())
+            .unwrap();
         store.clear().unwrap();
-        
+
         assert_eq!(store.list().len(), 0);
     }
+
+    #[test]
+    fn test_many_to_many_links() {
+        let (_dir, mut store) = temp_store();
+        store
+            .add_link(
+                SessionMapping::new("ses_1", "swarm")
+                    .with_pane("%1")
+                    .with_agent("coder"),
+            )
+            .unwrap();
+        store
+            .add_link(SessionMapping::new("ses_1", "swarm").with_pane("%2"))
+            .unwrap();
+        store
+            .add_link(SessionMapping::new("ses_2", "swarm").with_pane("%3"))
+            .unwrap();
+
+        assert_eq!(store.links_for_opencode("ses_1").len(), 2);
+        assert_eq!(store.links_for_tmux("swarm").len(), 3);
+        assert_eq!(
+            store.links_for_pane("%1")[0].agent_name.as_deref(),
+            Some("coder")
+        );
+
+        store.remove_link("ses_1", "swarm").unwrap();
+        assert_eq!(store.links_for_tmux("swarm").len(), 1);
+    }
+
+    #[test]
+    fn test_persists_rfc3339_and_version() {
+        let (dir, mut store) = temp_store();
+        store
+            .insert("ses_1".to_string(), "tmux_1".to_string())
+            .unwrap();
+
+        let content = fs::read_to_string(dir.path().join("sessions.json")).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&content).unwrap();
+        assert_eq!(value["version"], SCHEMA_VERSION);
+
+        let created_at = value["mappings"][0]["created_at"].as_str().unwrap();
+        assert!(chrono::DateTime::parse_from_rfc3339(created_at).is_ok());
+
+        let reloaded = SessionMappingStore::with_path(dir.path().join("sessions.json")).unwrap();
+        assert_eq!(reloaded.list(), store.list());
+    }
+
+    #[test]
+    fn test_migrates_legacy_file() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("sessions.json");
+        fs::write(
+            &path,
+            r#"{"ses_old": {"opencode_session_id": "ses_old", "tmux_session_name": "tmux_old",
+                "created_at": "SystemTime { tv_sec: 1700000000, tv_nsec: 42 }"}}"#,
+        )
+        .unwrap();
+
+        let store = SessionMappingStore::with_path(&path).unwrap();
+        let mappings = store.list();
+        assert_eq!(mappings.len(), 1);
+        assert_eq!(mappings[0].tmux_session_name, "tmux_old");
+        assert!(mappings[0].created_at.starts_with("2023-11-14T22:13:20"));
+    }
+
+    #[test]
+    fn test_corrupt_file_is_an_error() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("sessions.json");
+        fs::write(&path, "{ not json").unwrap();
+
+        assert!(matches!(
+            SessionMappingStore::with_path(&path),
+            Err(TmuxError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn test_newer_version_is_rejected() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("sessions.json");
+        fs::write(&path, r#"{"version": 99, "mappings": []}"#).unwrap();
+
+        assert!(SessionMappingStore::with_path(&path).is_err());
+    }
+
+    #[test]
+    fn test_concurrent_writers_keep_each_others_links() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("sessions.json");
+
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    let mut store = SessionMappingStore::empty(path);
+                    for j in 0..5 {
+                        store
+                            .insert(format!("ses_{}_{}", i, j), format!("tmux_{}", i))
+                            .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let store = SessionMappingStore::with_path(&path).unwrap();
+        assert_eq!(store.list().len(), 20);
+    }
 }
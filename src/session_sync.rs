@@ -0,0 +1,472 @@
+//! Session Sync Module
+//!
+//! Reconciles OpenCode sessions with tmux sessions through the
+//! `SessionMappingStore`. Creates tmux sessions for new OpenCode sessions,
+//! prunes mappings whose counterpart is gone and reports orphans.
+
+use crate::Result;
+use crate::error::TmuxError;
+use crate::messaging::read::LogReader;
+use crate::opencode::{OpenCodeClient, SessionInfo};
+use crate::session_mapping::{SessionMapping, SessionMappingStore};
+use crate::tmux::session as tmux_session;
+use crate::types::Session;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// Prefix for tmux sessions created by the reconciler
+const DEFAULT_TMUX_PREFIX: &str = "oc-";
+
+/// Characters of the session id appended to tell same-named sessions apart
+const ID_SUFFIX_LEN: usize = 6;
+
+/// Shortest pause between reconcile passes
+pub const MIN_SYNC_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Reconciler behaviour
+#[derive(Debug, Clone)]
+pub struct SyncOptions {
+    /// Create tmux sessions for OpenCode sessions that have none
+    pub create_missing: bool,
+    /// Remove mappings whose OpenCode or tmux session is gone
+    pub prune_stale: bool,
+    /// Prefix for created tmux session names. Unmapped tmux sessions are
+    /// only reported as orphans when they carry this prefix.
+    pub tmux_prefix: String,
+    /// Compute the report without touching tmux or the store
+    pub dry_run: bool,
+    /// Directory created sessions log their output to; the default log
+    /// directory if `None`
+    pub log_dir: Option<PathBuf>,
+}
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        Self {
+            create_missing: true,
+            prune_stale: true,
+            tmux_prefix: DEFAULT_TMUX_PREFIX.to_string(),
+            dry_run: false,
+            log_dir: None,
+        }
+    }
+}
+
+/// Link between an OpenCode session and a tmux session
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncLink {
+    pub opencode_session_id: String,
+    pub tmux_session_name: String,
+}
+
+/// What a reconcile pass did (or would do, in dry-run mode)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncReport {
+    /// tmux sessions created for new OpenCode sessions
+    pub created: Vec<SyncLink>,
+    /// Existing tmux sessions with the expected name that got mapped
+    pub adopted: Vec<SyncLink>,
+    /// Mappings removed because a side disappeared
+    pub pruned: Vec<SyncLink>,
+    /// OpenCode sessions left without a tmux session
+    pub orphaned_opencode: Vec<String>,
+    /// Prefixed tmux sessions with no OpenCode session
+    pub orphaned_tmux: Vec<String>,
+    /// Actions that failed while applying the plan
+    pub errors: Vec<String>,
+}
+
+impl SyncReport {
+    /// Whether the pass changed anything
+    pub fn has_changes(&self) -> bool {
+        !self.created.is_empty() || !self.adopted.is_empty() || !self.pruned.is_empty()
+    }
+}
+
+/// Compute a reconcile plan from the current state of both sides
+pub fn plan_sync(
+    opencode: &[SessionInfo],
+    tmux: &[Session],
+    mappings: &[SessionMapping],
+    options: &SyncOptions,
+) -> SyncReport {
+    let opencode_ids: HashSet<&str> = opencode.iter().map(|s| s.id.as_str()).collect();
+    let tmux_names: HashSet<&str> = tmux.iter().map(|s| s.name.as_str()).collect();
+    let mut report = SyncReport::default();
+
+    // Mappings that survive pruning
+    let mut live: Vec<&SessionMapping> = Vec::new();
+    for mapping in mappings {
+        let alive = opencode_ids.contains(mapping.opencode_session_id.as_str())
+            && tmux_names.contains(mapping.tmux_session_name.as_str());
+
+        if alive || !options.prune_stale {
+            live.push(mapping);
+        } else {
+            report.pruned.push(SyncLink {
+                opencode_session_id: mapping.opencode_session_id.clone(),
+                tmux_session_name: mapping.tmux_session_name.clone(),
+            });
+        }
+    }
+
+    let mapped_opencode: HashSet<&str> = live
+        .iter()
+        .map(|m| m.opencode_session_id.as_str())
+        .collect();
+    let mut claimed_tmux: HashSet<String> =
+        live.iter().map(|m| m.tmux_session_name.clone()).collect();
+
+    for session in opencode {
+        if mapped_opencode.contains(session.id.as_str()) {
+            continue;
+        }
+
+        let Some(tmux_session_name) =
+            unclaimed_name_for(session, &options.tmux_prefix, &claimed_tmux)
+        else {
+            report.orphaned_opencode.push(session.id.clone());
+            continue;
+        };
+        let link = SyncLink {
+            opencode_session_id: session.id.clone(),
+            tmux_session_name,
+        };
+
+        if tmux_names.contains(link.tmux_session_name.as_str()) {
+            claimed_tmux.insert(link.tmux_session_name.clone());
+            report.adopted.push(link);
+        } else if options.create_missing {
+            claimed_tmux.insert(link.tmux_session_name.clone());
+            report.created.push(link);
+        } else {
+            report.orphaned_opencode.push(session.id.clone());
+        }
+    }
+
+    for session in tmux {
+        if session.name.starts_with(&options.tmux_prefix) && !claimed_tmux.contains(&session.name) {
+            report.orphaned_tmux.push(session.name.clone());
+        }
+    }
+
+    report
+}
+
+/// tmux session name for an OpenCode session. tmux rejects `.` and `:`
+/// in session names, so those become `-`.
+pub fn tmux_name_for(session: &SessionInfo, prefix: &str) -> String {
+    let base = if session.name.trim().is_empty() {
+        &session.id
+    } else {
+        &session.name
+    };
+
+    format!("{}{}", prefix, sanitize(base))
+}
+
+/// `tmux_name_for`, unless another OpenCode session already holds that
+/// name. Then the end of the session id is appended, and failing that the
+/// whole id, so same-named sessions each get their own tmux session.
+fn unclaimed_name_for(
+    session: &SessionInfo,
+    prefix: &str,
+    claimed: &HashSet<String>,
+) -> Option<String> {
+    let name = tmux_name_for(session, prefix);
+    let id = sanitize(&session.id);
+    let short_id: String = id
+        .chars()
+        .skip(id.chars().count().saturating_sub(ID_SUFFIX_LEN))
+        .collect();
+
+    [
+        name.clone(),
+        format!("{}-{}", name, short_id),
+        format!("{}-{}", name, id),
+    ]
+    .into_iter()
+    .find(|candidate| !claimed.contains(candidate))
+}
+
+fn sanitize(text: &str) -> String {
+    text.trim()
+        .chars()
+        .map(|c| match c {
+            '.' | ':' => '-',
+            c if c.is_whitespace() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// Keeps OpenCode sessions and tmux sessions in sync
+pub struct SessionReconciler {
+    client: OpenCodeClient,
+    store: SessionMappingStore,
+    options: SyncOptions,
+}
+
+impl SessionReconciler {
+    /// Create reconciler
+    pub fn new(client: OpenCodeClient, store: SessionMappingStore, options: SyncOptions) -> Self {
+        Self {
+            client,
+            store,
+            options,
+        }
+    }
+
+    /// Mapping store being reconciled
+    pub fn store(&self) -> &SessionMappingStore {
+        &self.store
+    }
+
+    /// Run a single reconcile pass
+    pub async fn reconcile(&mut self) -> Result<SyncReport> {
+        let opencode = self.client.list_sessions().await?;
+        let tmux = match tmux_session::list_sessions() {
+            Ok(sessions) => sessions,
+            // No tmux server yet means no tmux sessions
+            Err(TmuxError::NotConnected) => Vec::new(),
+            Err(e) => return Err(e),
+        };
+
+        let mut report = plan_sync(&opencode, &tmux, &self.store.list(), &self.options);
+
+        if self.options.dry_run {
+            return Ok(report);
+        }
+
+        self.apply(&mut report);
+        Ok(report)
+    }
+
+    /// Reconcile every `interval`, but no more often than
+    /// `MIN_SYNC_INTERVAL`, until `stop` is set, handing each report to
+    /// `on_report`. Failed passes are reported through `on_error` and do
+    /// not end the loop.
+    pub async fn run<R, E>(
+        &mut self,
+        interval: Duration,
+        stop: Arc<AtomicBool>,
+        mut on_report: R,
+        mut on_error: E,
+    ) where
+        R: FnMut(&SyncReport),
+        E: FnMut(&TmuxError),
+    {
+        while !stop.load(Ordering::SeqCst) {
+            match self.reconcile().await {
+                Ok(report) => on_report(&report),
+                Err(e) => on_error(&e),
+            }
+            tokio::time::sleep(interval.max(MIN_SYNC_INTERVAL)).await;
+        }
+    }
+
+    fn apply(&mut self, report: &mut SyncReport) {
+        let mut applied_pruned = Vec::new();
+        for link in report.pruned.drain(..) {
+            match self
+                .store
+                .remove_link(&link.opencode_session_id, &link.tmux_session_name)
+            {
+                Ok(()) => applied_pruned.push(link),
+                Err(e) => report
+                    .errors
+                    .push(format!("prune {}: {}", link.opencode_session_id, e)),
+            }
+        }
+        report.pruned = applied_pruned;
+
+        let reader = match &self.options.log_dir {
+            Some(dir) => LogReader::with_dir(dir.clone()),
+            None => LogReader::new(),
+        };
+        let mut applied_created = Vec::new();
+        for link in report.created.drain(..) {
+            let result = tmux_session::new_session(&link.tmux_session_name).and_then(|session| {
+                tmux_session::pipe_session_log(&session, &reader.session_log_path(&session.id))?;
+                self.store.insert(
+                    link.opencode_session_id.clone(),
+                    link.tmux_session_name.clone(),
+                )
+            });
+            match result {
+                Ok(()) => applied_created.push(link),
+                Err(e) => {
+                    report
+                        .errors
+                        .push(format!("create {}: {}", link.tmux_session_name, e));
+                    report.orphaned_opencode.push(link.opencode_session_id);
+                }
+            }
+        }
+        report.created = applied_created;
+
+        let mut applied_adopted = Vec::new();
+        for link in report.adopted.drain(..) {
+            match self.store.insert(
+                link.opencode_session_id.clone(),
+                link.tmux_session_name.clone(),
+            ) {
+                Ok(()) => applied_adopted.push(link),
+                Err(e) => {
+                    report
+                        .errors
+                        .push(format!("adopt {}: {}", link.tmux_session_name, e));
+                    report.orphaned_opencode.push(link.opencode_session_id);
+                }
+            }
+        }
+        report.adopted = applied_adopted;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::SessionId;
+
+    fn opencode(id: &str, name: &str) -> SessionInfo {
+        SessionInfo {
+            id: id.to_string(),
+            name: name.to_string(),
+            created_at: String::new(),
+            updated_at: String::new(),
+        }
+    }
+
+    fn tmux(name: &str) -> Session {
+        Session {
+            id: SessionId(format!("${}", name.len())),
+            name: name.to_string(),
+            windows: Vec::new(),
+            attached: false,
+        }
+    }
+
+    fn mapping(opencode_id: &str, tmux_name: &str) -> SessionMapping {
+        SessionMapping::new(opencode_id, tmux_name)
+    }
+
+    #[test]
+    fn test_creates_missing_tmux_sessions() {
+        let report = plan_sync(
+            &[opencode("ses_1", "fix build")],
+            &[],
+            &[],
+            &SyncOptions::default(),
+        );
+
+        assert_eq!(report.created.len(), 1);
+        assert_eq!(report.created[0].tmux_session_name, "oc-fix_build");
+        assert!(report.orphaned_opencode.is_empty());
+    }
+
+    #[test]
+    fn test_adopts_existing_tmux_session() {
+        let report = plan_sync(
+            &[opencode("ses_1", "agent")],
+            &[tmux("oc-agent")],
+            &[],
+            &SyncOptions::default(),
+        );
+
+        assert_eq!(report.adopted.len(), 1);
+        assert!(report.created.is_empty());
+        assert!(report.orphaned_tmux.is_empty());
+    }
+
+    #[test]
+    fn test_prunes_stale_mappings() {
+        let report = plan_sync(
+            &[opencode("ses_1", "agent")],
+            &[tmux("oc-other")],
+            &[mapping("ses_1", "oc-gone"), mapping("ses_gone", "oc-other")],
+            &SyncOptions::default(),
+        );
+
+        assert_eq!(report.pruned.len(), 2);
+        // ses_1 lost its tmux session and gets a new one
+        assert_eq!(report.created.len(), 1);
+        // oc-other lost its OpenCode session
+        assert_eq!(report.orphaned_tmux, vec!["oc-other".to_string()]);
+    }
+
+    #[test]
+    fn test_reports_orphans_without_creating() {
+        let options = SyncOptions {
+            create_missing: false,
+            prune_stale: false,
+            ..SyncOptions::default()
+        };
+        let report = plan_sync(
+            &[opencode("ses_1", "agent")],
+            &[tmux("oc-lonely"), tmux("unrelated")],
+            &[mapping("ses_gone", "oc-gone")],
+            &options,
+        );
+
+        assert!(report.pruned.is_empty());
+        assert!(report.created.is_empty());
+        assert_eq!(report.orphaned_opencode, vec!["ses_1".to_string()]);
+        assert_eq!(report.orphaned_tmux, vec!["oc-lonely".to_string()]);
+    }
+
+    #[test]
+    fn test_same_named_sessions_get_distinct_tmux_sessions() {
+        let sessions = [
+            opencode("ses_01HAAAAAA1", "agent"),
+            opencode("ses_01HBBBBBB2", "agent"),
+            opencode("ses_01HCCCCCC3", "agent"),
+        ];
+        let report = plan_sync(&sessions, &[tmux("oc-agent")], &[], &SyncOptions::default());
+
+        assert_eq!(report.adopted.len(), 1);
+        assert_eq!(report.adopted[0].tmux_session_name, "oc-agent");
+        let created: Vec<&str> = report
+            .created
+            .iter()
+            .map(|link| link.tmux_session_name.as_str())
+            .collect();
+        assert_eq!(created, vec!["oc-agent-BBBBB2", "oc-agent-CCCCC3"]);
+        assert!(report.orphaned_opencode.is_empty());
+        assert!(report.orphaned_tmux.is_empty());
+
+        // Once mapped, the next pass leaves them alone
+        let mappings: Vec<SessionMapping> = report
+            .adopted
+            .iter()
+            .chain(&report.created)
+            .map(|link| mapping(&link.opencode_session_id, &link.tmux_session_name))
+            .collect();
+        let tmux_sessions = [
+            tmux("oc-agent"),
+            tmux("oc-agent-BBBBB2"),
+            tmux("oc-agent-CCCCC3"),
+        ];
+        let report = plan_sync(
+            &sessions,
+            &tmux_sessions,
+            &mappings,
+            &SyncOptions::default(),
+        );
+        assert!(!report.has_changes());
+        assert!(report.orphaned_opencode.is_empty());
+    }
+
+    #[test]
+    fn test_tmux_name_sanitized() {
+        let name = tmux_name_for(&opencode("ses_1", "v1.2: review"), "oc-");
+        assert_eq!(name, "oc-v1-2-_review");
+
+        let name = tmux_name_for(&opencode("ses_1", ""), "oc-");
+        assert_eq!(name, "oc-ses_1");
+    }
+}
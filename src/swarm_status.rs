@@ -0,0 +1,286 @@
+//! Swarm Status Module
+//!
+//! Snapshot of the tmux topology with what the agent in each pane is
+//! doing: whether it is idle, working, errored or dead, its last message
+//! and how many queued messages wait for it.
+
+use crate::Result;
+use crate::messaging::queue::{MessageQueue, QueueStats};
+use crate::messaging::read::LogReader;
+use crate::messaging::search::LogSource;
+use crate::messaging::stream::StreamingParser;
+use crate::opencode::{AgentResponse, DialectRegistry, MessageType};
+use crate::tmux::session as tmux_session;
+use crate::types::{Pane, Session, SessionId, WindowId};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// Log lines parsed for a pane's last message
+const TAIL_LINES: usize = 200;
+
+/// How recently a log must have grown for its agent to count as working
+const DEFAULT_ACTIVE_WINDOW: Duration = Duration::from_secs(10);
+
+/// What the agent in a pane is doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AgentState {
+    /// Waiting for input
+    Idle,
+    /// Writing output right now
+    Working,
+    /// Its last response was an error
+    Errored,
+    /// The pane's process has exited
+    Dead,
+}
+
+impl AgentState {
+    /// Infer the state from whether the pane's process is running, the last
+    /// response parsed from its log and how long ago the log last grew
+    pub fn infer(
+        alive: bool,
+        last: Option<&AgentResponse>,
+        quiet_for: Option<Duration>,
+        active_window: Duration,
+    ) -> Self {
+        if !alive {
+            AgentState::Dead
+        } else if last.is_some_and(|r| r.message_type == MessageType::Error) {
+            AgentState::Errored
+        } else if quiet_for.is_some_and(|quiet| quiet < active_window) {
+            AgentState::Working
+        } else {
+            AgentState::Idle
+        }
+    }
+}
+
+impl fmt::Display for AgentState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            AgentState::Idle => "idle",
+            AgentState::Working => "working",
+            AgentState::Errored => "errored",
+            AgentState::Dead => "dead",
+        };
+        f.write_str(name)
+    }
+}
+
+/// One pane of the topology and the state of its agent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaneStatus {
+    pub session_id: SessionId,
+    pub session_name: String,
+    pub window_id: WindowId,
+    pub window_name: String,
+    pub pane: Pane,
+    pub state: AgentState,
+    /// Last response, or last log line when no dialect is recognised
+    pub last_message: Option<String>,
+    /// When the pane's log last grew, in seconds since the Unix epoch
+    pub last_activity: Option<u64>,
+    /// Queued messages for the pane still waiting for delivery
+    pub queue_depth: usize,
+}
+
+/// Snapshot of every session, window and pane
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwarmStatus {
+    pub sessions: Vec<Session>,
+    /// Panes in session, window and pane order
+    pub panes: Vec<PaneStatus>,
+    /// Totals of the message queue, when one was given
+    pub queue: Option<QueueStats>,
+}
+
+impl SwarmStatus {
+    /// Capture the topology from tmux and each pane's state from its log:
+    /// the pane log when there is one, else the session log
+    pub fn collect(reader: &LogReader, queue: Option<&MessageQueue>) -> Result<Self> {
+        Self::collect_with(reader, queue, DEFAULT_ACTIVE_WINDOW)
+    }
+
+    /// Like [`collect`](Self::collect), counting a pane as working while its
+    /// log grew within `active_window`
+    pub fn collect_with(
+        reader: &LogReader,
+        queue: Option<&MessageQueue>,
+        active_window: Duration,
+    ) -> Result<Self> {
+        let sessions = tmux_session::list_sessions()?;
+        let depth = match queue {
+            Some(queue) => queue.depth_by_pane()?,
+            None => Default::default(),
+        };
+        let now = SystemTime::now();
+
+        let mut panes = Vec::new();
+        for session in &sessions {
+            for window in &session.windows {
+                for pane in &window.panes {
+                    let mut source = LogSource::Pane(pane.id.clone());
+                    if !reader.log_path(&source).exists() {
+                        source = LogSource::Session(session.id.clone());
+                    }
+                    let modified = modified(&reader.log_path(&source));
+                    let lines = reader.tail(&source, TAIL_LINES)?;
+                    let last = last_response(&lines);
+
+                    let state = AgentState::infer(
+                        pane.pid.is_some_and(process_alive),
+                        last.as_ref(),
+                        modified.and_then(|m| now.duration_since(m).ok()),
+                        active_window,
+                    );
+                    let last_message = last
+                        .as_ref()
+                        .and_then(summary)
+                        .or_else(|| lines.iter().rev().find(|l| !l.trim().is_empty()).cloned());
+
+                    panes.push(PaneStatus {
+                        session_id: session.id.clone(),
+                        session_name: session.name.clone(),
+                        window_id: window.id.clone(),
+                        window_name: window.name.clone(),
+                        pane: pane.clone(),
+                        state,
+                        last_message,
+                        last_activity: modified.and_then(|m| {
+                            m.duration_since(SystemTime::UNIX_EPOCH)
+                                .ok()
+                                .map(|d| d.as_secs())
+                        }),
+                        queue_depth: depth.get(&pane.id.0).copied().unwrap_or_default(),
+                    });
+                }
+            }
+        }
+
+        let queue = queue.map(MessageQueue::stats).transpose()?;
+        Ok(Self {
+            sessions,
+            panes,
+            queue,
+        })
+    }
+
+    /// Number of panes in each state, in `AgentState` order
+    pub fn counts(&self) -> [(AgentState, usize); 4] {
+        [
+            AgentState::Idle,
+            AgentState::Working,
+            AgentState::Errored,
+            AgentState::Dead,
+        ]
+        .map(|state| {
+            (
+                state,
+                self.panes.iter().filter(|p| p.state == state).count(),
+            )
+        })
+    }
+}
+
+/// Last response in the lines, when their dialect is recognised
+fn last_response(lines: &[String]) -> Option<AgentResponse> {
+    let text = lines.join("\n") + "\n";
+    let protocol = DialectRegistry::builtin().detect(&text)?;
+    let mut parser = StreamingParser::with_protocol(protocol);
+    let mut responses = parser.feed(&text);
+    responses.extend(parser.finish());
+    responses.pop()
+}
+
+/// One line describing a response
+fn summary(response: &AgentResponse) -> Option<String> {
+    if let Some(error) = &response.error {
+        return Some(error.lines().next().unwrap_or_default().to_string());
+    }
+    if let Some(line) = response
+        .content
+        .lines()
+        .rev()
+        .find(|l| !l.trim().is_empty())
+    {
+        return Some(line.trim().to_string());
+    }
+    let tools: Vec<&str> = response
+        .tool_calls
+        .iter()
+        .map(|call| call.tool_name.as_str())
+        .collect();
+    (!tools.is_empty()).then(|| tools.join(", "))
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    path.metadata().and_then(|m| m.modified()).ok()
+}
+
+/// Whether a process with the pid exists. `EPERM` means it does, owned by
+/// another user.
+fn process_alive(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // SAFETY: signal 0 only checks that the process exists
+    let found = unsafe { libc::kill(pid, 0) } == 0;
+    found || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(message_type: MessageType) -> AgentResponse {
+        AgentResponse::fixture(message_type, "Done.\n\n")
+    }
+
+    #[test]
+    fn test_infer_state() {
+        let window = DEFAULT_ACTIVE_WINDOW;
+        let recent = Some(Duration::from_secs(1));
+        let quiet = Some(Duration::from_secs(60));
+        let error = response(MessageType::Error);
+        let message = response(MessageType::Message);
+
+        assert_eq!(
+            AgentState::infer(false, None, recent, window),
+            AgentState::Dead
+        );
+        assert_eq!(
+            AgentState::infer(true, Some(&error), recent, window),
+            AgentState::Errored
+        );
+        assert_eq!(
+            AgentState::infer(true, Some(&message), recent, window),
+            AgentState::Working
+        );
+        assert_eq!(
+            AgentState::infer(true, Some(&message), quiet, window),
+            AgentState::Idle
+        );
+        assert_eq!(
+            AgentState::infer(true, None, None, window),
+            AgentState::Idle
+        );
+    }
+
+    #[test]
+    fn test_summary_and_liveness() {
+        assert_eq!(
+            summary(&response(MessageType::Message)).as_deref(),
+            Some("Done.")
+        );
+
+        let mut failed = response(MessageType::Error);
+        failed.error = Some("rate limited\nretry in 30s".to_string());
+        assert_eq!(summary(&failed).as_deref(), Some("rate limited"));
+
+        assert!(process_alive(std::process::id()));
+        assert!(!process_alive(u32::MAX));
+    }
+}
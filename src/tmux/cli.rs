@@ -1,11 +1,43 @@
 use crate::types::{Command, CommandTarget, Response, ResponseData};
 use crate::{Result, TmuxError};
+use std::path::{Path, PathBuf};
 use std::process::{Command as StdCommand, Output};
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 
+static DEFAULT_SOCKET: RwLock<Option<PathBuf>> = RwLock::new(None);
+static TRACE: AtomicBool = AtomicBool::new(false);
+
+/// Send every command that names neither a server nor a socket to the
+/// server listening on `socket`, like `tmux -S`. `None` restores tmux's
+/// own default.
+pub fn set_default_socket(socket: Option<PathBuf>) {
+    *DEFAULT_SOCKET.write().unwrap_or_else(|e| e.into_inner()) = socket;
+}
+
+pub fn default_socket() -> Option<PathBuf> {
+    DEFAULT_SOCKET
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+}
+
+/// Echo each tmux command line to stderr before it runs
+pub fn set_trace(enabled: bool) {
+    TRACE.store(enabled, Ordering::Relaxed);
+}
+
+fn trace(args: &[String]) {
+    if TRACE.load(Ordering::Relaxed) {
+        eprintln!("+ tmux {}", args.join(" "));
+    }
+}
+
 pub struct TmuxCommand {
     args: Vec<String>,
     server: Option<String>,
+    socket: Option<PathBuf>,
     session: Option<String>,
     command: Option<String>,
 }
@@ -15,6 +47,7 @@ impl TmuxCommand {
         Self {
             args: Vec::new(),
             server: None,
+            socket: None,
             session: None,
             command: None,
         }
@@ -37,6 +70,12 @@ impl TmuxCommand {
         self
     }
 
+    /// Server socket path, like `tmux -S`. Ignored when a server is named.
+    pub fn socket(mut self, socket: impl AsRef<Path>) -> Self {
+        self.socket = Some(socket.as_ref().to_path_buf());
+        self
+    }
+
     pub fn session(mut self, session: impl Into<String>) -> Self {
         self.session = Some(session.into());
         self
@@ -53,6 +92,9 @@ impl TmuxCommand {
         if let Some(ref server) = self.server {
             result.push("-L".to_string());
             result.push(server.clone());
+        } else if let Some(socket) = self.socket.clone().or_else(default_socket) {
+            result.push("-S".to_string());
+            result.push(socket.to_string_lossy().into_owned());
         }
 
         if let Some(ref command) = self.command {
@@ -70,6 +112,7 @@ impl TmuxCommand {
 
     pub fn execute(&self) -> Result<Response> {
         let args = self.build_args();
+        trace(&args);
         let output = StdCommand::new("tmux").args(&args).output().map_err(|e| {
             TmuxError::Process(e.kind(), format!("Failed to execute tmux command: {e}"))
         })?;
@@ -79,6 +122,7 @@ impl TmuxCommand {
 
     pub fn execute_with_timeout(&self, timeout: Duration) -> Result<Response> {
         let args = self.build_args();
+        trace(&args);
         let mut child = StdCommand::new("tmux").args(&args).spawn().map_err(|e| {
             TmuxError::Process(e.kind(), format!("Failed to spawn tmux command: {e}"))
         })?;
@@ -124,7 +168,9 @@ impl TmuxCommand {
 
             if error_msg.contains("not found") || error_msg.contains("no such") {
                 Err(TmuxError::NotFound(error_msg))
-            } else if error_msg.contains("not connected") || error_msg.contains("no server running")
+            } else if error_msg.contains("not connected")
+                || error_msg.contains("no server running")
+                || error_msg.contains("error connecting to")
             {
                 Err(TmuxError::NotConnected)
             } else {
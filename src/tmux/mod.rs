@@ -1,9 +1,11 @@
 mod cli;
 pub mod pane;
 pub mod session;
+pub mod target;
 pub mod window;
 
 pub use cli::*;
 pub use pane::*;
 pub use session::*;
+pub use target::*;
 pub use window::*;
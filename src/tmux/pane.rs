@@ -1,8 +1,10 @@
 use crate::tmux::cli::execute_command;
 use crate::types::{Command, CommandTarget, Pane, PaneId, ResponseData, SessionId, WindowId};
 use crate::{Result, TmuxError};
+use std::path::Path;
 
 pub fn new_pane(window_id: &WindowId) -> Result<Pane> {
+    let before = list_panes(window_id)?;
     let cmd = Command {
         command: "split-window".to_string(),
         target: CommandTarget::Window(window_id.clone()),
@@ -19,14 +21,15 @@ pub fn new_pane(window_id: &WindowId) -> Result<Pane> {
         ));
     }
 
-    let panes = list_panes(window_id)?;
-    panes
+    // Panes are listed by position, so the new one is the id not seen before
+    list_panes(window_id)?
         .into_iter()
-        .last()
+        .find(|p| before.iter().all(|b| b.id != p.id))
         .ok_or_else(|| TmuxError::NotFound("Pane not found after creation".to_string()))
 }
 
 pub fn split_pane_horizontal(window_id: &WindowId) -> Result<Pane> {
+    let before = list_panes(window_id)?;
     let cmd = Command {
         command: "split-window".to_string(),
         target: CommandTarget::Window(window_id.clone()),
@@ -41,10 +44,9 @@ pub fn split_pane_horizontal(window_id: &WindowId) -> Result<Pane> {
         })));
     }
 
-    let panes = list_panes(window_id)?;
-    panes
+    list_panes(window_id)?
         .into_iter()
-        .last()
+        .find(|p| before.iter().all(|b| b.id != p.id))
         .ok_or_else(|| TmuxError::NotFound("Pane not found after split".to_string()))
 }
 
@@ -65,28 +67,95 @@ pub fn kill_pane(pane_id: &PaneId) -> Result<()> {
         ));
     }
 
-        Ok(())
+    Ok(())
 }
 
-pub fn find_pane_by_session_name(session_name: &str) -> Result<Option<Pane>> {
-    // Get all sessions and find the matching session
-    use crate::tmux::session::list_sessions;
-    
-    let sessions = list_sessions()?;
-    let matching_session = sessions.iter().find(|s| s.name == session_name);
-    
-    match matching_session {
-        Some(session) => {
-            // Get panes from the first window
-            if let Some(first_window) = session.windows.first() {
-                let panes = list_panes(first_window)?;
-                return Ok(panes.first().cloned());
+/// Restart the pane's command, killing the running process first
+pub fn respawn_pane(pane_id: &PaneId) -> Result<()> {
+    let cmd = Command {
+        command: "respawn-pane".to_string(),
+        target: CommandTarget::Pane(pane_id.clone()),
+        args: vec!["-k".to_string()],
+    };
+
+    let response = execute_command(&cmd)?;
+
+    if !response.success {
+        return Err(TmuxError::Command(
+            response
+                .error
+                .unwrap_or_else(|| "Failed to respawn pane".to_string()),
+        ));
+    }
+
+    Ok(())
+}
+
+pub fn list_panes(window_id: &WindowId) -> Result<Vec<Pane>> {
+    let cmd = Command {
+        command: "list-panes".to_string(),
+        target: CommandTarget::Window(window_id.clone()),
+        args: vec![
+            "-F".to_string(),
+            "#{pane_id}:#{session_id}:#{pane_pid}:#{pane_active}:#{pane_current_path}".to_string(),
+        ],
+    };
+
+    let response = execute_command(&cmd)?;
+
+    if !response.success {
+        return Err(TmuxError::Command(
+            response
+                .error
+                .unwrap_or_else(|| "Failed to list panes".to_string()),
+        ));
+    }
+
+    match response.data {
+        ResponseData::Output(output) => {
+            if output.trim().is_empty() {
+                return Ok(Vec::new());
+            }
+
+            let mut panes = Vec::new();
+            for line in output.lines() {
+                // current_path goes last so colons inside it survive the split
+                let parts: Vec<&str> = line.splitn(5, ':').collect();
+                if parts.len() >= 4 {
+                    let current_path = parts
+                        .get(4)
+                        .filter(|p| !p.is_empty())
+                        .map(|p| p.to_string());
+
+                    panes.push(Pane {
+                        id: PaneId(parts[0].to_string()),
+                        window_id: window_id.clone(),
+                        session_id: SessionId(parts[1].to_string()),
+                        current_path,
+                        pid: parts[2].parse().ok(),
+                        active: parts[3] != "0",
+                    });
+                }
             }
+
+            Ok(panes)
         }
-        None => Ok(None)
+        _ => Ok(Vec::new()),
     }
 }
 
+pub fn find_pane_by_session_name(session_name: &str) -> Result<Option<Pane>> {
+    use crate::tmux::session::list_sessions;
+
+    let sessions = list_sessions()?;
+    let matching_session = sessions.into_iter().find(|s| s.name == session_name);
+
+    // The agent runs in the first pane of the session's first window
+    Ok(matching_session
+        .and_then(|session| session.windows.into_iter().next())
+        .and_then(|window| window.panes.into_iter().next()))
+}
+
 pub fn send_keys(pane_id: &PaneId, keys: impl AsRef<str>) -> Result<()> {
     let keys = keys.as_ref();
     let cmd = Command {
@@ -114,6 +183,75 @@ pub fn send_keys_enter(pane_id: &PaneId, keys: impl AsRef<str>) -> Result<()> {
     Ok(())
 }
 
+/// Type `text` into the pane as is. Unlike `send_keys`, key names such as
+/// `Enter` are not looked up and text starting with `-` is not read as a
+/// flag.
+pub fn send_literal(pane_id: &PaneId, text: impl AsRef<str>) -> Result<()> {
+    let cmd = Command {
+        command: "send-keys".to_string(),
+        target: CommandTarget::Pane(pane_id.clone()),
+        args: vec![
+            "-l".to_string(),
+            "--".to_string(),
+            text.as_ref().to_string(),
+        ],
+    };
+
+    let response = execute_command(&cmd)?;
+
+    if !response.success {
+        return Err(TmuxError::Command(
+            response
+                .error
+                .unwrap_or_else(|| "Failed to send text".to_string()),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Type `text` into the pane as is and press Enter, e.g. to submit a prompt
+pub fn send_literal_enter(pane_id: &PaneId, text: impl AsRef<str>) -> Result<()> {
+    send_literal(pane_id, text)?;
+    send_keys(pane_id, "Enter")?;
+    Ok(())
+}
+
+/// Append the pane's output to `log_path`, each line prefixed with the
+/// UTC time it was captured, `[2024-01-02 03:04:05] `. Does nothing if the
+/// pane is already piped somewhere.
+pub fn pipe_pane_log(pane_id: &PaneId, log_path: &Path) -> Result<()> {
+    let cmd = Command {
+        command: "pipe-pane".to_string(),
+        target: CommandTarget::Pane(pane_id.clone()),
+        args: vec!["-o".to_string(), timestamping_logger(log_path)],
+    };
+
+    let response = execute_command(&cmd)?;
+
+    if !response.success {
+        return Err(TmuxError::Command(
+            response
+                .error
+                .unwrap_or_else(|| "Failed to pipe pane output".to_string()),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Shell command that stamps each line read from stdin with the capture
+/// time and appends it to `log_path`
+fn timestamping_logger(log_path: &Path) -> String {
+    let path = log_path.to_string_lossy().replace('\'', "'\\''");
+    format!(
+        "while IFS= read -r line; do \
+         printf '[%s] %s\\n' \"$(date -u '+%Y-%m-%d %H:%M:%S')\" \"$line\"; \
+         done >> '{}'",
+        path
+    )
+}
+
 pub fn capture_pane_output(pane_id: &PaneId) -> Result<String> {
     let cmd = Command {
         command: "capture-pane".to_string(),
@@ -220,20 +358,44 @@ pub fn resize_pane(pane_id: &PaneId, width: Option<usize>, height: Option<usize>
     Ok(())
 }
 
-fn extract_session_id_from_window(window_id: &WindowId) -> Result<SessionId> {
-    window_id
-        .0
-        .split('@')
-        .next()
-        .map(|s| SessionId(s.to_string()))
-        .ok_or_else(|| TmuxError::Command(format!("Invalid window ID format: {}", window_id.0)))
-}
-
-fn extract_window_id(pane_id: &PaneId) -> Result<WindowId> {
-    pane_id
-        .0
-        .split('%')
-        .next()
-        .map(|s| WindowId(s.to_string()))
-        .ok_or_else(|| TmuxError::Command(format!("Invalid pane ID format: {}", pane_id.0)))
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::process::{Command as StdCommand, Stdio};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_timestamping_logger_prefixes_each_line() {
+        let dir = TempDir::new().unwrap();
+        let log_path = dir.path().join("it's here.log");
+
+        let mut child = StdCommand::new("sh")
+            .arg("-c")
+            .arg(timestamping_logger(&log_path))
+            .stdin(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(b"Running: bash cargo test\n  %s -n\n")
+            .unwrap();
+        assert!(child.wait().unwrap().success());
+
+        let log = std::fs::read_to_string(&log_path).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines.len(), 2, "{}", log);
+        for (line, text) in lines.iter().zip(["Running: bash cargo test", "  %s -n"]) {
+            let (stamp, rest) = line.split_at(22);
+            assert!(
+                crate::messaging::parse_timestamp(&stamp[1..20]).is_some(),
+                "{}",
+                line
+            );
+            assert_eq!(&stamp[20..], "] ");
+            assert_eq!(rest, text);
+        }
+    }
 }
@@ -1,6 +1,7 @@
 use crate::tmux::cli::execute_command;
 use crate::types::{Command, CommandTarget, ResponseData, Session, SessionId};
 use crate::{Result, TmuxError};
+use std::path::Path;
 
 pub fn new_session(name: impl Into<String>) -> Result<Session> {
     let name = name.into();
@@ -27,6 +28,17 @@ pub fn new_session(name: impl Into<String>) -> Result<Session> {
         .ok_or_else(|| TmuxError::NotFound(format!("Session '{name}' not found after creation")))
 }
 
+/// Log the output of the session's first pane, where the agent runs, to
+/// `log_path` with capture timestamps
+pub fn pipe_session_log(session: &Session, log_path: &Path) -> Result<()> {
+    let pane = session
+        .windows
+        .first()
+        .and_then(|window| window.panes.first())
+        .ok_or_else(|| TmuxError::NotFound(format!("Session '{}' has no pane", session.name)))?;
+    super::pane::pipe_pane_log(&pane.id, log_path)
+}
+
 pub fn kill_session(id: &SessionId) -> Result<()> {
     let cmd = Command {
         command: "kill-session".to_string(),
@@ -126,6 +138,28 @@ pub fn attach_session(id: &SessionId) -> Result<()> {
     Ok(())
 }
 
+/// Point the tmux client running this process at another session. Only
+/// works from inside tmux.
+pub fn switch_client(id: &SessionId) -> Result<()> {
+    let cmd = Command {
+        command: "switch-client".to_string(),
+        target: CommandTarget::Session(id.clone()),
+        args: vec![],
+    };
+
+    let response = execute_command(&cmd)?;
+
+    if !response.success {
+        return Err(TmuxError::Command(
+            response
+                .error
+                .unwrap_or_else(|| "Failed to switch client".to_string()),
+        ));
+    }
+
+    Ok(())
+}
+
 pub fn detach_session(id: &SessionId) -> Result<()> {
     let cmd = Command {
         command: "detach-client".to_string(),
@@ -0,0 +1,743 @@
+//! Target resolution shared by every command that addresses sessions,
+//! windows or panes.
+//!
+//! A target is written as one of:
+//!
+//! - `all`: every pane on the server
+//! - an id: `$1` for a session, `@3` for a window, `%7` for a pane
+//! - a tmux spec, `session:window.pane`, resolved by tmux itself
+//! - a tag, `@swarm_role=reviewer`, matching panes whose user option has
+//!   that value (set on the pane, its window or its session), or
+//!   `@swarm_role` for any value
+//! - a glob over names, `agent-*`
+//! - a name
+//!
+//! Names and globs are looked up among sessions first and among windows
+//! only when no session matches. Resolving a target picks sessions,
+//! windows or panes; a [`Selection`] then answers with whichever level a
+//! command acts on.
+
+use crate::messaging::query::glob_match;
+use crate::tmux::cli::execute_command;
+use crate::tmux::session::list_sessions;
+use crate::types::{
+    Command, CommandTarget, Pane, PaneId, ResponseData, Session, SessionId, Window, WindowId,
+};
+use crate::{Result, TmuxError};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    All,
+    Session(SessionId),
+    Window(WindowId),
+    Pane(PaneId),
+    /// `session:window.pane`, any part after the session may be left out
+    Spec(String),
+    /// User option, with the `@`, and the value it must have
+    Tag {
+        key: String,
+        value: Option<String>,
+    },
+    Glob(String),
+    Name(String),
+}
+
+/// Something a target picked
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Picked {
+    Session(SessionId),
+    Window(WindowId),
+    Pane(PaneId),
+}
+
+impl Target {
+    pub fn parse(text: &str) -> Result<Target> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(TmuxError::Parse("Empty target".to_string()));
+        }
+        if text == "all" {
+            return Ok(Target::All);
+        }
+        if let Some(id) = numeric_id(text, '$') {
+            return Ok(Target::Session(SessionId(id)));
+        }
+        if let Some(id) = numeric_id(text, '@') {
+            return Ok(Target::Window(WindowId(id)));
+        }
+        if let Some(id) = numeric_id(text, '%') {
+            return Ok(Target::Pane(PaneId(id)));
+        }
+        if text.starts_with('@') {
+            let (key, value) = match text.split_once('=') {
+                Some((key, value)) => (key, Some(value.to_string())),
+                None => (text, None),
+            };
+            if key.len() < 2 {
+                return Err(TmuxError::Parse(format!(
+                    "Tag '{}' has no name, e.g. @swarm_role=reviewer",
+                    text
+                )));
+            }
+            return Ok(Target::Tag {
+                key: key.to_string(),
+                value,
+            });
+        }
+        if text.contains(':') {
+            return Ok(Target::Spec(text.to_string()));
+        }
+        if text.contains('*') {
+            return Ok(Target::Glob(text.to_string()));
+        }
+        Ok(Target::Name(text.to_string()))
+    }
+
+    /// Resolve against the running server. Fails when nothing matches.
+    pub fn resolve(&self) -> Result<Selection> {
+        self.resolve_in(list_sessions()?)
+    }
+
+    /// Resolve against sessions already listed. Specs and tags still ask
+    /// the server.
+    pub fn resolve_in(&self, sessions: Vec<Session>) -> Result<Selection> {
+        let picked = match self {
+            Target::Spec(spec) => vec![resolve_spec(spec)?],
+            Target::Tag { key, .. } => self.pick(&sessions, &tag_values(key)?),
+            _ => self.pick(&sessions, &HashMap::new()),
+        };
+
+        if picked.is_empty() {
+            return Err(TmuxError::NotFound(format!(
+                "No session, window or pane matches '{}'",
+                self
+            )));
+        }
+        Ok(Selection {
+            target: self.to_string(),
+            sessions,
+            picked,
+        })
+    }
+
+    /// What the target picks out of `sessions`, in tmux order and without
+    /// duplicates. `tags` holds each pane's value of the tag a
+    /// [`Target::Tag`] matches. Specs need tmux and pick nothing here.
+    pub fn pick(&self, sessions: &[Session], tags: &HashMap<String, String>) -> Vec<Picked> {
+        let windows = || sessions.iter().flat_map(|s| &s.windows);
+        let panes = || windows().flat_map(|w| &w.panes);
+
+        let picked: Vec<Picked> = match self {
+            Target::All => panes().map(|p| Picked::Pane(p.id.clone())).collect(),
+            Target::Session(id) => sessions
+                .iter()
+                .filter(|s| s.id == *id)
+                .map(|s| Picked::Session(s.id.clone()))
+                .collect(),
+            Target::Window(id) => windows()
+                .filter(|w| w.id == *id)
+                .map(|w| Picked::Window(w.id.clone()))
+                .collect(),
+            Target::Pane(id) => panes()
+                .filter(|p| p.id == *id)
+                .map(|p| Picked::Pane(p.id.clone()))
+                .collect(),
+            Target::Spec(_) => Vec::new(),
+            Target::Tag { value, .. } => panes()
+                .filter(|p| match (tags.get(&p.id.0), value) {
+                    (Some(found), Some(value)) => found == value,
+                    (Some(found), None) => !found.is_empty(),
+                    (None, _) => false,
+                })
+                .map(|p| Picked::Pane(p.id.clone()))
+                .collect(),
+            Target::Glob(pattern) => pick_by_name(sessions, |name| glob_match(pattern, name)),
+            Target::Name(name) => pick_by_name(sessions, |found| found == name),
+        };
+
+        let mut unique = Vec::with_capacity(picked.len());
+        for item in picked {
+            if !unique.contains(&item) {
+                unique.push(item);
+            }
+        }
+        unique
+    }
+}
+
+impl FromStr for Target {
+    type Err = TmuxError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Target::parse(s)
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Target::All => write!(f, "all"),
+            Target::Session(id) => write!(f, "{}", id.0),
+            Target::Window(id) => write!(f, "{}", id.0),
+            Target::Pane(id) => write!(f, "{}", id.0),
+            Target::Tag {
+                key,
+                value: Some(value),
+            } => write!(f, "{}={}", key, value),
+            Target::Tag { key, value: None } => write!(f, "{}", key),
+            Target::Spec(text) | Target::Glob(text) | Target::Name(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+/// Parse and resolve in one step
+pub fn resolve_target(text: &str) -> Result<Selection> {
+    Target::parse(text)?.resolve()
+}
+
+/// The sessions, windows and panes a target picked, together with the
+/// server state they were picked from
+#[derive(Debug, Clone)]
+pub struct Selection {
+    target: String,
+    sessions: Vec<Session>,
+    picked: Vec<Picked>,
+}
+
+impl Selection {
+    /// Selection over an existing snapshot, e.g. for tests
+    pub fn new(target: impl Into<String>, sessions: Vec<Session>, picked: Vec<Picked>) -> Self {
+        Self {
+            target: target.into(),
+            sessions,
+            picked,
+        }
+    }
+
+    pub fn picked(&self) -> &[Picked] {
+        &self.picked
+    }
+
+    /// Sessions holding anything picked
+    pub fn sessions(&self) -> Vec<&Session> {
+        let mut found: Vec<&Session> = Vec::new();
+        for item in &self.picked {
+            let session = match item {
+                Picked::Session(id) => self.session_by_id(id),
+                Picked::Window(id) => self
+                    .window_by_id(id)
+                    .and_then(|w| self.session_by_id(&w.session_id)),
+                Picked::Pane(id) => self
+                    .pane_by_id(id)
+                    .and_then(|p| self.session_by_id(&p.session_id)),
+            };
+            if let Some(session) = session
+                && !found.iter().any(|s| s.id == session.id)
+            {
+                found.push(session);
+            }
+        }
+        found
+    }
+
+    /// Picked windows, the active window of picked sessions, and the
+    /// window of picked panes
+    pub fn windows(&self) -> Vec<&Window> {
+        self.collect_windows(false)
+    }
+
+    /// Picked windows, every window of picked sessions, and the window of
+    /// picked panes
+    pub fn all_windows(&self) -> Vec<&Window> {
+        self.collect_windows(true)
+    }
+
+    /// Picked panes, and the active pane of picked windows and of the
+    /// active window of picked sessions
+    pub fn panes(&self) -> Vec<&Pane> {
+        self.collect_panes(false)
+    }
+
+    /// Picked panes, and every pane of picked windows and sessions
+    pub fn all_panes(&self) -> Vec<&Pane> {
+        self.collect_panes(true)
+    }
+
+    /// The one session picked, for commands that act on a single session
+    pub fn session(&self) -> Result<&Session> {
+        self.single(self.sessions(), "sessions")
+    }
+
+    /// The one window picked, for commands that act on a single window
+    pub fn window(&self) -> Result<&Window> {
+        self.single(self.windows(), "windows")
+    }
+
+    /// The one pane picked, for commands that act on a single pane
+    pub fn pane(&self) -> Result<&Pane> {
+        self.single(self.panes(), "panes")
+    }
+
+    fn single<'a, T>(&self, mut found: Vec<&'a T>, what: &str) -> Result<&'a T> {
+        match found.len() {
+            1 => Ok(found.remove(0)),
+            0 => Err(TmuxError::NotFound(format!(
+                "No {} match '{}'",
+                what, self.target
+            ))),
+            n => Err(TmuxError::InvalidState(format!(
+                "'{}' matches {} {}; name exactly one",
+                self.target, n, what
+            ))),
+        }
+    }
+
+    fn collect_windows(&self, every: bool) -> Vec<&Window> {
+        let mut found: Vec<&Window> = Vec::new();
+        for item in &self.picked {
+            let windows: Vec<&Window> = match item {
+                Picked::Session(id) => match self.session_by_id(id) {
+                    Some(session) if every => session.windows.iter().collect(),
+                    Some(session) => active_window(session).into_iter().collect(),
+                    None => Vec::new(),
+                },
+                Picked::Window(id) => self.window_by_id(id).into_iter().collect(),
+                Picked::Pane(id) => self
+                    .pane_by_id(id)
+                    .and_then(|p| self.window_by_id(&p.window_id))
+                    .into_iter()
+                    .collect(),
+            };
+            for window in windows {
+                if !found.iter().any(|w| w.id == window.id) {
+                    found.push(window);
+                }
+            }
+        }
+        found
+    }
+
+    fn collect_panes(&self, every: bool) -> Vec<&Pane> {
+        let mut found: Vec<&Pane> = Vec::new();
+        for item in &self.picked {
+            let panes: Vec<&Pane> = match item {
+                Picked::Session(id) => match self.session_by_id(id) {
+                    Some(session) if every => {
+                        session.windows.iter().flat_map(|w| &w.panes).collect()
+                    }
+                    Some(session) => active_window(session)
+                        .map(|w| panes_of(w, every))
+                        .unwrap_or_default(),
+                    None => Vec::new(),
+                },
+                Picked::Window(id) => self
+                    .window_by_id(id)
+                    .map(|w| panes_of(w, every))
+                    .unwrap_or_default(),
+                Picked::Pane(id) => self.pane_by_id(id).into_iter().collect(),
+            };
+            for pane in panes {
+                if !found.iter().any(|p| p.id == pane.id) {
+                    found.push(pane);
+                }
+            }
+        }
+        found
+    }
+
+    fn session_by_id(&self, id: &SessionId) -> Option<&Session> {
+        self.sessions.iter().find(|s| s.id == *id)
+    }
+
+    fn window_by_id(&self, id: &WindowId) -> Option<&Window> {
+        self.sessions
+            .iter()
+            .flat_map(|s| &s.windows)
+            .find(|w| w.id == *id)
+    }
+
+    fn pane_by_id(&self, id: &PaneId) -> Option<&Pane> {
+        self.sessions
+            .iter()
+            .flat_map(|s| &s.windows)
+            .flat_map(|w| &w.panes)
+            .find(|p| p.id == *id)
+    }
+}
+
+/// Set a tag on a pane, or remove it when `value` is empty. `key` may be
+/// given with or without its `@`.
+pub fn set_tag(pane_id: &PaneId, key: &str, value: &str) -> Result<()> {
+    let key = tag_key(key);
+    let mut args = vec!["-p".to_string()];
+    if value.is_empty() {
+        args.extend(["-u".to_string(), key]);
+    } else {
+        args.extend([key, value.to_string()]);
+    }
+    let cmd = Command {
+        command: "set-option".to_string(),
+        target: CommandTarget::Pane(pane_id.clone()),
+        args,
+    };
+
+    let response = execute_command(&cmd)?;
+
+    if !response.success {
+        return Err(TmuxError::Command(
+            response
+                .error
+                .unwrap_or_else(|| "Failed to set tag".to_string()),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Each pane's value of a tag, as set on the pane, its window or its
+/// session. Panes without the tag are left out.
+pub fn tag_values(key: &str) -> Result<HashMap<String, String>> {
+    let cmd = Command {
+        command: "list-panes".to_string(),
+        target: CommandTarget::Server,
+        args: vec![
+            "-a".to_string(),
+            "-F".to_string(),
+            format!("#{{pane_id}}\t#{{{}}}", tag_key(key)),
+        ],
+    };
+
+    let response = execute_command(&cmd)?;
+
+    if !response.success {
+        return Err(TmuxError::Command(
+            response
+                .error
+                .unwrap_or_else(|| "Failed to read tags".to_string()),
+        ));
+    }
+
+    let mut values = HashMap::new();
+    if let ResponseData::Output(output) = response.data {
+        for line in output.lines() {
+            if let Some((pane_id, value)) = line.split_once('\t')
+                && !value.is_empty()
+            {
+                values.insert(pane_id.to_string(), value.to_string());
+            }
+        }
+    }
+    Ok(values)
+}
+
+fn tag_key(key: &str) -> String {
+    format!("@{}", key.trim_start_matches('@'))
+}
+
+/// `$12` style ids: the sigil followed by digits only
+fn numeric_id(text: &str, sigil: char) -> Option<String> {
+    let digits = text.strip_prefix(sigil)?;
+    (!digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit())).then(|| text.to_string())
+}
+
+fn pick_by_name<F>(sessions: &[Session], matches: F) -> Vec<Picked>
+where
+    F: Fn(&str) -> bool,
+{
+    let picked: Vec<Picked> = sessions
+        .iter()
+        .filter(|s| matches(&s.name))
+        .map(|s| Picked::Session(s.id.clone()))
+        .collect();
+    if !picked.is_empty() {
+        return picked;
+    }
+
+    sessions
+        .iter()
+        .flat_map(|s| &s.windows)
+        .filter(|w| matches(&w.name))
+        .map(|w| Picked::Window(w.id.clone()))
+        .collect()
+}
+
+/// Let tmux resolve `session:window.pane` to the level the spec names
+fn resolve_spec(spec: &str) -> Result<Picked> {
+    let cmd = Command {
+        command: "display-message".to_string(),
+        target: CommandTarget::Pane(PaneId(spec.to_string())),
+        args: vec![
+            "-p".to_string(),
+            "#{session_id}\t#{window_id}\t#{pane_id}".to_string(),
+        ],
+    };
+
+    let not_found = || TmuxError::NotFound(format!("No pane matches '{}'", spec));
+    // An unknown spec fails on some tmux versions and prints nothing on others
+    let output = match execute_command(&cmd) {
+        Ok(response) => match response.data {
+            ResponseData::Output(output) => output,
+            _ => return Err(not_found()),
+        },
+        Err(TmuxError::Command(_)) | Err(TmuxError::NotFound(_)) => return Err(not_found()),
+        Err(e) => return Err(e),
+    };
+
+    let ids: Vec<&str> = output.trim().split('\t').collect();
+    let [session_id, window_id, pane_id] = ids[..] else {
+        return Err(not_found());
+    };
+    if pane_id.is_empty() {
+        return Err(not_found());
+    }
+
+    let (_, rest) = spec.split_once(':').unwrap_or((spec, ""));
+    Ok(if rest.contains('.') {
+        Picked::Pane(PaneId(pane_id.to_string()))
+    } else if !rest.is_empty() {
+        Picked::Window(WindowId(window_id.to_string()))
+    } else {
+        Picked::Session(SessionId(session_id.to_string()))
+    })
+}
+
+fn active_window(session: &Session) -> Option<&Window> {
+    session
+        .windows
+        .iter()
+        .find(|w| w.active)
+        .or_else(|| session.windows.first())
+}
+
+/// Every pane of a window, or only its active one
+fn panes_of(window: &Window, every: bool) -> Vec<&Pane> {
+    if every {
+        window.panes.iter().collect()
+    } else {
+        active_pane(window).into_iter().collect()
+    }
+}
+
+fn active_pane(window: &Window) -> Option<&Pane> {
+    window
+        .panes
+        .iter()
+        .find(|p| p.active)
+        .or_else(|| window.panes.first())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pane(id: &str, window: &str, session: &str, active: bool) -> Pane {
+        Pane {
+            id: PaneId(id.to_string()),
+            window_id: WindowId(window.to_string()),
+            session_id: SessionId(session.to_string()),
+            current_path: None,
+            pid: None,
+            active,
+        }
+    }
+
+    fn window(id: &str, name: &str, session: &str, active: bool, panes: Vec<Pane>) -> Window {
+        Window {
+            id: WindowId(id.to_string()),
+            session_id: SessionId(session.to_string()),
+            name: name.to_string(),
+            panes,
+            active,
+        }
+    }
+
+    fn session(id: &str, name: &str, windows: Vec<Window>) -> Session {
+        Session {
+            id: SessionId(id.to_string()),
+            name: name.to_string(),
+            windows,
+            attached: false,
+        }
+    }
+
+    /// agent-1 ($1): @1 main (%1, %2 active), @2 logs (%3)
+    /// agent-2 ($2): @3 main (%4)
+    /// review ($3): @4 agent-9 (%5)
+    fn snapshot() -> Vec<Session> {
+        vec![
+            session(
+                "$1",
+                "agent-1",
+                vec![
+                    window(
+                        "@1",
+                        "main",
+                        "$1",
+                        true,
+                        vec![pane("%1", "@1", "$1", false), pane("%2", "@1", "$1", true)],
+                    ),
+                    window(
+                        "@2",
+                        "logs",
+                        "$1",
+                        false,
+                        vec![pane("%3", "@2", "$1", true)],
+                    ),
+                ],
+            ),
+            session(
+                "$2",
+                "agent-2",
+                vec![window(
+                    "@3",
+                    "main",
+                    "$2",
+                    true,
+                    vec![pane("%4", "@3", "$2", true)],
+                )],
+            ),
+            session(
+                "$3",
+                "review",
+                vec![window(
+                    "@4",
+                    "agent-9",
+                    "$3",
+                    true,
+                    vec![pane("%5", "@4", "$3", true)],
+                )],
+            ),
+        ]
+    }
+
+    fn select(text: &str, tags: &HashMap<String, String>) -> Selection {
+        let target = Target::parse(text).unwrap();
+        let picked = target.pick(&snapshot(), tags);
+        Selection::new(text, snapshot(), picked)
+    }
+
+    fn ids<T>(items: Vec<&T>, id: fn(&T) -> &str) -> Vec<&str> {
+        items.into_iter().map(id).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Target::parse("all").unwrap(), Target::All);
+        assert_eq!(
+            Target::parse("$1").unwrap(),
+            Target::Session(SessionId("$1".to_string()))
+        );
+        assert_eq!(
+            Target::parse("@3").unwrap(),
+            Target::Window(WindowId("@3".to_string()))
+        );
+        assert_eq!(
+            Target::parse("%7").unwrap(),
+            Target::Pane(PaneId("%7".to_string()))
+        );
+        assert_eq!(
+            Target::parse("@swarm_role=reviewer").unwrap(),
+            Target::Tag {
+                key: "@swarm_role".to_string(),
+                value: Some("reviewer".to_string()),
+            }
+        );
+        assert_eq!(
+            Target::parse("@swarm_role").unwrap(),
+            Target::Tag {
+                key: "@swarm_role".to_string(),
+                value: None,
+            }
+        );
+        assert_eq!(
+            Target::parse("work:build.1").unwrap(),
+            Target::Spec("work:build.1".to_string())
+        );
+        assert_eq!(
+            Target::parse("agent-*").unwrap(),
+            Target::Glob("agent-*".to_string())
+        );
+        assert_eq!(
+            Target::parse("$work").unwrap(),
+            Target::Name("$work".to_string())
+        );
+        assert!(Target::parse("").is_err());
+        assert!(Target::parse("@=x").is_err());
+    }
+
+    #[test]
+    fn test_display_round_trips() {
+        for text in [
+            "all", "$1", "@3", "%7", "@role=a", "@role", "s:w.1", "a-*", "work",
+        ] {
+            assert_eq!(Target::parse(text).unwrap().to_string(), text);
+        }
+    }
+
+    #[test]
+    fn test_all_picks_every_pane() {
+        let selection = select("all", &HashMap::new());
+        assert_eq!(selection.picked().len(), 5);
+        assert_eq!(selection.sessions().len(), 3);
+        assert_eq!(selection.windows().len(), 4);
+    }
+
+    #[test]
+    fn test_names_prefer_sessions() {
+        let selection = select("agent-*", &HashMap::new());
+        assert_eq!(
+            selection.picked(),
+            [
+                Picked::Session(SessionId("$1".to_string())),
+                Picked::Session(SessionId("$2".to_string())),
+            ]
+        );
+
+        let selection = select("main", &HashMap::new());
+        assert_eq!(ids(selection.windows(), |w| &w.id.0), ["@1", "@3"]);
+        assert!(selection.window().is_err());
+
+        assert!(select("nothing", &HashMap::new()).picked().is_empty());
+    }
+
+    #[test]
+    fn test_levels_of_a_session() {
+        let selection = select("agent-1", &HashMap::new());
+        assert_eq!(selection.session().unwrap().id.0, "$1");
+        assert_eq!(selection.window().unwrap().id.0, "@1");
+        assert_eq!(selection.pane().unwrap().id.0, "%2");
+        assert_eq!(ids(selection.all_windows(), |w| &w.id.0), ["@1", "@2"]);
+        assert_eq!(ids(selection.all_panes(), |p| &p.id.0), ["%1", "%2", "%3"]);
+    }
+
+    #[test]
+    fn test_levels_of_a_pane() {
+        let selection = select("%3", &HashMap::new());
+        assert_eq!(selection.session().unwrap().name, "agent-1");
+        assert_eq!(selection.window().unwrap().name, "logs");
+        assert_eq!(ids(selection.all_panes(), |p| &p.id.0), ["%3"]);
+    }
+
+    #[test]
+    fn test_tags() {
+        let tags: HashMap<String, String> =
+            [("%2", "reviewer"), ("%4", "coder"), ("%5", "reviewer")]
+                .into_iter()
+                .map(|(pane, value)| (pane.to_string(), value.to_string()))
+                .collect();
+
+        let selection = select("@swarm_role=reviewer", &tags);
+        assert_eq!(ids(selection.panes(), |p| &p.id.0), ["%2", "%5"]);
+        assert_eq!(
+            ids(selection.sessions(), |s| &s.name),
+            ["agent-1", "review"]
+        );
+        let error = selection.session().unwrap_err();
+        assert!(matches!(error, TmuxError::InvalidState(_)));
+
+        assert_eq!(select("@swarm_role", &tags).panes().len(), 3);
+    }
+}
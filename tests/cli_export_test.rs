@@ -0,0 +1,87 @@
+//! `swarm_test export` run end to end through the binary, once per format.
+//!
+//! Each run points `--socket` at a socket no server listens on, so the
+//! session argument is read as a log id rather than a live tmux target.
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+use tempfile::TempDir;
+
+const LOG: &str = "[2024-01-02 03:04:05] Running: bash cargo test\n\
+                   [2024-01-02 03:04:10] API Error: overloaded\n";
+
+fn export(dir: &Path, args: &[&str]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_swarm_test"))
+        .arg("--socket")
+        .arg(dir.join("no_server.sock"))
+        .arg("--log-dir")
+        .arg(dir)
+        .arg("--base-path")
+        .arg(dir)
+        .arg("export")
+        .arg("cli_export")
+        .args(args)
+        .output()
+        .expect("Failed to run swarm_test");
+    assert!(
+        output.status.success(),
+        "export {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+fn log_dir() -> TempDir {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("session_cli_export.log"), LOG).unwrap();
+    dir
+}
+
+#[test]
+fn test_export_markdown() {
+    let dir = log_dir();
+    for flag in ["--format", "-f"] {
+        let output = export(dir.path(), &[flag, "md"]);
+        let text = String::from_utf8(output.stdout).unwrap();
+        assert!(text.starts_with("# Transcript: cli_export"), "{}", text);
+        assert!(text.contains("Tool call **bash**"), "{}", text);
+    }
+}
+
+#[test]
+fn test_export_html() {
+    let dir = log_dir();
+    let output = export(dir.path(), &["--format", "html"]);
+    let text = String::from_utf8(output.stdout).unwrap();
+    assert!(text.starts_with("<!DOCTYPE html>"), "{}", text);
+    assert!(text.contains("API Error: overloaded"), "{}", text);
+}
+
+#[test]
+fn test_export_jsonl() {
+    let dir = log_dir();
+    let output = export(dir.path(), &["--format", "jsonl"]);
+    let text = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<serde_json::Value> = text
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 1, "{}", text);
+    assert_eq!(lines[0]["kind"], "response");
+    assert_eq!(lines[0]["response"]["tool_calls"][0]["tool_name"], "bash");
+    assert_eq!(lines[0]["response"]["errors"][0]["category"], "api");
+}
+
+#[test]
+fn test_export_to_file() {
+    let dir = log_dir();
+    let path = dir.path().join("transcript.html");
+    export(
+        dir.path(),
+        &["--format", "html", "--output", path.to_str().unwrap()],
+    );
+    let text = fs::read_to_string(&path).unwrap();
+    assert!(text.starts_with("<!DOCTYPE html>"), "{}", text);
+}
@@ -2,6 +2,7 @@ use std::fs;
 use std::path::PathBuf;
 use swarm_test::messaging::read::LogReader;
 use swarm_test::messaging::send::MessageSender;
+use swarm_test::tmux::pane;
 use swarm_test::tmux::session as tmux_session;
 use swarm_test::types::{PaneId, SessionId};
 
@@ -20,15 +21,15 @@ fn cleanup() {
     std::thread::sleep(std::time::Duration::from_millis(200));
 
     let log_dir = PathBuf::from(TEST_LOG_DIR);
-    if log_dir.exists() {
-        if let Ok(entries) = fs::read_dir(&log_dir) {
-            for entry in entries.flatten() {
-                let path = entry.path();
-                if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
-                    if name.starts_with("session_") {
-                        let _ = fs::remove_file(&path);
-                    }
-                }
+    if log_dir.exists()
+        && let Ok(entries) = fs::read_dir(&log_dir)
+    {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if let Some(name) = path.file_name().and_then(|n| n.to_str())
+                && name.starts_with("session_")
+            {
+                let _ = fs::remove_file(&path);
             }
         }
     }
@@ -69,14 +70,14 @@ fn test_full_session_lifecycle() {
     if rename_result.is_ok() {
         std::thread::sleep(std::time::Duration::from_millis(100));
 
-        if let Ok(all_sessions) = tmux_session::list_sessions() {
-            if let Some(renamed_session) = all_sessions.iter().find(|s| s.id == session_id) {
-                assert_eq!(
-                    renamed_session.name, new_name,
-                    "Session name should be updated to {}",
-                    new_name
-                );
-            }
+        if let Ok(all_sessions) = tmux_session::list_sessions()
+            && let Some(renamed_session) = all_sessions.iter().find(|s| s.id == session_id)
+        {
+            assert_eq!(
+                renamed_session.name, new_name,
+                "Session name should be updated to {}",
+                new_name
+            );
         }
     } else {
         eprintln!(
@@ -269,6 +270,33 @@ fn test_multiple_sessions() {
     cleanup();
 }
 
+#[test]
+fn test_prompt_starting_with_dash_is_typed_literally() {
+    // Not under TEST_SESSION_PREFIX, so other tests' cleanup leaves it alone
+    let session_name = format!("swarm_literal_{}", uuid::Uuid::new_v4());
+    let session = tmux_session::new_session(&session_name).expect("Failed to create session");
+    let pane_id = session.windows[0].panes[0].id.clone();
+
+    let prompt = "-l -- Enter; echo not run";
+    pane::send_literal(&pane_id, prompt).expect("Failed to send prompt");
+
+    let mut screen = String::new();
+    for _ in 0..50 {
+        screen = pane::capture_pane_output(&pane_id).expect("Failed to capture pane");
+        if screen.contains(prompt) {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+
+    tmux_session::kill_session(&session.id).ok();
+    assert!(
+        screen.contains(prompt),
+        "Prompt not typed as is: {}",
+        screen
+    );
+}
+
 #[test]
 fn test_log_reader_with_custom_dir() {
     let temp_dir = tempfile::TempDir::new().expect("Failed to create temp dir");
@@ -0,0 +1,277 @@
+use std::time::Duration;
+use swarm_test::config::{Config, MessageMode};
+use swarm_test::error::TmuxError;
+use swarm_test::opencode::{
+    Auth, Failure, MockOpenCodeServer, MockResponse, OpenCodeClient, OpenCodeDiscovery,
+    OpenCodeReader, OpenCodeSender, RetryPolicy, ServerStatus, Transport,
+};
+use swarm_test::session_mapping::SessionMappingStore;
+
+fn fast_retries() -> RetryPolicy {
+    RetryPolicy {
+        max_retries: 2,
+        base_delay: Duration::from_millis(1),
+        max_delay: Duration::from_millis(5),
+    }
+}
+
+#[tokio::test]
+async fn test_client_lists_sessions() {
+    let server = MockOpenCodeServer::start().expect("Failed to start mock server");
+    server.add_session("ses_a", "agent-a");
+    server.add_session("ses_b", "agent-b");
+
+    let client = OpenCodeClient::new(&server.url());
+
+    assert!(client.health_check().await.unwrap());
+
+    let sessions = client
+        .list_sessions()
+        .await
+        .expect("Failed to list sessions");
+    assert_eq!(sessions.len(), 2);
+    assert!(sessions.iter().any(|s| s.id == "ses_a"));
+}
+
+#[tokio::test]
+async fn test_client_prompt_is_recorded() {
+    let server = MockOpenCodeServer::start().expect("Failed to start mock server");
+    server.add_session("ses_a", "agent-a");
+
+    let client = OpenCodeClient::new(&server.url());
+    client
+        .session_prompt("ses_a", "Fix the build")
+        .await
+        .expect("Failed to send prompt");
+
+    let received = server.received();
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].endpoint, "prompt");
+    assert_eq!(received[0].content, "Fix the build");
+}
+
+#[tokio::test]
+async fn test_client_http_error_variant() {
+    let server = MockOpenCodeServer::start().expect("Failed to start mock server");
+
+    let client = OpenCodeClient::new(&server.url());
+    let result = client.get_session_status("missing").await;
+
+    assert!(matches!(result, Err(TmuxError::Http(404, _))));
+}
+
+#[tokio::test]
+async fn test_client_decode_error_variant() {
+    let server = MockOpenCodeServer::start().expect("Failed to start mock server");
+    server.script("GET", "/sessions", MockResponse::json(200, "not json"));
+
+    let client = OpenCodeClient::new(&server.url());
+    let result = client.list_sessions().await;
+
+    assert!(matches!(result, Err(TmuxError::Decode(_))));
+}
+
+#[tokio::test]
+async fn test_client_retries_idempotent_requests() {
+    let server = MockOpenCodeServer::start().expect("Failed to start mock server");
+    server.add_session("ses_a", "agent-a");
+    server.inject_failures(Failure::Status(503), 2);
+
+    let client = OpenCodeClient::builder(&server.url())
+        .retry_policy(fast_retries())
+        .build()
+        .unwrap();
+
+    let sessions = client
+        .list_sessions()
+        .await
+        .expect("Retries should succeed");
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(server.request_count(), 3);
+}
+
+#[tokio::test]
+async fn test_client_does_not_retry_prompts() {
+    let server = MockOpenCodeServer::start().expect("Failed to start mock server");
+    server.add_session("ses_a", "agent-a");
+    server.inject_failure(Failure::Status(503));
+
+    let client = OpenCodeClient::builder(&server.url())
+        .retry_policy(fast_retries())
+        .build()
+        .unwrap();
+
+    let result = client.session_prompt("ses_a", "hello").await;
+    assert!(matches!(result, Err(TmuxError::Http(503, _))));
+    assert_eq!(server.request_count(), 1);
+    assert!(server.received().is_empty());
+}
+
+#[tokio::test]
+async fn test_client_read_timeout() {
+    let server = MockOpenCodeServer::start().expect("Failed to start mock server");
+    server.set_latency(Duration::from_millis(500));
+
+    let client = OpenCodeClient::builder(&server.url())
+        .read_timeout(Duration::from_millis(50))
+        .retry_policy(RetryPolicy::none())
+        .build()
+        .unwrap();
+
+    let result = client.health_check().await;
+    assert!(matches!(result, Err(TmuxError::Transport(_))));
+}
+
+#[tokio::test]
+async fn test_reader_messages_and_summary() {
+    let server = MockOpenCodeServer::start().expect("Failed to start mock server");
+    server.add_session("ses_a", "agent-a");
+    server.push_message("ses_a", "user", "first");
+    server.push_message("ses_a", "assistant", "second");
+    server.push_message("ses_a", "user", "third");
+
+    let reader = OpenCodeReader::new(server.url());
+
+    let messages = reader.get_messages("ses_a", None).await.unwrap();
+    assert_eq!(messages.len(), 3);
+
+    let limited = reader.get_messages("ses_a", Some(2)).await.unwrap();
+    assert_eq!(limited.len(), 2);
+
+    let summary = reader.get_session_summary("ses_a").await.unwrap();
+    assert_eq!(summary.session_id, "ses_a");
+    assert_eq!(summary.messages.len(), 3);
+}
+
+#[tokio::test]
+async fn test_reader_uses_client_retries_and_errors() {
+    let server = MockOpenCodeServer::start().expect("Failed to start mock server");
+    server.add_session("ses_a", "agent-a");
+    server.push_message("ses_a", "user", "first");
+    server.inject_failures(Failure::Status(503), 2);
+
+    let client = OpenCodeClient::builder(&server.url())
+        .retry_policy(fast_retries())
+        .build()
+        .unwrap();
+    let reader = OpenCodeReader::with_client(client);
+
+    let messages = reader
+        .get_messages("ses_a", None)
+        .await
+        .expect("Retries should succeed");
+    assert_eq!(messages.len(), 1);
+    assert_eq!(server.request_count(), 3);
+
+    let result = reader.get_session_summary("missing").await;
+    assert!(matches!(result, Err(TmuxError::Http(404, _))));
+}
+
+#[tokio::test]
+async fn test_discovery_status() {
+    let server = MockOpenCodeServer::start().expect("Failed to start mock server");
+    let discovery = OpenCodeDiscovery::new(server.url());
+
+    let status = discovery.check_default_server().await;
+    assert!(status.running);
+    assert_eq!(status.status, ServerStatus::Available);
+
+    server.set_healthy(false);
+    let status = discovery.check_default_server().await;
+    assert!(!status.running);
+    assert_eq!(status.status, ServerStatus::Unavailable);
+
+    server.inject_failure(Failure::Disconnect);
+    let status = discovery.check_default_server().await;
+    assert_eq!(status.status, ServerStatus::Unknown);
+}
+
+#[tokio::test]
+async fn test_sender_delivers_over_opencode() {
+    let server = MockOpenCodeServer::start().expect("Failed to start mock server");
+    server.add_session("ses_a", "agent-a");
+    let temp_dir = tempfile::TempDir::new().expect("Failed to create temp dir");
+
+    let store = SessionMappingStore::empty(temp_dir.path().join("sessions.json"));
+    let mut sender = OpenCodeSender::with_store(Config::default(), store)
+        .expect("Failed to create sender")
+        .with_base_path(temp_dir.path());
+    sender.initialize(Some(server.url())).await.unwrap();
+
+    let report = sender
+        .send_prompt("ses_a", MessageMode::Auto, "hello")
+        .await
+        .unwrap();
+
+    assert_eq!(report.delivered_via, Some(Transport::OpenCode));
+    assert!(report.failures().is_empty());
+    assert_eq!(server.received().len(), 1);
+}
+
+#[tokio::test]
+async fn test_sender_falls_back_when_server_fails() {
+    let server = MockOpenCodeServer::start().expect("Failed to start mock server");
+    server.add_session("ses_a", "agent-a");
+    server.inject_failure(Failure::Status(500));
+    let temp_dir = tempfile::TempDir::new().expect("Failed to create temp dir");
+
+    // No tmux pane, whatever sessions the host's tmux server has
+    let store = SessionMappingStore::empty(temp_dir.path().join("sessions.json"));
+    let mut sender = OpenCodeSender::with_store(Config::default(), store)
+        .expect("Failed to create sender")
+        .with_base_path(temp_dir.path())
+        .with_pane_lookup(|_| Ok(None));
+    sender.initialize(Some(server.url())).await.unwrap();
+
+    let report = sender
+        .send_prompt("ses_a", MessageMode::Auto, "hello")
+        .await
+        .unwrap();
+
+    assert_eq!(report.delivered_via, Some(Transport::File));
+    let failures = report.failures();
+    assert_eq!(failures[0].0, Transport::OpenCode);
+    assert!(failures[0].1.contains("500"));
+    assert_eq!(failures[1].0, Transport::Tmux);
+    assert!(failures[1].1.contains("No tmux pane"));
+    assert!(server.received().is_empty());
+}
+
+#[tokio::test]
+async fn test_sender_authenticates_through_initialize() {
+    let server = MockOpenCodeServer::start().expect("Failed to start mock server");
+    server.add_session("ses_a", "agent-a");
+    server.require_auth(Some("Bearer secret"));
+    let temp_dir = tempfile::TempDir::new().expect("Failed to create temp dir");
+
+    let store = SessionMappingStore::empty(temp_dir.path().join("sessions.json"));
+    let mut sender = OpenCodeSender::with_store(Config::default(), store)
+        .expect("Failed to create sender")
+        .with_base_path(temp_dir.path())
+        .with_pane_lookup(|_| Ok(None))
+        .with_auth(Auth::Bearer("secret".to_string()));
+    sender.initialize(Some(server.url())).await.unwrap();
+
+    let report = sender
+        .send_prompt("ses_a", MessageMode::Auto, "hello")
+        .await
+        .unwrap();
+    assert_eq!(report.delivered_via, Some(Transport::OpenCode));
+    assert_eq!(server.received().len(), 1);
+
+    let store = SessionMappingStore::empty(temp_dir.path().join("sessions.json"));
+    let mut sender = OpenCodeSender::with_store(Config::default(), store)
+        .expect("Failed to create sender")
+        .with_base_path(temp_dir.path())
+        .with_pane_lookup(|_| Ok(None))
+        .with_auth(Auth::Bearer("wrong".to_string()));
+    sender.initialize(Some(server.url())).await.unwrap();
+
+    let report = sender
+        .send_prompt("ses_a", MessageMode::Auto, "hello")
+        .await
+        .unwrap();
+    assert_eq!(report.delivered_via, Some(Transport::File));
+    assert!(report.failures()[0].1.contains("401"));
+    assert_eq!(server.received().len(), 1);
+}
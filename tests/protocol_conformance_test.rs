@@ -0,0 +1,158 @@
+//! Dialect conformance corpus.
+//!
+//! Every `tests/fixtures/transcripts/<dialect>/<case>.txt` is a recorded
+//! transcript. The `<case>.json` file next to it holds the expected parse
+//! result for the dialect.
+
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use swarm_test::opencode::{DialectRegistry, MessageType};
+
+#[derive(Debug, Deserialize)]
+struct Expectation {
+    message_type: MessageType,
+    /// Tool names in order of appearance
+    #[serde(default)]
+    tools: Vec<String>,
+    /// Per tool call, arguments that must be present with these values
+    #[serde(default)]
+    arguments: Vec<serde_json::Map<String, serde_json::Value>>,
+    /// Substring of the response content
+    content: Option<String>,
+    /// Substring of the response error
+    error: Option<String>,
+    /// Whether the registry should detect the dialect from the transcript
+    #[serde(default = "default_detect")]
+    detect: bool,
+}
+
+fn default_detect() -> bool {
+    true
+}
+
+fn corpus_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/transcripts")
+}
+
+fn cases(dialect: &str) -> Vec<(String, String, Expectation)> {
+    let dir = corpus_dir().join(dialect);
+    let mut cases = Vec::new();
+
+    for entry in fs::read_dir(&dir).expect("Failed to read corpus dir") {
+        let path = entry.unwrap().path();
+        if path.extension().and_then(|e| e.to_str()) != Some("txt") {
+            continue;
+        }
+
+        let transcript = fs::read_to_string(&path).unwrap();
+        let expected = fs::read_to_string(path.with_extension("json"))
+            .unwrap_or_else(|_| panic!("Missing expectation for {}", path.display()));
+        let expectation: Expectation = serde_json::from_str(&expected)
+            .unwrap_or_else(|e| panic!("Bad expectation for {}: {}", path.display(), e));
+
+        let name = path.file_stem().unwrap().to_string_lossy().to_string();
+        cases.push((name, transcript, expectation));
+    }
+
+    cases.sort_by(|a, b| a.0.cmp(&b.0));
+    cases
+}
+
+fn check_dialect(dialect: &str) {
+    let registry = DialectRegistry::with_builtin();
+    let protocol = registry.get(dialect).expect("Dialect not registered");
+    let cases = cases(dialect);
+    assert!(!cases.is_empty(), "No transcripts for {}", dialect);
+
+    for (name, transcript, expected) in cases {
+        let case = format!("{}/{}", dialect, name);
+        let response = protocol.parse_response(&transcript).unwrap();
+
+        assert_eq!(response.message_type, expected.message_type, "{}", case);
+        assert_eq!(
+            response.success,
+            expected.message_type != MessageType::Error,
+            "{}",
+            case
+        );
+
+        let tools: Vec<&str> = response
+            .tool_calls
+            .iter()
+            .map(|c| c.tool_name.as_str())
+            .collect();
+        assert_eq!(tools, expected.tools, "{}", case);
+
+        for (call, arguments) in response.tool_calls.iter().zip(&expected.arguments) {
+            for (key, value) in arguments {
+                assert_eq!(
+                    call.arguments.get(key),
+                    Some(value),
+                    "{}: argument {} of {}",
+                    case,
+                    key,
+                    call.tool_name
+                );
+            }
+        }
+
+        if let Some(content) = &expected.content {
+            assert!(
+                response.content.contains(content.as_str()),
+                "{}: content {:?}",
+                case,
+                response.content
+            );
+        }
+
+        if let Some(error) = &expected.error {
+            let actual = response.error.as_deref().unwrap_or_default();
+            assert!(
+                actual.contains(error.as_str()),
+                "{}: error {:?}",
+                case,
+                actual
+            );
+        }
+
+        if expected.detect {
+            let detected = registry.detect(&transcript).unwrap();
+            assert_eq!(detected.name(), dialect, "{}: detected dialect", case);
+
+            let auto = registry.parse(&transcript).unwrap();
+            assert_eq!(auto.message_type, response.message_type, "{}: auto", case);
+        }
+    }
+}
+
+#[test]
+fn test_opencode_corpus() {
+    check_dialect("opencode");
+}
+
+#[test]
+fn test_claude_corpus() {
+    check_dialect("claude");
+}
+
+#[test]
+fn test_aider_corpus() {
+    check_dialect("aider");
+}
+
+#[test]
+fn test_shell_corpus() {
+    check_dialect("shell");
+}
+
+#[test]
+fn test_every_dialect_has_a_corpus() {
+    for dialect in DialectRegistry::with_builtin().names() {
+        assert!(
+            corpus_dir().join(dialect).is_dir(),
+            "No corpus for dialect {}",
+            dialect
+        );
+    }
+}